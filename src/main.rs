@@ -1,14 +1,308 @@
+use std::path::PathBuf;
+
+use log::LevelFilter;
+use serde::Serialize;
 use webvy_app::{
-    app::ProcessorApp,
-    processor::{ConfigurationProcessor, MarkdownFrontMatter, MarkdownProcessor, TeraProcessor},
+    build,
+    cancel::CancellationToken,
+    config::DiffMode,
+    diff::summarize_changes,
+    errors::ProcessorError,
+    logging::{self, LogFilter},
+    report::{BuildReport, Diagnostic, DiagnosticSink},
+    SiteOptions,
 };
 
+/// Set to anything but an empty string to dump front matter, as `--debug-matter` does.
+const DEBUG_MATTER_VAR: &str = "WEBVY_DEBUG_MATTER";
+
+/// Exit code for builds stopped by Ctrl-C, following the shell convention for SIGINT.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MessageFormat {
+    Human,
+    Json,
+}
+
+/// A newline-delimited JSON record emitted with `--message-format json`.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Record<'a> {
+    Diagnostic(&'a Diagnostic),
+    Error {
+        message: String,
+    },
+    Summary {
+        success: bool,
+        #[serde(flatten)]
+        report: Option<&'a BuildReport>,
+    },
+}
+
+impl Record<'_> {
+    fn emit(&self) {
+        println!("{}", serde_json::to_string(self).unwrap());
+    }
+}
+
+struct Args {
+    format: MessageFormat,
+    /// Each `-v` raises the log level by one, each `-q` lowers it by one.
+    verbosity: i8,
+    /// Compares outputs against the output directory instead of writing them.
+    diff: Option<DiffMode>,
+    /// Dumps the front matter of each page for inspection, also enabled by setting
+    /// `WEBVY_DEBUG_MATTER`.
+    debug_matter: bool,
+    /// Writes the output here instead of the configured output directory.
+    output: Option<PathBuf>,
+}
+
+impl Args {
+    /// The log level, starting from warnings for humans. JSON records would interleave
+    /// with log lines, so nothing is logged for them unless asked for with `-v`.
+    fn log_level(&self) -> LevelFilter {
+        let levels = [
+            LevelFilter::Off,
+            LevelFilter::Error,
+            LevelFilter::Warn,
+            LevelFilter::Info,
+            LevelFilter::Debug,
+            LevelFilter::Trace,
+        ];
+        let default = match self.format {
+            MessageFormat::Human => 2,
+            MessageFormat::Json => 0,
+        };
+
+        levels[(default + self.verbosity).clamp(0, 5) as usize]
+    }
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut format = MessageFormat::Human;
+    let mut verbosity = 0i8;
+    let mut diff = None;
+    let mut output = None;
+    let mut debug_matter = std::env::var_os(DEBUG_MATTER_VAR).is_some_and(|var| !var.is_empty());
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        if let Some(flags) = arg.strip_prefix('-').filter(|flags| {
+            !flags.is_empty() && flags.chars().all(|flag| flag == 'v' || flag == 'q')
+        }) {
+            for flag in flags.chars() {
+                verbosity += if flag == 'v' { 1 } else { -1 };
+            }
+
+            continue;
+        }
+
+        match arg.as_str() {
+            "--verbose" => {
+                verbosity += 1;
+                continue;
+            }
+            "--quiet" => {
+                verbosity -= 1;
+                continue;
+            }
+            "--diff" | "--diff=summary" => {
+                diff = Some(DiffMode::Summary);
+                continue;
+            }
+            "--diff=unified" => {
+                diff = Some(DiffMode::Unified);
+                continue;
+            }
+            _ if arg.starts_with("--diff=") => {
+                return Err(String::from("--diff must be `summary` or `unified`"));
+            }
+            "--debug-matter" => {
+                debug_matter = true;
+                continue;
+            }
+            _ => {}
+        }
+
+        if let Some(value) = arg.strip_prefix("--output") {
+            let value = match value {
+                "" => args.next(),
+                value if value.starts_with('=') => Some(value[1..].to_string()),
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            };
+
+            match value.filter(|dir| !dir.is_empty()) {
+                Some(dir) => output = Some(PathBuf::from(dir)),
+                None => return Err(String::from("--output must be given a directory")),
+            }
+
+            continue;
+        }
+
+        let value = match arg.strip_prefix("--message-format") {
+            Some("") => args.next(),
+            Some(value) if value.starts_with('=') => Some(value[1..].to_string()),
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        };
+
+        format = match value.as_deref() {
+            Some("human") => MessageFormat::Human,
+            Some("json") => MessageFormat::Json,
+            _ => return Err(String::from("--message-format must be `human` or `json`")),
+        };
+    }
+
+    Ok(Args {
+        format,
+        verbosity,
+        diff,
+        debug_matter,
+        output,
+    })
+}
+
+/// Lists the outputs a diff build would change, along with their diffs.
+fn print_changes(report: &BuildReport) {
+    println!("{}", summarize_changes(&report.changes));
+
+    for change in report.changes.iter() {
+        println!("  {:<8} {}", change.kind, change.path.display());
+    }
+
+    for diff in report
+        .changes
+        .iter()
+        .filter_map(|change| change.diff.as_ref())
+    {
+        print!("\n{}", diff);
+    }
+}
+
+/// Fails a diff build that found changes, so scripts can tell a deploy is pending.
+fn exit_on_changes(report: &BuildReport) {
+    if !report.changes.is_empty() {
+        std::process::exit(1);
+    }
+}
+
 fn main() {
-    env_logger::init();
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    let format = args.format;
+
+    // RUST_LOG enables more on top of the level set by the flags.
+    let filter = match std::env::var("RUST_LOG").map(|filter| filter.parse::<LogFilter>()) {
+        Ok(Ok(filter)) => Some(filter),
+        Ok(Err(e)) => {
+            eprintln!("RUST_LOG: {}", e);
+            std::process::exit(2);
+        }
+        Err(_) => None,
+    };
+
+    logging::configure(args.log_level(), filter.as_ref());
+
+    let cancel = CancellationToken::default();
+
+    // The first Ctrl-C lets the build stop cleanly, a second one exits immediately.
+    let handler = cancel.clone();
+    if let Err(e) = ctrlc::set_handler(move || {
+        if handler.is_cancelled() {
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+
+        handler.cancel();
+    }) {
+        log::warn!("Unable to install the Ctrl-C handler: {}", e);
+    }
+
+    let mut options = SiteOptions::default()
+        .with_cancellation(cancel)
+        .with_debug_matter(args.debug_matter);
+
+    if let Some(diff) = args.diff {
+        options = options.with_diff(diff);
+    }
+
+    if let Some(output) = args.output.clone() {
+        options = options.with_output(output);
+    }
+
+    if format == MessageFormat::Json {
+        options = options.with_diagnostic_sink(DiagnosticSink::new(|diagnostic| {
+            Record::Diagnostic(diagnostic).emit()
+        }));
+    }
+
+    match (build(options), format) {
+        (Ok(report), MessageFormat::Json) => {
+            Record::Summary {
+                success: true,
+                report: Some(&report),
+            }
+            .emit();
+
+            exit_on_changes(&report);
+        }
+        (Ok(report), MessageFormat::Human) => {
+            if args.diff.is_some() {
+                print_changes(&report);
+            }
+
+            exit_on_changes(&report);
+        }
+        (Err(ProcessorError::Interrupted), MessageFormat::Human) => {
+            eprintln!("{}", ProcessorError::Interrupted);
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+        (Err(e), MessageFormat::Json) => {
+            let interrupted = matches!(e, ProcessorError::Interrupted);
+            let errors = match e {
+                ProcessorError::Build(errors) => errors,
+                e => vec![e],
+            };
+
+            // Diagnostics have already been emitted as they were recorded.
+            errors
+                .iter()
+                .filter(|error| !matches!(error, ProcessorError::Diagnostic(_)))
+                .for_each(|error| {
+                    Record::Error {
+                        message: error.to_string(),
+                    }
+                    .emit()
+                });
+
+            Record::Summary {
+                success: false,
+                report: None,
+            }
+            .emit();
+
+            std::process::exit(if interrupted {
+                INTERRUPTED_EXIT_CODE
+            } else {
+                1
+            });
+        }
+        (Err(e), MessageFormat::Human) => {
+            eprintln!("{}", e);
+
+            if let ProcessorError::Build(errors) = e {
+                for error in errors {
+                    // Keeps the causes of diagnostics indented under them.
+                    eprintln!("  {}", error.to_string().replace('\n', "\n  "));
+                }
+            }
 
-    ProcessorApp::default()
-        .add_processor(ConfigurationProcessor::new("blog.toml"))
-        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::default())
-        .add_processor(TeraProcessor::default())
-        .run();
+            std::process::exit(1);
+        }
+    }
 }
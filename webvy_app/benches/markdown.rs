@@ -0,0 +1,72 @@
+//! Measures parsing and converting a synthetic site of small markdown pages, where
+//! the cost of queueing results from the parallel systems dominates, along with the
+//! peak memory of the process where the OS reports it. Run with
+//! `cargo bench --bench markdown`.
+use std::time::Instant;
+
+use toml::Value;
+use webvy_app::{
+    prelude::*,
+    processor::{FileConfig, InputDir},
+};
+
+const PAGES: usize = 10_000;
+const ITERATIONS: u32 = 5;
+
+fn main() {
+    let mut front_matter = toml::Table::new();
+    front_matter.insert(String::from("title"), Value::from("A page"));
+    front_matter.insert(String::from("tags"), Value::from(vec!["notes", "bench"]));
+
+    let body = "A short page, with *some* emphasis.\n\n\
+                ## A heading\n\n\
+                - a list\n- of things\n\n\
+                <!-- more -->\n\n\
+                And a [link](/elsewhere) to finish.\n";
+
+    let mut elapsed = Vec::new();
+
+    for _ in 0..ITERATIONS {
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+        app.init_resource::<SiteConfig>();
+
+        for page in 0..PAGES {
+            app.add_page(format!("posts/{}.md", page), front_matter.clone(), body);
+        }
+
+        let start = Instant::now();
+
+        app.add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .run()
+            .unwrap();
+
+        elapsed.push(start.elapsed());
+    }
+
+    elapsed.sort();
+
+    println!(
+        "markdown: {} pages in {:?} (median of {})",
+        PAGES,
+        elapsed[elapsed.len() / 2],
+        ITERATIONS
+    );
+
+    if let Some(peak) = peak_rss() {
+        println!("markdown: peak RSS {}", peak);
+    }
+}
+
+/// The peak resident set size of the process, as reported by Linux.
+fn peak_rss() -> Option<String> {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .map(|peak| peak.trim().to_string())
+}
@@ -0,0 +1,32 @@
+//! Measures the typography pass over a page of rendered markdown, as it runs over
+//! every page of a site when enabled. Run with `cargo bench --bench typography`.
+use std::{hint::black_box, time::Instant};
+
+use webvy_app::typography::{Typographer, TypographyRules};
+
+const ITERATIONS: u32 = 200;
+
+fn main() {
+    let paragraph = "<p>It took a while to walk the 12 km to the top of the hill — and \
+                     back again — but the view of the valley was worth it. \
+                     <em>Mostly</em> worth it, <a href=\"/a b\">in a way</a>.</p>\n";
+    let code = "<pre><code class=\"language-rust\">let a = b &lt; c;\n</code></pre>\n";
+    let html = [paragraph, paragraph, paragraph, code].concat().repeat(250);
+
+    let typographer = Typographer::new(&TypographyRules::default());
+
+    let start = Instant::now();
+
+    for _ in 0..ITERATIONS {
+        black_box(typographer.apply(black_box(&html)));
+    }
+
+    let elapsed = start.elapsed();
+
+    println!(
+        "typography: {} bytes in {:?} per page ({:.1} MB/s)",
+        html.len(),
+        elapsed / ITERATIONS,
+        (html.len() as f64 * f64::from(ITERATIONS)) / elapsed.as_secs_f64() / 1_000_000.0
+    );
+}
@@ -0,0 +1,36 @@
+//! Rewrites image URLs in rendered markdown to point at a CDN, by inserting a system
+//! between markdown conversion and the population of template contexts.
+//!
+//! Run from the repository root with `cargo run -p webvy_app --example cdn_images`.
+
+use webvy_app::prelude::*;
+
+const CDN: &str = "https://cdn.example.com/";
+
+fn rewrite_image_urls(mut q_pages: Query<&mut HtmlBody>) {
+    for mut html in q_pages.iter_mut() {
+        let rewritten = (*html)
+            .as_ref()
+            .replace("src=\"/images/", &format!("src=\"{}images/", CDN));
+
+        *html = HtmlBody::new(rewritten);
+    }
+}
+
+fn main() {
+    let mut app = ProcessorApp::new();
+
+    let result = app
+        .add_processor(ConfigurationProcessor::new("blog.toml"))
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .add_processor(TeraProcessor::new())
+        .add_processor(FeedProcessor::new())
+        .add_systems(PostProcess, rewrite_image_urls.before(TeraSet::Context))
+        .run()
+        .and_then(|_| app.finish());
+
+    match result {
+        Ok(report) => println!("Wrote {} page(s)", report.written),
+        Err(e) => eprintln!("{}", e),
+    }
+}
@@ -1,22 +1,42 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
 use bevy_ecs::{
-    schedule::{ExecutorKind, InternedScheduleLabel, IntoSystemConfigs, Schedule, ScheduleLabel},
+    schedule::{
+        ExecutorKind, InternedScheduleLabel, IntoSystemConfigs, IntoSystemSetConfigs, Schedule,
+        ScheduleLabel,
+    },
     system::{CommandQueue, Resource},
     world::World,
 };
 use bevy_tasks::{ComputeTaskPool, IoTaskPool, TaskPoolBuilder};
 use event_listener::{Event, Listener};
-use log::trace;
+use log::{error, trace, warn, LevelFilter};
 use smol::channel::{unbounded, Receiver};
 
-use crate::{deferred::DeferredTask, traits::ProcessorPlugin};
+use crate::{
+    build_info::{BuildClock, BuildInfo},
+    cancel::CancellationToken,
+    config::{BuildConfig, SiteConfig},
+    context::GlobalContext,
+    deferred::{drive_local_tasks, DeferredTask, LocalSpawn},
+    errors::{ProcessorError, ProcessorResult},
+    file::{classify_pages, VirtualContent},
+    logging::{self, LogFilter, LogFilterError, EXECUTOR},
+    output::OutputRegistry,
+    report::{
+        summarize, BuildErrors, BuildReport, Diagnostic, DiagnosticSink, Diagnostics, Severity,
+    },
+    traits::ProcessorPlugin,
+};
 
 pub struct ProcessorApp {
     world: World,
     schedules: Vec<InternedScheduleLabel>,
     deferred: Receiver<CommandQueue>,
+    local: Receiver<LocalSpawn>,
     finished: Arc<Event>,
+    log_level: LevelFilter,
+    log_filter: Option<LogFilter>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ScheduleLabel)]
@@ -25,6 +45,11 @@ pub struct Preload;
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ScheduleLabel)]
 pub struct Load;
 
+/// Loads the next batch of content, whenever [`PendingBatches`] has any left after
+/// [`Process`]. [`Process`] runs again after each batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ScheduleLabel)]
+pub struct LoadBatch;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ScheduleLabel)]
 pub struct Process;
 
@@ -34,15 +59,32 @@ pub struct PostProcess;
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ScheduleLabel)]
 pub struct Write;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ScheduleLabel)]
+pub struct Finish;
+
+/// Number of content batches loaders have yet to load. Loaders add their remaining
+/// batches during [`Load`], and take one off for every [`LoadBatch`] they load.
+#[derive(Debug, Default, Resource)]
+pub struct PendingBatches(pub usize);
+
 impl ProcessorApp {
     pub fn new() -> Self {
         setup_threadpool();
         let (sender, deferred) = unbounded();
+        let (local_sender, local) = unbounded();
         let finished = Arc::new(Event::new());
 
         let mut world = World::new();
 
-        world.insert_resource(DeferredTask::new(sender, finished.clone()));
+        world.insert_resource(DeferredTask::new(sender, local_sender, finished.clone()));
+        world.init_resource::<CancellationToken>();
+        world.init_resource::<BuildReport>();
+        world.init_resource::<BuildErrors>();
+        world.init_resource::<Diagnostics>();
+        world.init_resource::<BuildClock>();
+        world.init_resource::<OutputRegistry>();
+        world.init_resource::<GlobalContext>();
+        world.init_resource::<PendingBatches>();
 
         let (world, schedules) = Self::init_schedules(world);
 
@@ -50,7 +92,10 @@ impl ProcessorApp {
             world,
             schedules,
             deferred,
+            local,
             finished,
+            log_level: LevelFilter::Warn,
+            log_filter: None,
         }
     }
 
@@ -60,19 +105,27 @@ impl ProcessorApp {
         // Therefore there's little need to MT loading systems.
         let mut preload = Schedule::new(Preload);
         preload.set_executor_kind(ExecutorKind::SingleThreaded);
+        preload.add_systems(BuildInfo::init);
 
         let mut load = Schedule::new(Load);
         load.set_executor_kind(ExecutorKind::SingleThreaded);
 
+        // Only run between runs of Process, so not part of the ordered schedules.
+        let mut load_batch = Schedule::new(LoadBatch);
+        load_batch.set_executor_kind(ExecutorKind::SingleThreaded);
+
         // Heavy CPU processing should be happening here with little if any
         // IO occuring.
         let mut process = Schedule::new(Process);
         process.set_executor_kind(ExecutorKind::MultiThreaded);
+        process.add_systems(classify_pages);
 
         // Heavy CPU processing should be happening here with little if any
         // IO occuring.
         let mut postprocess = Schedule::new(PostProcess);
         postprocess.set_executor_kind(ExecutorKind::MultiThreaded);
+        // Catches pages spawned by processors during Process.
+        postprocess.add_systems(classify_pages);
 
         // Write schedule should be more focused around spawning io tasks
         // for outputting the results to disk storage. Therefore there's
@@ -81,19 +134,28 @@ impl ProcessorApp {
         let mut write = Schedule::new(Write);
         write.set_executor_kind(ExecutorKind::SingleThreaded);
 
+        // Finish schedule runs once every write has completed and been
+        // reported back, for bookkeeping such as recording what the build
+        // produced. Little work happens here, so no need to MT it.
+        let mut finish = Schedule::new(Finish);
+        finish.set_executor_kind(ExecutorKind::SingleThreaded);
+
         let schedules = vec![
             preload.label(),
             load.label(),
             process.label(),
             postprocess.label(),
             write.label(),
+            finish.label(),
         ];
 
         world.add_schedule(preload);
         world.add_schedule(load);
+        world.add_schedule(load_batch);
         world.add_schedule(process);
         world.add_schedule(postprocess);
         world.add_schedule(write);
+        world.add_schedule(finish);
 
         (world, schedules)
     }
@@ -110,6 +172,24 @@ impl ProcessorApp {
         self
     }
 
+    /// Adds systems to the schedule with the given label. Custom systems are ordered
+    /// against the built-in processors through their system sets, such as
+    /// [`MarkdownSet`](crate::processor::MarkdownSet) and
+    /// [`TeraSet`](crate::processor::TeraSet).
+    ///
+    /// ```no_run
+    /// use webvy_app::prelude::*;
+    ///
+    /// fn rewrite_html(mut q_pages: Query<&mut HtmlBody>) {
+    ///     for mut html in q_pages.iter_mut() {
+    ///         *html = HtmlBody::new((*html).as_ref().replace("http://", "https://"));
+    ///     }
+    /// }
+    ///
+    /// let mut app = ProcessorApp::new();
+    ///
+    /// app.add_systems(Process, rewrite_html.after(MarkdownSet::Render));
+    /// ```
     pub fn add_systems<M>(
         &mut self,
         label: impl ScheduleLabel,
@@ -122,61 +202,285 @@ impl ProcessorApp {
         self
     }
 
+    pub fn configure_sets(
+        &mut self,
+        label: impl ScheduleLabel,
+        sets: impl IntoSystemSetConfigs,
+    ) -> &mut Self {
+        self.world.schedule_scope(label, |_, schedule| {
+            schedule.configure_sets(sets);
+        });
+
+        self
+    }
+
     pub fn add_processor(&mut self, plugin: impl ProcessorPlugin) -> &mut Self {
         plugin.register(self);
 
         self
     }
 
-    pub fn run(&mut self) {
-        let compute = ComputeTaskPool::get();
-        let io = IoTaskPool::get();
-        let schedules = self.schedules.iter();
+    /// Adds a page that's processed as if it had been read from the content directory
+    /// at `path`. Errors if a file on disk is found at the same path.
+    pub fn add_page(
+        &mut self,
+        path: impl Into<PathBuf>,
+        front_matter: toml::Table,
+        body: impl Into<String>,
+    ) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(VirtualContent::default)
+            .add(path, front_matter, body);
+
+        self
+    }
 
-        for &schedule in schedules {
-            trace!(target: "executor", "Running schedule: {:?}", schedule);
+    /// Passes each diagnostic to `sink` as soon as it's recorded.
+    pub fn on_diagnostic(&mut self, sink: DiagnosticSink) -> &mut Self {
+        self.world.resource_mut::<Diagnostics>().set_sink(sink);
+
+        self
+    }
+
+    /// Logs everything at `level` or above to stderr, along with whatever the log filter
+    /// enables. Installs webvy's logger unless another one already is, in which case
+    /// that one is left alone. Can be called again at any time to change the level.
+    pub fn with_log_level(&mut self, level: LevelFilter) -> &mut Self {
+        self.log_level = level;
+        logging::configure(self.log_level, self.log_filter.as_ref());
+
+        self
+    }
+
+    /// Enables logging by the directives of `filter`, such as
+    /// `webvy_app::processor::tera=trace` or `webvy::render=debug`, on top of the log
+    /// level. See [`logging`] for the targets of each stage of a build.
+    pub fn with_log_filter(&mut self, filter: &str) -> Result<&mut Self, LogFilterError> {
+        self.log_filter = Some(filter.parse()?);
+        logging::configure(self.log_level, self.log_filter.as_ref());
+
+        Ok(self)
+    }
+
+    /// The token that stops this app's build when cancelled.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.world.resource::<CancellationToken>().clone()
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    pub fn report(&self) -> &BuildReport {
+        self.world.resource::<BuildReport>()
+    }
+
+    pub fn errors(&self) -> &BuildErrors {
+        self.world.resource::<BuildErrors>()
+    }
+
+    /// Runs every schedule in order, [`LoadBatch`] and [`Process`] running again for
+    /// each batch of content left to load. Returns [`ProcessorError::Interrupted`] if
+    /// the [`CancellationToken`] is cancelled, skipping the remaining schedules except
+    /// for [`Finish`] when output was already written. Every deferred task has reported
+    /// back by the time this returns.
+    pub fn run(&mut self) -> ProcessorResult<()> {
+        let cancel = self.cancellation_token();
+        let mut schedules = self.schedules.clone().into_iter();
+
+        while let Some(schedule) = schedules.next() {
+            // A build that has already failed leaves the output untouched.
+            if schedule == Write.intern() && self.has_failed() {
+                error!("The build has failed, stopping before writing any output");
+                break;
+            }
+
+            trace!(target: EXECUTOR, "Running schedule: {:?}", schedule);
             self.world.run_schedule(schedule);
+            self.settle();
+
+            if schedule == Process.intern() {
+                self.process_batches(&cancel);
+            }
+
+            // Configuration is loaded during Preload, so nothing else can run if it failed.
+            if schedule == Preload.intern() && !self.errors().is_empty() {
+                error!("Unable to load the configuration, stopping the build");
+                break;
+            }
+
+            if cancel.is_cancelled() {
+                warn!("Build interrupted, stopping");
+
+                // Record whatever was written, so the next build recognises it.
+                if schedule == Write.intern() {
+                    schedules = vec![Finish.intern()].into_iter();
+                    continue;
+                }
+
+                break;
+            }
+        }
+
+        // Commands applied after the last schedule can start more tasks, which have to
+        // report back before the report and errors are read.
+        self.settle();
+
+        if cancel.is_cancelled() {
+            Err(ProcessorError::Interrupted)
+        } else {
+            Ok(())
+        }
+    }
 
+    /// Loads and processes the content batches left after the first, one at a time.
+    fn process_batches(&mut self, cancel: &CancellationToken) {
+        while !cancel.is_cancelled() {
+            let pending = self.world.resource::<PendingBatches>().0;
+
+            if pending == 0 {
+                break;
+            }
+
+            trace!(target: EXECUTOR, "Loading the next of {} batches", pending);
+            self.world.run_schedule(LoadBatch);
+            self.settle();
+
+            // Nothing loaded the batch, so running again wouldn't either.
+            if self.world.resource::<PendingBatches>().0 >= pending {
+                error!("{} content batches were never loaded", pending);
+                break;
+            }
+
+            self.world.run_schedule(Process);
+            self.settle();
+        }
+    }
+
+    /// Waits for every deferred task to finish and applies the commands they sent,
+    /// until applying them leaves nothing else pending.
+    fn settle(&mut self) {
+        let compute = ComputeTaskPool::get();
+        let io = IoTaskPool::get();
+
+        loop {
             // Local tasks for the schedule MUST be exhausted before we can proceed.
             compute.with_local_executor(|cex| while cex.try_tick() {});
+            // Local deferred tasks are started here whether or not anything else is
+            // pending, as nothing else runs them.
+            drive_local_tasks(&self.local);
 
             // Remaining tasks on other threads
             let deferred_actions = self.world.resource::<DeferredTask>().waiting();
 
-            trace!(target: "executor", "Waiting on: {} actions", deferred_actions);
+            trace!(target: EXECUTOR, "Waiting on: {} actions", deferred_actions);
 
             if deferred_actions > 0 {
-                trace!(target: "executor", "Waiting for async processes to finish");
-
-                for _ in 0..deferred_actions {
-                    trace!(target: "executor", "Listening for a notification");
-                    loop {
-                        let listener = self.finished.listen();
-
-                        // Tick the local executor in case we are waiting for something there
-                        io.with_local_executor(|iex| while iex.try_tick() {});
-
-                        // Timeout so we can yield the main thread for ticking the local executor in case the task
-                        // is delayed there.
-                        if listener
-                            .wait_timeout(std::time::Duration::from_millis(100))
-                            .is_some()
-                        {
-                            trace!(target: "executor", "Received notification! Deferred task finished");
-                            break;
-                        }
+                trace!(target: EXECUTOR, "Waiting for async processes to finish");
+
+                // Tasks can finish before we start listening, so check the counter rather
+                // than relying on receiving a notification for each task.
+                while self.world.resource::<DeferredTask>().waiting() > 0 {
+                    trace!(target: EXECUTOR, "Listening for a notification");
+                    let listener = self.finished.listen();
+
+                    // Tick the local executors in case we are waiting for something there
+                    io.with_local_executor(|iex| while iex.try_tick() {});
+                    drive_local_tasks(&self.local);
+
+                    // Timeout so we can yield the main thread for ticking the local executor in case the task
+                    // is delayed there.
+                    if listener
+                        .wait_timeout(std::time::Duration::from_millis(100))
+                        .is_some()
+                    {
+                        trace!(target: EXECUTOR, "Received notification! Deferred task finished");
                     }
                 }
 
-                trace!(target: "executor", "All async processes finished!");
+                trace!(target: EXECUTOR, "All async processes finished!");
+            }
 
-                trace!(target: "executor", "Apply queued deferred commands before proceeding with next schedule");
-                let mut deferred_queue = CommandQueue::default();
-                while let Ok(mut commands) = self.deferred.try_recv() {
-                    deferred_queue.append(&mut commands);
-                }
-                deferred_queue.apply(&mut self.world);
+            if self.deferred.is_empty() {
+                break;
+            }
+
+            trace!(target: EXECUTOR, "Apply queued deferred commands before proceeding with next schedule");
+            let mut deferred_queue = CommandQueue::default();
+            while let Ok(mut commands) = self.deferred.try_recv() {
+                deferred_queue.append(&mut commands);
             }
+            deferred_queue.apply(&mut self.world);
+        }
+    }
+
+    fn build_config(&self) -> BuildConfig {
+        self.world
+            .get_resource::<SiteConfig>()
+            .map(|config| config.build.clone())
+            .unwrap_or_default()
+    }
+
+    /// Whether errors, or warnings in strict mode, have been recorded so far.
+    fn has_failed(&self) -> bool {
+        let build = self.build_config();
+
+        !self.errors().is_empty()
+            || self
+                .world
+                .resource::<Diagnostics>()
+                .iter()
+                .any(|diagnostic| fails_build(diagnostic, &build))
+    }
+
+    /// Consumes the results of [`ProcessorApp::run`], returning the build report if no
+    /// errors were encountered. Diagnostics are summarised, with errors and, in strict
+    /// mode, warnings failing the build.
+    pub fn finish(&mut self) -> ProcessorResult<BuildReport> {
+        let build = self.build_config();
+
+        let diagnostics: Vec<_> = self
+            .world
+            .resource_mut::<Diagnostics>()
+            .take()
+            .into_iter()
+            .filter(|diagnostic| {
+                diagnostic.severity == Severity::Error
+                    || !build.allow.iter().any(|code| code == diagnostic.code)
+            })
+            .collect();
+
+        if !diagnostics.is_empty() {
+            warn!("{}", summarize(&diagnostics));
+        }
+
+        let mut errors = std::mem::take(&mut self.world.resource_mut::<BuildErrors>().0);
+        let mut report = self.report().clone();
+        // Outputs are compared concurrently, so their changes come in any order.
+        report.changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+        for diagnostic in diagnostics {
+            if diagnostic.severity == Severity::Warning {
+                report.warnings += 1;
+            }
+
+            if fails_build(&diagnostic, &build) {
+                errors.push(ProcessorError::Diagnostic(diagnostic));
+            }
+        }
+
+        if report.panicked > 0 {
+            errors.push(ProcessorError::Panicked(report.panicked));
+        }
+
+        if errors.is_empty() {
+            Ok(report)
+        } else {
+            Err(ProcessorError::Build(errors))
         }
     }
 }
@@ -187,6 +491,11 @@ impl Default for ProcessorApp {
     }
 }
 
+fn fails_build(diagnostic: &Diagnostic, build: &BuildConfig) -> bool {
+    diagnostic.severity == Severity::Error
+        || (build.strict && !build.allow.iter().any(|code| code == diagnostic.code))
+}
+
 fn setup_threadpool() {
     let threads = bevy_tasks::available_parallelism();
 
@@ -200,7 +509,7 @@ fn setup_threadpool() {
             .build()
     });
 
-    trace!("Initialised {} compute threads", compute.thread_num());
+    trace!(target: EXECUTOR, "Initialised {} compute threads", compute.thread_num());
 
     let io = IoTaskPool::get_or_init(|| {
         TaskPoolBuilder::default()
@@ -209,5 +518,130 @@ fn setup_threadpool() {
             .build()
     });
 
-    trace!("Initialised {} io threads", io.thread_num());
+    trace!(target: EXECUTOR, "Initialised {} io threads", io.thread_num());
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::system::{Res, ResMut};
+
+    use super::*;
+
+    #[derive(Default, Resource)]
+    struct Written(bool);
+
+    #[derive(Default, Resource)]
+    struct LocalRuns(Vec<&'static str>);
+
+    fn queue_local_run(deferred: &DeferredTask, schedule: &'static str) {
+        deferred.scoped_task_local(move |scope| async move {
+            // Held across an await, so the task can't be sent between threads.
+            let name = std::rc::Rc::new(schedule);
+            smol::future::yield_now().await;
+
+            let mut queue = CommandQueue::default();
+            let name = *name;
+            queue.push(move |world: &mut World| {
+                world.resource_mut::<LocalRuns>().0.push(name);
+            });
+
+            scope.send(queue);
+        });
+    }
+
+    #[test]
+    fn local_tasks_finish_before_run_returns() {
+        let mut app = ProcessorApp::new();
+
+        app.init_resource::<LocalRuns>()
+            .add_systems(Process, |deferred: Res<DeferredTask>| {
+                queue_local_run(&deferred, "process")
+            })
+            .add_systems(Write, |deferred: Res<DeferredTask>| {
+                queue_local_run(&deferred, "write")
+            })
+            .add_systems(Finish, |deferred: Res<DeferredTask>| {
+                queue_local_run(&deferred, "finish")
+            })
+            .run()
+            .unwrap();
+
+        assert_eq!(
+            app.world().resource::<LocalRuns>().0,
+            ["process", "write", "finish"]
+        );
+    }
+
+    #[test]
+    fn tasks_started_by_the_last_commands_report_back() {
+        fn fail(deferred: &DeferredTask) {
+            deferred
+                .scoped_task(|scope| async move {
+                    let mut queue = CommandQueue::default();
+                    queue.push(|world: &mut World| {
+                        world.resource_mut::<BuildErrors>().0.push(
+                            ProcessorError::MissingContentDir {
+                                path: PathBuf::from("content"),
+                            },
+                        );
+                    });
+
+                    scope.send(queue);
+                })
+                .detach();
+        }
+
+        let mut app = ProcessorApp::new();
+
+        app.add_systems(Finish, |deferred: Res<DeferredTask>| {
+            deferred
+                .scoped_task(|scope| async move {
+                    // Applying this starts another task during the last schedule.
+                    let mut queue = CommandQueue::default();
+                    queue.push(|world: &mut World| fail(world.resource::<DeferredTask>()));
+
+                    scope.send(queue);
+                })
+                .detach();
+        })
+        .run()
+        .unwrap();
+
+        assert!(matches!(
+            app.errors().iter().collect::<Vec<_>>().as_slice(),
+            [ProcessorError::MissingContentDir { .. }]
+        ));
+        assert!(app.finish().is_err());
+    }
+
+    #[test]
+    fn cancelling_during_process_stops_before_write() {
+        let mut app = ProcessorApp::new();
+
+        app.init_resource::<Written>()
+            .add_systems(Process, |cancel: Res<CancellationToken>| cancel.cancel())
+            .add_systems(Write, |mut written: ResMut<Written>| written.0 = true);
+
+        assert!(matches!(app.run(), Err(ProcessorError::Interrupted)));
+        assert!(!app.world().resource::<Written>().0);
+    }
+
+    #[test]
+    fn failed_strict_builds_stop_before_write() {
+        let mut config = SiteConfig::default();
+        config.build.strict = true;
+
+        let mut app = ProcessorApp::new();
+
+        app.insert_resource(config)
+            .init_resource::<Written>()
+            .add_systems(Process, |mut diagnostics: ResMut<Diagnostics>| {
+                diagnostics.warning(None, "missing-date", "Post has no date")
+            })
+            .add_systems(Write, |mut written: ResMut<Written>| written.0 = true);
+
+        assert!(app.run().is_ok());
+        assert!(!app.world().resource::<Written>().0);
+        assert!(app.finish().is_err());
+    }
 }
@@ -0,0 +1,110 @@
+//! How files from the static directory are handled on their way to the output. Each
+//! is copied byte for byte unless `[assets.handlers]` maps its extension to another
+//! [`AssetHandler`], such as `svg = "minify"`. Processors add handlers of their own
+//! through `app.world_mut().get_resource_or_insert_with(AssetHandlers::default)`.
+
+use std::{collections::HashMap, fmt, path::Path, sync::Arc};
+
+use bevy_ecs::system::Resource;
+
+use crate::{config::AssetsConfig, svg::minify_svg};
+
+/// The handler used for any extension without one configured.
+pub const COPY: &str = "copy";
+
+/// The handler stripping what browsers don't need from SVG files.
+pub const MINIFY: &str = "minify";
+
+/// Turns the content of an asset into what's written to the output.
+pub trait AssetHandler: Send + Sync + 'static {
+    /// Handles `content`, read from `path`. Errors fail the build, naming the asset.
+    fn handle(&self, path: &Path, content: Vec<u8>) -> Result<Vec<u8>, String>;
+}
+
+/// Writes assets exactly as they were read.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CopyHandler;
+
+impl AssetHandler for CopyHandler {
+    fn handle(&self, _path: &Path, content: Vec<u8>) -> Result<Vec<u8>, String> {
+        Ok(content)
+    }
+}
+
+/// Minifies SVG files, with [`minify_svg`]. Other files are an error.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MinifyHandler;
+
+impl AssetHandler for MinifyHandler {
+    fn handle(&self, path: &Path, content: Vec<u8>) -> Result<Vec<u8>, String> {
+        if !path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("svg"))
+        {
+            return Err(String::from("Only SVG files can be minified"));
+        }
+
+        String::from_utf8(content)
+            .map(|svg| minify_svg(&svg).into_bytes())
+            .map_err(|_| String::from("SVG files have to be UTF-8 to be minified"))
+    }
+}
+
+/// Every handler assets can be given, by the name `[assets.handlers]` refers to them
+/// with. Always includes [`COPY`] and [`MINIFY`].
+#[derive(Clone, Resource)]
+pub struct AssetHandlers(HashMap<String, Arc<dyn AssetHandler>>);
+
+impl AssetHandlers {
+    pub fn register(&mut self, name: impl Into<String>, handler: impl AssetHandler) -> &mut Self {
+        self.0.insert(name.into(), Arc::new(handler));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn AssetHandler>> {
+        self.0.get(name).cloned()
+    }
+
+    /// The handler for the asset at `path`, by its extension. Extensions are compared
+    /// regardless of case, so `PNG` files are handled as `png` ones.
+    pub fn handler_for(
+        &self,
+        config: &AssetsConfig,
+        path: &Path,
+    ) -> Result<Arc<dyn AssetHandler>, UnknownHandler> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        let name = extension
+            .and_then(|extension| config.handlers.get(&extension))
+            .map_or(COPY, String::as_str);
+
+        self.get(name)
+            .ok_or_else(|| UnknownHandler(name.to_string()))
+    }
+}
+
+impl Default for AssetHandlers {
+    fn default() -> Self {
+        let mut handlers = Self(HashMap::new());
+        handlers
+            .register(COPY, CopyHandler)
+            .register(MINIFY, MinifyHandler);
+        handlers
+    }
+}
+
+impl fmt::Debug for AssetHandlers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<_> = self.0.keys().collect();
+        names.sort();
+
+        f.debug_tuple("AssetHandlers").field(&names).finish()
+    }
+}
+
+/// A handler named in `[assets.handlers]` that nothing registered.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Unknown asset handler `{0}`")]
+pub struct UnknownHandler(pub String);
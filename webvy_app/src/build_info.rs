@@ -0,0 +1,100 @@
+use bevy_ecs::system::{Commands, Res, Resource};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+
+/// Environment variable naming the commit the site is built from, e.g. a short git hash.
+pub const BUILD_REF_VAR: &str = "WEBVY_BUILD_REF";
+
+/// The time the build runs at, read by anything needing the current time so a build
+/// can be pinned to a fixed instant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Resource)]
+pub struct BuildClock(DateTime<Utc>);
+
+impl BuildClock {
+    pub fn fixed(now: DateTime<Utc>) -> Self {
+        Self(now)
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+impl Default for BuildClock {
+    fn default() -> Self {
+        Self(Utc::now())
+    }
+}
+
+/// What built the site and when, exposed to templates as `build`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Resource)]
+pub struct BuildInfo {
+    /// The version of webvy doing the build.
+    pub version: String,
+    /// The commit the site is built from, if known.
+    pub commit: Option<String>,
+    /// When the build ran, as an RFC 3339 timestamp.
+    pub timestamp: String,
+    /// The day the build ran, as `YYYY-MM-DD`.
+    pub date: String,
+}
+
+impl BuildInfo {
+    pub fn new(clock: &BuildClock, commit: Option<String>) -> Self {
+        let now = clock.now();
+
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            commit,
+            timestamp: now.to_rfc3339_opts(SecondsFormat::Secs, true),
+            date: now.format("%Y-%m-%d").to_string(),
+        }
+    }
+
+    /// Names webvy and the commit built from, for a `generator` meta tag.
+    pub fn generator(&self) -> String {
+        match &self.commit {
+            Some(commit) => format!("webvy {} ({})", self.version, commit),
+            None => format!("webvy {}", self.version),
+        }
+    }
+
+    /// Fills in the build info from the clock and [`BUILD_REF_VAR`], unless it was
+    /// already provided.
+    pub(crate) fn init(
+        mut commands: Commands,
+        clock: Res<BuildClock>,
+        info: Option<Res<BuildInfo>>,
+    ) {
+        if info.is_some() {
+            return;
+        }
+
+        let commit = std::env::var(BUILD_REF_VAR)
+            .ok()
+            .map(|commit| commit.trim().to_string())
+            .filter(|commit| !commit.is_empty());
+
+        commands.insert_resource(Self::new(&clock, commit));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn build_info_follows_the_clock() {
+        let clock = BuildClock::fixed(Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap());
+        let info = BuildInfo::new(&clock, Some(String::from("abc123")));
+
+        assert_eq!(info.timestamp, "2024-05-01T12:30:00Z");
+        assert_eq!(info.date, "2024-05-01");
+        assert_eq!(
+            info.generator(),
+            format!("webvy {} (abc123)", env!("CARGO_PKG_VERSION"))
+        );
+    }
+}
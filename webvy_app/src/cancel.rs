@@ -0,0 +1,22 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use bevy_ecs::system::Resource;
+
+/// Shared flag for stopping a build early. Systems and deferred tasks check it between
+/// pages, and [`ProcessorApp::run`](crate::app::ProcessorApp::run) stops once the
+/// current schedule is done.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
@@ -0,0 +1,147 @@
+use std::{io, path::Path};
+
+use serde::Deserialize;
+
+/// Extensions of text formats that shrink enough to be worth serving pre-compressed.
+const COMPRESSIBLE_EXTENSIONS: &[&str] = &["html", "css", "js", "json", "xml", "svg"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    Gzip,
+    Brotli,
+}
+
+impl Codec {
+    /// Extension appended to the original file name, e.g. `index.html.gz`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gz",
+            Self::Brotli => "br",
+        }
+    }
+
+    /// Whether support for the codec was compiled in.
+    pub fn is_available(&self) -> bool {
+        match self {
+            Self::Gzip => cfg!(feature = "gzip"),
+            Self::Brotli => cfg!(feature = "brotli"),
+        }
+    }
+
+    /// Compresses `content`, clamping `level` to the range the codec supports and
+    /// defaulting to its best compression.
+    pub fn compress(&self, content: &[u8], level: Option<u32>) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip => gzip(content, level.unwrap_or(9).min(9)),
+            Self::Brotli => brotli(content, level.unwrap_or(11).min(11)),
+        }
+    }
+
+    /// Decompresses `content`, as compressed with this codec.
+    pub fn decompress(&self, content: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip => gunzip(content),
+            Self::Brotli => unbrotli(content),
+        }
+    }
+}
+
+impl std::fmt::Display for Codec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Gzip => f.write_str("gzip"),
+            Self::Brotli => f.write_str("brotli"),
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+fn gzip(content: &[u8], level: u32) -> io::Result<Vec<u8>> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::new(level));
+
+    encoder.write_all(content)?;
+    encoder.finish()
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gzip(_content: &[u8], _level: u32) -> io::Result<Vec<u8>> {
+    Err(unsupported(Codec::Gzip))
+}
+
+#[cfg(feature = "gzip")]
+fn gunzip(content: &[u8]) -> io::Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut output = Vec::new();
+    flate2::read::GzDecoder::new(content).read_to_end(&mut output)?;
+
+    Ok(output)
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gunzip(_content: &[u8]) -> io::Result<Vec<u8>> {
+    Err(unsupported(Codec::Gzip))
+}
+
+#[cfg(feature = "brotli")]
+fn brotli(content: &[u8], level: u32) -> io::Result<Vec<u8>> {
+    use std::io::Write;
+
+    let mut output = Vec::new();
+
+    {
+        let mut writer = ::brotli::CompressorWriter::new(&mut output, 4096, level, 22);
+
+        writer.write_all(content)?;
+    }
+
+    Ok(output)
+}
+
+#[cfg(not(feature = "brotli"))]
+fn brotli(_content: &[u8], _level: u32) -> io::Result<Vec<u8>> {
+    Err(unsupported(Codec::Brotli))
+}
+
+#[cfg(feature = "brotli")]
+fn unbrotli(content: &[u8]) -> io::Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut output = Vec::new();
+    ::brotli::Decompressor::new(content, 4096).read_to_end(&mut output)?;
+
+    Ok(output)
+}
+
+#[cfg(not(feature = "brotli"))]
+fn unbrotli(_content: &[u8]) -> io::Result<Vec<u8>> {
+    Err(unsupported(Codec::Brotli))
+}
+
+#[cfg(not(all(feature = "gzip", feature = "brotli")))]
+fn unsupported(codec: Codec) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("webvy was built without {} support", codec),
+    )
+}
+
+/// Whether an output is a text format large enough to be worth compressing.
+pub fn is_compressible(path: &Path, size: usize, min_size: usize) -> bool {
+    size >= min_size
+        && path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| COMPRESSIBLE_EXTENSIONS.contains(&extension))
+}
+
+/// Options for writing pre-compressed siblings next to each output.
+#[derive(Debug, Clone, Default)]
+pub struct CompressOptions {
+    pub codecs: Vec<Codec>,
+    pub min_size: usize,
+    pub level: Option<u32>,
+}
@@ -0,0 +1,842 @@
+//! Site-wide settings read from the configuration file, shared by every processor.
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    path::{self, Path, PathBuf},
+};
+
+use bevy_ecs::{component::Component, system::Resource};
+use serde::{Deserialize, Serialize};
+use toml::Value;
+use webvy_core::SiteUrl;
+use webvy_matterparser::Parser as FrontMatterParser;
+
+use crate::{
+    compress::Codec,
+    front_matter::Authors,
+    report::Severity,
+    sanitize::SanitizeConfig,
+    timezone::Timezone,
+    typography::{Typographer, TypographyRules},
+};
+
+/// Whether the site is built for deployment or to be served locally while writing it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Resource)]
+pub enum BuildMode {
+    #[default]
+    Production,
+    Serve {
+        /// The address the site is served from, e.g. `http://localhost:1111`.
+        local_base: String,
+    },
+}
+
+impl BuildMode {
+    /// The name templates see as `config.mode`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Production => "production",
+            Self::Serve { .. } => "serve",
+        }
+    }
+}
+
+/// Site-wide settings deserialized from the configuration file.
+#[derive(Debug, Clone, Default, Deserialize, Resource)]
+pub struct SiteConfig {
+    pub title: Option<String>,
+    /// The author of pages that don't name one, either a key into `[authors]` or a name.
+    pub author: Option<String>,
+    #[serde(default)]
+    base_url: SiteUrl,
+    /// The IANA timezone dates and datetimes without an offset are in, such as
+    /// `Europe/Helsinki`, rather than UTC.
+    #[serde(default)]
+    pub timezone: Option<Timezone>,
+    /// Where the site is served from locally, used instead of `base_url` for its URLs.
+    #[serde(skip)]
+    local_base: Option<SiteUrl>,
+    #[serde(default)]
+    pub build: BuildConfig,
+    #[serde(default)]
+    pub files: FilesConfig,
+    #[serde(default)]
+    pub check: CheckConfig,
+    #[serde(default)]
+    pub feeds: FeedsConfig,
+    #[serde(default)]
+    pub markdown: MarkdownConfig,
+    #[serde(default)]
+    pub templates: TemplatesConfig,
+    /// Templates rendered on their own with the site context, such as `humans.txt`.
+    #[serde(default)]
+    pub extra_templates: Vec<ExtraTemplate>,
+    #[serde(default)]
+    pub assets: AssetsConfig,
+    /// CSS and JS files concatenated into single outputs, found under `[[bundles]]`.
+    #[serde(default)]
+    pub bundles: Vec<BundleConfig>,
+    #[serde(default)]
+    pub og_image: OgImageConfig,
+    #[serde(default)]
+    pub pwa: PwaConfig,
+    #[serde(default)]
+    pub stats: StatsConfig,
+    #[serde(default)]
+    pub sections: HashMap<String, SectionConfig>,
+    #[serde(default)]
+    pub authors: HashMap<String, AuthorConfig>,
+    #[serde(default)]
+    pub taxonomies: HashMap<String, TaxonomyConfig>,
+    /// Entries of the site's menus, keyed by menu name, found under `[[menu.<name>]]`.
+    /// Pages add themselves to menus from their front matter too.
+    #[serde(default)]
+    pub menu: HashMap<String, Vec<MenuItemConfig>>,
+    /// Values free for templates to use, underneath those of sections and pages.
+    #[serde(default)]
+    pub extra: toml::Table,
+}
+
+impl SiteConfig {
+    /// The production address of the site, even when it's being served locally.
+    pub fn base_url(&self) -> &SiteUrl {
+        &self.base_url
+    }
+
+    /// Joins a site relative path onto the configured `base_url`, or the local base
+    /// when the site is being served.
+    pub fn url_for(&self, path: &str) -> String {
+        self.local_base
+            .as_ref()
+            .unwrap_or(&self.base_url)
+            .join_path(path)
+    }
+
+    /// Resolves the files the configuration points at against `root`, the directory
+    /// of the config file, unless they're absolute.
+    pub fn resolve_paths(&mut self, root: &Path) {
+        for bundle in self.bundles.iter_mut() {
+            for file in bundle.files.iter_mut() {
+                *file = resolve(root, &file);
+            }
+        }
+
+        for template in [&mut self.og_image.template, &mut self.pwa.template]
+            .into_iter()
+            .flatten()
+        {
+            *template = resolve(root, &template);
+        }
+    }
+
+    /// Generates URLs relative to `local_base` rather than `base_url`.
+    pub fn serve_from(&mut self, local_base: SiteUrl) {
+        self.local_base = Some(local_base);
+    }
+
+    pub fn section(&self, name: &str) -> Option<&SectionConfig> {
+        self.sections.get(name)
+    }
+
+    pub fn author(&self, key: &str) -> Option<&AuthorConfig> {
+        self.authors.get(key)
+    }
+
+    /// The site wide `author`, looked up in `[authors]` when it's a known key and
+    /// otherwise used as the author's name.
+    pub fn default_author(&self) -> Option<Cow<'_, AuthorConfig>> {
+        let author = self.author.as_deref()?;
+
+        Some(self.author(author).map_or_else(
+            || {
+                Cow::Owned(AuthorConfig {
+                    name: author.to_string(),
+                    ..Default::default()
+                })
+            },
+            Cow::Borrowed,
+        ))
+    }
+
+    /// Resolves a page's author keys, skipping unknown ones. Pages without authors
+    /// fall back to the [default author](Self::default_author).
+    pub fn resolve_authors(&self, authors: Option<&Authors>) -> Vec<Cow<'_, AuthorConfig>> {
+        match authors {
+            Some(authors) => authors
+                .0
+                .iter()
+                .filter_map(|key| self.author(key))
+                .map(Cow::Borrowed)
+                .collect(),
+            None => self.default_author().into_iter().collect(),
+        }
+    }
+}
+
+/// A person writing for the site, found under `[authors.<key>]` and referred to by
+/// key from the front matter.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AuthorConfig {
+    pub name: String,
+    pub email: Option<String>,
+    pub url: Option<String>,
+    pub bio: Option<String>,
+    pub avatar: Option<String>,
+}
+
+/// An entry of a menu, found under `[[menu.<name>]]`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MenuItemConfig {
+    pub name: String,
+    /// Either a full URL, or a path joined onto the site's base URL.
+    pub url: String,
+    /// Lighter entries come first, with ties in order of name.
+    #[serde(default)]
+    pub weight: i64,
+    /// The name of the top level entry of the same menu this one is nested under.
+    pub parent: Option<String>,
+}
+
+/// Settings for a taxonomy such as `tags`, found under `[taxonomies.<name>]`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TaxonomyConfig {
+    /// Metadata for the taxonomy's terms, keyed by the slug of each term.
+    #[serde(default)]
+    pub terms: HashMap<String, TermConfig>,
+}
+
+/// How a taxonomy term is shown, found under `[taxonomies.<name>.terms.<slug>]` or in
+/// `data/<name>.toml`. Terms without a name are shown as written in the front matter.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TermConfig {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+}
+
+/// Per-section build settings, found under `[sections.<name>]` in the configuration
+/// file. Unset values fall back to the global defaults.
+#[derive(Debug, Clone, Default, Deserialize, Component)]
+pub struct SectionConfig {
+    paginate_by: Option<usize>,
+    feed: Option<bool>,
+    sort_by: Option<SortBy>,
+}
+
+impl SectionConfig {
+    pub fn paginate_by(&self) -> Option<usize> {
+        self.paginate_by
+    }
+
+    pub fn feed(&self) -> bool {
+        self.feed.unwrap_or_default()
+    }
+
+    pub fn sort_by(&self) -> SortBy {
+        self.sort_by.unwrap_or_default()
+    }
+}
+
+/// How the input directories are walked, found under `[files]` next to the directories
+/// themselves, which are read into [`FileConfig`] components instead.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct FilesConfig {
+    /// Skip files ignored by the `.gitignore` files of the git repository the site is
+    /// in, including those in the directories walked. Outside of a repository, nothing
+    /// is skipped.
+    pub respect_gitignore: bool,
+}
+
+/// Settings for how the build writes its output, found under `[build]`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BuildConfig {
+    /// Write every output file, even when the file on disk is already identical.
+    pub force_write: bool,
+    /// Maximum number of output files being written at the same time.
+    pub max_concurrent_writes: usize,
+    /// What to do when an output would replace a file the previous build didn't produce.
+    pub on_conflict: OnConflict,
+    /// Render pages marked as drafts.
+    pub drafts: bool,
+    /// Fail the build on warnings as well as errors.
+    pub strict: bool,
+    /// Diagnostic codes whose warnings are ignored.
+    pub allow: Vec<String>,
+    /// Codecs used to write pre-compressed copies of text outputs next to them.
+    pub compress: Vec<Codec>,
+    /// Outputs smaller than this many bytes aren't compressed.
+    pub compress_min_size: usize,
+    /// Compression level, clamped to what each codec supports. Defaults to the best.
+    pub compress_level: Option<u32>,
+    /// Write a JSON copy of every page next to its HTML, plus a `pages.json` index.
+    pub json_output: bool,
+    /// Expose `build.generator` to templates, naming webvy and the commit built from.
+    pub generator: bool,
+    /// Check rendered pages for malformed HTML, reporting what's found as warnings.
+    pub validate_html: bool,
+    /// Read content this many files at a time, processing each batch before reading
+    /// the next, so very large sites aren't held in memory all at once. `0` reads
+    /// everything up front.
+    pub batch_size: usize,
+    /// Compare outputs against the output directory instead of writing anything.
+    pub diff: DiffMode,
+    /// Outputs larger than this many bytes aren't shown as unified diffs.
+    pub diff_max_size: usize,
+    /// Lines of each output's unified diff shown before the rest is cut off.
+    pub diff_max_lines: usize,
+    /// Tell apart URLs differing only in case, for hosts that serve them separately.
+    /// Otherwise outputs such as `About.html` and `about.html` collide.
+    pub case_sensitive_urls: bool,
+    /// Whether URLs of directories end with a slash.
+    pub trailing_slash: TrailingSlash,
+    /// The file the host serves for a directory, written for section and site indexes.
+    pub index_file: String,
+    /// Walk into symlinked directories when reading content, templates and data.
+    /// Symlinked files are always read.
+    pub follow_symlinks: bool,
+    /// How many directories deep content, templates and data are read from.
+    pub max_depth: usize,
+    /// How many files a content, templates or data directory may hold before the
+    /// build gives up on it, suspecting the wrong directory was configured.
+    pub max_files: usize,
+    /// Content and data files ending in one of these extensions are decompressed with
+    /// the codec it maps to as they're read, and treated as if they didn't have it, so
+    /// `post.md.gz` is read as `post.md`.
+    pub decompress: BTreeMap<String, Codec>,
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        Self {
+            force_write: false,
+            max_concurrent_writes: 64,
+            on_conflict: OnConflict::default(),
+            drafts: false,
+            strict: false,
+            allow: Vec::new(),
+            compress: Vec::new(),
+            compress_min_size: 1024,
+            compress_level: None,
+            json_output: false,
+            generator: false,
+            validate_html: false,
+            batch_size: 0,
+            diff: DiffMode::default(),
+            diff_max_size: 64 * 1024,
+            diff_max_lines: 200,
+            case_sensitive_urls: false,
+            trailing_slash: TrailingSlash::default(),
+            index_file: String::from("index.html"),
+            follow_symlinks: false,
+            max_depth: 32,
+            max_files: 100_000,
+            decompress: BTreeMap::from([(String::from("gz"), Codec::Gzip)]),
+        }
+    }
+}
+
+/// Whether the build writes its output or only reports what it would change.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffMode {
+    #[default]
+    Off,
+    /// List the outputs that would be added, modified or removed.
+    Summary,
+    /// Also show a unified diff of each modified text output.
+    Unified,
+}
+
+/// How outputs named [`index_file`](BuildConfig::index_file) are linked.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrailingSlash {
+    /// By their directory, ending with a slash, such as `/notes/`.
+    #[default]
+    Always,
+    /// By their directory without a trailing slash, such as `/notes`. The root of the
+    /// site keeps its slash.
+    Never,
+    /// By the file itself, such as `/notes/index.html`, for hosts without directory
+    /// indexes.
+    Preserve,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    /// Refuse to write, reporting every conflicting file.
+    Error,
+    /// Move the existing file into the backup folder before writing.
+    Backup,
+    #[default]
+    Overwrite,
+}
+
+/// Checks run over every rendered page, found under `[check]`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CheckConfig {
+    pub a11y: A11yConfig,
+}
+
+/// Accessibility lints over rendered HTML, found under `[check.a11y]`. They're
+/// independent of `validate_html`, though both share a single scan of each page.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct A11yConfig {
+    pub enabled: bool,
+    /// Images without an `alt` attribute.
+    pub missing_alt: RuleLevel,
+    /// Links whose only text is something like "click here".
+    pub vague_links: RuleLevel,
+    /// Headings skipping a level, such as an `<h4>` straight after an `<h2>`.
+    pub heading_jumps: RuleLevel,
+    /// More than one `<h1>` in a page.
+    pub multiple_h1: RuleLevel,
+    /// Form fields without a label.
+    pub unlabelled_inputs: RuleLevel,
+}
+
+impl Default for A11yConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            missing_alt: RuleLevel::Warning,
+            vague_links: RuleLevel::Warning,
+            heading_jumps: RuleLevel::Warning,
+            multiple_h1: RuleLevel::Warning,
+            unlabelled_inputs: RuleLevel::Warning,
+        }
+    }
+}
+
+/// How a lint reports what it finds, if at all.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleLevel {
+    Off,
+    #[default]
+    Warning,
+    Error,
+}
+
+impl RuleLevel {
+    /// The severity of the rule's diagnostics, or `None` when it's turned off.
+    pub fn severity(self) -> Option<Severity> {
+        match self {
+            Self::Off => None,
+            Self::Warning => Some(Severity::Warning),
+            Self::Error => Some(Severity::Error),
+        }
+    }
+}
+
+/// Feed formats to emit and how many entries each feed holds, found under `[feeds]`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FeedsConfig {
+    pub atom: bool,
+    pub json: bool,
+    pub limit: Option<usize>,
+}
+
+impl Default for FeedsConfig {
+    fn default() -> Self {
+        Self {
+            atom: true,
+            json: false,
+            limit: None,
+        }
+    }
+}
+
+/// Which files in the templates directory are loaded and how they're rendered, found
+/// under `[templates]`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TemplatesConfig {
+    /// Extensions of the files loaded as templates, without the leading dot.
+    pub extensions: Vec<String>,
+    /// Drops the first newline after each block tag such as `{% if %}`, so tags on
+    /// lines of their own don't leave blank lines behind.
+    pub trim_blocks: bool,
+    /// Extensions of the outputs whose variables are HTML-escaped.
+    pub autoescape: Vec<String>,
+    /// Templates never escaped, whatever they're rendered to.
+    pub no_autoescape: Vec<String>,
+}
+
+impl TemplatesConfig {
+    /// Whether variables are escaped when `template` is rendered to `output`.
+    pub fn escapes(&self, template: &str, output: &Path) -> bool {
+        let Some(extension) = output.extension().and_then(|extension| extension.to_str()) else {
+            return false;
+        };
+
+        !self.no_autoescape.iter().any(|name| name == template)
+            && self
+                .autoescape
+                .iter()
+                .any(|escaped| escaped.trim_start_matches('.') == extension)
+    }
+}
+
+impl Default for TemplatesConfig {
+    fn default() -> Self {
+        Self {
+            extensions: ["html", "tera", "xml", "txt"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            trim_blocks: false,
+            autoescape: ["html", "htm", "xml"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            no_autoescape: Vec::new(),
+        }
+    }
+}
+
+/// A template rendered to an output of its own rather than for a page, found under
+/// `[[extra_templates]]`. Whether it's escaped follows `[templates]`, by default only
+/// HTML and XML outputs are.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ExtraTemplate {
+    /// The name of the template, relative to the templates directory.
+    pub template: String,
+    /// Where it's written, relative to the output directory.
+    pub output: PathBuf,
+    /// Values only this template sees, over those of the site, such as the `headline`
+    /// of a landing page.
+    #[serde(default)]
+    pub context: toml::Table,
+}
+
+/// How files from the static directory are copied, found under `[assets]`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct AssetsConfig {
+    /// Assets larger than this many bytes are warned about, most likely having been
+    /// added by mistake. Strict builds fail instead.
+    pub max_asset_size: u64,
+    /// SVGs larger than this many bytes are warned about when inlined, as they're
+    /// repeated in every page inlining them.
+    pub max_inline_svg_size: u64,
+    /// The handler of each extension, such as `svg = "minify"`. Anything else is
+    /// copied as is.
+    pub handlers: HashMap<String, String>,
+}
+
+impl Default for AssetsConfig {
+    fn default() -> Self {
+        Self {
+            max_asset_size: 20 * 1024 * 1024,
+            max_inline_svg_size: 16 * 1024,
+            handlers: HashMap::new(),
+        }
+    }
+}
+
+/// Files concatenated, in order, into one output, found under `[[bundles]]`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct BundleConfig {
+    /// The output written, relative to the output directory, such as `site.css`.
+    /// Templates find its URL with `asset_url(name="site.css")`.
+    pub name: String,
+    /// The files making up the bundle, in the order they're concatenated.
+    pub files: Vec<PathBuf>,
+    /// Strip comments and whitespace from CSS bundles.
+    #[serde(default)]
+    pub minify: bool,
+    /// Write the bundle as `site.<hash>.css`, so browsers can cache it indefinitely.
+    #[serde(default = "fingerprint_by_default")]
+    pub fingerprint: bool,
+}
+
+fn fingerprint_by_default() -> bool {
+    true
+}
+
+/// Generated social card images for posts, found under `[og_image]`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OgImageConfig {
+    /// The SVG template each post's image is rendered from. Images are only generated
+    /// when it's set.
+    pub template: Option<PathBuf>,
+    /// Size of the title's font, shrunk down to `min_font_size` for long titles.
+    pub font_size: f32,
+    pub min_font_size: f32,
+    /// How many characters of the title fit on a line at `font_size`.
+    pub line_chars: usize,
+    /// Lines the title wraps onto before being cut short.
+    pub max_lines: usize,
+}
+
+impl Default for OgImageConfig {
+    fn default() -> Self {
+        Self {
+            template: None,
+            font_size: 64.0,
+            min_font_size: 40.0,
+            line_chars: 28,
+            max_lines: 3,
+        }
+    }
+}
+
+/// The service worker making the site available offline, found under `[pwa]`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PwaConfig {
+    /// Generate a service worker precaching the site.
+    pub enabled: bool,
+    /// A Tera template replacing the built-in service worker.
+    pub template: Option<PathBuf>,
+    /// Outputs with these extensions are precached.
+    pub extensions: Vec<String>,
+    /// Outputs larger than this many bytes are left out of the precache.
+    pub max_asset_size: u64,
+}
+
+impl Default for PwaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            template: None,
+            extensions: ["html", "css", "js"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            max_asset_size: 1024 * 1024,
+        }
+    }
+}
+
+/// The generated page of statistics about the site's posts, found under `[stats]`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StatsConfig {
+    /// Generate the page. It isn't content, so it's never listed in feeds or search.
+    pub enabled: bool,
+    /// The template the page is rendered with.
+    pub template: String,
+    /// Where the page is written, `stats/` with the `index_file` unless set.
+    pub output: Option<PathBuf>,
+    /// How many of the most used tags are listed.
+    pub top_tags: usize,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            template: String::from("stats.html"),
+            output: None,
+            top_tags: 10,
+        }
+    }
+}
+
+/// Settings for how markdown is rendered, found under `[markdown]`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MarkdownConfig {
+    /// Insert non-breaking spaces into rendered text, following the language's rules.
+    pub typography: bool,
+    /// The language whose typography rules are used.
+    pub language: String,
+    /// Typography rules keyed by language, replacing the defaults for that language.
+    pub rules: HashMap<String, TypographyRules>,
+    /// Fences the front matter of pages, either a delimiter such as `+++` or `---`, or
+    /// `auto` to accept both.
+    pub front_matter_delimiter: String,
+    /// Separates a page's summary from the rest of its content.
+    pub summary_marker: String,
+    /// Number of words in summaries of pages without a `summary_marker`.
+    pub summary_length: usize,
+    /// Keep only the text of summaries, dropping any inline HTML.
+    pub summary_strip_markup: bool,
+    /// Reading speed the reading time of pages is worked out from.
+    pub words_per_minute: usize,
+    /// Descriptions longer than this many characters are reported, as search engines
+    /// cut them short.
+    pub description_length: usize,
+    /// Heading levels listed in a page's table of contents. Pages can override it with
+    /// `toc_levels`, or leave the table out with `toc = false`.
+    pub toc_levels: Vec<u8>,
+    /// Strips unsafe markup from rendered pages.
+    pub sanitize: SanitizeConfig,
+    /// Mark each block of a rendered page with a `<!-- srcline: N -->` comment naming
+    /// the line of the source file it starts on, which HTML validation reports
+    /// findings against. Comments survive sanitizing while this is set.
+    pub source_maps: bool,
+    /// Classes added to rendered elements, keyed by the kind of element, such as
+    /// `tables = "table is-striped"`.
+    pub classes: HashMap<String, String>,
+}
+
+impl MarkdownConfig {
+    /// A parser for each accepted front matter delimiter, in the order they're tried.
+    pub fn front_matter_parsers(&self) -> Vec<FrontMatterParser> {
+        let delimiters = match self.front_matter_delimiter.as_str() {
+            "auto" => vec!["+++", "---"],
+            delimiter => vec![delimiter],
+        };
+
+        delimiters
+            .into_iter()
+            .map(|delimiter| {
+                let parser = FrontMatterParser::new(delimiter);
+
+                if self.summary_marker.is_empty() {
+                    parser
+                } else {
+                    parser.with_excerpt(self.summary_marker.as_str())
+                }
+            })
+            .collect()
+    }
+
+    /// The typographer for the configured language, if typography is enabled.
+    pub fn typographer(&self) -> Option<Typographer> {
+        self.typography.then(|| {
+            Typographer::new(
+                self.rules
+                    .get(&self.language)
+                    .unwrap_or(&TypographyRules::default()),
+            )
+        })
+    }
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> Self {
+        Self {
+            typography: false,
+            language: String::from("en"),
+            rules: HashMap::new(),
+            front_matter_delimiter: String::from("+++"),
+            summary_marker: String::from("<!-- more -->"),
+            summary_length: 60,
+            summary_strip_markup: true,
+            words_per_minute: 200,
+            description_length: 160,
+            toc_levels: (1..=6).collect(),
+            sanitize: SanitizeConfig::default(),
+            source_maps: false,
+            classes: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    #[default]
+    Date,
+    Weight,
+    Title,
+}
+
+/// Joins `path` onto `root` unless it's absolute, dropping any `.` along the way.
+pub fn resolve(root: &Path, path: impl AsRef<Path>) -> PathBuf {
+    root.join(path)
+        .components()
+        .filter(|component| !matches!(component, path::Component::CurDir))
+        .collect()
+}
+
+/// The content roots, read in order. Configured as either a single path or an
+/// array of paths.
+#[derive(Debug, Component)]
+pub struct InputDir(Vec<PathBuf>);
+
+impl InputDir {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        Self(paths)
+    }
+
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(dir) => Some(Self(vec![dir.into()])),
+            Value::Array(dirs) => dirs
+                .iter()
+                .map(|dir| dir.as_str().map(PathBuf::from))
+                .collect::<Option<Vec<_>>>()
+                .map(Self),
+            _ => None,
+        }
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        self.0.as_slice()
+    }
+}
+
+#[derive(Debug, Component)]
+pub struct FileConfig;
+
+#[derive(Debug, Component)]
+pub struct OutputDir(PathBuf);
+
+impl OutputDir {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self(dir.into())
+    }
+
+    pub fn path(&self) -> &Path {
+        self.0.as_path()
+    }
+}
+
+/// The directory copied as is into the output directory, configured with
+/// `[files] static`.
+#[derive(Debug, Component)]
+pub struct StaticDir(PathBuf);
+
+impl StaticDir {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self(dir.into())
+    }
+
+    pub fn path(&self) -> &Path {
+        self.0.as_path()
+    }
+}
+
+/// The directory templates are read from, configured with `[files] templates`.
+#[derive(Debug, Component)]
+pub struct TemplatesDir(PathBuf);
+
+impl TemplatesDir {
+    /// Where templates are read from when `[files] templates` isn't set.
+    pub const DEFAULT: &'static str = "templates";
+
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self(dir.into())
+    }
+
+    pub fn path(&self) -> &Path {
+        self.0.as_path()
+    }
+}
+
+/// The directory data files are read from, configured with `[files] data`.
+#[derive(Debug, Component)]
+pub struct DataDir(PathBuf);
+
+impl DataDir {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self(dir.into())
+    }
+
+    pub fn path(&self) -> &Path {
+        self.0.as_path()
+    }
+}
@@ -0,0 +1,44 @@
+//! Site-wide values for templates, such as the latest posts or every tag with its
+//! count. Processors compute them once per build and insert them into the
+//! [`GlobalContext`], which every template then sees beneath its own context, rather
+//! than each page carrying a copy.
+
+use std::collections::BTreeMap;
+
+use bevy_ecs::system::Resource;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Values shown to every template under their key. They're set during
+/// [`PostProcess`](crate::app::PostProcess), before the template contexts of pages are
+/// populated, and keep their value across rebuilds until set again or removed. The
+/// variables a renderer provides itself, like `config` or `page`, take precedence over
+/// global values with the same key.
+#[derive(Debug, Clone, Default, PartialEq, Resource)]
+pub struct GlobalContext(BTreeMap<String, Value>);
+
+impl GlobalContext {
+    /// Sets `key` to `value`, replacing any value it had. Nothing is set when `value`
+    /// can't be serialized.
+    pub fn insert<T: Serialize + ?Sized>(
+        &mut self,
+        key: impl Into<String>,
+        value: &T,
+    ) -> Result<(), serde_json::Error> {
+        self.0.insert(key.into(), serde_json::to_value(value)?);
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.0.remove(key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0.get(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.0.iter().map(|(key, value)| (key.as_str(), value))
+    }
+}
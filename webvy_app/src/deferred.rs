@@ -1,5 +1,6 @@
 use std::{
     future::Future,
+    pin::Pin,
     rc::Rc,
     sync::{atomic::AtomicU32, Arc},
 };
@@ -8,19 +9,55 @@ use bevy_ecs::system::{CommandQueue, Resource};
 use bevy_tasks::{IoTaskPool, Task};
 use event_listener::{Event, IntoNotification};
 use log::trace;
-use smol::channel::Sender;
+use smol::{
+    channel::{Receiver, Sender},
+    LocalExecutor,
+};
+
+use crate::logging::EXECUTOR;
+
+/// Starts a local task once on the thread driving local tasks, since the task itself
+/// can't be sent there.
+pub(crate) type LocalSpawn = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = ()>>> + Send>;
+
+thread_local! {
+    /// Runs the local tasks of apps driven from this thread.
+    static LOCAL_EXECUTOR: LocalExecutor<'static> = const { LocalExecutor::new() };
+}
+
+/// Runs every local task queued in `spawns`, along with those already started on this
+/// thread, until none of them can make progress.
+pub(crate) fn drive_local_tasks(spawns: &Receiver<LocalSpawn>) {
+    LOCAL_EXECUTOR.with(|executor| loop {
+        while let Ok(spawn) = spawns.try_recv() {
+            executor.spawn(spawn()).detach();
+        }
+
+        while executor.try_tick() {}
+
+        if spawns.is_empty() {
+            break;
+        }
+    });
+}
 
 #[derive(Debug, Resource)]
 pub struct DeferredTask {
     channel: Sender<CommandQueue>,
+    local: Sender<LocalSpawn>,
     finished: Arc<Event>,
     waiting: Arc<AtomicU32>,
 }
 
 impl DeferredTask {
-    pub(crate) fn new(channel: Sender<CommandQueue>, finished: Arc<Event>) -> Self {
+    pub(crate) fn new(
+        channel: Sender<CommandQueue>,
+        local: Sender<LocalSpawn>,
+        finished: Arc<Event>,
+    ) -> Self {
         Self {
             channel,
+            local,
             finished,
             waiting: Arc::new(AtomicU32::new(0)),
         }
@@ -47,21 +84,27 @@ impl DeferredTask {
         })
     }
 
-    pub fn scoped_task_local<T: 'static, I, F>(&self, task: I) -> Task<T>
+    /// Runs a task that can't be sent between threads, such as one holding an `Rc`.
+    /// Systems run on whichever thread the schedule picks, so the task is started
+    /// on the thread running the app instead, which drives it to completion before
+    /// the schedule's queued commands are applied.
+    pub fn scoped_task_local<I, F>(&self, task: I)
     where
-        F: Future<Output = T> + 'static,
-        I: FnOnce(Rc<DeferredScope>) -> F + 'static,
+        F: Future<Output = ()> + 'static,
+        I: FnOnce(Rc<DeferredScope>) -> F + Send + 'static,
     {
         let guard = DeferredGuard::new(self);
-        let scope = Rc::new(DeferredScope::new(self));
-
-        IoTaskPool::get().spawn_local(async move {
-            let result = task(scope.clone()).await;
+        let scope = DeferredScope::new(self);
 
-            drop(guard);
+        self.local
+            .try_send(Box::new(move || -> Pin<Box<dyn Future<Output = ()>>> {
+                Box::pin(async move {
+                    task(Rc::new(scope)).await;
 
-            result
-        })
+                    drop(guard);
+                })
+            }))
+            .expect("Local task channel should always be open and never full");
     }
 }
 
@@ -86,9 +129,9 @@ impl Drop for DeferredGuard {
     fn drop(&mut self) {
         self.waiting
             .fetch_sub(1, std::sync::atomic::Ordering::Release);
-        trace!("{} listeners", self.finished.total_listeners());
+        trace!(target: EXECUTOR, "{} listeners", self.finished.total_listeners());
         self.finished.notify(1.relaxed());
-        trace!("1 listener notified: {}", self.finished.is_notified());
+        trace!(target: EXECUTOR, "1 listener notified: {}", self.finished.is_notified());
     }
 }
 
@@ -117,10 +160,12 @@ impl DeferredScope {
         IoTaskPool::get().spawn(task)
     }
 
+    /// Spawns a task on the thread driving local tasks. Only call this from within a
+    /// task started by [`DeferredTask::scoped_task_local`], as no other thread runs it.
     pub fn spawn_local<S: 'static>(
         &self,
         task: impl Future<Output = S> + 'static,
-    ) -> Task<S> {
-        IoTaskPool::get().spawn_local(task)
+    ) -> smol::Task<S> {
+        LOCAL_EXECUTOR.with(|executor| executor.spawn(task))
     }
 }
@@ -0,0 +1,397 @@
+//! Comparing a build's outputs against the output directory instead of writing them,
+//! for `--diff` builds checking what a deploy would change.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::config::{BuildConfig, DiffMode};
+
+/// Lines of unchanged context shown around each change in a unified diff.
+const CONTEXT_LINES: usize = 3;
+
+/// Outputs aren't diffed line by line once the changed lines of the old one times those
+/// of the new one exceed this, as that's what the comparison grows with.
+const MAX_DIFF_CELLS: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    /// Produced by the previous build, but no longer by this one.
+    Removed,
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Added => "added",
+            Self::Modified => "modified",
+            Self::Removed => "removed",
+        })
+    }
+}
+
+/// An output that would change, relative to the output directory, along with a
+/// unified diff of the change when one was asked for and the output is text.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutputChange {
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+/// How outputs are compared in a diff build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffOptions {
+    /// Include a unified diff of each modified text output.
+    pub unified: bool,
+    /// Outputs larger than this many bytes, before or after, aren't diffed.
+    pub max_size: usize,
+    /// Lines of each unified diff shown before the rest is cut off.
+    pub max_lines: usize,
+}
+
+impl DiffOptions {
+    /// The options for `[build]`, or `None` unless it asks for a diff.
+    pub fn from_config(build: &BuildConfig) -> Option<Self> {
+        match build.diff {
+            DiffMode::Off => None,
+            mode => Some(Self {
+                unified: mode == DiffMode::Unified,
+                max_size: build.diff_max_size,
+                max_lines: build.diff_max_lines,
+            }),
+        }
+    }
+}
+
+/// Compares `content` against what's in `file`, `path` being the file relative to the
+/// output directory. Returns `None` when the file already holds `content`.
+pub async fn compare_output(
+    file: &Path,
+    path: PathBuf,
+    content: &[u8],
+    options: &DiffOptions,
+) -> std::io::Result<Option<OutputChange>> {
+    let existing = match smol::fs::read(file).await {
+        Ok(existing) => existing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Some(OutputChange {
+                path,
+                kind: ChangeKind::Added,
+                diff: None,
+            }))
+        }
+        Err(e) => return Err(e),
+    };
+
+    if existing == content {
+        return Ok(None);
+    }
+
+    let diff = options
+        .unified
+        .then_some((existing.as_slice(), content))
+        .filter(|(old, new)| old.len().max(new.len()) <= options.max_size)
+        .and_then(|(old, new)| {
+            Some((
+                std::str::from_utf8(old).ok()?,
+                std::str::from_utf8(new).ok()?,
+            ))
+        })
+        .map(|(old, new)| unified_diff(&path, old, new, options.max_lines));
+
+    Ok(Some(OutputChange {
+        path,
+        kind: ChangeKind::Modified,
+        diff,
+    }))
+}
+
+/// Describes how many outputs would change, such as
+/// `12 file(s) would change, 3 added, 8 modified, 1 removed`.
+pub fn summarize_changes(changes: &[OutputChange]) -> String {
+    if changes.is_empty() {
+        return String::from("No files would change");
+    }
+
+    let count = |kind| changes.iter().filter(|change| change.kind == kind).count();
+
+    format!(
+        "{} file(s) would change, {} added, {} modified, {} removed",
+        changes.len(),
+        count(ChangeKind::Added),
+        count(ChangeKind::Modified),
+        count(ChangeKind::Removed)
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Renders the difference between `old` and `new` as a unified diff of `path`, cut off
+/// after `max_lines` lines.
+pub fn unified_diff(path: &Path, old: &str, new: &str, max_lines: usize) -> String {
+    let path = path.display();
+    let mut diff = format!("--- a/{}\n+++ b/{}\n", path, path);
+
+    let Some(lines) = diff_lines(old, new) else {
+        diff.push_str("@@ too large to compare line by line @@\n");
+        return diff;
+    };
+
+    let mut body = Vec::new();
+
+    for hunk in hunks(&lines) {
+        let (old_start, new_start) = hunk.start;
+        let old_len = hunk
+            .lines
+            .iter()
+            .filter(|line| !matches!(line, Line::Added(_)))
+            .count();
+        let new_len = hunk
+            .lines
+            .iter()
+            .filter(|line| !matches!(line, Line::Removed(_)))
+            .count();
+
+        body.push(format!(
+            "@@ -{},{} +{},{} @@",
+            old_start + 1,
+            old_len,
+            new_start + 1,
+            new_len
+        ));
+        body.extend(hunk.lines.iter().map(|line| match line {
+            Line::Same(line) => format!(" {}", line),
+            Line::Removed(line) => format!("-{}", line),
+            Line::Added(line) => format!("+{}", line),
+        }));
+    }
+
+    let hidden = body.len().saturating_sub(max_lines);
+
+    for line in body.into_iter().take(max_lines) {
+        diff.push_str(&line);
+        diff.push('\n');
+    }
+
+    if hidden > 0 {
+        diff.push_str(&format!("... {} more line(s)\n", hidden));
+    }
+
+    diff
+}
+
+/// Lines of `old` and `new` in order, each kept, removed or added, by their longest
+/// common subsequence. `None` when they're too large to compare.
+fn diff_lines<'a>(old: &'a str, new: &'a str) -> Option<Vec<Line<'a>>> {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+
+    // Lines shared at either end need no comparing.
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    if (a.len() + 1).saturating_mul(b.len() + 1) > MAX_DIFF_CELLS {
+        return None;
+    }
+
+    // The length of the longest common subsequence of a[i..] and b[j..].
+    let width = b.len() + 1;
+    let mut common = vec![0usize; (a.len() + 1) * width];
+
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i * width + j] = if a[i] == b[j] {
+                common[(i + 1) * width + j + 1] + 1
+            } else {
+                common[(i + 1) * width + j].max(common[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut lines: Vec<_> = old[..prefix].iter().map(|line| Line::Same(line)).collect();
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            lines.push(Line::Same(a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len()
+            && (j == b.len() || common[(i + 1) * width + j] >= common[i * width + j + 1])
+        {
+            lines.push(Line::Removed(a[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(b[j]));
+            j += 1;
+        }
+    }
+
+    lines.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| Line::Same(line)),
+    );
+
+    Some(lines)
+}
+
+struct Hunk<'a> {
+    /// The first line of the hunk in the old and new file, counting from zero.
+    start: (usize, usize),
+    lines: Vec<Line<'a>>,
+}
+
+/// Groups changed lines into hunks, along with the lines of context around them.
+/// Changes closer together than twice the context share a hunk.
+fn hunks<'a>(lines: &[Line<'a>]) -> Vec<Hunk<'a>> {
+    let changed: Vec<_> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Same(_)))
+        .map(|(index, _)| index)
+        .collect();
+
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+
+    for index in changed {
+        let start = index.saturating_sub(CONTEXT_LINES);
+        let end = (index + CONTEXT_LINES + 1).min(lines.len());
+
+        match ranges.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let before = &lines[..start];
+            let old_start = before
+                .iter()
+                .filter(|line| !matches!(line, Line::Added(_)))
+                .count();
+            let new_start = before
+                .iter()
+                .filter(|line| !matches!(line, Line::Removed(_)))
+                .count();
+
+            Hunk {
+                start: (old_start, new_start),
+                lines: lines[start..end].to_vec(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_changes_are_shown_as_unified_diffs() {
+        let old = "<html>\n<h1>Old</h1>\n<p>1</p>\n<p>2</p>\n<p>3</p>\n<p>4</p>\n<p>5</p>\n<p>6</p>\n<p>7</p>\n<p>8</p>\n</html>\n";
+        let new = "<html>\n<h1>New</h1>\n<p>1</p>\n<p>2</p>\n<p>3</p>\n<p>4</p>\n<p>5</p>\n<p>6</p>\n<p>7</p>\n<p>8</p>\n<footer></footer>\n</html>\n";
+
+        assert_eq!(
+            unified_diff(Path::new("index.html"), old, new, 100),
+            "--- a/index.html\n+++ b/index.html\n\
+             @@ -1,5 +1,5 @@\n <html>\n-<h1>Old</h1>\n+<h1>New</h1>\n <p>1</p>\n <p>2</p>\n <p>3</p>\n\
+             @@ -8,4 +8,5 @@\n <p>6</p>\n <p>7</p>\n <p>8</p>\n+<footer></footer>\n </html>\n"
+        );
+        assert_eq!(
+            unified_diff(Path::new("index.html"), old, new, 3),
+            "--- a/index.html\n+++ b/index.html\n\
+             @@ -1,5 +1,5 @@\n <html>\n-<h1>Old</h1>\n... 10 more line(s)\n"
+        );
+    }
+
+    #[test]
+    fn outputs_are_compared_against_the_output_directory() {
+        let dir = std::env::temp_dir().join("webvy_outputs_are_compared");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("same.html"), "same").unwrap();
+        std::fs::write(dir.join("text.html"), "old\n").unwrap();
+        std::fs::write(dir.join("large.html"), "old ".repeat(100)).unwrap();
+        std::fs::write(dir.join("image.png"), [0xff, 0xfe]).unwrap();
+
+        let options = DiffOptions {
+            unified: true,
+            max_size: 64,
+            max_lines: 100,
+        };
+        let compare = |name: &str, content: &[u8]| {
+            smol::block_on(compare_output(
+                &dir.join(name),
+                PathBuf::from(name),
+                content,
+                &options,
+            ))
+            .unwrap()
+            .map(|change| (change.kind, change.diff.is_some()))
+        };
+
+        assert_eq!(compare("same.html", b"same"), None);
+        assert_eq!(
+            compare("new.html", b"new"),
+            Some((ChangeKind::Added, false))
+        );
+        assert_eq!(
+            compare("text.html", b"new\n"),
+            Some((ChangeKind::Modified, true))
+        );
+        assert_eq!(
+            compare("large.html", b"new"),
+            Some((ChangeKind::Modified, false))
+        );
+        assert_eq!(
+            compare("image.png", &[0xff]),
+            Some((ChangeKind::Modified, false))
+        );
+
+        let change = |kind| OutputChange {
+            path: PathBuf::from("a"),
+            kind,
+            diff: None,
+        };
+
+        assert_eq!(summarize_changes(&[]), "No files would change");
+        assert_eq!(
+            summarize_changes(&[
+                change(ChangeKind::Added),
+                change(ChangeKind::Modified),
+                change(ChangeKind::Added),
+                change(ChangeKind::Removed),
+            ]),
+            "4 file(s) would change, 2 added, 1 modified, 1 removed"
+        );
+    }
+}
@@ -1,7 +1,11 @@
+use std::path::PathBuf;
+
 use bevy_ecs::system::CommandQueue;
 use smol::channel::{TryRecvError, TrySendError};
 use thiserror::Error;
 
+use crate::{files::WalkError, output::PathError, report::Diagnostic};
+
 #[derive(Error, Debug)]
 pub enum ProcessorError {
     #[error(transparent)]
@@ -11,7 +15,55 @@ pub enum ProcessorError {
     #[error("Deferred send Error occurred: {0}")]
     DeferredSend(#[from] TrySendError<CommandQueue>),
     #[error(transparent)]
-    DeserializeError(#[from] serde::de::value::Error)
+    DeserializeError(#[from] serde::de::value::Error),
+    #[error("Invalid configuration: {0}")]
+    Config(#[from] toml::de::Error),
+    #[error("Invalid local base URL: {0}")]
+    LocalBase(#[from] webvy_core::SiteUrlError),
+    #[error(
+        "{} exists in both the {} and {} content directories",
+        path.display(),
+        first.display(),
+        second.display()
+    )]
+    ContentCollision {
+        path: PathBuf,
+        first: PathBuf,
+        second: PathBuf,
+    },
+    #[error(
+        "{} is provided both in memory and by the {} content directory",
+        path.display(),
+        root.display()
+    )]
+    VirtualCollision { path: PathBuf, root: PathBuf },
+    #[error(
+        "Content directory {} doesn't exist, check `content` under [files] in the site config",
+        path.display()
+    )]
+    MissingContentDir { path: PathBuf },
+    #[error("{0}")]
+    Diagnostic(Diagnostic),
+    #[error("{0} page(s) panicked while being processed")]
+    Panicked(usize),
+    #[error("Build interrupted")]
+    Interrupted,
+    #[error("Build failed with {} error(s)", .0.len())]
+    Build(Vec<ProcessorError>),
+    #[error(
+        "Refusing to overwrite files not produced by webvy: {}",
+        paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ")
+    )]
+    OutputConflict { paths: Vec<PathBuf> },
+    #[error("Unable to write {}: {source}", path.display())]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    UnsafePath(#[from] PathError),
+    #[error(transparent)]
+    Walk(#[from] WalkError),
 }
 
 pub type ProcessorResult<T> = Result<T, ProcessorError>;
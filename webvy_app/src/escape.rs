@@ -0,0 +1,87 @@
+//! Escaping for user provided text written into markup outside of templates, where
+//! Tera's own escaping doesn't apply.
+
+/// Escapes text placed between HTML tags.
+pub fn escape_html_text(text: &str) -> String {
+    escape_with(text, |c| match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        _ => None,
+    })
+}
+
+/// Escapes text placed within a quoted HTML attribute value.
+pub fn escape_html_attr(text: &str) -> String {
+    escape_with(text, |c| match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '"' => Some("&quot;"),
+        '\'' => Some("&#39;"),
+        _ => None,
+    })
+}
+
+/// Escapes text placed within XML elements or attribute values.
+pub fn escape_xml(text: &str) -> String {
+    escape_with(text, |c| match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '"' => Some("&quot;"),
+        '\'' => Some("&apos;"),
+        _ => None,
+    })
+}
+
+/// Escapes every character `escape` has a replacement for, dropping the control
+/// characters XML and HTML don't allow.
+fn escape_with(text: &str, escape: impl Fn(char) -> Option<&'static str>) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars().filter(|c| is_allowed(*c)) {
+        match escape(c) {
+            Some(entity) => escaped.push_str(entity),
+            None => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+fn is_allowed(c: char) -> bool {
+    !matches!(c, '\u{0}'..='\u{8}' | '\u{b}' | '\u{c}' | '\u{e}'..='\u{1f}' | '\u{7f}' | '\u{fffe}' | '\u{ffff}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADVERSARIAL: &str = "<script>alert(\"x\")</script> & 'q' ]]> \u{0}\u{1b}\tend";
+
+    #[test]
+    fn markup_characters_are_escaped_for_each_context() {
+        assert_eq!(
+            escape_html_text(ADVERSARIAL),
+            "&lt;script&gt;alert(\"x\")&lt;/script&gt; &amp; 'q' ]]&gt; \tend"
+        );
+        assert_eq!(
+            escape_html_attr(ADVERSARIAL),
+            "&lt;script&gt;alert(&quot;x&quot;)&lt;/script&gt; &amp; &#39;q&#39; ]]&gt; \tend"
+        );
+        assert_eq!(
+            escape_xml(ADVERSARIAL),
+            "&lt;script&gt;alert(&quot;x&quot;)&lt;/script&gt; &amp; &apos;q&apos; ]]&gt; \tend"
+        );
+    }
+
+    #[test]
+    fn plain_text_is_left_alone() {
+        let text = "Crème brûlée, 日本語\nand\r\nmore";
+
+        assert_eq!(escape_html_text(text), text);
+        assert_eq!(escape_html_attr(text), text);
+        assert_eq!(escape_xml(text), text);
+    }
+}
@@ -1,7 +1,24 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, HashSet},
+    ffi::OsStr,
+    fmt,
+    path::{self, Path, PathBuf},
+    sync::Arc,
+};
 
-use bevy_ecs::{component::Component, system::Command};
+use bevy_ecs::{
+    component::Component,
+    entity::Entity,
+    query::{Changed, Or, Without},
+    system::{Command, Commands, Local, Query, Resource},
+    world::World,
+};
+use chrono::{DateTime, Utc};
 use log::trace;
+use serde::Serialize;
+use url::Url;
+
+use crate::config::SiteConfig;
 
 #[derive(Debug, Component, Clone)]
 pub struct FileName(pub String);
@@ -21,12 +38,90 @@ impl AsRef<Path> for FilePath {
     }
 }
 
+/// A page's identity from one build to the next, unlike its [`Entity`]. Derived from
+/// the page's path within the content directory, such as `blog/post.md`, unless set
+/// with `id` in the front matter, which keeps it the same when the page is renamed.
+#[derive(Debug, Component, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PageId(Arc<str>);
+
+impl PageId {
+    pub fn new(id: impl Into<Arc<str>>) -> Self {
+        Self(id.into())
+    }
+
+    /// The id of the page at `path`, relative to the content directory, separated by
+    /// `/` whatever the platform.
+    pub fn from_path(path: &Path) -> Self {
+        Self(
+            path.components()
+                .filter_map(|component| match component {
+                    std::path::Component::Normal(name) => name.to_str(),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("/")
+                .into(),
+        )
+    }
+}
+
+impl AsRef<str> for PageId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for PageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Where a page came from, so diagnostics point at the file to fix. Pages read from
+/// disk carry their path including the content directory, while pages that aren't
+/// carry a description such as `<virtual:blog/post.md>`.
 #[derive(Debug, Component, Clone)]
-pub struct HtmlBody(Box<str>);
+pub struct SourceFile(PathBuf);
+
+impl SourceFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self(path)
+    }
+
+    /// A pseudo source for a page generated rather than read, e.g. `<taxonomy:tags/rust>`.
+    pub fn synthetic(kind: &str, name: impl AsRef<Path>) -> Self {
+        Self(PathBuf::from(format!(
+            "<{}:{}>",
+            kind,
+            name.as_ref().display()
+        )))
+    }
+
+    /// Whether the page was generated rather than read from disk.
+    pub fn is_synthetic(&self) -> bool {
+        self.0
+            .to_str()
+            .is_some_and(|source| source.starts_with('<') && source.ends_with('>'))
+    }
+}
+
+impl AsRef<Path> for SourceFile {
+    fn as_ref(&self) -> &Path {
+        self.0.as_path()
+    }
+}
+
+#[derive(Debug, Component, Clone)]
+pub struct HtmlBody(Arc<str>);
 
 impl HtmlBody {
     pub fn new(body: String) -> Self {
-        Self(body.into_boxed_str())
+        Self(body.into())
+    }
+
+    /// The body, shared rather than copied.
+    pub fn shared(&self) -> Arc<str> {
+        self.0.clone()
     }
 }
 
@@ -36,6 +131,110 @@ impl AsRef<str> for HtmlBody {
     }
 }
 
+/// The rendered opening of a page, either the content before the summary marker or
+/// its first words.
+#[derive(Debug, Component, Clone)]
+pub struct Summary(pub Arc<str>);
+
+impl AsRef<str> for Summary {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+/// The number of words in the rendered text of a page, leaving out its markup.
+#[derive(Debug, Component, Clone, Copy, PartialEq, Eq)]
+pub struct WordCount(pub usize);
+
+/// The minutes it takes to read a page, at `[markdown] words_per_minute`.
+#[derive(Debug, Component, Clone, Copy, PartialEq, Eq)]
+pub struct ReadingTime(pub usize);
+
+/// The headings of a page listed in its table of contents, in document order.
+#[derive(Debug, Default, Component, Clone)]
+pub struct TableOfContents(pub Vec<TocEntry>);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TocEntry {
+    pub level: u8,
+    /// The anchor id of the heading, linked to with `#id`.
+    pub id: String,
+    pub title: String,
+}
+
+/// The public URL of a rendered page.
+#[derive(Debug, Component, Clone)]
+pub struct Permalink(pub String);
+
+impl AsRef<str> for Permalink {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+/// The URL a page declares as its original location, set with `canonical` in the
+/// front matter. Pages without one are canonical at their [`Permalink`].
+#[derive(Debug, Component, Clone)]
+pub struct CanonicalUrl(pub String);
+
+impl CanonicalUrl {
+    /// Whether the URL points somewhere other than the site's own host, meaning the
+    /// page is syndicated from elsewhere.
+    pub fn is_external(&self, config: &SiteConfig) -> bool {
+        Url::parse(&self.0)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .as_deref()
+            != config.base_url().host()
+    }
+}
+
+impl AsRef<str> for CanonicalUrl {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+/// The public URL of the feed for a section, attached to the section's index page.
+#[derive(Debug, Component, Clone)]
+pub struct FeedUrl(pub String);
+
+impl AsRef<str> for FeedUrl {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+/// The image shown when the page is shared, either set with `image` in the front
+/// matter or generated for it.
+#[derive(Debug, Component, Clone)]
+pub struct OgImage(pub String);
+
+impl AsRef<str> for OgImage {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+/// What processors listing the site's outputs, such as feeds and the JSON index, know
+/// of a page, attached by whatever produced it. Pages without one are left out of
+/// those listings, so processors of other crates opt their own pages in by adding it.
+#[derive(Debug, Component, Clone, PartialEq, Eq)]
+pub struct Indexable {
+    pub title: Option<String>,
+    pub date: Option<DateTime<Utc>>,
+    /// Whether the page belongs in a sitemap, which drafts and unlisted pages don't.
+    pub sitemap: bool,
+    /// Whether the page belongs in search indexes, such as `pages.json`, which unlisted
+    /// pages don't.
+    pub search: bool,
+}
+
+/// The `extra` values a page sees: the site's `[extra]`, overridden by its section's
+/// and then its own. Tables are merged key by key, anything else is replaced.
+#[derive(Debug, Default, Component, Clone, PartialEq)]
+pub struct PageExtra(pub toml::Table);
+
 #[derive(Debug, Component, Clone)]
 pub struct SectionName(Box<str>);
 
@@ -45,6 +244,26 @@ impl AsRef<str> for SectionName {
     }
 }
 
+/// Links a page within a section, the section's `_index.md` included, to the section's
+/// [`PageType::Section`] entity.
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub struct InSection(pub Entity);
+
+/// The `_index.md` page of a section, attached to the section's [`PageType::Section`]
+/// entity.
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub struct SectionIndex(pub Entity);
+
+/// What a section's `_index.md` says about it, attached to the section's
+/// [`PageType::Section`] entity and exposed to templates as `page.section`.
+#[derive(Debug, Default, Component, PartialEq, Eq, Clone, Serialize)]
+pub struct SectionInfo {
+    pub name: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub permalink: Option<String>,
+}
+
 #[derive(Debug, Component, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum PageType {
     Index,
@@ -53,21 +272,62 @@ pub enum PageType {
     Post,
 }
 
-pub(crate) struct EnumeratedSections(Box<str>);
+/// Whether a page type is already registered, for `section` or at the root.
+fn has_page_type(world: &mut World, page_type: PageType, section: Option<&str>) -> bool {
+    world
+        .query::<(&PageType, Option<&SectionName>)>()
+        .iter(world)
+        .any(|(registered, name)| *registered == page_type && name.map(|name| &*name.0) == section)
+}
+
+/// Registers a page type for pages at the root of the content, unless it already is.
+pub struct RootPageType(pub PageType);
+
+impl Command for RootPageType {
+    fn apply(self, world: &mut World) {
+        if !has_page_type(world, self.0, None) {
+            world.spawn(self.0);
+        }
+    }
+}
+
+/// Registers the page types of the content section in the directory `path`, unless
+/// they already are, taking its settings from `[sections]`.
+pub struct EnumeratedSections(Box<str>);
 
 impl EnumeratedSections {
     pub fn new(path: PathBuf) -> Option<Self> {
         Some(Self(path.file_stem()?.to_str()?.into()))
     }
+
+    pub fn name(&self) -> &str {
+        self.0.as_ref()
+    }
 }
 
 impl Command for EnumeratedSections {
-    fn apply(self, world: &mut bevy_ecs::world::World) {
+    fn apply(self, world: &mut World) {
         trace!("Enumerated section: {}", self.0);
-        world.spawn_batch([
-            (PageType::Post, SectionName(self.0.clone())),
-            (PageType::Section, SectionName(self.0)),
-        ]);
+
+        // Sections found by an earlier build, or enumerated twice, are kept as they are.
+        if !has_page_type(world, PageType::Post, Some(&self.0)) {
+            world.spawn((PageType::Post, SectionName(self.0.clone())));
+        }
+
+        if has_page_type(world, PageType::Section, Some(&self.0)) {
+            return;
+        }
+
+        let config = world
+            .get_resource::<SiteConfig>()
+            .and_then(|config| config.section(&self.0))
+            .cloned();
+
+        let mut section = world.spawn((PageType::Section, SectionName(self.0)));
+
+        if let Some(config) = config {
+            section.insert(config);
+        }
     }
 }
 
@@ -93,3 +353,261 @@ impl AsRef<Path> for PageType {
         Into::<&str>::into(self).as_ref()
     }
 }
+
+/// Where a page sits in the content, as worked out by [`classify`] from its
+/// [`FilePath`]. Attached to every page by [`classify_pages`] during
+/// [`Process`](crate::app::Process), so everything placing pages by their path reads the
+/// same answer.
+#[derive(Debug, Component, Clone, PartialEq, Eq)]
+pub struct Classified {
+    /// The directories the page is in, outermost first. The first names the section
+    /// the page would belong to. Shared between every page in the same directories.
+    pub dirs: Box<[Arc<str>]>,
+    pub kind: PageType,
+    /// Whether the page is the `index` of its directory, served at the directory itself.
+    pub is_bundle: bool,
+    /// How many directories deep the page is, 0 at the root of the content.
+    pub depth: usize,
+}
+
+impl Classified {
+    /// The name of the section the page belongs to, which is the first directory of its
+    /// path. Pages at the root of the content aren't in a section.
+    pub fn section(&self) -> Option<&str> {
+        self.dirs.first().map(AsRef::as_ref)
+    }
+
+    /// Whether the page is the `_index` of its section, rather than one nested deeper.
+    pub fn is_section_index(&self) -> bool {
+        self.kind == PageType::Section && self.depth == 1
+    }
+}
+
+/// How a page's path places it in the content, borrowed from the path itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Classification<'a> {
+    /// The path of the directory the page is in, empty at the root.
+    pub dir: &'a Path,
+    pub kind: PageType,
+    pub is_bundle: bool,
+    pub depth: usize,
+}
+
+impl<'a> Classification<'a> {
+    /// Whether the page lists others, being the `_index` of the content or a section.
+    pub fn is_listing(&self) -> bool {
+        matches!(self.kind, PageType::Index | PageType::Section)
+    }
+
+    /// The first directory of the page's path, naming its section.
+    pub fn section(&self) -> Option<&'a OsStr> {
+        self.dirs().next()
+    }
+
+    /// The directories the page is in, outermost first.
+    pub fn dirs(&self) -> impl Iterator<Item = &'a OsStr> {
+        self.dir
+            .components()
+            .filter_map(|component| match component {
+                path::Component::Normal(name) => Some(name),
+                _ => None,
+            })
+    }
+}
+
+/// Classifies a page by its path within the content. `_index` pages list the root or
+/// their section, and any other page is a post within a directory or a page at the
+/// root. Only the file stem is compared, so `_index.md` and `_index.html` alike are
+/// listings. Components other than directory names, such as `.`, are skipped.
+pub fn classify(path: &Path) -> Classification<'_> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let depth = dir
+        .components()
+        .filter(|component| matches!(component, path::Component::Normal(_)))
+        .count();
+    let stem = path.file_stem();
+    let is_listing = stem.is_some_and(|stem| stem == "_index");
+
+    let kind = match (depth, is_listing) {
+        (0, true) => PageType::Index,
+        (0, false) => PageType::Page,
+        (_, true) => PageType::Section,
+        (_, false) => PageType::Post,
+    };
+
+    Classification {
+        dir,
+        kind,
+        is_bundle: stem.is_some_and(|stem| stem == "index"),
+        depth,
+    }
+}
+
+/// Attaches [`Classified`] to pages without one, or whose path changed. Directory
+/// names are interned, so pages in the same directory share them.
+#[allow(clippy::type_complexity)]
+pub fn classify_pages(
+    mut commands: Commands,
+    q_pages: Query<(Entity, &FilePath), Or<(Without<Classified>, Changed<FilePath>)>>,
+    mut names: Local<HashSet<Arc<str>>>,
+) {
+    for (page, path) in q_pages.iter() {
+        let class = classify(path.as_ref());
+        let dirs = class
+            .dirs()
+            .map(|name| {
+                // Section names are shown, so only here is a directory made a string.
+                let name = name.to_string_lossy();
+
+                match names.get(name.as_ref()) {
+                    Some(interned) => interned.clone(),
+                    None => {
+                        let interned: Arc<str> = name.into();
+                        names.insert(interned.clone());
+                        interned
+                    }
+                }
+            })
+            .collect();
+
+        commands.entity(page).insert(Classified {
+            dirs,
+            kind: class.kind,
+            is_bundle: class.is_bundle,
+            depth: class.depth,
+        });
+    }
+}
+
+/// Pages provided in memory rather than read from a content directory, spawned during
+/// [`Load`](crate::app::Load) alongside the pages found on disk.
+#[derive(Debug, Default, Resource)]
+pub struct VirtualContent(pub(crate) BTreeMap<PathBuf, VirtualPage>);
+
+#[derive(Debug)]
+#[cfg_attr(not(feature = "markdown"), allow(dead_code))]
+pub(crate) struct VirtualPage {
+    pub(crate) front_matter: toml::Table,
+    pub(crate) body: String,
+}
+
+impl VirtualContent {
+    /// Adds a page at `path`, relative to the content directory, replacing any page
+    /// previously added at the same path.
+    pub fn add(
+        &mut self,
+        path: impl Into<PathBuf>,
+        front_matter: toml::Table,
+        body: impl Into<String>,
+    ) -> &mut Self {
+        self.0.insert(
+            path.into(),
+            VirtualPage {
+                front_matter,
+                body: body.into(),
+            },
+        );
+
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::schedule::Schedule;
+
+    use super::*;
+
+    #[test]
+    fn pages_are_classified_by_their_path() {
+        let class = |path: &'static str| {
+            let class = classify(Path::new(path));
+
+            (
+                class.kind,
+                class.section().and_then(OsStr::to_str),
+                class.depth,
+                class.is_bundle,
+            )
+        };
+
+        assert_eq!(class("_index.md"), (PageType::Index, None, 0, false));
+        assert_eq!(class("_index.html"), (PageType::Index, None, 0, false));
+        assert_eq!(class("about.md"), (PageType::Page, None, 0, false));
+        assert_eq!(class("index.md"), (PageType::Page, None, 0, true));
+        assert_eq!(class("my_index.md"), (PageType::Page, None, 0, false));
+        assert_eq!(class(""), (PageType::Page, None, 0, false));
+        assert_eq!(
+            class("notes/_index.md"),
+            (PageType::Section, Some("notes"), 1, false)
+        );
+        assert_eq!(
+            class("notes/a-note.md"),
+            (PageType::Post, Some("notes"), 1, false)
+        );
+        assert_eq!(
+            class("notes/trip/index.md"),
+            (PageType::Post, Some("notes"), 2, true)
+        );
+        assert_eq!(
+            class("notes/nested/_index.md"),
+            (PageType::Section, Some("notes"), 2, false)
+        );
+        assert_eq!(
+            class("./notes//c.md"),
+            (PageType::Post, Some("notes"), 1, false)
+        );
+    }
+
+    #[test]
+    fn only_the_index_of_a_section_itself_is_its_listing() {
+        let classified = |kind, depth| Classified {
+            dirs: Box::new([]),
+            kind,
+            is_bundle: false,
+            depth,
+        };
+
+        assert!(classify(Path::new("notes/_index.md")).is_listing());
+        assert!(classify(Path::new("_index.md")).is_listing());
+        assert!(!classify(Path::new("notes/_index/a.md")).is_listing());
+        assert!(classified(PageType::Section, 1).is_section_index());
+        assert!(!classified(PageType::Section, 2).is_section_index());
+        assert!(!classified(PageType::Index, 0).is_section_index());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn directories_that_are_not_utf8_are_still_sections() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"n\xffotes/a.md"));
+        let class = classify(path);
+
+        assert_eq!(class.kind, PageType::Post);
+        assert_eq!(class.section(), Some(OsStr::from_bytes(b"n\xffotes")));
+    }
+
+    #[test]
+    fn pages_in_the_same_directory_share_its_name() {
+        let mut world = World::new();
+        let a = world.spawn(FilePath::new("notes/a.md".into())).id();
+        let b = world.spawn(FilePath::new("notes/b.md".into())).id();
+        let root = world.spawn(FilePath::new("about.md".into())).id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(classify_pages);
+        schedule.run(&mut world);
+
+        let a = world.get::<Classified>(a).unwrap();
+        let b = world.get::<Classified>(b).unwrap();
+
+        assert_eq!(a.section(), Some("notes"));
+        assert!(Arc::ptr_eq(&a.dirs[0], &b.dirs[0]));
+        assert_eq!(world.get::<Classified>(root).unwrap().section(), None);
+    }
+}
@@ -1,46 +1,557 @@
-use std::path::{Path, PathBuf};
+//! Finding and reading files from the input directories. Writing outputs lives in
+//! [`io`](crate::io).
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
 
 use futures_concurrency::concurrent_stream::{ConcurrentStream, IntoConcurrentStream};
-use log::trace;
+use log::{debug, info, trace};
 use smol::{
-    fs::{read_dir, read_to_string},
+    fs::{canonicalize, read, read_dir, read_to_string},
     stream::StreamExt,
 };
 
-async fn find_all_files_in_directory(path: &Path) -> std::io::Result<Vec<PathBuf>> {
-    trace!("Reading directory: {}", path.display());
-    let mut entry = read_dir(path).await?;
+use crate::{compress::Codec, logging::LOAD, processor::SiteConfig};
+
+/// Limits on walking a directory, so a content path pointing somewhere unexpected,
+/// like the root of the filesystem, fails quickly instead of reading everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalkOptions {
+    /// Walk into symlinked directories, rather than skipping them.
+    pub follow_symlinks: bool,
+    /// How many directories deep files may be found.
+    pub max_depth: usize,
+    /// How many files may be found before giving up.
+    pub max_files: usize,
+    /// Skip files ignored by the `.gitignore` files of the git repository being
+    /// walked, if there is one.
+    pub respect_gitignore: bool,
+}
+
+impl WalkOptions {
+    /// Options following the site's `[build]` settings.
+    pub fn from_config(config: &SiteConfig) -> Self {
+        Self {
+            follow_symlinks: config.build.follow_symlinks,
+            max_depth: config.build.max_depth,
+            max_files: config.build.max_files,
+            respect_gitignore: config.files.respect_gitignore,
+        }
+    }
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        Self::from_config(&SiteConfig::default())
+    }
+}
+
+/// Why a directory couldn't be walked.
+#[derive(Debug, thiserror::Error)]
+pub enum WalkError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(
+        "{} is more than {max} directories deep, check the content path is right or raise \
+         `max_depth` under [build]",
+        path.display()
+    )]
+    TooDeep { path: PathBuf, max: usize },
+    #[error(
+        "Found more than {max} files in {}, check the content path is right or raise \
+         `max_files` under [build]",
+        path.display()
+    )]
+    TooManyFiles { path: PathBuf, max: usize },
+}
+
+/// Files found between each progress update while walking a directory.
+const WALK_PROGRESS_INTERVAL: usize = 10_000;
+
+/// Lists every file in a directory and its subdirectories, without reading them.
+/// Symlinked files are listed like any other, while symlinked directories are skipped
+/// unless following them. When following them, a directory reached again, such as
+/// through a link to one of its parents, is skipped rather than walked forever.
+/// When respecting `.gitignore` files, the `.git` directory is skipped as well.
+pub async fn find_all_files_in_directory(
+    path: &Path,
+    options: WalkOptions,
+) -> Result<Vec<PathBuf>, WalkError> {
+    let gitignores = match options.respect_gitignore {
+        true => GitIgnores::find(path).await?,
+        false => None,
+    };
+
+    let mut walk = Walk {
+        root: path,
+        options,
+        visited: HashSet::new(),
+        files: Vec::new(),
+        gitignores,
+    };
+
+    if options.follow_symlinks {
+        walk.visited.insert(canonicalize(path).await?);
+    }
+
+    walk.directory(path, 0).await?;
+
+    Ok(walk.files)
+}
+
+struct Walk<'a> {
+    root: &'a Path,
+    options: WalkOptions,
+    /// The canonical path of every directory walked, when following symlinks.
+    visited: HashSet<PathBuf>,
+    files: Vec<PathBuf>,
+    /// Present when respecting `.gitignore` files within a git repository.
+    gitignores: Option<GitIgnores>,
+}
+
+impl Walk<'_> {
+    async fn directory(&mut self, path: &Path, depth: usize) -> Result<(), WalkError> {
+        trace!("Reading directory: {}", path.display());
+        let mut entry = read_dir(path).await?;
+
+        if let Some(gitignores) = self.gitignores.as_mut() {
+            gitignores.enter(path).await?;
+        }
+
+        while let Some(entry) = entry.try_next().await? {
+            let path = entry.path();
+            let is_dir = path.is_dir();
+
+            if self
+                .gitignores
+                .as_ref()
+                .is_some_and(|gitignores| gitignores.ignores(&path, is_dir))
+            {
+                debug!("Skipping {}, which git ignores", path.display());
+                continue;
+            }
+
+            if is_dir {
+                if !self.options.follow_symlinks {
+                    if entry.file_type().await?.is_symlink() {
+                        debug!("Skipping symlinked directory {}", path.display());
+                        continue;
+                    }
+                } else if !self.visited.insert(canonicalize(&path).await?) {
+                    debug!("Skipping {}, which was already visited", path.display());
+                    continue;
+                }
 
-    let mut to_visit = Vec::new();
+                if depth >= self.options.max_depth {
+                    return Err(WalkError::TooDeep {
+                        path,
+                        max: self.options.max_depth,
+                    });
+                }
+
+                Box::pin(self.directory(path.as_path(), depth + 1)).await?;
+            } else if path.is_file() {
+                if self.files.len() >= self.options.max_files {
+                    return Err(WalkError::TooManyFiles {
+                        path: self.root.to_path_buf(),
+                        max: self.options.max_files,
+                    });
+                }
+
+                trace!("Found: {}", path.display());
+                self.files.push(path);
+
+                if self.files.len() % WALK_PROGRESS_INTERVAL == 0 {
+                    info!(
+                        target: LOAD,
+                        "Found {} files in {} so far",
+                        self.files.len(),
+                        self.root.display()
+                    );
+                }
+            }
+        }
+
+        if let Some(gitignores) = self.gitignores.as_mut() {
+            gitignores.leave();
+        }
+
+        Ok(())
+    }
+}
+
+/// The `.gitignore` files applying to the directory being walked, from the root of its
+/// repository down. Patterns are matched against canonical paths, as the repository
+/// was found from one.
+#[cfg(feature = "gitignore")]
+struct GitIgnores {
+    /// The directory walked, as given.
+    walked: PathBuf,
+    /// The directory walked, canonicalized.
+    canonical: PathBuf,
+    /// One for each directory entered, which is empty without a `.gitignore`.
+    stack: Vec<ignore::gitignore::Gitignore>,
+}
+
+#[cfg(feature = "gitignore")]
+impl GitIgnores {
+    /// The `.gitignore` files in the parents of `path` up to the root of its repository,
+    /// or `None` when it isn't in one.
+    async fn find(path: &Path) -> std::io::Result<Option<Self>> {
+        let canonical = canonicalize(path).await?;
+
+        let Some(repository) = canonical.ancestors().find(|dir| dir.join(".git").exists()) else {
+            debug!("{} isn't in a git repository", path.display());
+            return Ok(None);
+        };
+
+        let mut gitignores = Self {
+            walked: path.to_path_buf(),
+            canonical: canonical.clone(),
+            stack: Vec::new(),
+        };
+
+        let parents: Vec<_> = canonical
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(repository))
+            .collect();
+
+        for dir in parents.into_iter().rev() {
+            gitignores.push(dir).await?;
+        }
+
+        Ok(Some(gitignores))
+    }
+
+    async fn enter(&mut self, dir: &Path) -> std::io::Result<()> {
+        let dir = self.in_repository(dir);
+        self.push(&dir).await
+    }
+
+    fn leave(&mut self) {
+        self.stack.pop();
+    }
+
+    async fn push(&mut self, dir: &Path) -> std::io::Result<()> {
+        use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+        let file = dir.join(".gitignore");
+        let content = match read_to_string(&file).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                self.stack.push(Gitignore::empty());
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        trace!("Reading ignore patterns from {}", file.display());
+        let mut builder = GitignoreBuilder::new(dir);
+
+        for line in content.lines() {
+            if let Err(e) = builder.add_line(Some(file.clone()), line) {
+                debug!("Skipping pattern in {}: {}", file.display(), e);
+            }
+        }
 
-    while let Some(entry) = entry.try_next().await? {
-        let path = entry.path();
+        self.stack
+            .push(builder.build().unwrap_or_else(|_| Gitignore::empty()));
 
-        if path.is_dir() {
-            let paths = Box::pin(find_all_files_in_directory(path.as_path())).await?;
+        Ok(())
+    }
 
-            to_visit.extend(paths);
-        } else if path.is_file() {
-            trace!("Found: {}", path.display());
-            to_visit.push(path);
+    /// Whether the closest `.gitignore` with a pattern matching `path` ignores it.
+    fn ignores(&self, path: &Path, is_dir: bool) -> bool {
+        if is_dir && path.file_name().is_some_and(|name| name == ".git") {
+            return true;
         }
+
+        let path = self.in_repository(path);
+
+        self.stack
+            .iter()
+            .rev()
+            .map(|gitignore| gitignore.matched(&path, is_dir))
+            .find(|matched| !matched.is_none())
+            .is_some_and(|matched| matched.is_ignore())
     }
 
-    Ok(to_visit)
+    fn in_repository(&self, path: &Path) -> PathBuf {
+        self.canonical
+            .join(path.strip_prefix(&self.walked).unwrap_or(path))
+    }
+}
+
+/// Without gitignore support, nothing is ever ignored.
+#[cfg(not(feature = "gitignore"))]
+struct GitIgnores;
+
+#[cfg(not(feature = "gitignore"))]
+impl GitIgnores {
+    async fn find(_path: &Path) -> std::io::Result<Option<Self>> {
+        Ok(None)
+    }
+
+    async fn enter(&mut self, _dir: &Path) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn leave(&mut self) {}
+
+    fn ignores(&self, _path: &Path, _is_dir: bool) -> bool {
+        false
+    }
 }
 
 pub async fn read_all_from_directory(
     path: impl AsRef<Path>,
+    options: WalkOptions,
 ) -> Vec<std::io::Result<(PathBuf, String)>> {
-    match find_all_files_in_directory(path.as_ref()).await {
-        Ok(files) => files.into_co_stream().map(read_file).collect().await,
-        Err(e) => vec![Err(e)],
+    read_matching_from_directory(path, options, |_| true).await
+}
+
+/// Reads the files in a directory and its subdirectories for which `filter` returns
+/// true, given their path relative to the directory. The directory is walked as by
+/// [`find_all_files_in_directory`].
+pub async fn read_matching_from_directory(
+    path: impl AsRef<Path>,
+    options: WalkOptions,
+    filter: impl Fn(&Path) -> bool,
+) -> Vec<std::io::Result<(PathBuf, String)>> {
+    let path = path.as_ref();
+
+    match find_all_files_in_directory(path, options).await {
+        Ok(files) => {
+            read_files(
+                files
+                    .into_iter()
+                    .filter(|file| filter(file.strip_prefix(path).unwrap_or(file)))
+                    .collect(),
+            )
+            .await
+        }
+        Err(WalkError::Io(e)) => vec![Err(e)],
+        Err(e) => vec![Err(std::io::Error::other(e))],
     }
 }
 
+/// Reads every file in `files` concurrently, returning them in no particular order.
+pub async fn read_files(files: Vec<PathBuf>) -> Vec<std::io::Result<(PathBuf, String)>> {
+    files.into_co_stream().map(read_file).collect().await
+}
+
 async fn read_file(file: PathBuf) -> std::io::Result<(PathBuf, String)> {
     trace!("Reading {} from file", file.display());
     read_to_string(file.as_path())
         .await
         .map(move |body| (file, body))
 }
+
+/// The codec `path` is compressed with, going by the extensions under `[build]
+/// decompress`, along with the path it's read as once decompressed.
+pub fn compressed_path(codecs: &BTreeMap<String, Codec>, path: &Path) -> Option<(Codec, PathBuf)> {
+    let extension = path.extension()?.to_str()?;
+    let codec = codecs.get(extension)?;
+
+    Some((*codec, path.with_extension("")))
+}
+
+/// Why a file couldn't be read as text.
+#[derive(Debug, thiserror::Error)]
+pub enum ReadError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Unable to decompress {} as {codec}: {source}", path.display())]
+    Decompress {
+        path: PathBuf,
+        codec: Codec,
+        source: std::io::Error,
+    },
+}
+
+/// Reads every file in `files` concurrently like [`read_files`], decompressing those
+/// whose extension is one of `codecs`.
+pub async fn read_decompressed_files(
+    files: Vec<PathBuf>,
+    codecs: &BTreeMap<String, Codec>,
+) -> Vec<Result<(PathBuf, String), ReadError>> {
+    files
+        .into_iter()
+        .map(|file| {
+            let codec = compressed_path(codecs, &file).map(|(codec, _)| codec);
+            (file, codec)
+        })
+        .collect::<Vec<_>>()
+        .into_co_stream()
+        .map(read_decompressed_file)
+        .collect()
+        .await
+}
+
+async fn read_decompressed_file(
+    (file, codec): (PathBuf, Option<Codec>),
+) -> Result<(PathBuf, String), ReadError> {
+    let Some(codec) = codec else {
+        return Ok(read_file(file).await?);
+    };
+
+    trace!("Reading {} from compressed file", file.display());
+    let decompressed = codec
+        .decompress(&read(&file).await?)
+        .and_then(|content| String::from_utf8(content).map_err(std::io::Error::other));
+
+    match decompressed {
+        Ok(content) => Ok((file, content)),
+        Err(source) => Err(ReadError::Decompress {
+            path: file,
+            codec,
+            source,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_directories_are_only_followed_when_asked() {
+        use std::os::unix::fs::symlink;
+
+        let dir = std::env::temp_dir().join("webvy_symlinked_directories_are_followed");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("content/posts")).unwrap();
+        std::fs::create_dir_all(dir.join("shared")).unwrap();
+        std::fs::write(dir.join("content/a.md"), "A").unwrap();
+        std::fs::write(dir.join("content/posts/b.md"), "B").unwrap();
+        std::fs::write(dir.join("shared/c.md"), "C").unwrap();
+        symlink(dir.join("content/a.md"), dir.join("content/alias.md")).unwrap();
+        symlink(dir.join("shared"), dir.join("content/shared")).unwrap();
+        // Walking into this would never end.
+        symlink(dir.join("content"), dir.join("content/posts/loop")).unwrap();
+
+        let content = dir.join("content");
+        let find = |follow_symlinks| {
+            let mut files: Vec<_> = smol::block_on(find_all_files_in_directory(
+                &content,
+                WalkOptions {
+                    follow_symlinks,
+                    ..WalkOptions::default()
+                },
+            ))
+            .unwrap()
+            .into_iter()
+            .map(|file| file.strip_prefix(&content).unwrap().to_path_buf())
+            .collect();
+            files.sort();
+            files
+        };
+
+        assert_eq!(
+            find(false),
+            ["a.md", "alias.md", "posts/b.md"].map(PathBuf::from)
+        );
+        assert_eq!(
+            find(true),
+            ["a.md", "alias.md", "posts/b.md", "shared/c.md"].map(PathBuf::from)
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn walks_give_up_on_directories_too_deep_or_large() {
+        let dir = std::env::temp_dir().join("webvy_walks_give_up_on_large_directories");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("a/b/c")).unwrap();
+        std::fs::write(dir.join("one.md"), "1").unwrap();
+        std::fs::write(dir.join("a/two.md"), "2").unwrap();
+        std::fs::write(dir.join("a/b/c/three.md"), "3").unwrap();
+
+        let find = |max_depth, max_files| {
+            smol::block_on(find_all_files_in_directory(
+                &dir,
+                WalkOptions {
+                    follow_symlinks: false,
+                    max_depth,
+                    max_files,
+                    respect_gitignore: false,
+                },
+            ))
+        };
+
+        assert_eq!(find(3, 3).unwrap().len(), 3);
+
+        match find(2, 3) {
+            Err(WalkError::TooDeep { path, max: 2 }) => assert_eq!(path, dir.join("a/b/c")),
+            result => panic!("expected the walk to be too deep, got {:?}", result),
+        }
+
+        let error = find(3, 2).unwrap_err();
+        assert!(matches!(error, WalkError::TooManyFiles { max: 2, .. }));
+        assert!(error
+            .to_string()
+            .contains("raise `max_files` under [build]"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "gitignore")]
+    #[test]
+    fn gitignored_files_are_only_skipped_in_a_repository_when_asked() {
+        let dir = std::env::temp_dir().join("webvy_gitignored_files_are_skipped");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("content/scratch")).unwrap();
+        std::fs::create_dir_all(dir.join("content/posts")).unwrap();
+        std::fs::write(dir.join(".gitignore"), "content/scratch/\n*.tmp\n").unwrap();
+        std::fs::write(dir.join("content/posts/.gitignore"), "!keep.tmp\n").unwrap();
+        std::fs::write(dir.join("content/a.md"), "A").unwrap();
+        std::fs::write(dir.join("content/a.tmp"), "A").unwrap();
+        std::fs::write(dir.join("content/scratch/b.md"), "B").unwrap();
+        std::fs::write(dir.join("content/posts/c.tmp"), "C").unwrap();
+        std::fs::write(dir.join("content/posts/keep.tmp"), "C").unwrap();
+
+        let content = dir.join("content");
+        let find = |respect_gitignore| {
+            let mut files: Vec<_> = smol::block_on(find_all_files_in_directory(
+                &content,
+                WalkOptions {
+                    respect_gitignore,
+                    ..WalkOptions::default()
+                },
+            ))
+            .unwrap()
+            .into_iter()
+            .map(|file| file.strip_prefix(&content).unwrap().to_path_buf())
+            .collect();
+            files.sort();
+            files
+        };
+        let everything = [
+            "a.md",
+            "a.tmp",
+            "posts/.gitignore",
+            "posts/c.tmp",
+            "posts/keep.tmp",
+            "scratch/b.md",
+        ]
+        .map(PathBuf::from);
+
+        // Without a repository, the `.gitignore` files are just files.
+        assert_eq!(find(true), everything);
+
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+
+        assert_eq!(find(false), everything);
+        assert_eq!(
+            find(true),
+            ["a.md", "posts/.gitignore", "posts/keep.tmp"].map(PathBuf::from)
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,10 +1,187 @@
-use bevy_ecs::component::Component;
+use std::{collections::BTreeSet, fmt};
+
+use bevy_ecs::{component::Component, system::Resource};
+use chrono::{DateTime, Utc};
+
+use crate::timezone::{parse_date, Timezone};
 
 #[derive(Debug, Default, Clone, Component)]
 pub struct Title(pub String);
 
+/// The date of a page, set with `date` in the front matter, along with the instant it
+/// stands for. Dates and datetimes without an offset are in the site's `timezone`, or
+/// UTC without one, while those with an offset keep it.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Component)]
+pub struct Date {
+    /// The date as written, such as `2024-03-10` or `2024-03-10T09:00:00+02:00`.
+    pub original: String,
+    /// The instant in UTC, which posts are sorted by, or `None` when the date couldn't
+    /// be understood.
+    pub utc: Option<DateTime<Utc>>,
+}
+
+impl Date {
+    /// Interprets `original` as by [`parse_date`].
+    pub fn new(original: impl Into<String>, timezone: Option<&Timezone>) -> Self {
+        let original = original.into();
+        let utc = parse_date(&original, timezone);
+
+        Self { original, utc }
+    }
+
+    pub fn to_datetime(&self) -> Option<DateTime<Utc>> {
+        self.utc
+    }
+}
+
+#[derive(Debug, Default, Clone, Component)]
+pub struct Tags(pub Vec<String>);
+
+/// A short description of the page for search engines and link previews, set with
+/// `description` in the front matter.
+#[derive(Debug, Default, Clone, Component)]
+pub struct Description(pub String);
+
+/// Orders posts within sections sorted by weight, lightest first.
+#[derive(Debug, Default, Clone, Copy, Component)]
+pub struct Weight(pub i64);
+
+/// Keys into `[authors]` of the people who wrote the page, set with either `author`
+/// or `authors` in the front matter.
 #[derive(Debug, Default, Clone, Component)]
-pub struct Date(pub String);
+pub struct Authors(pub Vec<String>);
 
 #[derive(Debug, Clone, Component)]
 pub struct Draft;
+
+/// The menus a page adds itself to with `menu` in the front matter, either by name or
+/// as a table of menu names to the page's `parent` and `weight` in each.
+#[derive(Debug, Default, Clone, Component)]
+pub struct MenuEntries(pub Vec<MenuEntry>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MenuEntry {
+    pub menu: String,
+    pub parent: Option<String>,
+    /// Falls back to the page's [`Weight`].
+    pub weight: Option<i64>,
+}
+
+/// A template set with `template` in the front matter, used instead of the one for
+/// the page's type.
+#[derive(Debug, Clone, Component)]
+pub struct TemplateOverride(pub String);
+
+/// Marks a trusted page, left unsanitized with `sanitize = false` in the front matter.
+#[derive(Debug, Clone, Component)]
+pub struct Trusted;
+
+/// Heading levels listed in the page's table of contents, set with `toc_levels` in the
+/// front matter. Empty when the table is turned off with `toc = false`.
+#[derive(Debug, Default, Clone, Component)]
+pub struct TocLevels(pub Vec<u8>);
+
+/// Values under `[extra]` in the front matter, free for templates to use. Merged over
+/// those of the page's section and the site into its
+/// [`PageExtra`](crate::file::PageExtra).
+#[derive(Debug, Default, Clone, Component)]
+pub struct Extra(pub toml::Table);
+
+/// Marks a page whose body is written out verbatim, without a template.
+#[derive(Debug, Clone, Component)]
+pub struct Raw;
+
+/// Marks a page with `render = false`, loaded for other pages to use but never given a
+/// permalink, rendered or written out itself.
+#[derive(Debug, Clone, Component)]
+pub struct Headless;
+
+/// Marks a page with `unlisted = true`, or `in_listing = false`, shared by direct link
+/// only. It's rendered and written as usual, but left out of its section's posts,
+/// feeds, the JSON index and the date and taxonomy indexes, and templates see it as
+/// `page.noindex`.
+#[derive(Debug, Clone, Component)]
+pub struct Unlisted;
+
+/// Front matter values that were present but of the wrong type, left unextracted.
+#[derive(Debug, Default, Clone, Component)]
+pub struct FrontMatterErrors(pub Vec<FieldMismatch>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldMismatch {
+    pub key: &'static str,
+    /// What the key should hold, e.g. `a boolean`.
+    pub expected: &'static str,
+    /// What it held instead, e.g. `integer` or `string "yes"`.
+    pub found: String,
+}
+
+impl fmt::Display for FieldMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} should be {}, found {}",
+            self.key, self.expected, self.found
+        )
+    }
+}
+
+/// Every top-level front matter key something reads, so any other key on a page can be
+/// reported as most likely misspelled. Processors reading keys of their own from the
+/// front matter register them here, through
+/// `app.world_mut().get_resource_or_insert_with(FrontMatterKeys::default)`.
+#[derive(Debug, Default, Resource)]
+pub struct FrontMatterKeys(BTreeSet<String>);
+
+impl FrontMatterKeys {
+    pub fn register(&mut self, key: impl Into<String>) -> &mut Self {
+        self.0.insert(key.into());
+        self
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.0.contains(key)
+    }
+
+    /// The registered key closest to `key`, when few enough edits apart to be a typo.
+    pub fn suggest(&self, key: &str) -> Option<&str> {
+        let allowed = (key.chars().count() / 3).max(1);
+
+        self.0
+            .iter()
+            .map(|known| (edit_distance(key, known), known))
+            .filter(|(distance, _)| *distance <= allowed)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, known)| known.as_str())
+    }
+}
+
+/// Number of insertions, deletions, substitutions and swaps of adjacent characters
+/// turning `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Rows for the previous two prefixes of `a`, and the current one.
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+
+        before = std::mem::replace(&mut previous, current);
+    }
+
+    previous[b.len()]
+}
@@ -0,0 +1,245 @@
+//! Small helpers for working through rendered HTML without a full parser.
+
+/// Elements without a closing tag.
+pub(crate) const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Starts a comment marking where a block from a source file starts, see
+/// [`source_line_comment`].
+const SOURCE_LINE_COMMENT: &str = "<!-- srcline: ";
+
+/// A comment marking the block after it as starting on `line` of its source file, or
+/// marking where blocks from the source file end without one.
+pub fn source_line_comment(line: Option<usize>) -> String {
+    match line {
+        Some(line) => format!("{}{} -->\n", SOURCE_LINE_COMMENT, line),
+        None => format!("{}end -->\n", SOURCE_LINE_COMMENT),
+    }
+}
+
+/// Where each [`source_line_comment`] in `html` is, along with the line it marks.
+pub fn source_lines(html: &str) -> impl Iterator<Item = (usize, Option<usize>)> + '_ {
+    html.match_indices(SOURCE_LINE_COMMENT)
+        .filter_map(move |(offset, comment)| {
+            let rest = &html[offset + comment.len()..];
+            let line = &rest[..rest.find(" -->")?];
+
+            match line {
+                "end" => Some((offset, None)),
+                line => Some((offset, Some(line.parse().ok()?))),
+            }
+        })
+}
+
+/// Finds the end of the tag at the start of `html`, ignoring any `>` within quoted
+/// attribute values.
+pub fn tag_end(html: &str) -> usize {
+    let mut quote = None;
+
+    for (index, c) in html.char_indices().skip(1) {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), c) if open == c => quote = None,
+            (None, '>') => return index + 1,
+            _ => {}
+        }
+    }
+
+    html.len()
+}
+
+/// The name of a tag such as `<a href="..">` or `</a>`, and whether it's a closing tag.
+pub fn tag_name(tag: &str) -> (&str, bool) {
+    let tag = tag.trim_start_matches('<');
+    let closing = tag.starts_with('/');
+    let tag = tag.trim_start_matches('/');
+    let end = tag
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(tag.len());
+
+    (&tag[..end], closing)
+}
+
+/// Finds where the closing tag for `name` starts in `html`.
+pub fn find_closing_tag(html: &str, name: &str) -> usize {
+    html.match_indices("</")
+        .map(|(index, _)| index)
+        .find(|index| {
+            html.get(index + 2..index + 2 + name.len())
+                .is_some_and(|tag| tag.eq_ignore_ascii_case(name))
+        })
+        .unwrap_or(html.len())
+}
+
+/// The text of the first `name` element in `html`, with its markup stripped and its
+/// whitespace collapsed. Entities are kept as written. `None` when there's no such
+/// element or it holds no text.
+pub fn element_text(html: &str, name: &str) -> Option<String> {
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+
+        let end = tag_end(rest);
+        let (tag, closing) = tag_name(&rest[..end]);
+
+        rest = &rest[end..];
+
+        if !closing && tag.eq_ignore_ascii_case(name) {
+            let text = truncate_words(&rest[..find_closing_tag(rest, name)], usize::MAX, true);
+
+            return (!text.is_empty()).then_some(text);
+        }
+    }
+
+    None
+}
+
+/// Cuts `html` down to its first `words` words, ending with an ellipsis when anything
+/// was left out. With `strip_markup` only the text is kept, otherwise the markup is
+/// kept and any elements left open are closed.
+pub fn truncate_words(html: &str, words: usize, strip_markup: bool) -> String {
+    let mut output = String::with_capacity(html.len().min(words.saturating_mul(8)));
+    let mut open = Vec::new();
+    let mut count = 0;
+    let mut in_word = false;
+    let mut rest = html;
+
+    'outer: while !rest.is_empty() {
+        if rest.starts_with('<') {
+            let end = tag_end(rest);
+            let tag = &rest[..end];
+            let (name, closing) = tag_name(tag);
+            let name = name.to_ascii_lowercase();
+
+            rest = &rest[end..];
+
+            // Line breaks separate words, even without whitespace around them.
+            if matches!(name.as_str(), "br" | "hr") {
+                in_word = false;
+
+                if strip_markup && !output.is_empty() && !output.ends_with(' ') {
+                    output.push(' ');
+                }
+            }
+
+            if strip_markup || name.is_empty() {
+                continue;
+            }
+
+            if closing {
+                if let Some(index) = open.iter().rposition(|open| *open == name) {
+                    open.truncate(index);
+                }
+            } else if !VOID_ELEMENTS.contains(&name.as_str()) && !tag.ends_with("/>") {
+                open.push(name);
+            }
+
+            output.push_str(tag);
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+
+            for (index, c) in rest[..end].char_indices() {
+                if c.is_whitespace() {
+                    in_word = false;
+                } else if !in_word {
+                    if count == words {
+                        rest = &rest[index..];
+                        break 'outer;
+                    }
+
+                    in_word = true;
+                    count += 1;
+                }
+
+                if strip_markup && c.is_whitespace() {
+                    if !output.is_empty() && !output.ends_with(' ') {
+                        output.push(' ');
+                    }
+                } else {
+                    output.push(c);
+                }
+            }
+
+            rest = &rest[end..];
+        }
+    }
+
+    let truncated = !rest.is_empty();
+
+    output.truncate(output.trim_end().len());
+
+    if truncated {
+        output.push('…');
+    }
+
+    for name in open.iter().rev() {
+        output.push_str(&format!("</{}>", name));
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_within_the_limit_is_kept_whole() {
+        assert_eq!(
+            truncate_words("<p>One <em>two</em> three</p>", 3, false),
+            "<p>One <em>two</em> three</p>"
+        );
+        assert_eq!(
+            truncate_words("<p>One <em>two</em> three</p>\n", 3, true),
+            "One two three"
+        );
+    }
+
+    #[test]
+    fn truncation_closes_open_tags_without_cutting_words() {
+        assert_eq!(
+            truncate_words("<p>One <em>two three</em> four</p>", 2, false),
+            "<p>One <em>two…</em></p>"
+        );
+        assert_eq!(
+            truncate_words("<p>One <em>two</em> three</p>", 2, false),
+            "<p>One <em>two</em>…</p>"
+        );
+        assert_eq!(
+            truncate_words("<p>Line<br>break &amp; more</p>", 1, false),
+            "<p>Line<br>…</p>"
+        );
+    }
+
+    #[test]
+    fn stripped_summaries_collapse_whitespace() {
+        assert_eq!(
+            truncate_words(
+                "<h1>A  title</h1>\n<p>Some <a href=\"/x\">linked</a> text</p>",
+                4,
+                true
+            ),
+            "A title Some linked…"
+        );
+    }
+
+    #[test]
+    fn element_text_is_found_without_markup() {
+        let html = "<html><head><TITLE>Tom &amp;\n  <em>Jerry</em></TITLE></head>\
+                    <body><h1 class=\"a\"></h1><header><h1>About</h1></header></body></html>";
+
+        assert_eq!(
+            element_text(html, "title").as_deref(),
+            Some("Tom &amp; Jerry")
+        );
+        assert_eq!(element_text(html, "h1"), None);
+        assert_eq!(
+            element_text(html, "head").as_deref(),
+            Some("Tom &amp; Jerry")
+        );
+        assert_eq!(element_text(html, "h"), None);
+    }
+}
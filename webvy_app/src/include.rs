@@ -0,0 +1,407 @@
+//! Pages pulling in the content or summary of other pages, through the
+//! `{{ include_page(path="...") }}` and `{{ summary_of(path="...") }}` shortcodes.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    ops::Range,
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use bevy_ecs::system::Resource;
+
+/// How deeply pages may include pages including other pages, before giving up.
+pub const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// The content and summary of a page, as other pages see them.
+#[derive(Debug, Clone)]
+pub struct IncludedPage {
+    pub content: Arc<str>,
+    pub summary: Option<Arc<str>>,
+}
+
+/// A read-only snapshot of every page, by its path within the content directory. Taken
+/// during [`PostProcess`](crate::app::PostProcess) and shared with the template
+/// functions, which can't borrow the world. Clones share the same snapshot.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct PageSnapshot(Arc<Shared>);
+
+#[derive(Debug, Default)]
+struct Shared {
+    pages: RwLock<Arc<HashMap<PathBuf, IncludedPage>>>,
+    /// Whether a template included a page, so templates depend on every page.
+    used: AtomicBool,
+}
+
+impl PageSnapshot {
+    /// Replaces the snapshot with `pages`, for every clone.
+    pub fn replace(&mut self, pages: HashMap<PathBuf, IncludedPage>) {
+        *self.0.pages.write().unwrap() = Arc::new(pages);
+    }
+
+    fn pages(&self) -> Arc<HashMap<PathBuf, IncludedPage>> {
+        self.0.pages.read().unwrap().clone()
+    }
+
+    /// Records a template including a page.
+    pub fn mark_used(&self) {
+        self.0.used.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether any template included a page since the snapshot was created.
+    pub fn is_used(&self) -> bool {
+        self.0.used.load(Ordering::Relaxed)
+    }
+
+    /// The content of the page at `path`, with its own shortcodes expanded.
+    pub fn include_page(&self, path: &str) -> Result<String, IncludeError> {
+        include(&self.pages(), &normalize(path), &mut Vec::new())
+    }
+
+    /// The summary of the page at `path`, or nothing when it has none.
+    pub fn summary_of(&self, path: &str) -> Result<Arc<str>, IncludeError> {
+        summary_of(&self.pages(), &normalize(path), &[])
+    }
+
+    /// Expands the shortcodes in `content`, the content of the page at `page`. Nothing
+    /// is returned when it has none.
+    pub fn expand(&self, page: &Path, content: &str) -> Result<Option<String>, IncludeError> {
+        expand(&self.pages(), content, &mut vec![page.to_path_buf()])
+    }
+}
+
+/// Why a page couldn't be included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IncludeError {
+    /// The page at `path` doesn't exist, included through `chain`.
+    Missing { chain: Vec<PathBuf>, path: PathBuf },
+    /// A page ends up including itself, through every page listed.
+    Cycle(Vec<PathBuf>),
+    /// Pages include each other more than [`MAX_INCLUDE_DEPTH`] deep.
+    TooDeep(Vec<PathBuf>),
+    /// A shortcode that doesn't take a single `path` string.
+    Malformed(String),
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing { chain, path } => match chain.last() {
+                Some(page) => write!(
+                    f,
+                    "{} includes {}, which doesn't exist",
+                    page.display(),
+                    path.display()
+                ),
+                None => write!(f, "There's no page at {} to include", path.display()),
+            },
+            Self::Cycle(chain) => write!(
+                f,
+                "{} includes itself through {}",
+                chain[0].display(),
+                display_chain(chain)
+            ),
+            Self::TooDeep(chain) => write!(
+                f,
+                "Pages are included more than {} deep through {}",
+                MAX_INCLUDE_DEPTH,
+                display_chain(chain)
+            ),
+            Self::Malformed(shortcode) => write!(
+                f,
+                "Unable to read the shortcode {{{{ {} }}}}, it takes a single path, such as \
+                 include_page(path=\"snippets/note.md\")",
+                shortcode
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IncludeError {}
+
+fn display_chain(chain: &[PathBuf]) -> String {
+    chain
+        .iter()
+        .map(|page| page.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" → ")
+}
+
+/// Keeps only the normal components of `path`, so `/blog/a.md` is `blog/a.md`.
+fn normalize(path: &str) -> PathBuf {
+    Path::new(path)
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect()
+}
+
+fn include(
+    pages: &HashMap<PathBuf, IncludedPage>,
+    path: &Path,
+    chain: &mut Vec<PathBuf>,
+) -> Result<String, IncludeError> {
+    if let Some(start) = chain.iter().position(|page| page == path) {
+        let mut cycle = chain[start..].to_vec();
+        cycle.push(path.to_path_buf());
+
+        return Err(IncludeError::Cycle(cycle));
+    }
+
+    if chain.len() > MAX_INCLUDE_DEPTH {
+        let mut chain = chain.clone();
+        chain.push(path.to_path_buf());
+
+        return Err(IncludeError::TooDeep(chain));
+    }
+
+    let page = pages.get(path).ok_or_else(|| IncludeError::Missing {
+        chain: chain.clone(),
+        path: path.to_path_buf(),
+    })?;
+
+    chain.push(path.to_path_buf());
+    let content = expand(pages, &page.content, chain)?;
+    chain.pop();
+
+    Ok(content.unwrap_or_else(|| page.content.to_string()))
+}
+
+fn summary_of(
+    pages: &HashMap<PathBuf, IncludedPage>,
+    path: &Path,
+    chain: &[PathBuf],
+) -> Result<Arc<str>, IncludeError> {
+    let page = pages.get(path).ok_or_else(|| IncludeError::Missing {
+        chain: chain.to_vec(),
+        path: path.to_path_buf(),
+    })?;
+
+    Ok(page.summary.clone().unwrap_or_else(|| Arc::from("")))
+}
+
+fn expand(
+    pages: &HashMap<PathBuf, IncludedPage>,
+    content: &str,
+    chain: &mut Vec<PathBuf>,
+) -> Result<Option<String>, IncludeError> {
+    let shortcodes = find_shortcodes(content)?;
+
+    if shortcodes.is_empty() {
+        return Ok(None);
+    }
+
+    let mut expanded = String::with_capacity(content.len());
+    let mut end = 0;
+
+    for (range, shortcode) in shortcodes {
+        expanded.push_str(&content[end..range.start]);
+
+        match shortcode {
+            Shortcode::IncludePage(path) => expanded.push_str(&include(pages, &path, chain)?),
+            Shortcode::SummaryOf(path) => expanded.push_str(&summary_of(pages, &path, chain)?),
+        }
+
+        end = range.end;
+    }
+
+    expanded.push_str(&content[end..]);
+
+    Ok(Some(expanded))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Shortcode {
+    IncludePage(PathBuf),
+    SummaryOf(PathBuf),
+}
+
+impl Shortcode {
+    /// Parses the inside of `{{ }}`, which isn't a shortcode unless it calls one of
+    /// them. Markdown turns quotes into entities or typographic quotes, so any of
+    /// those are accepted around the path.
+    fn parse(inner: &str) -> Result<Option<Self>, IncludeError> {
+        let Some((name, args)) = inner.split_once('(') else {
+            return Ok(None);
+        };
+
+        let shortcode: fn(PathBuf) -> Self = match name.trim() {
+            "include_page" => Self::IncludePage,
+            "summary_of" => Self::SummaryOf,
+            _ => return Ok(None),
+        };
+
+        let mut args = args.to_string();
+        for quote in ["&quot;", "&#34;", "&#39;", "“", "”", "‘", "’", "'"] {
+            args = args.replace(quote, "\"");
+        }
+
+        args.trim()
+            .strip_suffix(')')
+            .and_then(|args| args.trim().strip_prefix("path"))
+            .and_then(|args| args.trim_start().strip_prefix('='))
+            .and_then(|args| args.trim().strip_prefix('"'))
+            .and_then(|args| args.strip_suffix('"'))
+            .filter(|path| !path.is_empty() && !path.contains('"'))
+            .map(|path| Some(shortcode(normalize(path))))
+            .ok_or_else(|| IncludeError::Malformed(inner.to_string()))
+    }
+}
+
+/// Finds every shortcode in `content`, outside of code. One alone in a paragraph takes
+/// the paragraph with it, so included blocks aren't left inside a `<p>`.
+fn find_shortcodes(content: &str) -> Result<Vec<(Range<usize>, Shortcode)>, IncludeError> {
+    let mut shortcodes = Vec::new();
+    let mut from = 0;
+
+    while let Some(start) = content[from..].find("{{").map(|start| from + start) {
+        let Some(end) = content[start..].find("}}").map(|end| start + end + 2) else {
+            break;
+        };
+
+        let shortcode = match in_code(&content[..start]) {
+            true => None,
+            false => Shortcode::parse(content[start + 2..end - 2].trim())?,
+        };
+
+        let Some(shortcode) = shortcode else {
+            from = start + 2;
+            continue;
+        };
+
+        let paragraph = content[..start].strip_suffix("<p>").zip(
+            content[end..]
+                .strip_prefix("</p>")
+                .map(|after| content.len() - after.len()),
+        );
+        let range = match paragraph {
+            Some((before, after)) => before.len()..after,
+            None => start..end,
+        };
+
+        shortcodes.push((range, shortcode));
+        from = end;
+    }
+
+    Ok(shortcodes)
+}
+
+/// Whether the end of `before` is within a `<code>` element.
+fn in_code(before: &str) -> bool {
+    before
+        .rfind("<code")
+        .is_some_and(|open| before.rfind("</code>").map_or(true, |close| close < open))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot_of(pages: &[(&str, &str, Option<&str>)]) -> PageSnapshot {
+        let mut snapshot = PageSnapshot::default();
+
+        snapshot.replace(
+            pages
+                .iter()
+                .map(|(path, content, summary)| {
+                    (
+                        PathBuf::from(path),
+                        IncludedPage {
+                            content: Arc::from(*content),
+                            summary: summary.map(Arc::from),
+                        },
+                    )
+                })
+                .collect(),
+        );
+
+        snapshot
+    }
+
+    #[test]
+    fn shortcodes_are_expanded_outside_of_code() {
+        let snapshot = snapshot_of(&[
+            ("snippets/note.md", "<aside>Note</aside>", None),
+            ("blog/other.md", "<p>Other</p>", Some("<p>Short</p>")),
+        ]);
+
+        let content = "<p>{{ include_page(path=&quot;snippets/note.md&quot;) }}</p>\n\
+                       <p>See {{summary_of(path=“/blog/other.md”)}} and {{ title }}</p>\n\
+                       <pre><code>{{ include_page(path=&quot;snippets/note.md&quot;) }}</code></pre>";
+
+        assert_eq!(
+            snapshot
+                .expand(Path::new("blog/post.md"), content)
+                .unwrap()
+                .unwrap(),
+            "<aside>Note</aside>\n\
+             <p>See <p>Short</p> and {{ title }}</p>\n\
+             <pre><code>{{ include_page(path=&quot;snippets/note.md&quot;) }}</code></pre>"
+        );
+        assert_eq!(
+            snapshot.expand(Path::new("blog/post.md"), "<p>{{ title }}</p>"),
+            Ok(None)
+        );
+        assert_eq!(
+            snapshot
+                .expand(
+                    Path::new("blog/post.md"),
+                    "{{ include_page(file=\"a.md\") }}"
+                )
+                .unwrap_err(),
+            IncludeError::Malformed(String::from("include_page(file=\"a.md\")"))
+        );
+    }
+
+    #[test]
+    fn missing_and_cyclic_includes_are_errors() {
+        let snapshot = snapshot_of(&[
+            ("a.md", "{{ include_page(path=\"b.md\") }}", None),
+            ("b.md", "{{ include_page(path=\"a.md\") }}", None),
+            ("c.md", "{{ include_page(path=\"missing.md\") }}", None),
+        ]);
+
+        assert_eq!(
+            snapshot
+                .expand(Path::new("post.md"), "{{ include_page(path=\"c.md\") }}")
+                .unwrap_err()
+                .to_string(),
+            format!(
+                "{} includes {}, which doesn't exist",
+                Path::new("c.md").display(),
+                Path::new("missing.md").display()
+            )
+        );
+        assert_eq!(
+            snapshot.include_page("a.md").unwrap_err(),
+            IncludeError::Cycle(vec!["a.md".into(), "b.md".into(), "a.md".into()])
+        );
+        assert_eq!(
+            snapshot.summary_of("missing.md").unwrap_err().to_string(),
+            "There's no page at missing.md to include"
+        );
+
+        let chain: Vec<_> = (0..=MAX_INCLUDE_DEPTH + 1)
+            .map(|depth| {
+                (
+                    format!("{}.md", depth),
+                    format!("{{{{ include_page(path=\"{}.md\") }}}}", depth + 1),
+                )
+            })
+            .collect();
+        let deep = snapshot_of(
+            &chain
+                .iter()
+                .map(|(path, content)| (path.as_str(), content.as_str(), None))
+                .collect::<Vec<_>>(),
+        );
+
+        assert!(matches!(
+            deep.include_page("0.md"),
+            Err(IncludeError::TooDeep(chain)) if chain.len() == MAX_INCLUDE_DEPTH + 2
+        ));
+    }
+}
@@ -0,0 +1,1016 @@
+//! Writing outputs into the output directory. Every file a build produces goes
+//! through here, and it's the supported way for processors of other crates to emit
+//! files of their own:
+//!
+//! - Systems producing a batch of outputs pipe them into [`write_to_disk`], or
+//!   [`write_bytes_to_disk`] for binary files, which write them once the schedule
+//!   finishes and record them in the [`Manifest`].
+//! - Async code writing a file at a time, such as within a
+//!   [`DeferredTask`], uses [`write_output`] with the [`WriteOptions`] of the build.
+//!
+//! Either way, outputs are reserved in the [`OutputRegistry`](crate::output::OutputRegistry)
+//! first and joined safely onto the output directory. Files are only replaced when
+//! their content changes, and are written to a temporary file renamed over the output,
+//! so nothing ever sees half a file. The number of files written at once is capped by
+//! `max_concurrent_writes` under `[build]`.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    ffi::OsString,
+    future::Future,
+    hash::{DefaultHasher, Hasher},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use bevy_ecs::{
+    query::With,
+    system::{CommandQueue, In, Query, Res},
+    world::World,
+};
+use bevy_tasks::{ComputeTaskPool, IoTaskPool, Task};
+use log::{error, info, trace};
+use smol::{
+    fs::{metadata, remove_file, rename, DirBuilder, File},
+    io::{AsyncReadExt, AsyncWriteExt, BufWriter},
+    lock::Semaphore,
+    Timer,
+};
+
+use crate::{
+    cancel::CancellationToken,
+    compress::{is_compressible, CompressOptions},
+    deferred::DeferredTask,
+    diff::{compare_output, DiffOptions, OutputChange},
+    errors::ProcessorError,
+    logging::WRITE,
+    manifest::{is_known_output, Manifest, OutputDigest, BACKUP_DIR},
+    output::{safe_join, OutputPath},
+    processor::{FileConfig, OnConflict, OutputDir, SiteConfig},
+    report::{BuildErrors, BuildReport},
+};
+
+const HASH_CHUNK_SIZE: usize = 8 * 1024;
+const WRITE_RETRIES: u32 = 3;
+
+/// OS error codes for running out of file handles, which clear up once other writes finish.
+#[cfg(unix)]
+const TOO_MANY_OPEN_FILES: &[i32] = &[23, 24];
+#[cfg(windows)]
+const TOO_MANY_OPEN_FILES: &[i32] = &[4];
+#[cfg(not(any(unix, windows)))]
+const TOO_MANY_OPEN_FILES: &[i32] = &[];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteOutcome {
+    Written,
+    Unchanged,
+    /// Left alone, as the build was cancelled or only compares its outputs.
+    Skipped,
+}
+
+/// Writes `content` to a temporary file beside `file` and renames it over `file`, so
+/// the output is either entirely old or entirely new. The temporary file is removed
+/// when either step fails.
+pub async fn write_file_to_disk(file: &Path, content: &[u8]) -> std::io::Result<()> {
+    let temporary = temporary_path(file);
+
+    let result = async {
+        let mut writer = BufWriter::new(File::create(&temporary).await?);
+
+        writer.write_all(content).await?;
+
+        writer.flush().await?;
+
+        rename(&temporary, file).await
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = remove_file(&temporary).await;
+    }
+
+    result
+}
+
+/// A hidden file beside `file`, such as `.index.html.tmp` for `index.html`.
+fn temporary_path(file: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(file.file_name().unwrap_or_default());
+    name.push(".tmp");
+
+    file.with_file_name(name)
+}
+
+fn hash_chunks<'a>(chunks: impl Iterator<Item = &'a [u8]>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    chunks.for_each(|chunk| hasher.write(chunk));
+
+    hasher.finish()
+}
+
+/// The size and hash of `content`, hashed the same way as files on disk are.
+pub fn digest(content: &[u8]) -> OutputDigest {
+    OutputDigest {
+        size: content.len() as u64,
+        hash: hash_chunks(content.chunks(HASH_CHUNK_SIZE)),
+    }
+}
+
+async fn hash_file(file: &Path) -> std::io::Result<u64> {
+    let mut file = File::open(file).await?;
+    let mut buffer = vec![0; HASH_CHUNK_SIZE];
+    let mut hasher = DefaultHasher::new();
+
+    loop {
+        let read = file.read(&mut buffer).await?;
+
+        if read == 0 {
+            break;
+        }
+
+        hasher.write(&buffer[..read]);
+    }
+
+    Ok(hasher.finish())
+}
+
+/// Checks whether the file on disk already holds `content`. The size is compared
+/// first, and only files of matching size are streamed through the hasher.
+pub async fn is_unchanged(file: &Path, content: &[u8]) -> std::io::Result<bool> {
+    match metadata(file).await {
+        Ok(existing) if existing.is_file() && existing.len() == content.len() as u64 => {
+            Ok(hash_file(file).await? == hash_chunks(content.chunks(HASH_CHUNK_SIZE)))
+        }
+        Ok(_) => Ok(false),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes `content` unless the file already holds it, or `force` is set.
+pub async fn write_if_changed(
+    file: &Path,
+    content: &[u8],
+    force: bool,
+) -> std::io::Result<WriteOutcome> {
+    if !force && is_unchanged(file, content).await? {
+        trace!("Unchanged {}", file.display());
+        return Ok(WriteOutcome::Unchanged);
+    }
+
+    trace!("Writing {}", file.display());
+    write_file_to_disk(file, content).await?;
+
+    Ok(WriteOutcome::Written)
+}
+
+fn is_transient(error: &std::io::Error) -> bool {
+    error.kind() == std::io::ErrorKind::Interrupted
+        || error
+            .raw_os_error()
+            .is_some_and(|code| TOO_MANY_OPEN_FILES.contains(&code))
+}
+
+/// Same as [`write_if_changed`], but retries with a backoff when the write fails due
+/// to a transient error such as running out of file handles.
+pub async fn write_with_retry(
+    file: &Path,
+    content: &[u8],
+    force: bool,
+) -> std::io::Result<WriteOutcome> {
+    let mut attempt = 0;
+
+    loop {
+        match write_if_changed(file, content, force).await {
+            Err(e) if attempt < WRITE_RETRIES && is_transient(&e) => {
+                attempt += 1;
+                trace!(
+                    "Retrying write of {} after error: {} (attempt {})",
+                    file.display(),
+                    e,
+                    attempt
+                );
+                Timer::after(Duration::from_millis(10 << attempt)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Caps how many futures run their work at the same time.
+#[derive(Debug, Clone)]
+pub struct WriteLimiter(Arc<Semaphore>);
+
+impl WriteLimiter {
+    pub fn new(limit: usize) -> Self {
+        Self(Arc::new(Semaphore::new(limit.max(1))))
+    }
+
+    pub async fn run<T>(&self, task: impl Future<Output = T>) -> T {
+        let _permit = self.0.acquire().await;
+
+        task.await
+    }
+}
+
+/// Creates the directory and any missing parents, treating one that already exists as
+/// a success.
+pub async fn create_directory(directory: &Path) -> std::io::Result<()> {
+    trace!("Creating directory: {}", directory.display());
+
+    match DirBuilder::new().recursive(true).create(directory).await {
+        Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Writes a single output, reserved in the
+/// [`OutputRegistry`](crate::output::OutputRegistry), into the output directory of
+/// `options`. It's written as each output of [`write_pages`] is, sharing its limit on
+/// concurrent writes, though without compressed siblings or checks for files a previous
+/// build didn't write. Unlike [`write_to_disk`], nothing is recorded in the
+/// [`Manifest`], which is up to the caller using the [`digest`] of `content`.
+pub async fn write_output(
+    options: &WriteOptions,
+    output: &OutputPath,
+    content: &[u8],
+) -> std::io::Result<WriteOutcome> {
+    let file = safe_join(&options.output_dir, output)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    if options.cancel.is_cancelled() || options.diff.is_some() {
+        trace!("Skipping write of {}", file.display());
+        return Ok(WriteOutcome::Skipped);
+    }
+
+    if let Some(directory) = file.parent() {
+        create_directory(directory).await?;
+    }
+
+    options
+        .limiter
+        .run(write_with_retry(&file, content, options.force))
+        .await
+}
+
+/// Options shared by every write of a batch of output files.
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    pub output_dir: PathBuf,
+    pub force: bool,
+    pub on_conflict: OnConflict,
+    pub previous: Option<Arc<HashSet<PathBuf>>>,
+    pub limiter: WriteLimiter,
+    /// Writes not yet started once this is cancelled are skipped.
+    pub cancel: CancellationToken,
+    pub compress: CompressOptions,
+    /// Compares outputs against the output directory instead of writing them.
+    pub diff: Option<DiffOptions>,
+}
+
+impl WriteOptions {
+    /// Options following the site's `[build]` settings.
+    pub fn from_config(
+        config: &SiteConfig,
+        output_dir: &Path,
+        manifest: &Manifest,
+        cancel: &CancellationToken,
+    ) -> Self {
+        Self {
+            output_dir: output_dir.to_path_buf(),
+            force: config.build.force_write,
+            on_conflict: config.build.on_conflict,
+            previous: manifest.previous(),
+            limiter: WriteLimiter::new(config.build.max_concurrent_writes),
+            cancel: cancel.clone(),
+            compress: CompressOptions {
+                codecs: config.build.compress.clone(),
+                min_size: config.build.compress_min_size,
+                level: config.build.compress_level,
+            },
+            diff: DiffOptions::from_config(&config.build),
+        }
+    }
+}
+
+/// Outcome of writing a batch of output files.
+#[derive(Debug, Default)]
+pub struct WriteSummary {
+    pub report: BuildReport,
+    /// Outputs now on disk, relative to the output directory, with the digest of
+    /// their content.
+    pub outputs: Vec<(PathBuf, OutputDigest)>,
+    /// Outputs that would change, when comparing rather than writing them.
+    pub changes: Vec<OutputChange>,
+    pub errors: Vec<ProcessorError>,
+}
+
+/// Finds existing files among the outputs that the previous build didn't produce.
+async fn find_conflicts(pages: &[(PathBuf, Vec<u8>)], options: &WriteOptions) -> Vec<PathBuf> {
+    let mut conflicts = Vec::new();
+
+    for (output_path, _) in pages {
+        let relative = output_path
+            .strip_prefix(&options.output_dir)
+            .unwrap_or(output_path);
+
+        if !is_known_output(options.previous.as_deref(), relative)
+            && metadata(output_path).await.is_ok()
+        {
+            conflicts.push(output_path.clone());
+        }
+    }
+
+    conflicts
+}
+
+async fn backup_file(file: &Path, output_dir: &Path) -> std::io::Result<()> {
+    let backup = output_dir
+        .join(BACKUP_DIR)
+        .join(file.strip_prefix(output_dir).unwrap_or(file));
+
+    if let Some(directory) = backup.parent() {
+        create_directory(directory).await?;
+    }
+
+    info!(target: WRITE, "Backing up {} to {}", file.display(), backup.display());
+    rename(file, backup).await
+}
+
+/// Compresses every eligible output on the compute pool, returning the outputs along
+/// with their compressed siblings and the number of bytes the siblings saved.
+async fn add_compressed_siblings(
+    pages: Vec<(PathBuf, Vec<u8>)>,
+    options: &CompressOptions,
+) -> (Vec<(PathBuf, Vec<u8>)>, u64) {
+    if options.codecs.is_empty() {
+        return (pages, 0);
+    }
+
+    let tasks: Vec<Task<_>> = pages
+        .into_iter()
+        .map(|(output_path, content)| {
+            let options = options.clone();
+
+            ComputeTaskPool::get().spawn(async move {
+                let mut siblings = Vec::new();
+
+                if is_compressible(&output_path, content.len(), options.min_size) {
+                    for codec in options.codecs {
+                        match codec.compress(&content, options.level) {
+                            // Compressing tiny or already dense files can make them bigger
+                            Ok(compressed) if compressed.len() < content.len() => {
+                                let mut path = output_path.clone().into_os_string();
+                                path.push(".");
+                                path.push(codec.extension());
+
+                                siblings.push((PathBuf::from(path), compressed));
+                            }
+                            Ok(_) => {}
+                            Err(e) => error!(
+                                "Unable to compress {} with {}: {}",
+                                output_path.display(),
+                                codec,
+                                e
+                            ),
+                        }
+                    }
+                }
+
+                (output_path, content, siblings)
+            })
+        })
+        .collect();
+
+    let mut outputs = Vec::new();
+    let mut saved = 0;
+
+    for task in tasks {
+        let (output_path, content, siblings) = task.await;
+
+        saved += siblings
+            .iter()
+            .map(|(_, compressed)| (content.len() - compressed.len()) as u64)
+            .sum::<u64>();
+
+        outputs.push((output_path, content));
+        outputs.extend(siblings);
+    }
+
+    (outputs, saved)
+}
+
+/// Writes each `(output_path, content)` pair to disk on the IO pool, along with any
+/// pre-compressed siblings, returning the tally of written and unchanged files along
+/// with any errors encountered.
+pub async fn write_pages(
+    pages: Vec<(PathBuf, impl Into<Vec<u8>>)>,
+    options: WriteOptions,
+) -> WriteSummary {
+    let mut summary = WriteSummary::default();
+
+    let pages = pages
+        .into_iter()
+        .map(|(output_path, content)| (output_path, content.into()))
+        .collect();
+
+    let (pages, saved) = add_compressed_siblings(pages, &options.compress).await;
+
+    summary.report.bytes_saved = saved;
+
+    if let Some(diff) = options.diff {
+        compare_pages(pages, &options, diff, &mut summary).await;
+
+        return summary;
+    }
+
+    if options.on_conflict != OnConflict::Overwrite {
+        let conflicts = find_conflicts(&pages, &options).await;
+
+        if !conflicts.is_empty() {
+            if options.on_conflict == OnConflict::Error {
+                let error = ProcessorError::OutputConflict { paths: conflicts };
+
+                error!("{}", error);
+                summary.errors.push(error);
+
+                return summary;
+            }
+
+            for file in conflicts {
+                if let Err(source) = backup_file(&file, &options.output_dir).await {
+                    summary
+                        .errors
+                        .push(ProcessorError::Write { path: file, source });
+                }
+            }
+        }
+    }
+
+    // Create every needed directory once up front, so concurrent writes into the same new
+    // directory don't race each other to create it.
+    let directories: BTreeSet<&Path> = pages
+        .iter()
+        .filter_map(|(output_path, _)| output_path.parent())
+        .collect();
+
+    for directory in directories {
+        if let Err(e) = create_directory(directory).await {
+            error!("Error creating directory {}: {}", directory.display(), e);
+        }
+    }
+
+    let force = options.force;
+
+    let tasks: Vec<Task<_>> = pages
+        .into_iter()
+        .map(|(output_path, content)| {
+            trace!("Spawning write task for {}", output_path.display());
+
+            let limiter = options.limiter.clone();
+            let cancel = options.cancel.clone();
+
+            IoTaskPool::get().spawn(async move {
+                limiter
+                    .run(async {
+                        if cancel.is_cancelled() {
+                            trace!("Skipping write of {}", output_path.display());
+                            return Ok(None);
+                        }
+
+                        write_with_retry(output_path.as_path(), &content, force)
+                            .await
+                            .map(Some)
+                    })
+                    .await
+                    .map(|outcome| {
+                        outcome.map(|outcome| (output_path.clone(), outcome, digest(&content)))
+                    })
+                    .map_err(|source| ProcessorError::Write {
+                        path: output_path,
+                        source,
+                    })
+            })
+        })
+        .collect();
+
+    for task in tasks {
+        match task.await {
+            Ok(None) => {}
+            Ok(Some((output_path, outcome, digest))) => {
+                match outcome {
+                    WriteOutcome::Written => summary.report.written += 1,
+                    WriteOutcome::Unchanged => summary.report.unchanged += 1,
+                    WriteOutcome::Skipped => continue,
+                }
+
+                summary.outputs.push((
+                    output_path
+                        .strip_prefix(&options.output_dir)
+                        .map_or_else(|_| output_path.clone(), Path::to_path_buf),
+                    digest,
+                ));
+            }
+            Err(e) => {
+                error!("{}", e);
+                summary.errors.push(e);
+            }
+        }
+    }
+
+    summary
+}
+
+/// Compares each output against the file already on disk on the IO pool, recording the
+/// outputs that would change instead of writing anything.
+async fn compare_pages(
+    pages: Vec<(PathBuf, Vec<u8>)>,
+    options: &WriteOptions,
+    diff: DiffOptions,
+    summary: &mut WriteSummary,
+) {
+    let tasks: Vec<Task<_>> = pages
+        .into_iter()
+        .map(|(output_path, content)| {
+            let limiter = options.limiter.clone();
+            let relative = output_path
+                .strip_prefix(&options.output_dir)
+                .map_or_else(|_| output_path.clone(), Path::to_path_buf);
+
+            IoTaskPool::get().spawn(async move {
+                let change = limiter
+                    .run(compare_output(
+                        &output_path,
+                        relative.clone(),
+                        &content,
+                        &diff,
+                    ))
+                    .await
+                    .map_err(|source| ProcessorError::Write {
+                        path: output_path,
+                        source,
+                    })?;
+
+                Ok((relative, digest(&content), change))
+            })
+        })
+        .collect();
+
+    for task in tasks {
+        match task.await {
+            Ok((relative, digest, change)) => {
+                match change {
+                    Some(change) => summary.changes.push(change),
+                    None => summary.report.unchanged += 1,
+                }
+
+                summary.outputs.push((relative, digest));
+            }
+            Err(e) => {
+                error!("{}", e);
+                summary.errors.push(e);
+            }
+        }
+    }
+}
+
+/// Writes each `(output_path, content)` pair to disk on the IO pool. Meant to be piped
+/// into from systems producing output files, with paths reserved in the
+/// [`OutputRegistry`](crate::output::OutputRegistry).
+pub fn write_to_disk(
+    In(pages): In<Vec<(OutputPath, String)>>,
+    config: Res<SiteConfig>,
+    manifest: Res<Manifest>,
+    q_config: Query<&OutputDir, With<FileConfig>>,
+    cancel: Res<CancellationToken>,
+    deferred: Res<DeferredTask>,
+) {
+    let pages = pages
+        .into_iter()
+        .map(|(output_path, content)| (output_path, content.into_bytes()))
+        .collect();
+
+    write_bytes_to_disk(In(pages), config, manifest, q_config, cancel, deferred);
+}
+
+/// Like [`write_to_disk`], for systems producing binary files.
+pub fn write_bytes_to_disk(
+    In(pages): In<Vec<(OutputPath, Vec<u8>)>>,
+    config: Res<SiteConfig>,
+    manifest: Res<Manifest>,
+    q_config: Query<&OutputDir, With<FileConfig>>,
+    cancel: Res<CancellationToken>,
+    deferred: Res<DeferredTask>,
+) {
+    let output_dir = q_config.single().path();
+    let mut unsafe_paths = Vec::new();
+    let pages: Vec<_> = pages
+        .into_iter()
+        .filter_map(
+            |(output_path, content)| match safe_join(output_dir, &output_path) {
+                Ok(path) => Some((path, content)),
+                Err(e) => {
+                    unsafe_paths.push(ProcessorError::from(e));
+                    None
+                }
+            },
+        )
+        .collect();
+
+    let options = WriteOptions::from_config(&config, output_dir, &manifest, &cancel);
+
+    deferred
+        .scoped_task(move |scope| async move {
+            info!(target: WRITE, "Writing rendered content to disk");
+
+            let WriteSummary {
+                report,
+                outputs,
+                changes,
+                errors,
+            } = write_pages(pages, options).await;
+
+            info!(
+                target: WRITE,
+                "{} files written, {} unchanged, {} bytes saved by compression",
+                report.written, report.unchanged, report.bytes_saved
+            );
+
+            let mut queue = CommandQueue::default();
+
+            queue.push(move |world: &mut World| {
+                let mut build = world.resource_mut::<BuildReport>();
+
+                build.written += report.written;
+                build.unchanged += report.unchanged;
+                build.bytes_saved += report.bytes_saved;
+                build.changes.extend(changes);
+
+                world.resource_mut::<Manifest>().record_digests(outputs);
+                world
+                    .resource_mut::<BuildErrors>()
+                    .0
+                    .extend(unsafe_paths.into_iter().chain(errors));
+            });
+
+            scope.send(queue);
+        })
+        .detach();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{
+        diff::ChangeKind,
+        output::{OutputClaim, OutputRegistry},
+    };
+
+    fn options(dir: &Path, on_conflict: OnConflict) -> WriteOptions {
+        WriteOptions {
+            output_dir: dir.to_path_buf(),
+            force: false,
+            on_conflict,
+            previous: None,
+            limiter: WriteLimiter::new(16),
+            cancel: CancellationToken::default(),
+            compress: CompressOptions::default(),
+            diff: None,
+        }
+    }
+
+    #[test]
+    fn foreign_files_are_protected_from_being_overwritten() {
+        IoTaskPool::get_or_init(Default::default);
+
+        let dir = std::env::temp_dir().join("webvy_foreign_files_are_protected");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("notes")).unwrap();
+        std::fs::write(dir.join("notes/index.html"), "hand written").unwrap();
+
+        let pages = || vec![(dir.join("notes/index.html"), String::from("generated"))];
+
+        let summary = smol::block_on(write_pages(pages(), options(&dir, OnConflict::Error)));
+
+        assert!(matches!(
+            summary.errors.as_slice(),
+            [ProcessorError::OutputConflict { paths }] if paths == &[dir.join("notes/index.html")]
+        ));
+        assert_eq!(
+            std::fs::read_to_string(dir.join("notes/index.html")).unwrap(),
+            "hand written"
+        );
+
+        let summary = smol::block_on(write_pages(pages(), options(&dir, OnConflict::Backup)));
+
+        assert!(summary.errors.is_empty(), "{:?}", summary.errors);
+        assert_eq!(
+            summary.outputs,
+            [(PathBuf::from("notes/index.html"), digest(b"generated"))]
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join(BACKUP_DIR).join("notes/index.html")).unwrap(),
+            "hand written"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("notes/index.html")).unwrap(),
+            "generated"
+        );
+
+        // Files listed in the previous manifest were produced by webvy, so aren't conflicts
+        let mut known = options(&dir, OnConflict::Error);
+        known.previous = Some(Arc::new(HashSet::from([PathBuf::from("notes/index.html")])));
+
+        let summary = smol::block_on(write_pages(pages(), known));
+
+        assert!(summary.errors.is_empty(), "{:?}", summary.errors);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn diff_builds_compare_without_writing() {
+        IoTaskPool::get_or_init(Default::default);
+
+        let dir = std::env::temp_dir().join("webvy_diff_builds_compare_without_writing");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("index.html"), "<h1>Old</h1>\n").unwrap();
+        std::fs::write(dir.join("about.html"), "About").unwrap();
+
+        let mut options = options(&dir, OnConflict::Error);
+        options.diff = Some(DiffOptions {
+            unified: true,
+            max_size: 1024,
+            max_lines: 100,
+        });
+
+        let pages = vec![
+            (dir.join("index.html"), String::from("<h1>New</h1>\n")),
+            (dir.join("about.html"), String::from("About")),
+            (dir.join("notes/first.html"), String::from("First")),
+        ];
+
+        let summary = smol::block_on(write_pages(pages, options));
+
+        assert!(summary.errors.is_empty(), "{:?}", summary.errors);
+        assert_eq!(summary.report.written, 0);
+        assert_eq!(summary.report.unchanged, 1);
+        assert_eq!(summary.outputs.len(), 3);
+
+        let mut changes: Vec<_> = summary
+            .changes
+            .iter()
+            .map(|change| (change.path.as_path(), change.kind, change.diff.as_deref()))
+            .collect();
+        changes.sort();
+
+        assert_eq!(
+            changes,
+            [
+                (
+                    Path::new("index.html"),
+                    ChangeKind::Modified,
+                    Some(
+                        "--- a/index.html\n+++ b/index.html\n\
+                         @@ -1,1 +1,1 @@\n-<h1>Old</h1>\n+<h1>New</h1>\n"
+                    )
+                ),
+                (Path::new("notes/first.html"), ChangeKind::Added, None),
+            ]
+        );
+
+        // Nothing was written, not even the directories of new outputs.
+        assert_eq!(
+            std::fs::read_to_string(dir.join("index.html")).unwrap(),
+            "<h1>Old</h1>\n"
+        );
+        assert!(!dir.join("notes").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn write_limiter_caps_concurrent_tasks() {
+        let limiter = WriteLimiter::new(3);
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let executor = smol::Executor::new();
+
+        let tasks: Vec<_> = (0..20)
+            .map(|_| {
+                let limiter = limiter.clone();
+                let in_flight = in_flight.clone();
+                let peak = peak.clone();
+
+                executor.spawn(async move {
+                    limiter
+                        .run(async {
+                            let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                            peak.fetch_max(current, Ordering::SeqCst);
+                            Timer::after(Duration::from_millis(5)).await;
+                            in_flight.fetch_sub(1, Ordering::SeqCst);
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        smol::block_on(executor.run(async {
+            for task in tasks {
+                task.await;
+            }
+        }));
+
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn concurrent_writes_into_a_fresh_nested_directory() {
+        IoTaskPool::get_or_init(Default::default);
+
+        let dir = std::env::temp_dir().join("webvy_concurrent_writes_into_a_fresh_directory");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let pages: Vec<_> = (0..500)
+            .map(|index| {
+                let section = dir.join(format!("section-{}", index % 5)).join("nested");
+
+                (
+                    section.join(format!("page-{}.html", index)),
+                    index.to_string(),
+                )
+            })
+            .collect();
+
+        let summary = smol::block_on(write_pages(pages, options(&dir, OnConflict::Overwrite)));
+
+        assert!(summary.errors.is_empty(), "{:?}", summary.errors);
+        assert_eq!(summary.report.written, 500);
+
+        smol::block_on(async {
+            // Creating a directory that already exists is not an error
+            create_directory(&dir.join("section-0")).await.unwrap();
+        });
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(all(feature = "gzip", feature = "brotli"))]
+    #[test]
+    fn compressed_siblings_are_written_and_skipped_when_unchanged() {
+        use crate::compress::Codec;
+
+        IoTaskPool::get_or_init(Default::default);
+        ComputeTaskPool::get_or_init(Default::default);
+
+        let dir = std::env::temp_dir().join("webvy_compressed_siblings_are_written");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let pages = || {
+            vec![
+                (dir.join("index.html"), "<p>Hello</p>".repeat(200)),
+                (dir.join("small.html"), String::from("<p>Hi</p>")),
+                (dir.join("data.bin"), "0".repeat(4096)),
+            ]
+        };
+
+        let mut options = options(&dir, OnConflict::Overwrite);
+        options.compress = CompressOptions {
+            codecs: vec![Codec::Gzip, Codec::Brotli],
+            min_size: 1024,
+            level: None,
+        };
+
+        let summary = smol::block_on(write_pages(pages(), options.clone()));
+
+        assert!(summary.errors.is_empty(), "{:?}", summary.errors);
+        assert_eq!(summary.report.written, 5);
+        assert!(summary.report.bytes_saved > 0);
+
+        let mut outputs: Vec<_> = summary.outputs.into_iter().map(|(path, _)| path).collect();
+        outputs.sort();
+
+        assert_eq!(
+            outputs,
+            [
+                "data.bin",
+                "index.html",
+                "index.html.br",
+                "index.html.gz",
+                "small.html"
+            ]
+            .map(PathBuf::from)
+        );
+
+        let summary = smol::block_on(write_pages(pages(), options));
+
+        assert_eq!(summary.report.written, 0);
+        assert_eq!(summary.report.unchanged, 5);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn transient_errors_are_detected() {
+        assert!(is_transient(&std::io::Error::from(
+            std::io::ErrorKind::Interrupted
+        )));
+        assert!(!is_transient(&std::io::Error::from(
+            std::io::ErrorKind::NotFound
+        )));
+
+        for &code in TOO_MANY_OPEN_FILES {
+            assert!(is_transient(&std::io::Error::from_raw_os_error(code)));
+        }
+    }
+
+    #[test]
+    fn identical_content_is_not_rewritten() {
+        let dir = std::env::temp_dir().join("webvy_identical_content_is_not_rewritten");
+        let file = dir.join("page.html");
+        let content = "<p>Hello</p>".repeat(HASH_CHUNK_SIZE);
+
+        smol::block_on(async {
+            create_directory(&dir).await.unwrap();
+
+            assert_eq!(
+                write_if_changed(&file, content.as_bytes(), false)
+                    .await
+                    .unwrap(),
+                WriteOutcome::Written
+            );
+            assert_eq!(
+                write_if_changed(&file, content.as_bytes(), false)
+                    .await
+                    .unwrap(),
+                WriteOutcome::Unchanged
+            );
+            assert_eq!(
+                write_if_changed(&file, content.as_bytes(), true)
+                    .await
+                    .unwrap(),
+                WriteOutcome::Written
+            );
+
+            let changed = content.replace("Hello", "Howdy");
+
+            assert_eq!(
+                write_if_changed(&file, changed.as_bytes(), false)
+                    .await
+                    .unwrap(),
+                WriteOutcome::Written
+            );
+            assert_eq!(smol::fs::read_to_string(&file).await.unwrap(), changed);
+        });
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn single_outputs_are_written_whole_into_the_output_directory() {
+        let dir = std::env::temp_dir().join("webvy_single_outputs_are_written_whole");
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut registry = OutputRegistry::default();
+        let output = registry
+            .reserve("search/index.json", OutputClaim::new("search index", None))
+            .unwrap();
+        let options = options(&dir, OnConflict::Overwrite);
+
+        smol::block_on(async {
+            assert_eq!(
+                write_output(&options, &output, b"[]").await.unwrap(),
+                WriteOutcome::Written
+            );
+            assert_eq!(
+                write_output(&options, &output, b"[]").await.unwrap(),
+                WriteOutcome::Unchanged
+            );
+
+            options.cancel.cancel();
+
+            assert_eq!(
+                write_output(&options, &output, b"[1]").await.unwrap(),
+                WriteOutcome::Skipped
+            );
+        });
+
+        let files: Vec<_> = std::fs::read_dir(dir.join("search"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+
+        assert_eq!(files, ["index.json"]);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("search/index.json")).unwrap(),
+            "[]"
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,8 +1,39 @@
 pub mod app;
+pub mod assets;
+pub mod build_info;
+pub mod cancel;
+pub mod compress;
+pub mod config;
+pub mod context;
 pub mod deferred;
+pub mod diff;
 pub mod errors;
+pub mod escape;
 pub mod file;
 pub mod files;
 pub mod front_matter;
+pub mod html;
+pub mod include;
+pub mod io;
+pub mod logging;
+pub mod manifest;
+pub mod menu;
+pub mod output;
+pub mod panic;
+pub mod prelude;
 pub mod processor;
+pub mod report;
+pub mod sanitize;
+#[cfg(all(feature = "config", feature = "markdown", feature = "tera"))]
+pub mod site;
+pub mod slug;
+pub mod svg;
+pub mod taxonomy;
+pub mod timezone;
 pub mod traits;
+pub mod typography;
+pub mod validate;
+pub mod value;
+
+#[cfg(all(feature = "config", feature = "markdown", feature = "tera"))]
+pub use site::{build, SiteOptions};
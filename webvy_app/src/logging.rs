@@ -0,0 +1,162 @@
+//! Sets up logging for builds. Progress through each stage of a build is logged under
+//! the targets below, so `webvy::render=info` follows rendering without knowing which
+//! processor does it. Everything else, such as traces of individual files, is logged
+//! under its module path, like `webvy_app::processor::tera`.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{OnceLock, RwLock},
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Reading configuration, content, templates and data from disk.
+pub const LOAD: &str = "webvy::load";
+/// Rendering pages, feeds, images and other outputs.
+pub const RENDER: &str = "webvy::render";
+/// Writing outputs to disk, along with removing stale ones.
+pub const WRITE: &str = "webvy::write";
+/// Running schedules and waiting on deferred tasks between them.
+pub const EXECUTOR: &str = "webvy::executor";
+
+/// A comma separated list of filter directives, each a target or module path, a level,
+/// or both as `target=level`, such as `warn,webvy::render=debug`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilter(String);
+
+impl FromStr for LogFilter {
+    type Err = LogFilterError;
+
+    fn from_str(filter: &str) -> Result<Self, Self::Err> {
+        let mut directives = Vec::new();
+
+        for directive in filter.split(',').map(str::trim) {
+            match directive.split_once('=') {
+                Some((target, level))
+                    if !target.trim().is_empty() && level.trim().parse::<LevelFilter>().is_ok() =>
+                {
+                    directives.push(format!("{}={}", target.trim(), level.trim()))
+                }
+                Some(_) => return Err(LogFilterError(directive.to_string())),
+                None if directive.is_empty() => {}
+                None => directives.push(directive.to_string()),
+            }
+        }
+
+        Ok(Self(directives.join(",")))
+    }
+}
+
+impl fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A filter directive that doesn't name a target or has an unknown level.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid log filter directive `{0}`, expected a target, a level or `target=level`")]
+pub struct LogFilterError(String);
+
+/// The logger installed by [`configure`], whose filters can be swapped at any time.
+struct WebvyLogger(RwLock<Option<env_logger::Logger>>);
+
+impl Log for WebvyLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.0
+            .read()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|logger| logger.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if let Some(logger) = self.0.read().unwrap().as_ref() {
+            logger.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(logger) = self.0.read().unwrap().as_ref() {
+            logger.flush();
+        }
+    }
+}
+
+static LOGGER: WebvyLogger = WebvyLogger(RwLock::new(None));
+
+/// Whether [`LOGGER`] is the global logger, decided the first time logging is
+/// configured.
+static INSTALLED: OnceLock<bool> = OnceLock::new();
+
+/// Logs everything at `level` or above, along with whatever `filter` enables, writing
+/// to stderr. The first call installs the logger, later ones replace its filters.
+/// When another logger was installed first it's left alone, and `false` is returned.
+pub fn configure(level: LevelFilter, filter: Option<&LogFilter>) -> bool {
+    let installed = *INSTALLED.get_or_init(|| log::set_logger(&LOGGER).is_ok());
+
+    if !installed {
+        log::debug!("Another logger is already installed, leaving its filters as they are");
+        return false;
+    }
+
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(level);
+
+    if let Some(filter) = filter {
+        builder.parse_filters(&filter.0);
+    }
+
+    let logger = builder.build();
+
+    log::set_max_level(logger.filter());
+    *LOGGER.0.write().unwrap() = Some(logger);
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_are_checked_for_unknown_levels() {
+        for filter in [
+            "webvy_app::processor::tera=trace",
+            "warn,webvy::render=debug, webvy::executor = off",
+            "info",
+            "webvy::load",
+            "",
+        ] {
+            assert!(filter.parse::<LogFilter>().is_ok(), "{filter}");
+        }
+
+        assert_eq!(
+            "warn, webvy::render = debug,"
+                .parse::<LogFilter>()
+                .unwrap()
+                .to_string(),
+            "warn,webvy::render=debug"
+        );
+
+        assert_eq!(
+            "warn,webvy::render=loud".parse::<LogFilter>(),
+            Err(LogFilterError(String::from("webvy::render=loud")))
+        );
+        assert_eq!(
+            "=debug".parse::<LogFilter>(),
+            Err(LogFilterError(String::from("=debug")))
+        );
+    }
+
+    #[test]
+    fn configuring_over_another_logger_leaves_it_alone() {
+        // Stands in for an application that set up its own logging first.
+        let _ = env_logger::Builder::new().is_test(true).try_init();
+        let filter = "webvy::render=trace".parse().unwrap();
+
+        assert!(!configure(LevelFilter::Trace, Some(&filter)));
+        assert!(!configure(LevelFilter::Off, None));
+    }
+}
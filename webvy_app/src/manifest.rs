@@ -0,0 +1,229 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bevy_ecs::{
+    query::With,
+    system::{CommandQueue, Query, Res, ResMut, Resource},
+    world::World,
+};
+use log::{error, info};
+
+use crate::{
+    cancel::CancellationToken,
+    config::{DiffMode, FileConfig, OutputDir, SiteConfig},
+    deferred::DeferredTask,
+    diff::{ChangeKind, OutputChange},
+    io::{create_directory, write_file_to_disk},
+    logging::WRITE,
+    output::safe_join,
+    report::BuildReport,
+};
+
+/// File within the output directory listing every output the last build produced.
+pub const MANIFEST_FILE: &str = ".webvy-manifest";
+
+/// Folder within the output directory where foreign files are moved to when backing
+/// them up.
+pub const BACKUP_DIR: &str = ".webvy-backup";
+
+/// The size and content hash of an output written by this build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputDigest {
+    pub size: u64,
+    pub hash: u64,
+}
+
+/// Output files produced by the previous build and the current one, relative to the
+/// output directory.
+#[derive(Debug, Default, Resource)]
+pub struct Manifest {
+    previous: Option<Arc<HashSet<PathBuf>>>,
+    outputs: BTreeSet<PathBuf>,
+    digests: HashMap<PathBuf, OutputDigest>,
+}
+
+impl Manifest {
+    /// The outputs of the previous build, or `None` if it left no manifest behind.
+    pub fn previous(&self) -> Option<Arc<HashSet<PathBuf>>> {
+        self.previous.clone()
+    }
+
+    #[cfg(feature = "config")]
+    pub(crate) fn set_previous(&mut self, previous: HashSet<PathBuf>) {
+        self.previous = Some(Arc::new(previous));
+    }
+
+    pub fn record(&mut self, outputs: impl IntoIterator<Item = PathBuf>) {
+        self.outputs.extend(outputs);
+    }
+
+    /// Records outputs along with the digest of the content written to them.
+    pub fn record_digests(&mut self, outputs: impl IntoIterator<Item = (PathBuf, OutputDigest)>) {
+        for (output, digest) in outputs {
+            self.digests.insert(output.clone(), digest);
+            self.outputs.insert(output);
+        }
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item = &Path> {
+        self.outputs.iter().map(PathBuf::as_path)
+    }
+
+    /// Drops outputs that were removed, so they're no longer listed.
+    pub fn forget<'a>(&mut self, outputs: impl IntoIterator<Item = &'a Path>) {
+        for output in outputs {
+            self.outputs.remove(output);
+            self.digests.remove(output);
+        }
+    }
+
+    /// The digest of an output written by this build, if it was recorded with one.
+    pub fn digest(&self, output: &Path) -> Option<OutputDigest> {
+        self.digests.get(output).copied()
+    }
+
+    /// Carries the previous build's outputs over into this one, for when the build was
+    /// interrupted before producing all of them.
+    #[cfg_attr(not(feature = "config"), allow(dead_code))]
+    pub(crate) fn keep_previous(&mut self) {
+        if let Some(previous) = &self.previous {
+            self.outputs.extend(previous.iter().cloned());
+        }
+    }
+
+    #[cfg_attr(not(feature = "config"), allow(dead_code))]
+    pub(crate) fn render(&self) -> String {
+        self.outputs
+            .iter()
+            .map(|path| format!("{}\n", path.display()))
+            .collect()
+    }
+}
+
+/// Whether `path`, relative to the output directory, was produced by the previous build.
+pub fn is_known_output(previous: Option<&HashSet<PathBuf>>, path: &Path) -> bool {
+    previous.is_some_and(|previous| previous.contains(path))
+}
+
+#[cfg(feature = "config")]
+pub(crate) async fn read_manifest(output_dir: &Path) -> std::io::Result<Option<HashSet<PathBuf>>> {
+    match smol::fs::read_to_string(output_dir.join(MANIFEST_FILE)).await {
+        Ok(manifest) => Ok(Some(
+            manifest
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(PathBuf::from)
+                .collect(),
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Reports the outputs of the previous build that this one no longer produces as
+/// removed, when the build only compares its outputs. Outputs already gone from the
+/// output directory aren't reported.
+#[cfg_attr(not(feature = "config"), allow(dead_code))]
+pub(crate) fn record_removed_outputs(
+    q_config: Query<&OutputDir, With<FileConfig>>,
+    config: Res<SiteConfig>,
+    manifest: Res<Manifest>,
+    cancel: Res<CancellationToken>,
+    deferred: Res<DeferredTask>,
+) {
+    if config.build.diff == DiffMode::Off || cancel.is_cancelled() {
+        return;
+    }
+
+    let Some(previous) = manifest.previous() else {
+        return;
+    };
+
+    let dir = q_config.single().path().to_path_buf();
+    let mut removed: Vec<_> = previous
+        .iter()
+        .filter(|output| !manifest.outputs.contains(*output))
+        .cloned()
+        .collect();
+
+    if removed.is_empty() {
+        return;
+    }
+
+    removed.sort();
+
+    deferred
+        .scoped_task(|scope| async move {
+            let mut changes = Vec::new();
+
+            for path in removed {
+                let Ok(file) = safe_join(&dir, &path) else {
+                    continue;
+                };
+
+                if smol::fs::metadata(file).await.is_ok() {
+                    changes.push(OutputChange {
+                        path,
+                        kind: ChangeKind::Removed,
+                        diff: None,
+                    });
+                }
+            }
+
+            if changes.is_empty() {
+                return;
+            }
+
+            let mut queue = CommandQueue::default();
+
+            queue.push(move |world: &mut World| {
+                world.resource_mut::<BuildReport>().changes.extend(changes);
+            });
+
+            scope.send(queue);
+        })
+        .detach();
+}
+
+/// Writes the outputs of this build to [`MANIFEST_FILE`], or keeps those of the previous
+/// build if it was interrupted. Nothing is written when the build only compares its
+/// outputs.
+#[cfg_attr(not(feature = "config"), allow(dead_code))]
+pub(crate) fn write_manifest(
+    q_config: Query<&OutputDir, With<FileConfig>>,
+    config: Res<SiteConfig>,
+    mut manifest: ResMut<Manifest>,
+    cancel: Res<CancellationToken>,
+    deferred: Res<DeferredTask>,
+) {
+    if config.build.diff != DiffMode::Off {
+        return;
+    }
+
+    let path = q_config.single().path().to_path_buf();
+
+    if cancel.is_cancelled() {
+        manifest.keep_previous();
+    }
+
+    let manifest = manifest.render();
+
+    deferred
+        .scoped_task(|_| async move {
+            info!(target: WRITE, "Writing the build manifest");
+
+            if let Err(e) = create_directory(path.as_path()).await {
+                error!("Unable to create {}: {}", path.display(), e);
+            }
+
+            if let Err(e) =
+                write_file_to_disk(path.join(MANIFEST_FILE).as_path(), manifest.as_bytes()).await
+            {
+                error!("Unable to write the build manifest: {}", e);
+            }
+        })
+        .detach();
+}
@@ -0,0 +1,71 @@
+//! The site's menus, put together from the `[[menu.<name>]]` entries of the
+//! configuration and the pages adding themselves with `menu` in their front matter.
+//! Templates see them as `menus`, each menu holding its `items` and, on pages, the
+//! name of the `active` item linking to the page.
+
+use std::collections::BTreeMap;
+
+use bevy_ecs::system::Resource;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Every menu of the site, by name. Built once per build during
+/// [`PostProcess`](crate::app::PostProcess).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Resource)]
+pub struct Menus(BTreeMap<String, Vec<MenuItem>>);
+
+/// An entry of a menu, along with the entries nested under it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MenuItem {
+    pub name: String,
+    pub url: String,
+    pub weight: i64,
+    pub children: Vec<MenuItem>,
+}
+
+impl Menus {
+    pub fn new(menus: BTreeMap<String, Vec<MenuItem>>) -> Self {
+        Self(menus)
+    }
+
+    /// The top level entries of `menu`, lightest first.
+    pub fn items(&self, menu: &str) -> &[MenuItem] {
+        self.0.get(menu).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    /// The menus as templates see them, with the item of each linking to `permalink`
+    /// as its `active` one. Trailing slashes are ignored, so `/docs` and `/docs/` are
+    /// the same page.
+    pub fn context(&self, permalink: Option<&str>) -> Value {
+        let menus = self
+            .0
+            .iter()
+            .map(|(name, items)| {
+                let active = permalink.and_then(|permalink| active_item(items, permalink));
+
+                (
+                    name.clone(),
+                    serde_json::json!({ "items": items, "active": active }),
+                )
+            })
+            .collect();
+
+        Value::Object(menus)
+    }
+}
+
+fn active_item<'a>(items: &'a [MenuItem], permalink: &str) -> Option<&'a str> {
+    let permalink = permalink.trim_end_matches('/');
+
+    items.iter().find_map(|item| {
+        if item.url.trim_end_matches('/') == permalink {
+            Some(item.name.as_str())
+        } else {
+            active_item(&item.children, permalink)
+        }
+    })
+}
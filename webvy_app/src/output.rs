@@ -0,0 +1,671 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+use bevy_ecs::{entity::Entity, system::Resource};
+
+use crate::{
+    config::{SiteConfig, TrailingSlash},
+    report::Diagnostics,
+};
+
+/// A path within the output directory reserved in the [`OutputRegistry`]. Writers only
+/// accept these, so every output has to be reserved before it can be written.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OutputPath(PathBuf);
+
+impl OutputPath {
+    pub fn as_path(&self) -> &Path {
+        &self.0
+    }
+
+    /// The path as a site relative URL path, separated by `/`.
+    pub fn to_url_path(&self) -> String {
+        url_path(&self.0)
+    }
+
+    /// The public URL the output is served at.
+    pub fn url(&self, config: &SiteConfig) -> String {
+        locate(config, &self.0).url
+    }
+}
+
+/// Where a page or other output lives: the file written within the output directory,
+/// and the public URL it's served at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub output: PathBuf,
+    pub url: String,
+}
+
+/// Locates `path`, relative to the output directory. Every writer and everything
+/// linking to an output goes through here, so the file written and the URL linked
+/// always agree. Index files are linked by their directory, in the configured
+/// [`TrailingSlash`] style, keeping the subpath of `base_url`, or the local base when
+/// the site is being served.
+pub fn locate(config: &SiteConfig, path: impl AsRef<Path>) -> Location {
+    let output = normalize(path.as_ref());
+    let path = url_path(&output);
+    let directory = match path.strip_suffix(config.build.index_file.as_str()) {
+        Some(directory) if directory.is_empty() || directory.ends_with('/') => Some(directory),
+        _ => None,
+    };
+    let url = config.url_for(match (directory, config.build.trailing_slash) {
+        (Some(directory), TrailingSlash::Always) => directory,
+        (Some(directory), TrailingSlash::Never) => directory.trim_end_matches('/'),
+        (Some(_), TrailingSlash::Preserve) | (None, _) => &path,
+    });
+
+    Location { output, url }
+}
+
+/// Keeps only the normal components of `path`, so `./a//b` and `/a/b` are both `a/b`.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect()
+}
+
+fn url_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| component.as_os_str().to_str())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The URL path `path` is served at by hosts with pretty URLs, which serve both
+/// `notes/index.html` and `notes.html` at `/notes`. Lowercased unless the host tells
+/// apart URLs differing only in case.
+fn url_key(path: &Path, case_sensitive: bool) -> String {
+    let path = url_path(path);
+    let key = match path.strip_suffix("index.html") {
+        Some(directory) if directory.is_empty() || directory.ends_with('/') => directory,
+        _ => path.strip_suffix(".html").unwrap_or(&path),
+    }
+    .trim_end_matches('/');
+
+    if case_sensitive {
+        key.to_string()
+    } else {
+        key.to_lowercase()
+    }
+}
+
+/// Joins `path` onto `base`, for writing within a directory. `.` and `..` are resolved
+/// without touching the filesystem, and only within `path`, so a `base` reached
+/// through a symlink is left alone. Backslashes separate components whatever the
+/// platform, so Windows paths are caught everywhere.
+pub fn safe_join(base: impl AsRef<Path>, path: impl AsRef<Path>) -> Result<PathBuf, PathError> {
+    let (base, path) = (base.as_ref(), path.as_ref());
+    let error = |escape: fn(PathBuf) -> PathError| Err(escape(path.to_path_buf()));
+    let mut joined = base.to_path_buf();
+    let mut depth = 0usize;
+
+    for (index, component) in path.components().enumerate() {
+        let name = match component {
+            Component::Prefix(_) | Component::RootDir => return error(PathError::Absolute),
+            Component::CurDir => continue,
+            Component::ParentDir => {
+                if depth == 0 {
+                    return error(PathError::Escapes);
+                }
+
+                joined.pop();
+                depth -= 1;
+                continue;
+            }
+            Component::Normal(name) => name,
+        };
+
+        // Names that aren't UTF-8 can't hold a backslash or drive letter of note.
+        let Some(name) = name.to_str() else {
+            joined.push(name);
+            depth += 1;
+            continue;
+        };
+
+        let drive = name.as_bytes().first().is_some_and(u8::is_ascii_alphabetic)
+            && name.as_bytes().get(1) == Some(&b':');
+
+        if name.starts_with('\\') || (index == 0 && drive) {
+            return error(PathError::Absolute);
+        }
+
+        for segment in name.split('\\') {
+            match segment {
+                "" | "." => {}
+                ".." if depth == 0 => return error(PathError::Escapes),
+                ".." => {
+                    joined.pop();
+                    depth -= 1;
+                }
+                segment => {
+                    joined.push(segment);
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    Ok(joined)
+}
+
+/// A path that can't be safely joined onto a directory with [`safe_join`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PathError {
+    #[error(
+        "Refusing to write {}, which is absolute rather than relative to the output directory",
+        .0.display()
+    )]
+    Absolute(PathBuf),
+    #[error(
+        "Refusing to write {}, which leads outside of the output directory",
+        .0.display()
+    )]
+    Escapes(PathBuf),
+}
+
+impl AsRef<Path> for OutputPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+/// What reserved an output: the producer, such as `page` or `feed`, and the source
+/// file it was produced from, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputClaim {
+    pub producer: &'static str,
+    pub source: Option<PathBuf>,
+    /// How the output's path was chosen, such as the slug of a file name, when it
+    /// isn't plain from the source.
+    pub via: Option<String>,
+    /// The page entity the output belongs to, released when the page is removed.
+    pub page: Option<Entity>,
+}
+
+impl OutputClaim {
+    pub fn new(producer: &'static str, source: impl Into<Option<PathBuf>>) -> Self {
+        Self {
+            producer,
+            source: source.into(),
+            via: None,
+            page: None,
+        }
+    }
+
+    /// Describes how the output's path was chosen, named when it collides.
+    pub fn via(mut self, via: impl Into<String>) -> Self {
+        self.via = Some(via.into());
+        self
+    }
+
+    /// Ties the output to `page`, so it goes away along with the page.
+    pub fn for_page(mut self, page: Entity) -> Self {
+        self.page = Some(page);
+        self
+    }
+}
+
+impl fmt::Display for OutputClaim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            Some(source) => write!(f, "{} {}", self.producer, source.display())?,
+            None => write!(f, "{}", self.producer)?,
+        }
+
+        match &self.via {
+            Some(via) => write!(f, " via {}", via),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A reservation of a path that was already reserved by something else, or of a
+/// different path served at the same URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputCollision {
+    pub path: PathBuf,
+    pub first: OutputClaim,
+    pub second: OutputClaim,
+    /// The path reserved by the first claim and the URL both are served at, when the
+    /// paths themselves differ.
+    pub served: Option<(PathBuf, String)>,
+}
+
+impl OutputCollision {
+    /// Records the collision as an error against the source of the second claim.
+    pub fn report(self, diagnostics: &mut Diagnostics) {
+        let code = match self.served {
+            Some(_) => "url-collision",
+            None => "output-collision",
+        };
+
+        diagnostics.error(self.second.source.clone(), code, self.to_string());
+    }
+}
+
+impl fmt::Display for OutputCollision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some((first_path, url)) = &self.served else {
+            return write!(
+                f,
+                "{} is written by both {} and {}",
+                self.path.display(),
+                self.first,
+                self.second
+            );
+        };
+
+        write!(
+            f,
+            "/{} is served by both {} ({}) and {} ({})",
+            url,
+            self.first,
+            url_path(first_path),
+            self.second,
+            url_path(&self.path)
+        )?;
+
+        let second_url = url_key(&self.path, true);
+
+        if *url != second_url && url.to_lowercase() == second_url.to_lowercase() {
+            write!(
+                f,
+                ", which only differ in case. Set `case_sensitive_urls` under [build] if \
+                 the host tells them apart"
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Every path within the output directory claimed by this build. Producers reserve
+/// their outputs here ahead of [`Write`](crate::app::Write), so two of them writing
+/// to the same file, or to different files served at the same URL, fails the build
+/// before anything is written.
+#[derive(Debug, Default, Resource)]
+pub struct OutputRegistry {
+    claims: HashMap<PathBuf, OutputClaim>,
+    /// The path reserved for each URL, keyed as by [`url_key`].
+    urls: HashMap<String, PathBuf>,
+    case_sensitive: bool,
+}
+
+impl OutputRegistry {
+    /// Reserves `path`, relative to the output directory, for `claim`.
+    pub fn reserve(
+        &mut self,
+        path: impl AsRef<Path>,
+        claim: OutputClaim,
+    ) -> Result<OutputPath, Box<OutputCollision>> {
+        let path = normalize(path.as_ref());
+
+        if let Some(first) = self.claims.get(&path) {
+            return Err(Box::new(OutputCollision {
+                path,
+                first: first.clone(),
+                second: claim,
+                served: None,
+            }));
+        }
+
+        let url = url_key(&path, self.case_sensitive);
+
+        if let Some(first_path) = self.urls.get(&url) {
+            return Err(Box::new(OutputCollision {
+                first: self.claims[first_path].clone(),
+                second: claim,
+                served: Some((first_path.clone(), url_key(first_path, true))),
+                path,
+            }));
+        }
+
+        self.urls.insert(url, path.clone());
+        self.claims.insert(path.clone(), claim);
+
+        Ok(OutputPath(path))
+    }
+
+    /// Sets whether URLs differing only in case are told apart, as they are by some
+    /// hosts. They aren't by default, so outputs differing only in case collide.
+    pub fn set_case_sensitive(&mut self, case_sensitive: bool) {
+        if self.case_sensitive == case_sensitive {
+            return;
+        }
+
+        self.case_sensitive = case_sensitive;
+        self.urls = self
+            .claims
+            .keys()
+            .map(|path| (url_key(path, case_sensitive), path.clone()))
+            .collect();
+    }
+
+    /// Whatever reserved `path`, if it has been reserved.
+    pub fn claim(&self, path: impl AsRef<Path>) -> Option<&OutputClaim> {
+        self.claims.get(path.as_ref())
+    }
+
+    /// Releases every output of `page`, returning their paths so they can be removed.
+    pub fn release_page(&mut self, page: Entity) -> Vec<PathBuf> {
+        let released: Vec<_> = self
+            .claims
+            .iter()
+            .filter(|(_, claim)| claim.page == Some(page))
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        for path in released.iter() {
+            self.claims.remove(path);
+            self.urls.remove(&url_key(path, self.case_sensitive));
+        }
+
+        released
+    }
+}
+
+/// Outputs of pages removed since the last build, deleted from the output directory
+/// unless something else produced them again.
+#[derive(Debug, Default, Resource)]
+pub struct StaleOutputs(pub Vec<PathBuf>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outputs_can_only_be_reserved_once() {
+        let mut registry = OutputRegistry::default();
+
+        let page = registry
+            .reserve(
+                "/tags/index.html",
+                OutputClaim::new("page", PathBuf::from("content/tags/_index.md")),
+            )
+            .unwrap();
+
+        assert_eq!(page.as_path(), Path::new("tags/index.html"));
+        assert_eq!(page.to_url_path(), "tags/index.html");
+
+        let collision = registry
+            .reserve("./tags//index.html", OutputClaim::new("taxonomy", None))
+            .unwrap_err();
+
+        assert_eq!(
+            collision.to_string(),
+            format!(
+                "{} is written by both page {} and taxonomy",
+                Path::new("tags/index.html").display(),
+                Path::new("content/tags/_index.md").display()
+            )
+        );
+        assert_eq!(
+            registry
+                .claim("tags/index.html")
+                .map(|claim| claim.producer),
+            Some("page")
+        );
+    }
+
+    #[test]
+    fn paths_escaping_the_base_are_rejected() {
+        let base = Path::new("site/../public");
+
+        for hostile in [
+            "../../etc/passwd",
+            "..",
+            "a/../../b",
+            "a/./../..",
+            "a\\..\\..\\b",
+            "..\\secret",
+            ".\\..\\x",
+        ] {
+            assert_eq!(
+                safe_join(base, hostile),
+                Err(PathError::Escapes(PathBuf::from(hostile))),
+                "{}",
+                hostile
+            );
+        }
+
+        for hostile in [
+            "/etc/passwd",
+            "//server/share",
+            "C:\\x",
+            "c:x",
+            "C:/Windows/win.ini",
+            "\\\\server\\share\\x",
+            "\\x",
+        ] {
+            assert_eq!(
+                safe_join(base, hostile),
+                Err(PathError::Absolute(PathBuf::from(hostile))),
+                "{}",
+                hostile
+            );
+        }
+
+        // Only the joined path is resolved, leaving the base as it's given.
+        for (path, joined) in [
+            ("index.html", "site/../public/index.html"),
+            ("./a//b/./c.html", "site/../public/a/b/c.html"),
+            ("a/../b.html", "site/../public/b.html"),
+            ("a\\b\\..\\c.html", "site/../public/a/c.html"),
+            ("notes/c:x.html", "site/../public/notes/c:x.html"),
+            ("", "site/../public"),
+        ] {
+            assert_eq!(safe_join(base, path), Ok(PathBuf::from(joined)), "{}", path);
+        }
+    }
+
+    #[test]
+    fn outputs_served_at_the_same_url_collide() {
+        let mut registry = OutputRegistry::default();
+
+        registry
+            .reserve(
+                "notes/index.html",
+                OutputClaim::new("page", PathBuf::from("content/notes/_index.md")),
+            )
+            .unwrap();
+        registry
+            .reserve(
+                "About.html",
+                OutputClaim::new("page", PathBuf::from("content/About.html")),
+            )
+            .unwrap();
+
+        let collision = registry
+            .reserve(
+                "notes.html",
+                OutputClaim::new("page", PathBuf::from("content/Notes!.md")).via("slug `notes`"),
+            )
+            .unwrap_err();
+
+        assert_eq!(
+            collision.to_string(),
+            format!(
+                "/notes is served by both page {} (notes/index.html) and page {} via slug \
+                 `notes` (notes.html)",
+                Path::new("content/notes/_index.md").display(),
+                Path::new("content/Notes!.md").display()
+            )
+        );
+
+        let collision = registry
+            .reserve("about.html", OutputClaim::new("extra template", None))
+            .unwrap_err();
+
+        assert_eq!(
+            collision.to_string(),
+            format!(
+                "/About is served by both page {} (About.html) and extra template \
+                 (about.html), which only differ in case. Set `case_sensitive_urls` under \
+                 [build] if the host tells them apart",
+                Path::new("content/About.html").display()
+            )
+        );
+
+        registry.set_case_sensitive(true);
+
+        assert!(registry
+            .reserve("about.html", OutputClaim::new("extra template", None))
+            .is_ok());
+        assert!(registry
+            .reserve("notes", OutputClaim::new("extra template", None))
+            .is_err());
+        assert!(registry
+            .reserve("notes/atom.xml", OutputClaim::new("feed", None))
+            .is_ok());
+    }
+
+    #[test]
+    fn index_files_are_linked_in_the_configured_style() {
+        let styles = [
+            (
+                "always",
+                "index.html",
+                [
+                    "/blog/",
+                    "/blog/notes/",
+                    "/blog/notes/first.html",
+                    "/blog/atom.xml",
+                ],
+            ),
+            (
+                "never",
+                "index.html",
+                [
+                    "/blog/",
+                    "/blog/notes",
+                    "/blog/notes/first.html",
+                    "/blog/atom.xml",
+                ],
+            ),
+            (
+                "preserve",
+                "index.html",
+                [
+                    "/blog/index.html",
+                    "/blog/notes/index.html",
+                    "/blog/notes/first.html",
+                    "/blog/atom.xml",
+                ],
+            ),
+            (
+                "always",
+                "default.htm",
+                [
+                    "/blog/",
+                    "/blog/notes/",
+                    "/blog/notes/first.html",
+                    "/blog/atom.xml",
+                ],
+            ),
+            (
+                "never",
+                "default.htm",
+                [
+                    "/blog/",
+                    "/blog/notes",
+                    "/blog/notes/first.html",
+                    "/blog/atom.xml",
+                ],
+            ),
+        ];
+
+        for (style, index_file, urls) in styles {
+            let config = toml::from_str::<SiteConfig>(&format!(
+                "base_url = \"https://example.com/blog\"\n\
+                 [build]\ntrailing_slash = \"{}\"\nindex_file = \"{}\"",
+                style, index_file
+            ))
+            .unwrap();
+            let paths = [
+                index_file.to_string(),
+                format!("notes/{}", index_file),
+                String::from("notes/first.html"),
+                String::from("atom.xml"),
+            ];
+
+            for (path, url) in paths.iter().zip(urls) {
+                let location = locate(&config, path);
+
+                assert_eq!(location.output, PathBuf::from(path), "{style} {path}");
+                assert_eq!(
+                    location.url,
+                    format!("https://example.com{}", url),
+                    "{style} {path}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn outputs_and_urls_agree_for_every_base() {
+        let bases = [
+            ("https://example.com", None, ""),
+            ("https://example.com/blog/", None, "/blog"),
+            (
+                "https://example.com/blog",
+                Some("http://localhost:1111"),
+                "",
+            ),
+        ];
+        let paths = [
+            ("index.html", "index.html", "/"),
+            ("notes/index.html", "notes/index.html", "/notes/"),
+            (
+                "./notes//first.html",
+                "notes/first.html",
+                "/notes/first.html",
+            ),
+            ("notes/atom.xml", "notes/atom.xml", "/notes/atom.xml"),
+            (
+                "notes/not-index.html",
+                "notes/not-index.html",
+                "/notes/not-index.html",
+            ),
+        ];
+
+        for (base_url, local_base, prefix) in bases {
+            let mut config =
+                toml::from_str::<SiteConfig>(&format!("base_url = \"{}\"", base_url)).unwrap();
+            let origin = match local_base {
+                Some(local_base) => {
+                    config.serve_from(webvy_core::SiteUrl::parse(local_base).unwrap());
+                    local_base
+                }
+                None => "https://example.com",
+            };
+
+            for (path, output, url) in paths {
+                let location = locate(&config, path);
+
+                assert_eq!(
+                    location.output,
+                    PathBuf::from(output),
+                    "{path} on {base_url}"
+                );
+                assert_eq!(
+                    location.url,
+                    format!("{}{}{}", origin, prefix, url),
+                    "{path} on {base_url}"
+                );
+            }
+        }
+
+        let mut registry = OutputRegistry::default();
+        let config =
+            toml::from_str::<SiteConfig>("base_url = \"https://example.com/blog\"").unwrap();
+        let output = registry
+            .reserve("notes/index.html", OutputClaim::new("page", None))
+            .unwrap();
+
+        assert_eq!(output.url(&config), "https://example.com/blog/notes/");
+    }
+}
@@ -0,0 +1,98 @@
+//! Containing panics while processing a single page, so a page hitting a bug is
+//! reported against its source rather than taking down the whole build.
+
+use std::{
+    any::Any,
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::PathBuf,
+};
+
+use bevy_ecs::{system::Command, world::World};
+
+use crate::report::{BuildReport, Diagnostics};
+
+/// Runs `f`, returning the message of any panic instead of unwinding further. Only the
+/// work of a single page should run within, so nothing half done outlives the panic.
+pub fn catch_page_panic<R>(f: impl FnOnce() -> R) -> Result<R, String> {
+    catch_unwind(AssertUnwindSafe(f)).map_err(|payload| panic_message(payload.as_ref()))
+}
+
+/// The message a panic was raised with, when it has one.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| String::from("panicked without a message"))
+}
+
+/// Records a page that panicked during `stage`, such as `sanitizing`, as an error
+/// against its [`SourceFile`](crate::file::SourceFile) and in the [`BuildReport`]'s
+/// count of panicked pages.
+#[derive(Debug, Clone)]
+pub struct PagePanicked {
+    pub source: Option<PathBuf>,
+    pub stage: &'static str,
+    pub message: String,
+}
+
+impl Command for PagePanicked {
+    fn apply(self, world: &mut World) {
+        world.resource_mut::<Diagnostics>().error(
+            self.source,
+            "page-panicked",
+            format!("Panicked while {}: {}", self.stage, self.message),
+        );
+        world.resource_mut::<BuildReport>().panicked += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{app::ProcessorApp, errors::ProcessorError};
+
+    use super::*;
+
+    #[test]
+    fn panics_are_caught_with_their_message() {
+        assert_eq!(catch_page_panic(|| 1), Ok(1));
+        assert_eq!(
+            catch_page_panic(|| -> () { panic!("static message") }),
+            Err(String::from("static message"))
+        );
+        assert_eq!(
+            catch_page_panic(|| -> () { panic!("formatted {}", 1) }),
+            Err(String::from("formatted 1"))
+        );
+        assert_eq!(
+            catch_page_panic(|| std::panic::panic_any(1)),
+            Err(String::from("panicked without a message"))
+        );
+    }
+
+    #[test]
+    fn panicked_pages_fail_the_build() {
+        let mut app = ProcessorApp::new();
+
+        PagePanicked {
+            source: Some(PathBuf::from("content/broken.md")),
+            stage: "sanitizing",
+            message: String::from("index out of bounds"),
+        }
+        .apply(app.world_mut());
+
+        let diagnostic = app.world().resource::<Diagnostics>().iter().next().cloned();
+        let diagnostic = diagnostic.unwrap();
+        assert_eq!(diagnostic.code, "page-panicked");
+        assert_eq!(diagnostic.page, Some(PathBuf::from("content/broken.md")));
+        assert_eq!(
+            diagnostic.message,
+            "Panicked while sanitizing: index out of bounds"
+        );
+
+        let Err(ProcessorError::Build(errors)) = app.finish() else {
+            panic!("the build should have failed");
+        };
+        assert!(matches!(errors.last(), Some(ProcessorError::Panicked(1))));
+    }
+}
@@ -0,0 +1,50 @@
+pub use bevy_ecs::{
+    entity::Entity,
+    query::{With, Without},
+    schedule::{IntoSystemConfigs, IntoSystemSetConfigs},
+    system::{Commands, Query, Res, ResMut},
+};
+
+pub use crate::{
+    app::{Finish, Load, LoadBatch, PostProcess, Preload, Process, ProcessorApp, Write},
+    assets::{AssetHandler, AssetHandlers},
+    build_info::{BuildClock, BuildInfo},
+    cancel::CancellationToken,
+    context::GlobalContext,
+    errors::{ProcessorError, ProcessorResult},
+    file::{
+        FileName, FilePath, HtmlBody, Indexable, PageExtra, PageId, Permalink, ReadingTime,
+        Summary, WordCount,
+    },
+    front_matter::{
+        Authors, Date, Description, Draft, Extra, FrontMatterKeys, Headless, MenuEntries, Raw,
+        Tags, Title, Unlisted, Weight,
+    },
+    menu::{MenuItem, Menus},
+    output::{locate, Location, OutputClaim, OutputPath, OutputRegistry},
+    processor::{
+        AssetUrls, BuildMode, BundleProcessor, DataProcessor, SiteConfig, StaticProcessor,
+    },
+    report::{BuildReport, Diagnostics},
+    traits::{Extractor, ProcessorPlugin},
+};
+
+#[cfg(feature = "config")]
+pub use crate::processor::ConfigurationProcessor;
+
+#[cfg(feature = "markdown")]
+pub use crate::processor::{
+    DateIndex, FeedProcessor, JsonProcessor, KeepMarkdown, MarkdownFrontMatter, MarkdownProcessor,
+    MarkdownSet, MatterDebug, SectionPosts, TaxonomyIndex,
+};
+
+#[cfg(feature = "tera")]
+pub use crate::processor::{
+    ExtraPage, ExtraPages, PwaProcessor, RenderedPages, TeraProcessor, TeraSet,
+};
+
+#[cfg(all(feature = "markdown", feature = "tera"))]
+pub use crate::processor::{OgImageProcessor, StatsProcessor};
+
+#[cfg(all(feature = "config", feature = "markdown", feature = "tera"))]
+pub use crate::site::{build, SiteOptions};
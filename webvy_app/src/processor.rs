@@ -1,8 +1,51 @@
 #![allow(clippy::type_complexity)]
+mod bundle;
+#[cfg(feature = "config")]
 mod configuration;
+mod data;
+#[cfg(feature = "markdown")]
+mod feed;
+#[cfg(feature = "markdown")]
+mod indexes;
+#[cfg(feature = "markdown")]
+mod json;
+#[cfg(feature = "markdown")]
 mod markdown;
+#[cfg(feature = "markdown")]
+mod menus;
+#[cfg(all(feature = "markdown", feature = "tera"))]
+mod og_image;
+#[cfg(feature = "tera")]
+mod pwa;
+#[cfg(feature = "markdown")]
+mod sections;
+mod static_files;
+#[cfg(all(feature = "markdown", feature = "tera"))]
+mod stats;
+#[cfg(feature = "tera")]
 mod tera;
 
+pub use crate::config::*;
+pub use bundle::{AssetUrls, BundleProcessor};
+#[cfg(feature = "config")]
 pub use configuration::*;
+pub use data::*;
+#[cfg(feature = "markdown")]
+pub use feed::*;
+#[cfg(feature = "markdown")]
+pub use indexes::{DateIndex, TaxonomyIndex};
+#[cfg(feature = "markdown")]
+pub use json::*;
+#[cfg(feature = "markdown")]
 pub use markdown::*;
+#[cfg(all(feature = "markdown", feature = "tera"))]
+pub use og_image::OgImageProcessor;
+#[cfg(feature = "tera")]
+pub use pwa::PwaProcessor;
+#[cfg(feature = "markdown")]
+pub use sections::SectionPosts;
+pub use static_files::StaticProcessor;
+#[cfg(all(feature = "markdown", feature = "tera"))]
+pub use stats::StatsProcessor;
+#[cfg(feature = "tera")]
 pub use tera::*;
@@ -0,0 +1,413 @@
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use bevy_ecs::{
+    schedule::IntoSystemConfigs,
+    system::{CommandQueue, Commands, IntoSystem, Res, ResMut, Resource},
+    world::World,
+};
+use log::{info, trace};
+use smol::fs::read;
+
+use crate::{
+    app::{Load, PostProcess, ProcessorApp, Write},
+    config::{BundleConfig, SiteConfig},
+    deferred::DeferredTask,
+    io::{digest, write_bytes_to_disk},
+    logging::LOAD,
+    output::{OutputClaim, OutputPath, OutputRegistry},
+    report::{BuildReport, Diagnostics},
+    traits::ProcessorPlugin,
+};
+
+/// Concatenates the CSS and JS files listed under `[[bundles]]` into one output each,
+/// named after a hash of their content unless `fingerprint = false`. Templates link to
+/// them through `asset_url(name="...")`, so the URL changes along with the bundle.
+#[derive(Debug, Default)]
+pub struct BundleProcessor;
+
+impl BundleProcessor {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn read_bundles_task(config: Res<SiteConfig>, deferred: Res<DeferredTask>) {
+        let bundles = config.bundles.clone();
+
+        deferred
+            .scoped_task(move |scope| async move {
+                if !bundles.is_empty() {
+                    info!(target: LOAD, "Reading {} bundles from disk", bundles.len());
+                }
+
+                let mut loaded = Vec::new();
+                let mut missing = Vec::new();
+
+                for bundle in bundles {
+                    let mut parts = Vec::with_capacity(bundle.files.len());
+
+                    for file in bundle.files.iter() {
+                        match read(file).await {
+                            Ok(content) => parts.push(content),
+                            Err(e) => missing.push((bundle.name.clone(), file.clone(), e)),
+                        }
+                    }
+
+                    // A bundle missing any of its files would break the pages using it.
+                    if parts.len() == bundle.files.len() {
+                        loaded.push(Bundle::concatenate(&bundle, parts));
+                    }
+                }
+
+                let mut queue = CommandQueue::default();
+
+                queue.push(move |world: &mut World| {
+                    let mut diagnostics = world.resource_mut::<Diagnostics>();
+
+                    for (name, file, e) in missing {
+                        diagnostics.error(
+                            file.clone(),
+                            "missing-bundle-input",
+                            format!(
+                                "Bundle `{}` lists `{}`, which can't be read: {}",
+                                name,
+                                file.display(),
+                                e
+                            ),
+                        );
+                    }
+
+                    world.insert_resource(Bundles(loaded));
+                });
+
+                scope.send(queue);
+            })
+            .detach();
+    }
+
+    fn reserve_outputs(
+        mut commands: Commands,
+        config: Res<SiteConfig>,
+        bundles: Option<Res<Bundles>>,
+        mut assets: ResMut<AssetUrls>,
+        mut registry: ResMut<OutputRegistry>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        let Some(bundles) = bundles else {
+            return;
+        };
+
+        let mut outputs = Vec::new();
+        let mut urls = BTreeMap::new();
+
+        for bundle in bundles.0.iter() {
+            match registry.reserve(&bundle.output, OutputClaim::new("bundle", None)) {
+                Ok(output) => {
+                    urls.insert(bundle.name.clone(), output.url(&config));
+                    outputs.push((output, bundle.clone()));
+                }
+                Err(collision) => collision.report(&mut diagnostics),
+            }
+        }
+
+        // Replacing the same URLs would have every page rendered again.
+        if *assets.0.read().unwrap() != urls {
+            assets.replace(urls);
+        }
+
+        commands.insert_resource(BundleOutputs(outputs));
+    }
+
+    fn render_bundles(
+        outputs: Option<Res<BundleOutputs>>,
+        mut report: ResMut<BuildReport>,
+    ) -> Vec<(OutputPath, Vec<u8>)> {
+        let Some(outputs) = outputs else {
+            return Vec::new();
+        };
+
+        outputs
+            .0
+            .iter()
+            .map(|(output, bundle)| {
+                report.bundles += 1;
+                report.bundled_files += bundle.files;
+                report.bundled_bytes += bundle.content.len() as u64;
+
+                (output.clone(), bundle.content.clone())
+            })
+            .collect()
+    }
+}
+
+impl ProcessorPlugin for BundleProcessor {
+    fn register(self, app: &mut ProcessorApp) {
+        let reserve = Self::reserve_outputs.into_configs();
+        // Pages link to bundles, so their URLs have to be known ahead of the contexts.
+        #[cfg(feature = "tera")]
+        let reserve = reserve.before(super::TeraSet::Context);
+
+        app.init_resource::<AssetUrls>()
+            .add_systems(Load, Self::read_bundles_task)
+            .add_systems(PostProcess, reserve)
+            .add_systems(Write, Self::render_bundles.pipe(write_bytes_to_disk));
+    }
+}
+
+/// The URL of every bundle, by its configured name. Shared with the `asset_url`
+/// template function, which can't borrow the world. Clones share the same URLs.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct AssetUrls(Arc<RwLock<BTreeMap<String, String>>>);
+
+impl AssetUrls {
+    /// Replaces the URLs with `urls`, for every clone.
+    pub fn replace(&mut self, urls: BTreeMap<String, String>) {
+        *self.0.write().unwrap() = urls;
+    }
+
+    /// The URL of the bundle named `name`.
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.0.read().unwrap().get(name).cloned()
+    }
+}
+
+/// The bundles read during this build, leaving out those missing any of their files.
+#[derive(Debug, Resource)]
+struct Bundles(Vec<Bundle>);
+
+/// The bundles along with where they're written.
+#[derive(Debug, Resource)]
+struct BundleOutputs(Vec<(OutputPath, Bundle)>);
+
+#[derive(Debug, Clone)]
+struct Bundle {
+    name: String,
+    /// Where the bundle is written, fingerprinted or not.
+    output: String,
+    content: Vec<u8>,
+    /// How many files it was made from.
+    files: usize,
+}
+
+impl Bundle {
+    /// Joins `parts` in order, each on lines of their own so a trailing `//` comment
+    /// can't swallow the start of the next file.
+    fn concatenate(config: &BundleConfig, parts: Vec<Vec<u8>>) -> Self {
+        let files = parts.len();
+        let mut content = Vec::with_capacity(parts.iter().map(Vec::len).sum());
+
+        for part in parts {
+            content.extend_from_slice(&part);
+
+            if !content.is_empty() && !content.ends_with(b"\n") {
+                content.push(b'\n');
+            }
+        }
+
+        let is_css = Path::new(&config.name)
+            .extension()
+            .is_some_and(|extension| extension == "css");
+
+        if config.minify {
+            match is_css {
+                true => content = minify_css(&String::from_utf8_lossy(&content)).into_bytes(),
+                false => trace!("Only CSS bundles are minified, leaving {}", config.name),
+            }
+        }
+
+        let output = match config.fingerprint {
+            true => fingerprint(&config.name, &content),
+            false => config.name.clone(),
+        };
+
+        Self {
+            name: config.name.clone(),
+            output,
+            content,
+            files,
+        }
+    }
+}
+
+/// Puts a hash of `content` ahead of the extension of `name`, as in `site.1a2b3c4d.css`.
+fn fingerprint(name: &str, content: &[u8]) -> String {
+    let hash = format!("{:016x}", digest(content).hash);
+    let hash = &hash[..8];
+
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !extension.contains('/') => {
+            format!("{}.{}.{}", stem, hash, extension)
+        }
+        _ => format!("{}.{}", name, hash),
+    }
+}
+
+/// Strips comments and whitespace from CSS, keeping the single spaces separating
+/// selectors and values. Strings are left untouched.
+fn minify_css(css: &str) -> String {
+    // Whitespace around these never changes the meaning of a stylesheet. A space
+    // ahead of a `:` can, as in `a :hover`, so it's only dropped after one.
+    let is_separator = |c: char| matches!(c, '{' | '}' | ';' | ',' | '>');
+
+    let mut minified = String::with_capacity(css.len());
+    let mut chars = css.chars().peekable();
+    let mut space = false;
+
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            space = true;
+            continue;
+        }
+
+        if c == '/' && chars.peek() == Some(&'*') {
+            chars.next();
+
+            let mut previous = ' ';
+            for c in chars.by_ref() {
+                if previous == '*' && c == '/' {
+                    break;
+                }
+                previous = c;
+            }
+
+            space = true;
+            continue;
+        }
+
+        if space
+            && !is_separator(c)
+            && minified
+                .chars()
+                .next_back()
+                .is_some_and(|last| !is_separator(last) && last != ':')
+        {
+            minified.push(' ');
+        }
+        space = false;
+        minified.push(c);
+
+        if c == '"' || c == '\'' {
+            while let Some(next) = chars.next() {
+                minified.push(next);
+
+                match next {
+                    '\\' => minified.extend(chars.next()),
+                    _ if next == c => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    minified
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Value;
+
+    use crate::{
+        config::{FileConfig, InputDir, OutputDir},
+        manifest::Manifest,
+    };
+
+    use super::*;
+
+    #[test]
+    fn css_is_minified_without_changing_its_meaning() {
+        let css = "/* Layout */\na :hover,\nb > i {\n  color: red;\n  content: \"a  /* b */\";\n  margin: 0 auto; /* centred */\n}\n";
+
+        assert_eq!(
+            minify_css(css),
+            "a :hover,b>i{color:red;content:\"a  /* b */\";margin:0 auto;}"
+        );
+        assert_eq!(
+            fingerprint("css/site.css", b"a{}").len(),
+            "css/site.css".len() + 9
+        );
+        assert!(fingerprint("css/site.css", b"a{}").starts_with("css/site."));
+        assert_ne!(fingerprint("app.js", b"a"), fingerprint("app.js", b"b"));
+    }
+
+    #[test]
+    fn bundles_are_concatenated_in_order() {
+        let dir = std::env::temp_dir().join("webvy_bundles_are_concatenated_in_order");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("assets")).unwrap();
+        std::fs::write(dir.join("assets/reset.css"), "* { margin: 0; }").unwrap();
+        std::fs::write(
+            dir.join("assets/site.css"),
+            "/* Site */\nbody {\n  color: red;\n}\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("assets/app.js"), "// Runs last").unwrap();
+
+        let bundle = |name: &str, files: &[&str], options: &str| {
+            let files: Vec<_> = files
+                .iter()
+                .map(|file| dir.join("assets").join(file))
+                .collect();
+
+            format!(
+                "[[bundles]]\nname = {:?}\nfiles = {:?}\n{}\n",
+                name, files, options
+            )
+        };
+        let build = |config: &str| {
+            let mut app = ProcessorApp::new();
+
+            app.world_mut().spawn((
+                FileConfig,
+                InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+                OutputDir::new(dir.join("public")),
+            ));
+            app.insert_resource(toml::from_str::<SiteConfig>(config).unwrap())
+                .init_resource::<Manifest>()
+                .add_processor(BundleProcessor::new())
+                .run()
+                .unwrap();
+            app
+        };
+
+        let mut app = build(&format!(
+            "{}{}",
+            bundle("css/site.css", &["reset.css", "site.css"], "minify = true"),
+            bundle("app.js", &["site.css", "app.js"], "fingerprint = false"),
+        ));
+
+        let assets = app.world().resource::<AssetUrls>();
+        let css = assets.get("css/site.css").unwrap();
+        assert!(css.starts_with("/css/site.") && css.ends_with(".css"));
+        assert_eq!(assets.get("app.js").as_deref(), Some("/app.js"));
+
+        let css = std::fs::read_to_string(dir.join("public").join(&css[1..])).unwrap();
+        let js = std::fs::read_to_string(dir.join("public/app.js")).unwrap();
+        assert_eq!(css, "*{margin:0;}body{color:red;}");
+        assert_eq!(js, "/* Site */\nbody {\n  color: red;\n}\n// Runs last\n");
+
+        let report = app.finish().unwrap();
+        assert_eq!(report.bundles, 2);
+        assert_eq!(report.bundled_files, 4);
+        assert_eq!(report.bundled_bytes, (css.len() + js.len()) as u64);
+
+        // A missing file fails the build, naming the bundle listing it.
+        let mut app = build(&bundle("broken.js", &["app.js", "missing.js"], ""));
+
+        assert_eq!(app.world().resource::<AssetUrls>().get("broken.js"), None);
+        let diagnostics: Vec<_> = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .cloned()
+            .collect();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "missing-bundle-input");
+        assert!(diagnostics[0]
+            .message
+            .starts_with("Bundle `broken.js` lists"));
+        assert!(app.finish().is_err());
+    }
+}
@@ -1,9 +1,12 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
 
 use bevy_ecs::{
-    component::Component,
     query::With,
-    system::{CommandQueue, Commands, Query, Res, Resource},
+    schedule::IntoSystemConfigs,
+    system::{CommandQueue, Commands, Query, Res, ResMut, Resource},
     world::World,
 };
 use log::{error, info};
@@ -12,140 +15,439 @@ use smol::{
     stream::StreamExt,
 };
 use toml::{Table, Value};
+use webvy_core::SiteUrl;
 
 use crate::{
-    app::{Load, Preload, ProcessorApp},
+    app::{Finish, Load, Preload, Process, ProcessorApp},
+    config::{
+        resolve, BuildMode, DataDir, DiffMode, FileConfig, InputDir, OutputDir, SiteConfig,
+        StaticDir, TemplatesDir,
+    },
     deferred::DeferredTask,
-    file::{EnumeratedSections, PageType},
+    file::{EnumeratedSections, PageType, RootPageType, SectionName},
+    logging::LOAD,
+    manifest::{read_manifest, record_removed_outputs, write_manifest, Manifest},
+    output::OutputRegistry,
+    report::{BuildErrors, BuildReport, Diagnostics, SitePaths},
     traits::ProcessorPlugin,
 };
 
 #[derive(Debug, Clone, Resource)]
-pub struct ConfigurationProcessor(PathBuf);
+pub struct ConfigurationProcessor {
+    path: PathBuf,
+    output: Option<PathBuf>,
+    drafts: Option<bool>,
+    strict: Option<bool>,
+    diff: Option<DiffMode>,
+    mode: BuildMode,
+}
 
 impl ConfigurationProcessor {
     pub fn new(path: impl Into<PathBuf>) -> Self {
-        Self(path.into())
+        Self {
+            path: path.into(),
+            output: None,
+            drafts: None,
+            strict: None,
+            diff: None,
+            mode: BuildMode::Production,
+        }
+    }
+
+    /// Writes the output to `output` instead of the configured output directory.
+    pub fn with_output(mut self, output: impl Into<PathBuf>) -> Self {
+        self.output = Some(output.into());
+        self
+    }
+
+    /// Overrides whether draft pages are rendered.
+    pub fn with_drafts(mut self, drafts: bool) -> Self {
+        self.drafts = Some(drafts);
+        self
+    }
+
+    /// Overrides whether warnings fail the build.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = Some(strict);
+        self
+    }
+
+    /// Overrides whether outputs are compared against the output directory instead of
+    /// being written.
+    pub fn with_diff(mut self, diff: DiffMode) -> Self {
+        self.diff = Some(diff);
+        self
+    }
+
+    /// Builds the site for `mode`, production by default.
+    pub fn with_mode(mut self, mode: BuildMode) -> Self {
+        self.mode = mode;
+        self
     }
 
     fn init_section_page_types(
         mut commands: Commands,
         q_config: Query<&InputDir, With<FileConfig>>,
+        config: Res<SiteConfig>,
         deferred: Res<DeferredTask>,
     ) {
-        let path = q_config.single().path().to_path_buf();
+        let paths = q_config.single().paths().to_vec();
+        let follow_symlinks = config.build.follow_symlinks;
 
-        commands.spawn_batch([PageType::Index, PageType::Page]);
+        commands.add(RootPageType(PageType::Index));
+        commands.add(RootPageType(PageType::Page));
 
         deferred
-            .scoped_task(|ex| async move {
-                info!("Enumerating content sections");
-                match Self::read_first_level_directory(path.as_path()).await {
-                    Ok(commands) => ex.send(commands),
-                    Err(err) => error!("Unable to read content directory: {}", err),
+            .scoped_task(move |ex| async move {
+                info!(target: LOAD, "Enumerating content sections");
+                let mut names = HashSet::new();
+                let mut queue = CommandQueue::default();
+
+                for path in paths {
+                    match Self::read_first_level_directory(path.as_path(), follow_symlinks).await {
+                        Ok(sections) => sections
+                            .into_iter()
+                            // Sections are unioned across all content roots
+                            .filter(|section| names.insert(section.name().to_string()))
+                            .for_each(|section| queue.push(section)),
+                        Err(err) => error!(
+                            "Unable to read content directory {}: {}",
+                            path.display(),
+                            err
+                        ),
+                    }
                 }
+
+                ex.send(queue);
             })
             .detach();
     }
 
-    async fn read_first_level_directory(path: &Path) -> std::io::Result<CommandQueue> {
+    /// Lists the sections of a content directory, skipping symlinked directories
+    /// the same way content is read, unless following them.
+    async fn read_first_level_directory(
+        path: &Path,
+        follow_symlinks: bool,
+    ) -> std::io::Result<Vec<EnumeratedSections>> {
         let mut entry = read_dir(path).await?;
 
-        let to_visit = CommandQueue::default();
+        let mut sections = Vec::new();
 
-        entry.try_fold(to_visit, |mut queue, entry| {
+        while let Some(entry) = entry.try_next().await? {
             let path = entry.path();
 
-            if let Some(section) = path.is_dir().then(|| EnumeratedSections::new(path)).flatten() {
-                queue.push(section);
+            if !path.is_dir() || (!follow_symlinks && entry.file_type().await?.is_symlink()) {
+                continue;
             }
 
-            Ok(queue)
-        }).await
+            if let Some(section) = EnumeratedSections::new(path) {
+                sections.push(section);
+            }
+        }
+
+        Ok(sections)
     }
 
-    fn init_config(config_path: Res<Self>, deferred: Res<DeferredTask>) {
-        let path = config_path.0.to_path_buf();
+    fn init_config(config: Res<Self>, deferred: Res<DeferredTask>) {
+        let ConfigurationProcessor {
+            path,
+            output,
+            drafts,
+            strict,
+            diff,
+            mode,
+        } = config.clone();
 
         deferred
-            .scoped_task(|scope| async move {
-                info!("Reading and loading configuration");
-                if let Err(e) = read_to_string(path.as_path())
-                    .await
-                    .map(|config_file| {
-                        let mut queue = CommandQueue::default();
+            .scoped_task(move |scope| async move {
+                info!(target: LOAD, "Reading and loading configuration");
+                let mut queue = CommandQueue::default();
 
+                match read_to_string(path.as_path()).await {
+                    Ok(config_file) => {
                         queue.push(move |commands: &mut World| {
                             match toml::from_str::<Table>(&config_file) {
                                 Ok(config_file) => {
-                                    if let Some(files) =
-                                        config_file.get("files").and_then(Value::as_table)
+                                    match Value::Table(config_file.clone()).try_into::<SiteConfig>()
                                     {
-                                        let mut file_config = commands.spawn(FileConfig);
+                                        Ok(mut site_config) => {
+                                            site_config.resolve_paths(&config_root(&path));
+
+                                            if let Some(drafts) = drafts {
+                                                site_config.build.drafts = drafts;
+                                            }
 
-                                        if let Some(content) =
-                                            files.get("content").and_then(Value::as_str)
-                                        {
-                                            file_config.insert(InputDir::new(content));
+                                            if let Some(strict) = strict {
+                                                site_config.build.strict = strict;
+                                            }
+
+                                            if let Some(diff) = diff {
+                                                site_config.build.diff = diff;
+                                            }
+
+                                            if let BuildMode::Serve { local_base } = &mode {
+                                                match SiteUrl::parse(local_base) {
+                                                    Ok(local_base) => {
+                                                        site_config.serve_from(local_base)
+                                                    }
+                                                    Err(e) => {
+                                                        error!("Error with local base URL: {}", e);
+                                                        commands
+                                                            .resource_mut::<BuildErrors>()
+                                                            .0
+                                                            .push(e.into());
+                                                    }
+                                                }
+                                            }
+
+                                            let mut diagnostics =
+                                                commands.resource_mut::<Diagnostics>();
+
+                                            site_config.build.compress.retain(|codec| {
+                                                if !codec.is_available() {
+                                                    diagnostics.warning(
+                                                        None,
+                                                        "unsupported-codec",
+                                                        format!(
+                                                            "webvy was built without {} support, skipping it",
+                                                            codec
+                                                        ),
+                                                    );
+                                                }
+
+                                                codec.is_available()
+                                            });
+
+                                            if site_config.files.respect_gitignore
+                                                && !cfg!(feature = "gitignore")
+                                            {
+                                                diagnostics.warning(
+                                                    None,
+                                                    "unsupported-gitignore",
+                                                    "webvy was built without gitignore support, \
+                                                     so `.gitignore` files aren't respected",
+                                                );
+                                            }
+
+                                            commands.insert_resource(site_config);
+                                        }
+                                        Err(e) => {
+                                            error!("Error with site configuration: {}", e);
+                                            commands.resource_mut::<BuildErrors>().0.push(e.into());
                                         }
+                                    }
+
+                                    // Paths in the config are relative to it rather than
+                                    // to wherever webvy runs from.
+                                    let root = config_root(&path);
+                                    let files = config_file.get("files").and_then(Value::as_table);
+                                    let path_of = |key: &str| {
+                                        files
+                                            .and_then(|files| files.get(key))
+                                            .and_then(Value::as_str)
+                                            .map(|dir| resolve(&root, dir))
+                                    };
+                                    let mut paths = SitePaths {
+                                        config: Some(resolve(&current_dir(), &path)),
+                                        output: path_of("output"),
+                                        templates: Some(
+                                            path_of("templates").unwrap_or_else(|| {
+                                                resolve(&root, TemplatesDir::DEFAULT)
+                                            }),
+                                        ),
+                                        static_files: path_of("static"),
+                                        data: path_of("data"),
+                                        ..Default::default()
+                                    };
 
-                                        if let Some(output) =
-                                            files.get("output").and_then(Value::as_str)
-                                        {
-                                            file_config.insert(OutputDir::new(output));
+                                    if let Some(content) =
+                                        files.and_then(|files| files.get("content"))
+                                    {
+                                        match InputDir::from_value(content) {
+                                            Some(input) => {
+                                                paths.content = input
+                                                    .paths()
+                                                    .iter()
+                                                    .map(|dir| resolve(&root, dir))
+                                                    .collect();
+                                            }
+                                            None => error!(
+                                                "files.content must be a path or an array of paths"
+                                            ),
                                         }
                                     }
+
+                                    // An output given to the processor is relative to where
+                                    // webvy runs from, like any other argument.
+                                    if let Some(output) = output {
+                                        paths.output = Some(resolve(&current_dir(), output));
+                                    }
+
+                                    log_paths(&paths);
+
+                                    let mut file_config = commands.spawn(FileConfig);
+
+                                    if files.is_some_and(|files| files.contains_key("content")) {
+                                        file_config.insert(InputDir::new(paths.content.clone()));
+                                    }
+
+                                    if let Some(output) = &paths.output {
+                                        file_config.insert(OutputDir::new(output));
+                                    }
+
+                                    if let Some(templates) = &paths.templates {
+                                        file_config.insert(TemplatesDir::new(templates));
+                                    }
+
+                                    if let Some(data) = &paths.data {
+                                        file_config.insert(DataDir::new(data));
+                                    }
+
+                                    if let Some(dir) = &paths.static_files {
+                                        file_config.insert(StaticDir::new(dir));
+                                    }
+
+                                    commands.resource_mut::<BuildReport>().paths = paths;
                                 }
                                 Err(e) => {
                                     error!("Error with deserializing: {}", e);
+                                    commands.resource_mut::<BuildErrors>().0.push(e.into());
                                 }
                             };
                         });
-
-                        queue
-                    })
-                    .map(|queue| scope.send(queue))
-                {
-                    error!("Error with reading: {}", e);
+                    }
+                    Err(e) => {
+                        error!("Error with reading: {}", e);
+                        queue.push(move |world: &mut World| {
+                            world.resource_mut::<BuildErrors>().0.push(e.into());
+                        });
+                    }
                 }
+
+                scope.send(queue);
             })
             .detach();
     }
-}
 
-impl ProcessorPlugin for ConfigurationProcessor {
-    fn register(self, app: &mut ProcessorApp) {
-        app.insert_resource(self)
-            .add_systems(Preload, Self::init_config)
-            .add_systems(Load, Self::init_section_page_types);
+    fn configure_outputs(config: Res<SiteConfig>, mut registry: ResMut<OutputRegistry>) {
+        registry.set_case_sensitive(config.build.case_sensitive_urls);
     }
-}
 
-#[derive(Debug, Component)]
-pub struct InputDir(PathBuf);
+    fn load_manifest(q_config: Query<&OutputDir, With<FileConfig>>, deferred: Res<DeferredTask>) {
+        let path = q_config.single().path().to_path_buf();
+
+        deferred
+            .scoped_task(|scope| async move {
+                match read_manifest(path.as_path()).await {
+                    Ok(Some(previous)) => {
+                        let mut queue = CommandQueue::default();
 
-impl InputDir {
-    fn new(dir: impl Into<PathBuf>) -> Self {
-        Self(dir.into())
+                        queue.push(move |world: &mut World| {
+                            world.resource_mut::<Manifest>().set_previous(previous);
+                        });
+
+                        scope.send(queue);
+                    }
+                    Ok(None) => info!(target: LOAD, "No manifest from a previous build"),
+                    Err(e) => error!("Unable to read the build manifest: {}", e),
+                }
+            })
+            .detach();
     }
 
-    pub fn path(&self) -> &Path {
-        self.0.as_path()
+    fn validate_section_config(
+        config: Res<SiteConfig>,
+        q_sections: Query<(&PageType, &SectionName)>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        config
+            .sections
+            .keys()
+            .filter(|key| {
+                !q_sections.iter().any(|(page_type, section)| {
+                    *page_type == PageType::Section && section.as_ref() == key.as_str()
+                })
+            })
+            .for_each(|key| {
+                diagnostics.warning(
+                    None,
+                    "unknown-section",
+                    format!(
+                        "Configuration for [sections.{}] doesn't match any content section",
+                        key
+                    ),
+                );
+            });
     }
 }
 
-#[derive(Debug, Component)]
-pub struct FileConfig;
+/// The directory of the config file, which the paths within it are relative to.
+fn config_root(config: &Path) -> PathBuf {
+    resolve(&current_dir(), config.parent().unwrap_or(Path::new("")))
+}
+
+/// The working directory, or an empty path leaving paths relative to it when it can't
+/// be read.
+fn current_dir() -> PathBuf {
+    std::env::current_dir().unwrap_or_default()
+}
 
-#[derive(Debug, Component)]
-pub struct OutputDir(PathBuf);
+fn log_paths(paths: &SitePaths) {
+    let show = |path: &Option<PathBuf>| {
+        path.as_ref()
+            .map_or(String::from("none"), |path| path.display().to_string())
+    };
+    let content: Vec<_> = paths
+        .content
+        .iter()
+        .map(|dir| dir.display().to_string())
+        .collect();
 
-impl OutputDir {
-    fn new(dir: impl Into<PathBuf>) -> Self {
-        Self(dir.into())
+    info!(target: LOAD, "Config: {}", show(&paths.config));
+    info!(target: LOAD, "Content: {}", content.join(", "));
+    info!(target: LOAD, "Output: {}", show(&paths.output));
+    info!(target: LOAD, "Templates: {}", show(&paths.templates));
+    info!(target: LOAD, "Static files: {}", show(&paths.static_files));
+    info!(target: LOAD, "Data: {}", show(&paths.data));
+}
+
+impl ProcessorPlugin for ConfigurationProcessor {
+    fn register(self, app: &mut ProcessorApp) {
+        app.insert_resource(self.mode.clone())
+            .insert_resource(self)
+            .init_resource::<SiteConfig>()
+            .init_resource::<Manifest>()
+            .add_systems(Preload, Self::init_config)
+            .add_systems(
+                Load,
+                (
+                    Self::init_section_page_types,
+                    Self::load_manifest,
+                    Self::configure_outputs,
+                ),
+            )
+            .add_systems(Process, Self::validate_section_config)
+            .add_systems(Finish, (record_removed_outputs, write_manifest).chain());
+
+        #[cfg(debug_assertions)]
+        app.add_systems(Process, assert_unique_page_types);
     }
+}
+
+/// Panics when a page type is registered more than once, which would leave pages to
+/// pick between them arbitrarily. Only checked in debug builds.
+#[cfg(debug_assertions)]
+pub(crate) fn assert_unique_page_types(q_page_types: Query<(&PageType, Option<&SectionName>)>) {
+    let mut registered = HashSet::new();
+
+    for (page_type, section) in q_page_types.iter() {
+        let section: Option<&str> = section.map(AsRef::as_ref);
 
-    pub fn path(&self) -> &Path {
-        self.0.as_path()
+        assert!(
+            registered.insert((*page_type, section)),
+            "The {} page type{} is registered more than once",
+            page_type,
+            section.map_or(String::new(), |section| format!(" of section {}", section))
+        );
     }
 }
@@ -0,0 +1,268 @@
+use std::path::Path;
+
+use bevy_ecs::{
+    query::With,
+    system::{CommandQueue, Query, Res, Resource},
+    world::World,
+};
+use log::{error, info, trace};
+use serde_json::{Map, Value};
+
+use crate::{
+    app::{Load, ProcessorApp},
+    config::{DataDir, FileConfig, SiteConfig},
+    deferred::DeferredTask,
+    files::{
+        compressed_path, find_all_files_in_directory, read_decompressed_files, ReadError,
+        WalkOptions,
+    },
+    logging::LOAD,
+    report::Diagnostics,
+    traits::ProcessorPlugin,
+    value::toml_to_json,
+};
+
+/// Loads the files in the `[files] data` directory, exposing each one to templates as
+/// `data.<file name>`. JSON and TOML files are used as is, while CSV and TSV files
+/// become an array of rows keyed by their header.
+#[derive(Debug, Default)]
+pub struct DataProcessor;
+
+impl DataProcessor {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn read_data_directory_task(
+        q_config: Query<&DataDir, With<FileConfig>>,
+        config: Res<SiteConfig>,
+        deferred: Res<DeferredTask>,
+    ) {
+        let Ok(dir) = q_config.get_single() else {
+            return;
+        };
+
+        let dir = dir.path().to_path_buf();
+        let walk = WalkOptions::from_config(&config);
+        let codecs = config.build.decompress.clone();
+
+        deferred
+            .scoped_task(move |scope| async move {
+                info!(target: LOAD, "Reading data files from disk");
+                let mut files = Vec::new();
+                let mut failures = Vec::new();
+
+                let found = match find_all_files_in_directory(&dir, walk).await {
+                    Ok(found) => found,
+                    Err(err) => {
+                        error!("Error reading data directory: {}", err);
+                        Vec::new()
+                    }
+                };
+
+                for res in read_decompressed_files(found, &codecs).await {
+                    match res {
+                        // Compressed files are parsed as the format they hold.
+                        Ok((path, content)) => match compressed_path(&codecs, &path) {
+                            Some((_, decompressed)) => files.push((decompressed, content)),
+                            None => files.push((path, content)),
+                        },
+                        Err(ReadError::Io(err)) => error!("Error reading data file: {}", err),
+                        Err(err) => failures.push(err),
+                    }
+                }
+
+                let mut queue = CommandQueue::default();
+
+                queue.push(move |world: &mut World| {
+                    let mut data = Map::new();
+                    let mut diagnostics = world.resource_mut::<Diagnostics>();
+
+                    for failure in failures {
+                        if let ReadError::Decompress { path, .. } = &failure {
+                            diagnostics.error(
+                                path.clone(),
+                                "undecompressable-file",
+                                failure.to_string(),
+                            );
+                        }
+                    }
+
+                    for (path, content) in files {
+                        let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+                            continue;
+                        };
+
+                        if let Some(value) = parse_data_file(&path, &content, &mut diagnostics) {
+                            trace!("Loaded data file {}", path.display());
+                            data.insert(name.to_string(), value);
+                        }
+                    }
+
+                    // Reinserting the same data would have every page rendered again.
+                    if world
+                        .get_resource::<SiteData>()
+                        .map_or(true, |existing| existing.0 != data)
+                    {
+                        world.insert_resource(SiteData(data));
+                    }
+                });
+
+                scope.send(queue);
+            })
+            .detach();
+    }
+}
+
+impl ProcessorPlugin for DataProcessor {
+    fn register(self, app: &mut ProcessorApp) {
+        app.init_resource::<SiteData>()
+            .add_systems(Load, Self::read_data_directory_task);
+    }
+}
+
+/// The contents of every data file, keyed by file name without its extension.
+#[derive(Debug, Default, Resource)]
+pub struct SiteData(pub Map<String, Value>);
+
+fn parse_data_file(path: &Path, content: &str, diagnostics: &mut Diagnostics) -> Option<Value> {
+    let extension = path.extension().and_then(|extension| extension.to_str())?;
+
+    let parsed = match extension {
+        "json" => serde_json::from_str(content).map_err(|e| e.to_string()),
+        "toml" => toml::from_str::<toml::Value>(content)
+            .map(|value| toml_to_json(&value))
+            .map_err(|e| e.to_string()),
+        "csv" => return parse_table(path, content, b',', diagnostics),
+        "tsv" => return parse_table(path, content, b'\t', diagnostics),
+        _ => {
+            trace!("Skipping unknown data file {}", path.display());
+            return None;
+        }
+    };
+
+    match parsed {
+        Ok(value) => Some(value),
+        Err(e) => {
+            diagnostics.error(path.to_path_buf(), "invalid-data", e);
+            None
+        }
+    }
+}
+
+#[cfg(feature = "csv")]
+fn parse_table(
+    path: &Path,
+    content: &str,
+    delimiter: u8,
+    diagnostics: &mut Diagnostics,
+) -> Option<Value> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .from_reader(content.as_bytes());
+
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => {
+            diagnostics.error(path.to_path_buf(), "invalid-data", e.to_string());
+            return None;
+        }
+    };
+
+    let rows = reader
+        .records()
+        .filter_map(|record| match record {
+            Ok(record) => Some(Value::Object(
+                headers
+                    .iter()
+                    .zip(record.iter())
+                    .map(|(header, field)| (header.to_string(), coerce_field(field)))
+                    .collect(),
+            )),
+            Err(e) => {
+                let row = e.position().map_or(0, |position| position.line());
+
+                diagnostics.warning(
+                    path.to_path_buf(),
+                    "malformed-row",
+                    format!("Skipping malformed row on line {}: {}", row, e),
+                );
+
+                None
+            }
+        })
+        .collect();
+
+    Some(Value::Array(rows))
+}
+
+#[cfg(not(feature = "csv"))]
+fn parse_table(
+    path: &Path,
+    _content: &str,
+    _delimiter: u8,
+    diagnostics: &mut Diagnostics,
+) -> Option<Value> {
+    diagnostics.warning(
+        path.to_path_buf(),
+        "unsupported-data",
+        "webvy was built without csv support, skipping it",
+    );
+
+    None
+}
+
+/// Turns fields that are unambiguously booleans or numbers into those types, leaving
+/// everything else, such as numbers with leading zeros, as strings.
+#[cfg_attr(not(feature = "csv"), allow(dead_code))]
+fn coerce_field(field: &str) -> Value {
+    match field {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        field if field.trim() == field => serde_json::from_str::<serde_json::Number>(field)
+            .map_or_else(|_| Value::from(field), Value::Number),
+        field => Value::from(field),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_are_only_coerced_when_unambiguous() {
+        assert_eq!(coerce_field("true"), Value::Bool(true));
+        assert_eq!(coerce_field("42"), Value::from(42));
+        assert_eq!(coerce_field("-1.5"), Value::from(-1.5));
+        assert_eq!(coerce_field("007"), Value::from("007"));
+        assert_eq!(coerce_field(" 1"), Value::from(" 1"));
+        assert_eq!(coerce_field("TRUE"), Value::from("TRUE"));
+        assert_eq!(coerce_field("NaN"), Value::from("NaN"));
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn tables_become_typed_rows_with_malformed_rows_reported() {
+        let mut diagnostics = Diagnostics::default();
+        let content = "title,year,venue\n\
+                       \"Ships, Boats\",2021,\"Line one\nline two\"\n\
+                       Too,Few\n\
+                       Last,2023,Conf\n";
+
+        let rows = parse_table(Path::new("talks.csv"), content, b',', &mut diagnostics).unwrap();
+
+        assert_eq!(
+            rows,
+            serde_json::json!([
+                { "title": "Ships, Boats", "year": 2021, "venue": "Line one\nline two" },
+                { "title": "Last", "year": 2023, "venue": "Conf" },
+            ])
+        );
+
+        let diagnostics = diagnostics.take();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "malformed-row");
+        assert!(diagnostics[0].message.contains("line 4"));
+    }
+}
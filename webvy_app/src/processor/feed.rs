@@ -0,0 +1,602 @@
+use std::{borrow::Cow, path::Path};
+
+use bevy_ecs::{
+    component::Component,
+    entity::Entity,
+    query::Without,
+    schedule::IntoSystemConfigs,
+    system::{Commands, IntoSystem, Query, Res, ResMut},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{debug, info, trace};
+use serde::Serialize;
+
+use crate::{
+    app::{PostProcess, Process, ProcessorApp, Write},
+    build_info::BuildClock,
+    config::{AuthorConfig, FeedsConfig, SectionConfig, SiteConfig},
+    escape::escape_xml,
+    file::{
+        CanonicalUrl, FeedUrl, HtmlBody, Indexable, PageType, Permalink, SectionIndex, SectionName,
+        SourceFile, Summary,
+    },
+    front_matter::{Authors, Tags},
+    io::write_to_disk,
+    logging::RENDER,
+    output::{locate, OutputClaim, OutputPath, OutputRegistry},
+    report::Diagnostics,
+    traits::ProcessorPlugin,
+};
+
+use super::{markdown::MarkdownSet, sections::SectionPosts};
+
+const ATOM_FILE: &str = "atom.xml";
+const JSON_FILE: &str = "feed.json";
+
+/// Emits Atom and/or JSON feeds for every section with `feed = true` in its section
+/// config, depending on the formats enabled in `[feeds]`.
+#[derive(Debug, Default)]
+pub struct FeedProcessor;
+
+impl FeedProcessor {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn assign_feed_urls(
+        mut commands: Commands,
+        config: Res<SiteConfig>,
+        q_sections: Query<(&PageType, &SectionName, &SectionConfig, &SectionIndex)>,
+        q_pages: Query<Entity, Without<FeedUrl>>,
+    ) {
+        let Some(feed_file) = primary_feed_file(&config.feeds) else {
+            return;
+        };
+
+        for (_, section, _, SectionIndex(index)) in q_sections
+            .iter()
+            .filter(|(page_type, _, config, _)| **page_type == PageType::Section && config.feed())
+        {
+            if let Ok(page) = q_pages.get(*index) {
+                let feed_url = locate(&config, Path::new(section.as_ref()).join(feed_file)).url;
+
+                trace!("Section {} has feed {}", section.as_ref(), feed_url);
+                commands.entity(page).insert(FeedUrl(feed_url));
+            }
+        }
+    }
+
+    fn reserve_feed_outputs(
+        mut commands: Commands,
+        config: Res<SiteConfig>,
+        q_sections: Query<
+            (
+                Entity,
+                &PageType,
+                &SectionName,
+                &SectionConfig,
+                Option<&SectionIndex>,
+            ),
+            Without<FeedOutputs>,
+        >,
+        q_pages: Query<&SourceFile>,
+        mut registry: ResMut<OutputRegistry>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        for (entity, _, section, _, index) in
+            q_sections.iter().filter(|(_, page_type, _, config, _)| {
+                **page_type == PageType::Section && config.feed()
+            })
+        {
+            let source = index
+                .and_then(|SectionIndex(index)| q_pages.get(*index).ok())
+                .map(|source| source.as_ref().to_path_buf());
+
+            let mut reserve = |enabled: bool, file: &str| {
+                if !enabled {
+                    return None;
+                }
+
+                registry
+                    .reserve(
+                        Path::new(section.as_ref()).join(file),
+                        OutputClaim::new("feed", source.clone()),
+                    )
+                    .map_err(|collision| collision.report(&mut diagnostics))
+                    .ok()
+            };
+
+            let outputs = FeedOutputs {
+                atom: reserve(config.feeds.atom, ATOM_FILE),
+                json: reserve(config.feeds.json, JSON_FILE),
+            };
+
+            commands.entity(entity).insert(outputs);
+        }
+    }
+
+    fn render_section_feeds(
+        config: Res<SiteConfig>,
+        q_sections: Query<(&SectionName, &FeedOutputs)>,
+        section_posts: Res<SectionPosts>,
+        q_posts: Query<(
+            &Permalink,
+            &HtmlBody,
+            &Indexable,
+            Option<&Summary>,
+            Option<&Tags>,
+            Option<&Authors>,
+            Option<&CanonicalUrl>,
+        )>,
+        clock: Res<BuildClock>,
+    ) -> Vec<(OutputPath, String)> {
+        let now = clock.now();
+        let mut feeds = Vec::new();
+
+        info!(target: RENDER, "Rendering section feeds");
+
+        for (section, outputs) in q_sections.iter() {
+            let entries = select_entries(
+                section_posts
+                    .iter_posts(section.as_ref())
+                    .filter_map(|post| {
+                        let entry = q_posts.get(post).ok();
+
+                        if entry.is_none() {
+                            debug!("Leaving {:?} out of the {} feed", post, section.as_ref());
+                        }
+
+                        entry
+                    })
+                    // Syndicated posts belong in the feed of the site they came from
+                    .filter(|(.., canonical)| {
+                        !canonical.is_some_and(|canonical| canonical.is_external(&config))
+                    })
+                    .map(
+                        |(permalink, body, indexable, summary, tags, authors, _)| FeedEntry {
+                            title: indexable.title.as_deref().unwrap_or(""),
+                            permalink: permalink.as_ref(),
+                            content: body.as_ref(),
+                            summary: summary.map(AsRef::as_ref),
+                            date: indexable.date,
+                            tags: tags.map_or(&[], |tags| tags.0.as_slice()),
+                            authors: config.resolve_authors(authors),
+                        },
+                    ),
+                now,
+                config.feeds.limit,
+            );
+
+            if let Some(output) = &outputs.atom {
+                trace!("Rendered Atom feed for {}", section.as_ref());
+                feeds.push((
+                    output.clone(),
+                    render_atom(&config, section.as_ref(), &entries, now),
+                ));
+            }
+
+            if let Some(output) = &outputs.json {
+                trace!("Rendered JSON feed for {}", section.as_ref());
+                feeds.push((
+                    output.clone(),
+                    render_json(&config, section.as_ref(), &entries),
+                ));
+            }
+        }
+
+        feeds
+    }
+}
+
+impl ProcessorPlugin for FeedProcessor {
+    fn register(self, app: &mut ProcessorApp) {
+        app.init_resource::<SectionPosts>()
+            .add_systems(
+                Process,
+                Self::assign_feed_urls.after(MarkdownSet::ParseMatter),
+            )
+            .add_systems(PostProcess, Self::reserve_feed_outputs)
+            .add_systems(Write, Self::render_section_feeds.pipe(write_to_disk));
+    }
+}
+
+/// Where a section's feeds are written, for each enabled format.
+#[derive(Debug, Component)]
+struct FeedOutputs {
+    atom: Option<OutputPath>,
+    json: Option<OutputPath>,
+}
+
+struct FeedEntry<'a> {
+    title: &'a str,
+    permalink: &'a str,
+    content: &'a str,
+    summary: Option<&'a str>,
+    date: Option<DateTime<Utc>>,
+    tags: &'a [String],
+    authors: Vec<Cow<'a, AuthorConfig>>,
+}
+
+/// The feed URL advertised to templates, preferring Atom when both formats are enabled.
+fn primary_feed_file(feeds: &FeedsConfig) -> Option<&'static str> {
+    if feeds.atom {
+        Some(ATOM_FILE)
+    } else if feeds.json {
+        Some(JSON_FILE)
+    } else {
+        None
+    }
+}
+
+/// Drops future dated entries and sorts the rest newest first, shared by every feed
+/// format so they always contain the same items.
+fn select_entries<'a>(
+    entries: impl Iterator<Item = FeedEntry<'a>>,
+    now: DateTime<Utc>,
+    limit: Option<usize>,
+) -> Vec<FeedEntry<'a>> {
+    let mut entries: Vec<FeedEntry> = entries
+        .filter(|entry| entry.date.map_or(true, |date| date <= now))
+        .collect();
+
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.date));
+
+    if let Some(limit) = limit {
+        entries.truncate(limit);
+    }
+
+    entries
+}
+
+fn feed_title(config: &SiteConfig, section: &str) -> String {
+    config.title.as_deref().map_or_else(
+        || section.to_string(),
+        |site| format!("{} - {}", site, section),
+    )
+}
+
+/// The URL of a section's index page.
+fn section_url(config: &SiteConfig, section: &str) -> String {
+    locate(config, Path::new(section).join(&config.build.index_file)).url
+}
+
+fn render_atom(
+    config: &SiteConfig,
+    section: &str,
+    entries: &[FeedEntry],
+    now: DateTime<Utc>,
+) -> String {
+    let title = feed_title(config, section);
+    let section_url = section_url(config, section);
+    let feed_url = locate(config, Path::new(section).join(ATOM_FILE)).url;
+    let updated = entries
+        .iter()
+        .filter_map(|entry| entry.date)
+        .max()
+        .unwrap_or(now);
+
+    let mut feed = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+
+    feed.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    feed.push_str(&format!("  <title>{}</title>\n", escape_xml(&title)));
+    feed.push_str(&format!(
+        "  <link href=\"{}\" rel=\"self\" type=\"application/atom+xml\"/>\n",
+        escape_xml(&feed_url)
+    ));
+    feed.push_str(&format!(
+        "  <link href=\"{}\"/>\n",
+        escape_xml(&section_url)
+    ));
+    feed.push_str(&format!("  <updated>{}</updated>\n", format_date(updated)));
+    feed.push_str(&format!("  <id>{}</id>\n", escape_xml(&feed_url)));
+
+    if let Some(author) = config.default_author() {
+        feed.push_str(&format!("  {}\n", atom_author(&author)));
+    }
+
+    for entry in entries {
+        feed.push_str("  <entry>\n");
+        feed.push_str(&format!("    <title>{}</title>\n", escape_xml(entry.title)));
+        feed.push_str(&format!(
+            "    <link href=\"{}\"/>\n",
+            escape_xml(entry.permalink)
+        ));
+        feed.push_str(&format!("    <id>{}</id>\n", escape_xml(entry.permalink)));
+        feed.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            format_date(entry.date.unwrap_or(updated))
+        ));
+        for author in &entry.authors {
+            feed.push_str(&format!("    {}\n", atom_author(author)));
+        }
+        for tag in entry.tags {
+            feed.push_str(&format!("    <category term=\"{}\"/>\n", escape_xml(tag)));
+        }
+        if let Some(summary) = entry.summary {
+            feed.push_str(&format!(
+                "    <summary type=\"html\">{}</summary>\n",
+                escape_xml(summary)
+            ));
+        }
+        feed.push_str(&format!(
+            "    <content type=\"html\">{}</content>\n",
+            escape_xml(entry.content)
+        ));
+        feed.push_str("  </entry>\n");
+    }
+
+    feed.push_str("</feed>\n");
+
+    feed
+}
+
+fn atom_author(author: &AuthorConfig) -> String {
+    let mut element = format!("<author><name>{}</name>", escape_xml(&author.name));
+
+    if let Some(email) = author.email.as_deref() {
+        element.push_str(&format!("<email>{}</email>", escape_xml(email)));
+    }
+
+    if let Some(url) = author.url.as_deref() {
+        element.push_str(&format!("<uri>{}</uri>", escape_xml(url)));
+    }
+
+    element.push_str("</author>");
+
+    element
+}
+
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: String,
+    home_page_url: String,
+    feed_url: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonAuthor<'a>>,
+    items: Vec<JsonItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonAuthor<'a> {
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    avatar: Option<&'a str>,
+}
+
+impl<'a> From<&'a AuthorConfig> for JsonAuthor<'a> {
+    fn from(author: &'a AuthorConfig) -> Self {
+        Self {
+            name: &author.name,
+            url: author.url.as_deref(),
+            avatar: author.avatar.as_deref(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonItem<'a> {
+    id: &'a str,
+    url: &'a str,
+    title: &'a str,
+    content_html: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date_published: Option<String>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonAuthor<'a>>,
+}
+
+fn render_json(config: &SiteConfig, section: &str, entries: &[FeedEntry]) -> String {
+    let default_author = config.default_author();
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: feed_title(config, section),
+        home_page_url: section_url(config, section),
+        feed_url: locate(config, Path::new(section).join(JSON_FILE)).url,
+        authors: default_author
+            .as_deref()
+            .map(JsonAuthor::from)
+            .into_iter()
+            .collect(),
+        items: entries
+            .iter()
+            .map(|entry| JsonItem {
+                id: entry.permalink,
+                url: entry.permalink,
+                title: entry.title,
+                content_html: entry.content,
+                summary: entry.summary,
+                date_published: entry.date.map(format_date),
+                tags: entry.tags,
+                authors: entry
+                    .authors
+                    .iter()
+                    .map(|author| JsonAuthor::from(author.as_ref()))
+                    .collect(),
+            })
+            .collect(),
+    };
+
+    serde_json::to_string_pretty(&feed).expect("JSON feed should always be serializable")
+}
+
+fn format_date(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_matter::Date;
+
+    #[test]
+    fn sections_without_posts_render_an_empty_feed() {
+        let config = SiteConfig::default();
+        let now = Utc::now();
+
+        let feed = render_atom(&config, "notes", &[], now);
+
+        assert!(feed.contains("<link href=\"/notes/atom.xml\" rel=\"self\""));
+        assert!(feed.contains(&format!("<updated>{}</updated>", format_date(now))));
+        assert!(!feed.contains("<entry>"));
+        assert!(feed.ends_with("</feed>\n"));
+    }
+
+    #[test]
+    fn json_feed_contains_required_fields() {
+        let config: SiteConfig =
+            toml::from_str("title = \"Site\"\nauthor = \"Me\"\nbase_url = \"https://example.com\"")
+                .unwrap();
+        let tags = vec![String::from("rust")];
+        let entries = [FeedEntry {
+            title: "A \"quoted\" title",
+            permalink: "https://example.com/notes/a.html",
+            content: "<p>Some \"html\" &amp; text</p>\n",
+            summary: None,
+            date: Date::new("2024-03-10", None).to_datetime(),
+            tags: &tags,
+            authors: Vec::new(),
+        }];
+
+        let feed: serde_json::Value =
+            serde_json::from_str(&render_json(&config, "notes", &entries)).unwrap();
+
+        assert_eq!(feed["version"], "https://jsonfeed.org/version/1.1");
+        assert_eq!(feed["title"], "Site - notes");
+        assert_eq!(feed["feed_url"], "https://example.com/notes/feed.json");
+        assert_eq!(feed["authors"][0]["name"], "Me");
+
+        let item = &feed["items"][0];
+
+        assert_eq!(item["id"], "https://example.com/notes/a.html");
+        assert_eq!(item["url"], "https://example.com/notes/a.html");
+        assert_eq!(item["title"], "A \"quoted\" title");
+        assert_eq!(item["content_html"], "<p>Some \"html\" &amp; text</p>\n");
+        assert_eq!(item["date_published"], "2024-03-10T00:00:00Z");
+        assert_eq!(item["tags"][0], "rust");
+    }
+
+    #[test]
+    fn entries_resolve_their_authors() {
+        let config: SiteConfig = toml::from_str(
+            "author = \"ann\"\n\
+             [authors.ann]\nname = \"Ann\"\nemail = \"ann@example.com\"\n\
+             [authors.bob]\nname = \"Bob\"\nurl = \"https://bob.example\"",
+        )
+        .unwrap();
+        let authors = Authors(vec![String::from("bob"), String::from("nobody")]);
+        let entries = [FeedEntry {
+            title: "",
+            permalink: "",
+            content: "",
+            summary: None,
+            date: None,
+            tags: &[],
+            authors: config.resolve_authors(Some(&authors)),
+        }];
+
+        let feed = render_atom(&config, "notes", &entries, Utc::now());
+
+        assert!(
+            feed.contains("  <author><name>Ann</name><email>ann@example.com</email></author>\n")
+        );
+        assert!(
+            feed.contains("    <author><name>Bob</name><uri>https://bob.example</uri></author>\n")
+        );
+        assert!(!feed.contains("nobody"));
+
+        let fallback = config.resolve_authors(None);
+
+        assert_eq!(
+            fallback
+                .iter()
+                .map(|author| author.name.as_str())
+                .collect::<Vec<_>>(),
+            ["Ann"]
+        );
+    }
+
+    #[test]
+    fn entries_are_filtered_sorted_and_limited() {
+        let entry = |title, date: &str| FeedEntry {
+            title,
+            permalink: "",
+            content: "",
+            summary: None,
+            date: Date::new(date, None).to_datetime(),
+            tags: &[],
+            authors: Vec::new(),
+        };
+        let now = Date::new("2024-06-01", None).to_datetime().unwrap();
+
+        let entries = select_entries(
+            [
+                entry("old", "2024-01-01"),
+                entry("future", "2024-12-01"),
+                entry("newest", "2024-05-01"),
+                entry("middle", "2024-03-01"),
+            ]
+            .into_iter(),
+            now,
+            Some(2),
+        );
+
+        let titles: Vec<_> = entries.iter().map(|entry| entry.title).collect();
+
+        assert_eq!(titles, ["newest", "middle"]);
+    }
+
+    #[test]
+    fn adversarial_titles_keep_feeds_well_formed() {
+        let config: SiteConfig =
+            toml::from_str("title = \"<b>Site</b> & 'co'\"\nauthor = \"A \\\"quoted\\\" <name>\"")
+                .unwrap();
+        let title = "<script>alert(1)</script> ]]> \"q\" & 'a'\u{1}\u{1b}";
+        let tags = vec![String::from("a\"b<c>")];
+        let entries = [FeedEntry {
+            title,
+            permalink: "https://example.com/?a=1&b=\"2\"",
+            content: "<p>]]></p>",
+            summary: None,
+            date: None,
+            tags: &tags,
+            authors: config.resolve_authors(None),
+        }];
+
+        let atom = render_atom(&config, "notes", &entries, Utc::now());
+
+        assert!(!atom.contains("<script>"));
+        assert!(!atom.contains("]]>"));
+        assert!(!atom.contains(|c: char| c.is_control() && c != '\n'));
+        assert!(atom.contains("&lt;script&gt;alert(1)&lt;/script&gt; ]]&gt; &quot;q&quot;"));
+        assert!(atom.contains("<category term=\"a&quot;b&lt;c&gt;\"/>"));
+
+        // Every element that's opened is closed again, in order
+        let mut open = Vec::new();
+
+        for (index, _) in atom.match_indices('<').filter(|(index, _)| *index > 0) {
+            let tag = &atom[index..index + crate::html::tag_end(&atom[index..])];
+            let (name, closing) = crate::html::tag_name(tag);
+
+            if closing {
+                assert_eq!(open.pop(), Some(name), "unbalanced {}", tag);
+            } else if !tag.ends_with("/>") {
+                open.push(name);
+            }
+        }
+
+        assert!(open.is_empty());
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render_json(&config, "notes", &entries)).unwrap();
+
+        assert_eq!(json["items"][0]["title"], title);
+        assert_eq!(json["authors"][0]["name"], "A \"quoted\" <name>");
+    }
+}
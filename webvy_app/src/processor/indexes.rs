@@ -0,0 +1,339 @@
+use std::collections::BTreeMap;
+
+use bevy_ecs::{
+    change_detection::DetectChangesMut,
+    entity::{Entity, EntityHashMap},
+    query::{Has, With, Without},
+    system::{Query, Res, ResMut, Resource},
+};
+use chrono::Datelike;
+
+use crate::{
+    build_info::BuildClock,
+    config::{SiteConfig, SortBy},
+    file::{Classified, FilePath, PageType},
+    front_matter::{Date, Draft, Headless, Tags, Title, Unlisted, Weight},
+    slug::slugify,
+    taxonomy::TAGS,
+};
+
+use super::{
+    markdown::MarkdownPost,
+    sections::{is_listed, sort_posts, SortablePost},
+};
+
+/// Every listed page with a date, by year and month in the site's timezone, newest
+/// first within each month. Only the pages section listings would show are included,
+/// leaving out headless pages and section indexes too. Built once per build during
+/// [`PostProcess`](crate::app::PostProcess), in
+/// [`MarkdownSet::Indexes`](super::MarkdownSet::Indexes).
+#[derive(Debug, Default, PartialEq, Eq, Resource)]
+pub struct DateIndex(BTreeMap<i32, BTreeMap<u32, Vec<Entity>>>);
+
+impl DateIndex {
+    /// The years with pages, oldest first.
+    pub fn years(&self) -> impl Iterator<Item = i32> + '_ {
+        self.0.keys().copied()
+    }
+
+    /// The months of `year` with pages, from 1 for January, with their pages.
+    pub fn months(&self, year: i32) -> impl Iterator<Item = (u32, &[Entity])> {
+        self.0
+            .get(&year)
+            .into_iter()
+            .flatten()
+            .map(|(month, pages)| (*month, pages.as_slice()))
+    }
+
+    /// The pages dated within `month` of `year`, from 1 for January.
+    pub fn pages(&self, year: i32, month: u32) -> &[Entity] {
+        self.0
+            .get(&year)
+            .and_then(|months| months.get(&month))
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Every listed page of each term of each taxonomy, newest first. Terms are keyed by
+/// their slug, so `Rust` and `rust` are the same term. Includes the same pages as the
+/// [`DateIndex`], dated or not.
+#[derive(Debug, Default, PartialEq, Eq, Resource)]
+pub struct TaxonomyIndex(BTreeMap<String, BTreeMap<String, Vec<Entity>>>);
+
+impl TaxonomyIndex {
+    /// The slugs of the terms of `taxonomy`, alphabetically, with their pages.
+    pub fn terms(&self, taxonomy: &str) -> impl Iterator<Item = (&str, &[Entity])> {
+        self.0
+            .get(taxonomy)
+            .into_iter()
+            .flatten()
+            .map(|(term, pages)| (term.as_str(), pages.as_slice()))
+    }
+
+    /// The pages of `term`, as written or as a slug.
+    pub fn pages(&self, taxonomy: &str, term: &str) -> &[Entity] {
+        self.0
+            .get(taxonomy)
+            .and_then(|terms| terms.get(&slugify(term)))
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Builds the [`DateIndex`] and [`TaxonomyIndex`] from scratch, only replacing them
+/// when they differ so systems watching for changes don't see one every build.
+pub(super) fn build_indexes(
+    config: Res<SiteConfig>,
+    q_pages: Query<
+        (
+            Entity,
+            &FilePath,
+            &Classified,
+            Option<&Date>,
+            Option<&Tags>,
+            Option<&Title>,
+            Option<&Weight>,
+            Has<Draft>,
+        ),
+        (With<MarkdownPost>, Without<Unlisted>, Without<Headless>),
+    >,
+    clock: Res<BuildClock>,
+    mut dates: ResMut<DateIndex>,
+    mut taxonomies: ResMut<TaxonomyIndex>,
+) {
+    let now = clock.now();
+    let mut tags = EntityHashMap::default();
+    let mut posts: Vec<_> = q_pages
+        .iter()
+        .filter(|(_, _, class, ..)| matches!(class.kind, PageType::Page | PageType::Post))
+        .filter_map(|(entity, path, _, date, page_tags, title, weight, draft)| {
+            let date = date.and_then(Date::to_datetime);
+
+            if !is_listed(&config, draft, date, now) {
+                return None;
+            }
+
+            if let Some(page_tags) = page_tags {
+                tags.insert(entity, page_tags);
+            }
+
+            Some(SortablePost {
+                entity,
+                path: path.as_ref(),
+                date,
+                title: title.map(|title| title.0.as_str()),
+                weight: weight.map(|weight| weight.0),
+            })
+        })
+        .collect();
+
+    // Sorting everything up front keeps each month and term in listing order.
+    sort_posts(&mut posts, SortBy::Date);
+
+    let mut by_date = DateIndex::default();
+    let mut by_term = TaxonomyIndex::default();
+
+    for post in posts.iter() {
+        if let Some(date) = post.date {
+            let date = config
+                .timezone
+                .as_ref()
+                .map_or(date.naive_utc(), |timezone| timezone.from_utc(date));
+
+            by_date
+                .0
+                .entry(date.year())
+                .or_default()
+                .entry(date.month())
+                .or_default()
+                .push(post.entity);
+        }
+
+        let terms = tags
+            .get(&post.entity)
+            .map_or(&[][..], |tags| tags.0.as_slice());
+        let mut slugs: Vec<_> = terms.iter().map(|term| slugify(term)).collect();
+        slugs.sort();
+        slugs.dedup();
+
+        for slug in slugs {
+            by_term
+                .0
+                .entry(TAGS.to_string())
+                .or_default()
+                .entry(slug)
+                .or_default()
+                .push(post.entity);
+        }
+    }
+
+    dates.set_if_neq(by_date);
+    taxonomies.set_if_neq(by_term);
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use toml::Value;
+
+    use crate::{
+        app::ProcessorApp,
+        processor::{FileConfig, InputDir, MarkdownFrontMatter, MarkdownProcessor},
+    };
+
+    use super::*;
+
+    #[test]
+    fn listed_pages_are_indexed_by_date_and_term() {
+        let mut app = ProcessorApp::new();
+        let page = |matter: &str| toml::from_str::<toml::Table>(matter).unwrap();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+        app.insert_resource(BuildClock::fixed(
+            Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap(),
+        ))
+        .init_resource::<SiteConfig>()
+        .add_page("a.md", page("date = 2024-05-02\ntags = [\"Rust\"]"), "")
+        .add_page(
+            "b.md",
+            page("date = 2024-05-20\ntags = [\"rust\", \"Bevy\", \"Rust\"]"),
+            "",
+        )
+        .add_page("c.md", page("date = 2023-12-31"), "")
+        .add_page("undated.md", page("tags = [\"bevy\"]"), "")
+        .add_page("draft.md", page("date = 2024-05-03\ndraft = true"), "")
+        .add_page(
+            "future.md",
+            page("date = 2024-07-01\ntags = [\"rust\"]"),
+            "",
+        )
+        .add_page("hidden.md", page("date = 2024-05-04\nrender = false"), "")
+        .add_page(
+            "unlisted.md",
+            page("date = 2024-05-05\nin_listing = false"),
+            "",
+        )
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .run()
+        .unwrap();
+
+        let names = |app: &ProcessorApp, pages: &[Entity]| -> Vec<String> {
+            pages
+                .iter()
+                .map(|page| {
+                    app.world()
+                        .get::<FilePath>(*page)
+                        .unwrap()
+                        .as_ref()
+                        .display()
+                        .to_string()
+                })
+                .collect()
+        };
+
+        let dates = app.world().resource::<DateIndex>();
+        assert_eq!(dates.years().collect::<Vec<_>>(), [2023, 2024]);
+        assert_eq!(
+            dates
+                .months(2024)
+                .map(|(month, _)| month)
+                .collect::<Vec<_>>(),
+            [5]
+        );
+        let may = dates.pages(2024, 5).to_vec();
+        let december = dates.pages(2023, 12).to_vec();
+        assert_eq!(names(&app, &may), ["b.md", "a.md"]);
+        assert_eq!(names(&app, &december), ["c.md"]);
+
+        let taxonomies = app.world().resource::<TaxonomyIndex>();
+        assert_eq!(
+            taxonomies
+                .terms(TAGS)
+                .map(|(term, _)| term)
+                .collect::<Vec<_>>(),
+            ["bevy", "rust"]
+        );
+        let rust = taxonomies.pages(TAGS, "Rust").to_vec();
+        let bevy = taxonomies.pages(TAGS, "bevy").to_vec();
+        assert_eq!(names(&app, &rust), ["b.md", "a.md"]);
+        assert_eq!(names(&app, &bevy), ["b.md", "undated.md"]);
+
+        // Rebuilds index the pages as they are now.
+        let mut q_pages = app.world_mut().query::<(&FilePath, &mut Tags)>();
+        for (path, mut tags) in q_pages.iter_mut(app.world_mut()) {
+            if path.as_ref() == std::path::Path::new("a.md") {
+                tags.0 = vec![String::from("gamedev")];
+            }
+        }
+
+        app.run().unwrap();
+
+        let taxonomies = app.world().resource::<TaxonomyIndex>();
+        let rust = taxonomies.pages(TAGS, "rust").to_vec();
+        let gamedev = taxonomies.pages(TAGS, "gamedev").to_vec();
+        assert_eq!(names(&app, &rust), ["b.md"]);
+        assert_eq!(names(&app, &gamedev), ["a.md"]);
+    }
+
+    #[cfg(feature = "timezones")]
+    #[test]
+    fn dates_are_sorted_and_indexed_in_the_site_timezone() {
+        let mut app = ProcessorApp::new();
+        let page = |matter: &str| toml::from_str::<toml::Table>(matter).unwrap();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+        app.insert_resource(BuildClock::fixed(
+            Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap(),
+        ))
+        .insert_resource(toml::from_str::<SiteConfig>("timezone = \"Europe/Helsinki\"").unwrap())
+        // Midnight in Helsinki, still the 29th of February in UTC.
+        .add_page("local.md", page("date = 2024-03-01"), "")
+        .add_page("utc.md", page("date = 2024-02-29T23:00:00Z"), "")
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .run()
+        .unwrap();
+
+        let mut q_dates = app.world_mut().query::<(&FilePath, &Date)>();
+        let mut dates: Vec<_> = q_dates
+            .iter(app.world())
+            .map(|(path, date)| (path.as_ref().display().to_string(), date.utc))
+            .collect();
+        dates.sort();
+
+        assert_eq!(
+            dates,
+            [
+                (
+                    String::from("local.md"),
+                    Some(Utc.with_ymd_and_hms(2024, 2, 29, 22, 0, 0).unwrap())
+                ),
+                (
+                    String::from("utc.md"),
+                    Some(Utc.with_ymd_and_hms(2024, 2, 29, 23, 0, 0).unwrap())
+                ),
+            ]
+        );
+
+        let dates = app.world().resource::<DateIndex>();
+        let march: Vec<_> = dates
+            .pages(2024, 3)
+            .iter()
+            .map(|page| {
+                app.world()
+                    .get::<FilePath>(*page)
+                    .unwrap()
+                    .as_ref()
+                    .display()
+                    .to_string()
+            })
+            .collect();
+
+        assert_eq!(march, ["utc.md", "local.md"]);
+        assert!(dates.pages(2024, 2).is_empty());
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::BTreeMap;
+
+use bevy_ecs::{
+    component::Component,
+    entity::Entity,
+    query::{Has, With, Without},
+    system::{Commands, IntoSystem, Query, Res, ResMut, Resource},
+};
+use log::{debug, info, trace};
+use serde::Serialize;
+
+use crate::{
+    app::{PostProcess, ProcessorApp, Write},
+    config::SiteConfig,
+    file::{
+        FileName, FilePath, HtmlBody, Indexable, PageExtra, PageId, Permalink, ReadingTime,
+        SourceFile, Summary,
+    },
+    front_matter::{Date, Description, Draft, Headless, Tags},
+    io::write_to_disk,
+    logging::RENDER,
+    output::{OutputClaim, OutputPath, OutputRegistry},
+    report::Diagnostics,
+    traits::ProcessorPlugin,
+    value::toml_to_json,
+};
+
+use super::markdown::MarkdownFrontMatter;
+
+const INDEX_FILE: &str = "pages.json";
+
+/// Front matter keys already exposed as their own fields, left out of `extra`. Values
+/// under `[extra]` are merged with the section's and site's, overriding other keys.
+const FRONT_MATTER_KEYS: &[&str] = &[
+    "title",
+    "date",
+    "tags",
+    "description",
+    "canonical",
+    "author",
+    "authors",
+    "weight",
+    "template",
+    "output",
+    "extra",
+    "raw",
+    "draft",
+    "unlisted",
+    "sanitize",
+    "toc",
+    "toc_levels",
+];
+
+/// Writes a JSON copy of every page next to its HTML, plus a `pages.json` index of
+/// them all without their bodies, when `[build] json_output` is enabled.
+#[derive(Debug, Default)]
+pub struct JsonProcessor;
+
+impl JsonProcessor {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn reserve_outputs(
+        mut commands: Commands,
+        config: Res<SiteConfig>,
+        q_pages: Query<
+            (
+                Entity,
+                &FilePath,
+                &FileName,
+                &SourceFile,
+                Option<&Indexable>,
+                Has<Draft>,
+            ),
+            (With<HtmlBody>, Without<Headless>),
+        >,
+        mut registry: ResMut<OutputRegistry>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        if !config.build.json_output {
+            return;
+        }
+
+        match registry.reserve(INDEX_FILE, OutputClaim::new("JSON index", None)) {
+            Ok(index) => commands.insert_resource(JsonIndex(index)),
+            Err(collision) => collision.report(&mut diagnostics),
+        }
+
+        for (page, path, file_name, source, ..) in q_pages
+            .iter()
+            .filter(|(.., draft)| !draft || config.build.drafts)
+            .filter(|(_, path, .., indexable, _)| {
+                if indexable.is_none() {
+                    debug!("Leaving {} out of the JSON output", path.as_ref().display());
+                }
+
+                indexable.is_some()
+            })
+        {
+            match registry.reserve(
+                path.as_ref()
+                    .with_file_name(&file_name.0)
+                    .with_extension("json"),
+                OutputClaim::new("JSON page", source.as_ref().to_path_buf()).for_page(page),
+            ) {
+                Ok(output) => {
+                    commands.entity(page).insert(JsonOutput(output));
+                }
+                Err(collision) => collision.report(&mut diagnostics),
+            }
+        }
+    }
+
+    fn render_page_json(
+        index: Option<Res<JsonIndex>>,
+        q_pages: Query<(
+            &JsonOutput,
+            &FilePath,
+            &HtmlBody,
+            &Indexable,
+            Option<&Description>,
+            Option<&Date>,
+            Option<&Tags>,
+            Option<&Permalink>,
+            Option<&Summary>,
+            Option<&ReadingTime>,
+            Option<&MarkdownFrontMatter>,
+            Option<&PageExtra>,
+            Option<&PageId>,
+        )>,
+    ) -> Vec<(OutputPath, String)> {
+        if index.is_none() && q_pages.is_empty() {
+            return Vec::new();
+        }
+
+        info!(target: RENDER, "Rendering pages as JSON");
+
+        let pages = q_pages
+            .iter()
+            .map(
+                |(
+                    JsonOutput(output),
+                    path,
+                    html,
+                    indexable,
+                    description,
+                    date,
+                    tags,
+                    permalink,
+                    summary,
+                    reading_time,
+                    front_matter,
+                    extra,
+                    id,
+                )| {
+                    trace!("Rendering {} as JSON", path.as_ref().display());
+
+                    (
+                        output.clone(),
+                        indexable.search,
+                        PageJson {
+                            id: id.map(AsRef::as_ref),
+                            title: indexable.title.as_deref(),
+                            description: description.map(|description| description.0.as_str()),
+                            date: date.map(|date| date.original.as_str()),
+                            tags: tags.map_or(&[], |tags| tags.0.as_slice()),
+                            permalink: permalink.map(AsRef::as_ref),
+                            summary: summary.map(AsRef::as_ref),
+                            reading_time: reading_time.map(|time| time.0),
+                            html: Some(html.as_ref()),
+                            extra: front_matter
+                                .and_then(MarkdownFrontMatter::access)
+                                .into_iter()
+                                .flatten()
+                                .filter(|(key, _)| !FRONT_MATTER_KEYS.contains(&key.as_str()))
+                                .chain(extra.into_iter().flat_map(|extra| &extra.0))
+                                .map(|(key, value)| (key.as_str(), toml_to_json(value)))
+                                .collect(),
+                        },
+                    )
+                },
+            )
+            .collect();
+
+        render_json_files(pages, index.map(|index| index.0.clone()))
+    }
+}
+
+impl ProcessorPlugin for JsonProcessor {
+    fn register(self, app: &mut ProcessorApp) {
+        app.add_systems(PostProcess, Self::reserve_outputs)
+            .add_systems(Write, Self::render_page_json.pipe(write_to_disk));
+    }
+}
+
+/// Where a page's JSON copy is written.
+#[derive(Debug, Component)]
+struct JsonOutput(OutputPath);
+
+/// Where the index of every page is written.
+#[derive(Debug, Resource)]
+struct JsonIndex(OutputPath);
+
+#[derive(Debug, Clone, Serialize)]
+struct PageJson<'a> {
+    /// Identifies the page across builds, and across renames when set explicitly.
+    id: Option<&'a str>,
+    title: Option<&'a str>,
+    description: Option<&'a str>,
+    date: Option<&'a str>,
+    tags: &'a [String],
+    permalink: Option<&'a str>,
+    summary: Option<&'a str>,
+    /// In minutes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reading_time: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html: Option<&'a str>,
+    extra: BTreeMap<&'a str, serde_json::Value>,
+}
+
+/// Serializes each page next to where its HTML is written, plus the index of every page
+/// marked for search, sorted by output path.
+fn render_json_files(
+    mut pages: Vec<(OutputPath, bool, PageJson)>,
+    index_output: Option<OutputPath>,
+) -> Vec<(OutputPath, String)> {
+    pages.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+
+    let index: Vec<_> = pages
+        .iter()
+        .filter(|(_, search, _)| *search)
+        .map(|(.., page)| PageJson {
+            html: None,
+            ..page.clone()
+        })
+        .collect();
+
+    let mut files: Vec<_> = pages
+        .iter()
+        .map(|(path, _, page)| {
+            (
+                path.clone(),
+                serde_json::to_string_pretty(page).expect("pages should always be serializable"),
+            )
+        })
+        .collect();
+
+    if let Some(index_output) = index_output {
+        files.push((
+            index_output,
+            serde_json::to_string_pretty(&index).expect("pages should always be serializable"),
+        ));
+    }
+
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn pages_are_written_next_to_their_html_with_an_index() {
+        let tags = vec![String::from("rust")];
+        let page = |title, html| PageJson {
+            id: None,
+            title: Some(title),
+            description: Some("About the post"),
+            date: Some("2024-03-10"),
+            tags: &tags,
+            permalink: Some("/blog/post.html"),
+            summary: None,
+            reading_time: None,
+            html: Some(html),
+            extra: BTreeMap::from([("cover", serde_json::json!("cover.png"))]),
+        };
+
+        let mut registry = OutputRegistry::default();
+        let mut reserve = |path: &str| {
+            registry
+                .reserve(path, OutputClaim::new("test", None))
+                .unwrap()
+        };
+
+        let files = render_json_files(
+            vec![
+                (reserve("index.json"), true, page("Home", "<p>Home</p>")),
+                (reserve("blog/post.json"), true, page("Post", "<p>Post</p>")),
+            ],
+            Some(reserve(INDEX_FILE)),
+        );
+
+        let paths: Vec<_> = files.iter().map(|(path, _)| path.as_path()).collect();
+
+        assert_eq!(
+            paths,
+            [
+                Path::new("blog/post.json"),
+                Path::new("index.json"),
+                Path::new("pages.json"),
+            ]
+        );
+
+        let post: serde_json::Value = serde_json::from_str(&files[0].1).unwrap();
+
+        assert_eq!(
+            post,
+            serde_json::json!({
+                "id": null,
+                "title": "Post",
+                "description": "About the post",
+                "date": "2024-03-10",
+                "tags": ["rust"],
+                "permalink": "/blog/post.html",
+                "summary": null,
+                "html": "<p>Post</p>",
+                "extra": { "cover": "cover.png" },
+            })
+        );
+
+        let index: serde_json::Value = serde_json::from_str(&files[2].1).unwrap();
+
+        assert_eq!(index[1]["title"], "Home");
+        assert!(index[0].get("html").is_none());
+    }
+
+    #[test]
+    fn only_indexable_pages_are_in_the_index() {
+        use toml::Value;
+
+        use crate::{
+            app::ProcessorApp,
+            config::{FileConfig, InputDir, OutputDir},
+            file::PageType,
+            manifest::Manifest,
+            processor::{MarkdownFrontMatter, MarkdownProcessor},
+        };
+
+        let dir = std::env::temp_dir().join("webvy_only_indexable_pages_are_in_the_index");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.clone()),
+        ));
+        app.world_mut().spawn(PageType::Page);
+        // Stands in for a page of another crate's processor, which didn't opt in.
+        app.world_mut().spawn((
+            FilePath::new("plugin.md".into()),
+            FileName(String::from("plugin.html")),
+            SourceFile::synthetic("plugin", "plugin.md"),
+            HtmlBody::new(String::from("<p>Plugin</p>")),
+        ));
+        app.insert_resource(toml::from_str::<SiteConfig>("[build]\njson_output = true").unwrap())
+            .init_resource::<Manifest>()
+            .add_page(
+                "about.html",
+                toml::Table::new(),
+                "<html><head><title>About us</title></head><body><h1>Hi</h1></body></html>",
+            )
+            .add_page(
+                "post.md",
+                toml::from_str("title = \"Post\"\ndate = 2024-05-01").unwrap(),
+                "# Heading",
+            )
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .add_processor(JsonProcessor::new())
+            .run()
+            .unwrap();
+
+        let index: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.join(INDEX_FILE)).unwrap()).unwrap();
+        let mut titles: Vec<_> = index
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|page| page["title"].as_str().unwrap())
+            .collect();
+        titles.sort();
+
+        assert_eq!(titles, ["About us", "Post"]);
+        assert!(!dir.join("plugin.json").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
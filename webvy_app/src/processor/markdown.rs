@@ -1,77 +1,289 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    hash::{DefaultHasher, Hash, Hasher},
     marker::PhantomData,
-    path::{Path, PathBuf},
+    path::{self, Path, PathBuf},
+    sync::Arc,
 };
 
 use bevy_ecs::{
+    change_detection::{DetectChanges, DetectChangesMut},
     component::Component,
     entity::Entity,
-    query::{With, Without},
-    schedule::IntoSystemConfigs,
-    system::{CommandQueue, Commands, EntityCommands, ParallelCommands, Query, Res, Resource},
+    query::{Changed, Has, With, Without},
+    schedule::{IntoSystemConfigs, IntoSystemSetConfigs, SystemSet},
+    system::{
+        CommandQueue, Commands, EntityCommands, ParallelCommands, Query, Res, ResMut, Resource,
+    },
     world::World,
 };
-use log::{error, info, trace};
-use pulldown_cmark::{html, Options, Parser};
+use bevy_tasks::ComputeTaskPool;
+use log::{debug, error, info, trace};
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use serde::de::DeserializeOwned;
 use toml::Value;
-use webvy_matterparser::Parser as FrontMatterParser;
+use url::Url;
+use webvy_matterparser::{ParseError, ParsedData, Parser as FrontMatterParser};
 
 use crate::{
-    app::{Load, Process, ProcessorApp},
+    app::{Finish, Load, LoadBatch, PendingBatches, PostProcess, Process, ProcessorApp},
+    compress::Codec,
+    config::{DiffMode, FileConfig, InputDir, OutputDir, SectionConfig, SiteConfig, SortBy},
     deferred::DeferredTask,
-    file::{FileName, FilePath, HtmlBody},
-    files::read_all_from_directory,
-    front_matter::{Date, Draft, Title},
+    errors::ProcessorError,
+    escape::escape_html_attr,
+    file::{
+        classify, classify_pages, CanonicalUrl, FileName, FilePath, HtmlBody, InSection, Indexable,
+        PageExtra, PageId, Permalink, ReadingTime, SectionIndex, SourceFile, Summary,
+        TableOfContents, TocEntry, VirtualContent, WordCount,
+    },
+    files::{
+        compressed_path, find_all_files_in_directory, read_decompressed_files, ReadError,
+        WalkError, WalkOptions,
+    },
+    front_matter::{
+        Authors, Date, Description, Draft, Extra, FieldMismatch, FrontMatterErrors,
+        FrontMatterKeys, Headless, MenuEntries, MenuEntry, Raw, Tags, TemplateOverride, Title,
+        TocLevels, Trusted, Unlisted, Weight,
+    },
+    html::{element_text, source_line_comment, truncate_words},
+    io::{create_directory, write_file_to_disk},
+    logging::{LOAD, WRITE},
+    manifest::{record_removed_outputs, Manifest},
+    menu::Menus,
+    output::{locate, safe_join, OutputRegistry, StaleOutputs},
+    panic::{catch_page_panic, PagePanicked},
+    report::{BuildErrors, BuildReport, Diagnostics},
+    sanitize::Sanitizer,
+    slug::{slugify, unique_slug},
+    timezone::parse_date,
     traits::{Extractor, ProcessorPlugin},
+    value::table_to_json,
 };
 
-use super::configuration::{FileConfig, InputDir};
+use super::{
+    indexes::{build_indexes, DateIndex, TaxonomyIndex},
+    menus::build_menus,
+    sections::{build_section_posts, link_sections, SectionPosts},
+};
 
 pub struct MarkdownProcessor<T: Extractor> {
+    matter_components: Vec<MatterComponent>,
     _marker: PhantomData<T>,
 }
 
 impl<T: Extractor + Send + Sync> MarkdownProcessor<T> {
     pub fn new() -> Self {
         Self {
+            matter_components: Vec::new(),
             _marker: PhantomData,
         }
     }
 
+    /// Deserializes the `key` table of a page's front matter into `C`, inserting it on
+    /// the page when present. Values that don't deserialize are reported, and left out.
+    pub fn register_matter_component<C: DeserializeOwned + Component>(
+        mut self,
+        key: impl Into<String>,
+    ) -> Self {
+        self.matter_components.push(MatterComponent {
+            key: key.into(),
+            insert: Box::new(|value: &Value, entity: &mut EntityCommands| {
+                let component: C = value.clone().try_into().map_err(|e| e.to_string())?;
+
+                entity.insert(component);
+
+                Ok(())
+            }),
+        });
+        self
+    }
+
     fn read_content_directory_task(
         q_config: Query<&InputDir, With<FileConfig>>,
+        config: Res<SiteConfig>,
+        mut virtual_content: ResMut<VirtualContent>,
         deferred: Res<DeferredTask>,
     ) {
-        let path = q_config.single().path().to_path_buf();
+        let roots = q_config.single().paths().to_vec();
+        let virtual_pages = std::mem::take(&mut virtual_content.0);
+        let batch_size = config.build.batch_size;
+        let walk = WalkOptions::from_config(&config);
+        let codecs = config.build.decompress.clone();
 
         deferred
-            .scoped_task(|scope| async move {
+            .scoped_task(move |scope| async move {
                 let mut command_queue = CommandQueue::default();
 
-                info!("Reading markdown content from disk");
+                info!(target: LOAD, "Reading markdown content from disk");
+
+                let mut origins: HashMap<PathBuf, PathBuf> = HashMap::new();
+                let mut files = Vec::new();
+                let mut errors = Vec::new();
 
-                let data = read_all_from_directory(path.as_path())
-                    .await
-                    .into_iter()
-                    .filter_map(|res| match res {
-                        Ok(file) => Some(file),
+                for root in roots.iter() {
+                    if !smol::fs::metadata(root)
+                        .await
+                        .is_ok_and(|meta| meta.is_dir())
+                    {
+                        let error = ProcessorError::MissingContentDir { path: root.clone() };
+
+                        error!("{}", error);
+                        errors.push(error);
+
+                        continue;
+                    }
+
+                    let found = match find_all_files_in_directory(root.as_path(), walk).await {
+                        Ok(found) => found,
+                        Err(WalkError::Io(err)) => {
+                            error!("Error reading directory: {}", err);
+
+                            continue;
+                        }
                         Err(err) => {
-                            error!("Error reading file: {}", err);
+                            let error = ProcessorError::from(err);
+
+                            error!("{}", error);
+                            errors.push(error);
 
-                            None
+                            continue;
                         }
-                    })
-                    .scan(path, |origin, (page_path, content)| {
-                        let page_path = page_path.strip_prefix(origin).unwrap().to_path_buf();
+                    };
+
+                    for source in found {
+                        let page_path = source.strip_prefix(root).unwrap();
+                        // Compressed pages are processed as if they were read as is.
+                        let page_path = compressed_path(&codecs, page_path)
+                            .map_or_else(|| page_path.to_path_buf(), |(_, path)| path);
+
+                        if virtual_pages.contains_key(&page_path) {
+                            let error = ProcessorError::VirtualCollision {
+                                path: page_path,
+                                root: root.clone(),
+                            };
+
+                            error!("{}", error);
+                            errors.push(error);
+
+                            continue;
+                        }
+
+                        if let Some(first) = origins.get(&page_path) {
+                            let error = ProcessorError::ContentCollision {
+                                path: page_path,
+                                first: first.clone(),
+                                second: root.clone(),
+                            };
+
+                            error!("{}", error);
+                            errors.push(error);
+
+                            continue;
+                        }
+
+                        origins.insert(page_path.clone(), root.clone());
+                        files.push((source, page_path));
+                    }
+                }
+
+                let missing_dirs = errors
+                    .iter()
+                    .any(|error| matches!(error, ProcessorError::MissingContentDir { .. }));
+                let pages_found = files.len() + virtual_pages.len();
+                let listed: Arc<HashSet<PathBuf>> =
+                    Arc::new(files.iter().map(|(source, _)| source.clone()).collect());
+
+                let mut batches: VecDeque<_> = match batch_size {
+                    0 => VecDeque::from([files]),
+                    size => files.chunks(size).map(<[_]>::to_vec).collect(),
+                };
+                let (pages, failures) =
+                    read_content(batches.pop_front().unwrap_or_default(), &codecs).await;
+
+                command_queue.push(move |world: &mut World| {
+                    // A missing directory is already an error, so only flag empty ones
+                    if pages_found == 0 && !missing_dirs {
+                        world.resource_mut::<Diagnostics>().warning(
+                            None,
+                            "no-content",
+                            format!(
+                                "No content found in {}, nothing will be rendered",
+                                roots
+                                    .iter()
+                                    .map(|root| root.display().to_string())
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            ),
+                        );
+                    }
+
+                    report_unreadable_content(world, failures);
+                    spawn_content(world, &listed, pages);
+
+                    // Batches left over by an interrupted build are replaced by these.
+                    let stale = world
+                        .remove_resource::<PendingContent>()
+                        .map_or(0, |pending| pending.batches.len());
+                    let mut pending = world.resource_mut::<PendingBatches>();
 
-                        trace!("Spawning {}", page_path.display());
+                    pending.0 = pending.0 - stale + batches.len();
+                    world.insert_resource(PendingContent { listed, batches });
+                    world.resource_mut::<BuildReport>().pages_loaded += virtual_pages.len();
 
-                        Some((FilePath::new(page_path), MarkdownPost(content)))
-                    });
+                    for (path, page) in virtual_pages {
+                        trace!("Spawning virtual page {}", path.display());
+
+                        let html = is_html(&path);
+                        let mut entity = world.spawn((
+                            SourceFile::synthetic("virtual", &path),
+                            FilePath::new(path),
+                            MarkdownPost::new(String::new()),
+                            MarkdownBody(page.body),
+                            MarkdownFrontMatter(Some(page.front_matter)),
+                        ));
+
+                        if html {
+                            entity.insert(HtmlSource);
+                        }
+                    }
+
+                    world.resource_mut::<BuildErrors>().0.extend(errors);
+                });
+
+                scope.send(command_queue);
+            })
+            .detach();
+    }
+
+    fn read_content_batch_task(
+        pending: Option<ResMut<PendingContent>>,
+        config: Res<SiteConfig>,
+        mut batches: ResMut<PendingBatches>,
+        deferred: Res<DeferredTask>,
+    ) {
+        let Some(mut pending) = pending else {
+            return;
+        };
+
+        let Some(files) = pending.batches.pop_front() else {
+            return;
+        };
+
+        let listed = pending.listed.clone();
+        let codecs = config.build.decompress.clone();
+        batches.0 -= 1;
+
+        deferred
+            .scoped_task(|scope| async move {
+                info!(target: LOAD, "Reading the next {} content files from disk", files.len());
+
+                let (pages, failures) = read_content(files, &codecs).await;
+                let mut command_queue = CommandQueue::default();
 
                 command_queue.push(move |world: &mut World| {
-                    world.spawn_batch(data);
+                    report_unreadable_content(world, failures);
+                    spawn_content(world, &listed, pages);
                 });
 
                 scope.send(command_queue);
@@ -80,138 +292,3459 @@ impl<T: Extractor + Send + Sync> MarkdownProcessor<T> {
     }
 
     fn parse_page_format(
-        commands: ParallelCommands,
-        q_pages: Query<(Entity, &MarkdownPost, &FilePath)>,
+        mut commands: Commands,
+        config: Res<SiteConfig>,
+        q_pages: Query<
+            (
+                Entity,
+                &MarkdownPost,
+                &FilePath,
+                &SourceFile,
+                Has<HtmlSource>,
+            ),
+            Without<MarkdownBody>,
+        >,
+        mut diagnostics: ResMut<Diagnostics>,
     ) {
         info!("Parsing the page format into front matter and body components");
-        let matter = FrontMatterParser::default();
-
-        q_pages.par_iter().for_each(|(page, content, path)| {
-            if let Some(mut markdown) = matter.parse(&content.0) {
-                trace!("Parsing markdown: {}", path.as_ref().display());
-                commands.command_scope(move |mut commands| {
-                    commands.entity(page).insert((
-                        MarkdownBody(markdown.take_content()),
-                        MarkdownFrontMatter(markdown.take_matter()),
-                    ));
-                });
-            } else {
-                error!("Couldn't parse page: {}", path.as_ref().display());
+        let parsers = config.markdown.front_matter_parsers();
+        let source_maps = config.markdown.source_maps;
+        let pages: Vec<_> = q_pages.iter().collect();
+
+        let parsed = map_in_batches(&pages, |&(_, post, path, _, html)| {
+            match parse_front_matter(&parsers, &post.content) {
+                Ok(mut markdown) => {
+                    trace!("Parsing markdown: {}", path.as_ref().display());
+                    let excerpt = markdown.take_excerpt();
+                    let content = markdown.take_content();
+                    let matter = MarkdownFrontMatter(markdown.take_matter());
+                    let lines = (source_maps && !html)
+                        .then(|| BodyLines::new(&post.content, excerpt.as_deref(), &content));
+
+                    Ok(match excerpt {
+                        // The excerpt stays part of the page, the marker is dropped.
+                        Some(excerpt) => (
+                            MarkdownBody(format!("{}\n\n{}", excerpt, content)),
+                            matter,
+                            Some(MarkdownExcerpt(excerpt)),
+                            lines,
+                        ),
+                        None => (MarkdownBody(content), matter, None, lines),
+                    })
+                }
+                // Front matter is optional for HTML pages
+                Err(ParseError::MissingFrontMatter) if html => Ok((
+                    MarkdownBody(post.content.clone()),
+                    MarkdownFrontMatter(None),
+                    None,
+                    None,
+                )),
+                Err(ParseError::MissingFrontMatter) => Err((
+                    "invalid-page",
+                    String::from("Couldn't parse the page into front matter and body"),
+                )),
+                Err(error @ ParseError::InvalidMatter { .. }) => {
+                    Err(("invalid-front-matter", error.to_string()))
+                }
+                Err(error) => Err(("invalid-page", error.to_string())),
             }
         });
+
+        let mut bodies = Vec::with_capacity(parsed.len());
+        let mut excerpts = Vec::new();
+        let mut body_lines = Vec::new();
+
+        for (&(page, _, _, source, _), parsed) in pages.iter().zip(parsed) {
+            match parsed {
+                Ok(Ok((body, matter, excerpt, lines))) => {
+                    bodies.push((page, (body, matter)));
+                    excerpts.extend(excerpt.map(|excerpt| (page, excerpt)));
+                    body_lines.extend(lines.map(|lines| (page, lines)));
+                }
+                Ok(Err((code, error))) => {
+                    diagnostics.error(source.as_ref().to_path_buf(), code, error)
+                }
+                Err(message) => commands.add(PagePanicked {
+                    source: Some(source.as_ref().to_path_buf()),
+                    stage: "parsing front matter",
+                    message,
+                }),
+            }
+        }
+
+        commands.insert_or_spawn_batch(bodies);
+        commands.insert_or_spawn_batch(excerpts);
+        commands.insert_or_spawn_batch(body_lines);
+    }
+
+    /// Writes what [`parse_page_format`](Self::parse_page_format) sees of each page
+    /// into the [`MatterDebug`] directory, as `<page path>.json`: the front matter as
+    /// written, the table it parsed into or why it didn't.
+    fn dump_front_matter(
+        config: Res<SiteConfig>,
+        debug: Option<Res<MatterDebug>>,
+        q_config: Query<&OutputDir, With<FileConfig>>,
+        q_pages: Query<(&MarkdownPost, &FilePath, &SourceFile), Without<MarkdownBody>>,
+        deferred: Res<DeferredTask>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        let Some(dir) = debug.map(|debug| debug.path().to_path_buf()) else {
+            return;
+        };
+
+        // Dumps are never outputs, so they mustn't be left among them.
+        if q_config
+            .get_single()
+            .is_ok_and(|output| dir.starts_with(output.path()))
+        {
+            diagnostics.warning(
+                None,
+                "debug-in-output",
+                format!(
+                    "Not dumping front matter into {}, which is within the output directory",
+                    dir.display()
+                ),
+            );
+            return;
+        }
+
+        let parsers = config.markdown.front_matter_parsers();
+        let dumps: Vec<_> = q_pages
+            .iter()
+            .map(|(post, path, source)| {
+                let raw = parsers
+                    .iter()
+                    .find_map(|parser| parser.split(&post.content).ok())
+                    .map(|(matter, _)| matter);
+                let (parsed, error) = match parse_front_matter(&parsers, &post.content) {
+                    Ok(mut parsed) => (
+                        parsed.take_matter().map(|table| table_to_json(&table)),
+                        None,
+                    ),
+                    Err(error) => (None, Some(error.to_string())),
+                };
+                let dump = serde_json::json!({
+                    "source": source.as_ref(),
+                    "raw": raw,
+                    "parsed": parsed,
+                    "error": error,
+                });
+
+                let mut file = dir.join(path.as_ref()).into_os_string();
+                file.push(".json");
+
+                (
+                    PathBuf::from(file),
+                    serde_json::to_string_pretty(&dump).unwrap_or_default(),
+                )
+            })
+            .collect();
+
+        if dumps.is_empty() {
+            return;
+        }
+
+        info!(
+            "Dumping the front matter of {} pages into {}",
+            dumps.len(),
+            dir.display()
+        );
+
+        deferred
+            .scoped_task(move |_scope| async move {
+                for (file, dump) in dumps {
+                    let result = async {
+                        if let Some(parent) = file.parent() {
+                            create_directory(parent).await?;
+                        }
+
+                        write_file_to_disk(&file, dump.as_bytes()).await
+                    }
+                    .await;
+
+                    if let Err(e) = result {
+                        error!("Unable to dump front matter to {}: {}", file.display(), e);
+                    }
+                }
+            })
+            .detach();
     }
 
     fn parse_frontmatter(
         mut commands: Commands,
         q_markdown: Query<
-            (Entity, &MarkdownFrontMatter, &FilePath),
+            (Entity, &MarkdownFrontMatter, &FilePath, &SourceFile),
             (With<MarkdownPost>, Without<MarkdownParsed>),
         >,
+        matter_components: Res<MatterComponents>,
+        mut diagnostics: ResMut<Diagnostics>,
     ) {
         info!("Parsing frontmatter from loaded markdown pages");
-        q_markdown.iter().for_each(|(entity, front_matter, path)| {
-            let mut post = commands.entity(entity);
+        q_markdown
+            .iter()
+            .for_each(|(entity, front_matter, path, source)| {
+                let mut post = commands.entity(entity);
 
-            front_matter.extract_from_path(&mut post, path.as_ref());
-            front_matter.extract(&mut post);
+                front_matter.extract_from_path(&mut post, path.as_ref());
+                front_matter.extract(&mut post);
 
-            post.insert(MarkdownParsed);
-        });
+                for component in matter_components.0.iter() {
+                    let Some(value) = front_matter
+                        .access()
+                        .and_then(|data| data.get(&component.key))
+                    else {
+                        continue;
+                    };
+
+                    if let Err(e) = (component.insert)(value, &mut post) {
+                        diagnostics.error(
+                            source.as_ref().to_path_buf(),
+                            "invalid-front-matter",
+                            format!("{} couldn't be read: {}", component.key, e.trim_end()),
+                        );
+                    }
+                }
+
+                post.insert(MarkdownParsed);
+            });
     }
 
-    fn convert_markdown_to_html(
-        par_commands: ParallelCommands,
-        q_markdown: Query<(Entity, &MarkdownBody), (With<MarkdownPost>, Without<HtmlBody>)>,
+    /// Interprets the dates of pages in the site's timezone, which front matter is
+    /// extracted without. Every date is revisited when the configuration changes.
+    fn localize_dates(config: Res<SiteConfig>, mut q_dates: Query<&mut Date>) {
+        let Some(timezone) = config.timezone.as_ref() else {
+            return;
+        };
+
+        for mut date in q_dates.iter_mut() {
+            if !config.is_changed() && !date.is_changed() {
+                continue;
+            }
+
+            let utc = parse_date(&date.original, Some(timezone));
+
+            if date.utc != utc {
+                date.utc = utc;
+            }
+        }
+    }
+
+    fn check_front_matter_types(
+        mut commands: Commands,
+        q_markdown: Query<(Entity, &SourceFile, &FrontMatterErrors), With<MarkdownParsed>>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        for (entity, source, errors) in q_markdown.iter() {
+            for mismatch in errors.0.iter() {
+                diagnostics.error(
+                    source.as_ref().to_path_buf(),
+                    "invalid-front-matter",
+                    mismatch.to_string(),
+                );
+            }
+
+            commands.entity(entity).remove::<FrontMatterErrors>();
+        }
+    }
+
+    fn check_front_matter_keys(
+        q_markdown: Query<(&SourceFile, &MarkdownFrontMatter), With<MarkdownParsed>>,
+        keys: Res<FrontMatterKeys>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        for (source, front_matter) in q_markdown.iter() {
+            let Some(data) = front_matter.access() else {
+                continue;
+            };
+
+            for key in data.keys().filter(|key| !keys.contains(key)) {
+                let message = match keys.suggest(key) {
+                    Some(known) => {
+                        format!("Unknown front matter key {}, did you mean {}?", key, known)
+                    }
+                    None => format!("Unknown front matter key {}", key),
+                };
+
+                diagnostics.warning(
+                    source.as_ref().to_path_buf(),
+                    "unknown-front-matter",
+                    message,
+                );
+            }
+        }
+    }
+
+    fn check_post_dates(
+        q_markdown: Query<(&FilePath, &SourceFile, &InSection, Has<Date>), With<MarkdownParsed>>,
+        q_sections: Query<Option<&SectionConfig>>,
+        mut diagnostics: ResMut<Diagnostics>,
     ) {
-        info!("Parsing frontmatter from markdown page");
         q_markdown
-            .par_iter()
-            .for_each(|(entity, MarkdownBody(body))| {
-                let parser = Parser::new_ext(body, Options::all());
-                let mut html = String::new();
-                html::push_html(&mut html, parser);
-                par_commands.command_scope(move |mut commands| {
-                    commands.entity(entity).insert(HtmlBody::new(html));
-                });
+            .iter()
+            .filter(|(.., has_date)| !has_date)
+            .filter(|(path, ..)| !path.as_ref().ends_with("_index.md"))
+            .filter_map(|(_, source, InSection(section), _)| {
+                let section_config = q_sections.get(*section).ok()?;
+
+                // Only posts in sections sorted by date need one.
+                (section_config.map_or(SortBy::default(), SectionConfig::sort_by) == SortBy::Date)
+                    .then_some(source)
+            })
+            .for_each(|source| {
+                diagnostics.warning(
+                    source.as_ref().to_path_buf(),
+                    "missing-date",
+                    "Post has no date",
+                );
             });
     }
-}
 
-impl<T: Extractor + Send + Sync + 'static> ProcessorPlugin for MarkdownProcessor<T> {
-    fn register(self, app: &mut ProcessorApp) {
-        app.add_systems(Load, Self::read_content_directory_task)
-            .add_systems(
-                Process,
-                (
-                    Self::parse_page_format,
-                    (Self::parse_frontmatter, Self::convert_markdown_to_html),
-                )
-                    .chain(),
-            );
+    fn check_descriptions(
+        config: Res<SiteConfig>,
+        q_markdown: Query<(&SourceFile, &Description), With<MarkdownParsed>>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        let limit = config.markdown.description_length;
+
+        q_markdown
+            .iter()
+            .filter(|(_, description)| description.0.chars().count() > limit)
+            .for_each(|(source, description)| {
+                diagnostics.warning(
+                    source.as_ref().to_path_buf(),
+                    "long-description",
+                    format!(
+                        "Description is {} characters long, search engines may cut it past {}",
+                        description.0.chars().count(),
+                        limit
+                    ),
+                );
+            });
     }
-}
 
-impl<T: Extractor + Send + Sync> Default for MarkdownProcessor<T> {
-    fn default() -> Self {
-        Self::new()
+    fn check_authors(
+        config: Res<SiteConfig>,
+        q_markdown: Query<(&SourceFile, &Authors), With<MarkdownParsed>>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        for (source, authors) in q_markdown.iter() {
+            authors
+                .0
+                .iter()
+                .filter(|key| config.author(key).is_none())
+                .for_each(|key| {
+                    diagnostics.warning(
+                        source.as_ref().to_path_buf(),
+                        "unknown-author",
+                        format!("Author {} isn't configured under [authors]", key),
+                    );
+                });
+        }
     }
-}
 
-#[derive(Debug, Component)]
-pub struct MarkdownFrontMatter(Option<toml::Table>);
+    /// Fails the build when pages share an id, reported against all but the first
+    /// page by source path.
+    fn check_page_ids(
+        q_pages: Query<(&SourceFile, &PageId), With<MarkdownParsed>>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        let mut pages: Vec<_> = q_pages.iter().collect();
+        pages.sort_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
 
-impl MarkdownFrontMatter {
-    pub fn access(&self) -> Option<&toml::Table> {
-        self.0.as_ref()
+        let mut first: HashMap<&PageId, &SourceFile> = HashMap::new();
+
+        for (source, id) in pages {
+            match first.get(id) {
+                Some(first) => diagnostics.error(
+                    source.as_ref().to_path_buf(),
+                    "duplicate-page-id",
+                    format!(
+                        "Page id {} is already used by {}",
+                        id,
+                        first.as_ref().display()
+                    ),
+                ),
+                None => {
+                    first.insert(id, source);
+                }
+            }
+        }
     }
-}
 
-impl Extractor for MarkdownFrontMatter {
-    fn extract(&self, entity: &mut EntityCommands) {
-        if let Some(data) = self.access() {
-            if let Some(title) = data.get("title").map(Value::to_string) {
-                entity.insert(Title(title));
+    fn validate_canonical_urls(
+        mut commands: Commands,
+        q_markdown: Query<(Entity, &SourceFile, &CanonicalUrl), With<MarkdownParsed>>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        q_markdown
+            .iter()
+            .filter(|(.., canonical)| {
+                !Url::parse(canonical.as_ref())
+                    .is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+            })
+            .for_each(|(entity, source, canonical)| {
+                diagnostics.warning(
+                    source.as_ref().to_path_buf(),
+                    "invalid-canonical",
+                    format!(
+                        "Canonical URL {} isn't an absolute http(s) URL, ignoring it",
+                        canonical.as_ref()
+                    ),
+                );
+
+                commands.entity(entity).remove::<CanonicalUrl>();
+            });
+    }
+
+    fn assign_permalinks(
+        mut commands: Commands,
+        config: Res<SiteConfig>,
+        q_markdown: Query<
+            (Entity, &FilePath, &FileName),
+            (With<MarkdownPost>, Without<Permalink>, Without<Headless>),
+        >,
+    ) {
+        q_markdown.iter().for_each(|(entity, path, file_name)| {
+            let mut page = commands.entity(entity);
+
+            // Indexes are written as whatever the host serves for a directory.
+            let file_name = match path.as_ref().file_stem() {
+                Some(stem) if stem == "_index" && file_name.0 == "index.html" => {
+                    page.insert(FileName(config.build.index_file.clone()));
+                    &config.build.index_file
+                }
+                _ => &file_name.0,
+            };
+            let location = locate(&config, path.as_ref().with_file_name(file_name));
+
+            page.insert(Permalink(location.url));
+        });
+    }
+
+    /// Merges the `extra` values of every page over those of its section and the site.
+    /// A section's values come from its `_index.md`.
+    fn cascade_extra(
+        mut commands: Commands,
+        config: Res<SiteConfig>,
+        q_pages: Query<
+            (
+                Entity,
+                Option<&InSection>,
+                Option<&Extra>,
+                Option<&PageExtra>,
+            ),
+            With<MarkdownPost>,
+        >,
+        q_sections: Query<&SectionIndex>,
+    ) {
+        for (page, in_section, extra, current) in q_pages.iter() {
+            let mut merged = config.extra.clone();
+
+            if let Some((.., Some(section_extra), _)) = in_section
+                .and_then(|InSection(section)| q_sections.get(*section).ok())
+                .and_then(|SectionIndex(index)| q_pages.get(*index).ok())
+            {
+                merge_extra(&mut merged, &section_extra.0);
             }
 
-            if let Some(date) = data.get("date").map(Value::to_string) {
-                entity.insert(Date(date));
+            if let Some(extra) = extra {
+                merge_extra(&mut merged, &extra.0);
             }
 
-            if data
-                .get("draft")
-                .and_then(|value| value.as_bool())
-                .is_some_and(|draft| draft)
-            {
-                entity.insert(Draft);
+            // Left alone when unchanged, so the page isn't rendered again.
+            if current.map(|current| &current.0) != Some(&merged) {
+                commands.entity(page).insert(PageExtra(merged));
             }
         }
     }
 
-    fn extract_from_path(&self, entity: &mut EntityCommands, path: &Path) {
-        if let Some(file_name) = path
-            .file_name()
-            .and_then(|file_name| file_name.to_str())
-            .map(|file_name| {
-                if file_name.contains("_index") {
-                    String::from("index.html")
-                } else {
-                    format!("{}.html", file_name.trim_end_matches(".md"))
-                }
-            })
-        {
-            entity.insert(FileName(file_name));
+    fn convert_markdown_to_html(
+        mut commands: Commands,
+        config: Res<SiteConfig>,
+        q_markdown: Query<
+            (
+                Entity,
+                &SourceFile,
+                &MarkdownBody,
+                Option<&TocLevels>,
+                Option<&BodyLines>,
+            ),
+            (With<MarkdownPost>, Without<HtmlSource>, Without<HtmlBody>),
+        >,
+    ) {
+        info!("Parsing frontmatter from markdown page");
+        let pages: Vec<_> = q_markdown.iter().collect();
+
+        let converted = map_in_batches(&pages, |&(_, _, MarkdownBody(body), levels, lines)| {
+            let (html, mut toc) = render_markdown(body, &config.markdown.classes, lines);
+            let levels = levels.map_or(&config.markdown.toc_levels, |levels| &levels.0);
+
+            // Headings keep their anchors even when left out of the table.
+            toc.retain(|entry| levels.contains(&entry.level));
+
+            (HtmlBody::new(html), TableOfContents(toc))
+        });
+
+        let mut bodies = Vec::with_capacity(converted.len());
+
+        for (&(page, source, ..), converted) in pages.iter().zip(converted) {
+            match converted {
+                Ok(converted) => bodies.push((page, converted)),
+                Err(message) => commands.add(PagePanicked {
+                    source: Some(source.as_ref().to_path_buf()),
+                    stage: "rendering markdown",
+                    message,
+                }),
+            }
         }
+
+        commands.insert_or_spawn_batch(bodies);
     }
-}
 
-#[derive(Debug, Clone, Component)]
-struct MarkdownBody(String);
+    /// Warns about kinds of element under `[markdown.classes]` that aren't classed.
+    fn check_classes(config: Res<SiteConfig>, mut diagnostics: ResMut<Diagnostics>) {
+        let mut unknown: Vec<_> = config
+            .markdown
+            .classes
+            .keys()
+            .filter(|kind| !CLASSED_ELEMENTS.contains(&kind.as_str()))
+            .collect();
+        unknown.sort();
 
-#[derive(Debug, Component)]
-pub struct MarkdownPost(String);
+        for kind in unknown {
+            diagnostics.warning(
+                None,
+                "unknown-class-element",
+                format!(
+                    "[markdown.classes] has a class for {}, which isn't one of {}",
+                    kind,
+                    CLASSED_ELEMENTS.join(", ")
+                ),
+            );
+        }
+    }
 
-#[derive(Debug, Component)]
-struct MarkdownParsed;
+    fn summarize_pages(
+        par_commands: ParallelCommands,
+        config: Res<SiteConfig>,
+        q_pages: Query<
+            (
+                Entity,
+                &SourceFile,
+                &HtmlBody,
+                Option<&MarkdownExcerpt>,
+                Has<HtmlSource>,
+                Has<Trusted>,
+            ),
+            (With<MarkdownPost>, Without<Summary>),
+        >,
+    ) {
+        let markdown = &config.markdown;
+        // Excerpts are taken from the source, so they need sanitizing like the body did
+        let sanitizer = (markdown.sanitize.enabled && Sanitizer::is_available())
+            .then(|| Sanitizer::new(&markdown.sanitize));
+
+        q_pages
+            .par_iter()
+            .for_each(|(entity, source, body, excerpt, html_source, trusted)| {
+                let summary = catch_page_panic(|| match excerpt {
+                    Some(MarkdownExcerpt(excerpt)) => {
+                        let excerpt = if html_source {
+                            excerpt.clone()
+                        } else {
+                            markdown_to_html(excerpt, &markdown.classes)
+                        };
+                        let excerpt = match &sanitizer {
+                            Some(sanitizer) if !trusted => sanitizer.clean(&excerpt),
+                            _ => excerpt,
+                        };
+
+                        if markdown.summary_strip_markup {
+                            truncate_words(&excerpt, usize::MAX, true)
+                        } else {
+                            excerpt.trim_end().to_string()
+                        }
+                    }
+                    None => truncate_words(
+                        body.as_ref(),
+                        markdown.summary_length,
+                        markdown.summary_strip_markup,
+                    ),
+                });
+
+                par_commands.command_scope(move |mut commands| match summary {
+                    Ok(summary) => {
+                        commands.entity(entity).insert(Summary(summary.into()));
+                    }
+                    Err(message) => commands.add(PagePanicked {
+                        source: Some(source.as_ref().to_path_buf()),
+                        stage: "summarizing",
+                        message,
+                    }),
+                });
+            });
+    }
+
+    fn apply_typography(
+        mut commands: Commands,
+        par_commands: ParallelCommands,
+        config: Res<SiteConfig>,
+        mut q_html: Query<
+            (Entity, &SourceFile, &mut HtmlBody),
+            (With<MarkdownPost>, Without<HtmlSource>, Without<Typeset>),
+        >,
+    ) {
+        let Some(typographer) = config.markdown.typographer() else {
+            return;
+        };
+
+        let typeset: Vec<_> = q_html.iter().map(|(page, ..)| (page, Typeset)).collect();
+        commands.insert_or_spawn_batch(typeset);
+
+        info!("Applying typography rules to rendered markdown");
+        q_html.par_iter_mut().for_each(|(_, source, mut html)| {
+            match catch_page_panic(|| typographer.apply((*html).as_ref())) {
+                Ok(typeset) => *html = HtmlBody::new(typeset),
+                Err(message) => report_panic(&par_commands, Some(source), "typesetting", message),
+            }
+        });
+    }
+
+    /// Counts the words of pages whose body changed, and how long they take to read.
+    fn measure_pages(
+        mut commands: Commands,
+        config: Res<SiteConfig>,
+        q_pages: Query<(Entity, &HtmlBody, Option<&WordCount>), Changed<HtmlBody>>,
+    ) {
+        let per_minute = config.markdown.words_per_minute.max(1);
+
+        for (page, html, counted) in q_pages.iter() {
+            let words = truncate_words(html.as_ref(), usize::MAX, true)
+                .split_whitespace()
+                .count();
+
+            if counted != Some(&WordCount(words)) {
+                commands
+                    .entity(page)
+                    .insert((WordCount(words), ReadingTime(words.div_ceil(per_minute))));
+            }
+        }
+    }
+
+    fn sanitize_pages(
+        mut commands: Commands,
+        config: Res<SiteConfig>,
+        par_commands: ParallelCommands,
+        mut q_html: Query<
+            (Entity, Option<&SourceFile>, &mut HtmlBody),
+            (Without<Trusted>, Without<Sanitized>),
+        >,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        let sanitize = &config.markdown.sanitize;
+
+        if !sanitize.enabled {
+            return;
+        }
 
-#[derive(Debug, Default, Resource)]
-pub struct SectionIndex(pub HashMap<PathBuf, Vec<Entity>>);
+        if !Sanitizer::is_available() {
+            diagnostics.error(
+                None,
+                "sanitize-unavailable",
+                "webvy was built without sanitize support, so pages can't be sanitized",
+            );
+            return;
+        }
+
+        // Comments marking source lines are what `source_maps` is for.
+        let sanitizer = match config.markdown.source_maps {
+            true => Sanitizer::new(sanitize).keeping_comments(),
+            false => Sanitizer::new(sanitize),
+        };
+        let sanitized: Vec<_> = q_html.iter().map(|(page, ..)| (page, Sanitized)).collect();
+        commands.insert_or_spawn_batch(sanitized);
+
+        info!("Sanitizing rendered pages");
+        q_html.par_iter_mut().for_each(|(_, source, mut html)| {
+            match catch_page_panic(|| sanitizer.clean((*html).as_ref())) {
+                Ok(clean) => *html = HtmlBody::new(clean),
+                Err(message) => report_panic(&par_commands, source, "sanitizing", message),
+            }
+        });
+    }
+
+    fn remove_stale_outputs(
+        q_config: Query<&OutputDir, With<FileConfig>>,
+        config: Res<SiteConfig>,
+        mut stale: ResMut<StaleOutputs>,
+        registry: Res<OutputRegistry>,
+        manifest: Option<ResMut<Manifest>>,
+        deferred: Res<DeferredTask>,
+    ) {
+        // Outputs produced again, e.g. by the page an edited one was replaced with,
+        // are left in place.
+        let stale: Vec<_> = std::mem::take(&mut stale.0)
+            .into_iter()
+            .filter(|output| registry.claim(output).is_none())
+            .collect();
+
+        let Ok(dir) = q_config.get_single() else {
+            return;
+        };
+
+        if stale.is_empty() {
+            return;
+        }
+
+        if let Some(mut manifest) = manifest {
+            manifest.forget(stale.iter().map(PathBuf::as_path));
+        }
+
+        // A diff build reports them as removed instead.
+        if config.build.diff != DiffMode::Off {
+            return;
+        }
+
+        let files: Vec<_> = stale
+            .iter()
+            .filter_map(|output| {
+                // Stale outputs come from the manifest left on disk, so aren't trusted.
+                safe_join(dir.path(), output)
+                    .inspect_err(|e| error!("{}", e))
+                    .ok()
+            })
+            .flat_map(|file| {
+                let siblings = [Codec::Gzip, Codec::Brotli].map(|codec| {
+                    let mut sibling = file.clone().into_os_string();
+                    sibling.push(".");
+                    sibling.push(codec.extension());
+                    PathBuf::from(sibling)
+                });
+
+                std::iter::once(file).chain(siblings)
+            })
+            .collect();
+
+        deferred
+            .scoped_task(|_| async move {
+                info!(target: WRITE, "Removing the outputs of removed pages");
+
+                for file in files {
+                    match smol::fs::remove_file(&file).await {
+                        Ok(()) => trace!("Removed {}", file.display()),
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                        Err(e) => error!("Unable to remove {}: {}", file.display(), e),
+                    }
+                }
+            })
+            .detach();
+    }
+
+    /// Drops the raw markdown of rendered pages, unless they're marked to keep it.
+    fn release_raw_content(
+        mut q_pages: Query<
+            (&mut MarkdownPost, Option<&mut MarkdownBody>),
+            (With<HtmlBody>, Without<KeepMarkdown>),
+        >,
+    ) {
+        for (mut post, body) in q_pages.iter_mut() {
+            if !post.content.is_empty() {
+                post.content = String::new();
+            }
+
+            if let Some(mut body) = body.filter(|body| !body.0.is_empty()) {
+                body.0 = String::new();
+            }
+        }
+    }
+
+    /// Attaches an [`Indexable`] to every page that's written out. HTML pages without a
+    /// `title` in their front matter are titled by their `<title>`, or failing that
+    /// their first `<h1>`.
+    fn index_pages(
+        mut commands: Commands,
+        q_pages: Query<
+            (
+                Entity,
+                Option<&Title>,
+                Option<&Date>,
+                &HtmlBody,
+                Option<&Indexable>,
+                Has<HtmlSource>,
+                Has<Draft>,
+                Has<Unlisted>,
+            ),
+            (With<MarkdownPost>, Without<Headless>),
+        >,
+    ) {
+        for (page, title, date, html, indexed, is_html, draft, unlisted) in q_pages.iter() {
+            let title = title.map(|title| title.0.clone()).or_else(|| {
+                is_html
+                    .then(|| {
+                        element_text(html.as_ref(), "title")
+                            .or_else(|| element_text(html.as_ref(), "h1"))
+                    })
+                    .flatten()
+            });
+            let indexable = Indexable {
+                title,
+                date: date.and_then(Date::to_datetime),
+                sitemap: !draft && !unlisted,
+                search: !unlisted,
+            };
+
+            if indexed != Some(&indexable) {
+                commands.entity(page).insert(indexable);
+            }
+        }
+    }
+
+    fn use_html_bodies(
+        mut commands: Commands,
+        q_html: Query<(Entity, &MarkdownBody), (With<HtmlSource>, Without<HtmlBody>)>,
+    ) {
+        q_html.iter().for_each(|(entity, MarkdownBody(body))| {
+            commands.entity(entity).insert(HtmlBody::new(body.clone()));
+        });
+    }
+}
+
+impl<T: Extractor + Send + Sync + 'static> ProcessorPlugin for MarkdownProcessor<T> {
+    fn register(self, app: &mut ProcessorApp) {
+        let mut keys = app
+            .world_mut()
+            .get_resource_or_insert_with(FrontMatterKeys::default);
+
+        for key in MarkdownFrontMatter::KEYS {
+            keys.register(*key);
+        }
+
+        for component in self.matter_components.iter() {
+            keys.register(component.key.as_str());
+        }
+
+        let menus = build_menus.in_set(MarkdownSet::Menus);
+        // Templates see the menus as a global value, so they're set ahead of contexts.
+        #[cfg(feature = "tera")]
+        let menus = menus.before(super::TeraSet::Context);
+
+        app.insert_resource(MatterComponents(self.matter_components))
+            .init_resource::<VirtualContent>()
+            .init_resource::<SectionPosts>()
+            .init_resource::<DateIndex>()
+            .init_resource::<TaxonomyIndex>()
+            .init_resource::<Menus>()
+            .init_resource::<StaleOutputs>()
+            .configure_sets(
+                Process,
+                (
+                    MarkdownSet::ParseMatter,
+                    MarkdownSet::Convert,
+                    MarkdownSet::Render,
+                    MarkdownSet::Sanitize,
+                    MarkdownSet::Refine,
+                    MarkdownSet::Release,
+                )
+                    .chain()
+                    .after(classify_pages),
+            )
+            .add_systems(
+                Load,
+                Self::read_content_directory_task.in_set(MarkdownSet::Load),
+            )
+            .add_systems(
+                LoadBatch,
+                Self::read_content_batch_task.in_set(MarkdownSet::Load),
+            )
+            .add_systems(
+                Process,
+                (
+                    (
+                        Self::dump_front_matter,
+                        Self::parse_page_format,
+                        Self::parse_frontmatter,
+                        (
+                            Self::localize_dates,
+                            Self::check_front_matter_types,
+                            Self::check_front_matter_keys,
+                            Self::check_descriptions,
+                            Self::check_authors,
+                            Self::check_page_ids,
+                            Self::validate_canonical_urls,
+                            Self::assign_permalinks,
+                        ),
+                        link_sections,
+                        (Self::check_post_dates, Self::cascade_extra),
+                    )
+                        .chain()
+                        .in_set(MarkdownSet::ParseMatter),
+                    (
+                        Self::check_classes,
+                        Self::convert_markdown_to_html,
+                        Self::use_html_bodies,
+                    )
+                        .in_set(MarkdownSet::Convert),
+                    Self::sanitize_pages.in_set(MarkdownSet::Sanitize),
+                    (
+                        Self::summarize_pages,
+                        Self::apply_typography,
+                        Self::measure_pages,
+                    )
+                        .chain()
+                        .in_set(MarkdownSet::Refine),
+                    Self::index_pages.in_set(MarkdownSet::Refine),
+                    Self::release_raw_content.in_set(MarkdownSet::Release),
+                ),
+            )
+            .add_systems(
+                PostProcess,
+                (
+                    build_section_posts.in_set(MarkdownSet::Sections),
+                    build_indexes.in_set(MarkdownSet::Indexes),
+                    menus,
+                ),
+            )
+            .add_systems(
+                Finish,
+                Self::remove_stale_outputs.before(record_removed_outputs),
+            );
+    }
+}
+
+/// Stages of the markdown processor, for ordering custom systems against it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemSet)]
+pub enum MarkdownSet {
+    /// Reads content from disk and spawns page entities, during [`Load`] and, for
+    /// content read in batches, [`LoadBatch`].
+    Load,
+    /// Splits pages into front matter and body, extracting the front matter into
+    /// components and linking pages to their sections, during [`Process`].
+    ParseMatter,
+    /// Converts markdown bodies into [`HtmlBody`], giving headings their ids and
+    /// elements the classes under `[markdown.classes]`, during [`Process`].
+    Convert,
+    /// Passes adding markup to the converted [`HtmlBody`], such as highlighting, during
+    /// [`Process`]. They see the classes added during conversion, and run ahead of
+    /// sanitization.
+    Render,
+    /// Strips unsafe markup from [`HtmlBody`] when `[markdown.sanitize]` is enabled,
+    /// during [`Process`].
+    Sanitize,
+    /// Summarizes and typesets the sanitized [`HtmlBody`], during [`Process`].
+    Refine,
+    /// Drops the raw markdown of rendered pages not marked with [`KeepMarkdown`], as
+    /// the last stage of [`Process`]. Systems reading [`MarkdownPost::content`] run
+    /// ahead of it.
+    Release,
+    /// Sorts the posts of every section into [`SectionPosts`], during [`PostProcess`].
+    Sections,
+    /// Indexes listed pages by date into [`DateIndex`] and by term into
+    /// [`TaxonomyIndex`], during [`PostProcess`]. Systems using them run after it.
+    Indexes,
+    /// Builds the [`Menus`] from the configuration and the `menu` of each page, during
+    /// [`PostProcess`].
+    Menus,
+}
+
+impl<T: Extractor + Send + Sync> Default for MarkdownProcessor<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Inserts a component deserialized from a front matter value onto a page.
+type MatterInserter = Box<dyn Fn(&Value, &mut EntityCommands) -> Result<(), String> + Send + Sync>;
+
+/// A front matter table registered with
+/// [`MarkdownProcessor::register_matter_component`].
+struct MatterComponent {
+    key: String,
+    insert: MatterInserter,
+}
+
+#[derive(Resource)]
+struct MatterComponents(Vec<MatterComponent>);
+
+#[derive(Debug, Component)]
+pub struct MarkdownFrontMatter(Option<toml::Table>);
+
+impl MarkdownFrontMatter {
+    /// The keys read by [`Extractor::extract`], registered in [`FrontMatterKeys`].
+    pub const KEYS: &'static [&'static str] = &[
+        "title",
+        "description",
+        "date",
+        "canonical",
+        "template",
+        "output",
+        "authors",
+        "author",
+        "tags",
+        "extra",
+        "weight",
+        "toc",
+        "toc_levels",
+        "raw",
+        "sanitize",
+        "draft",
+        "render",
+        "in_listing",
+        "unlisted",
+        "id",
+        "menu",
+    ];
+
+    pub fn access(&self) -> Option<&toml::Table> {
+        self.0.as_ref()
+    }
+}
+
+impl Extractor for MarkdownFrontMatter {
+    fn extract(&self, entity: &mut EntityCommands) {
+        let Some(data) = self.access() else {
+            return;
+        };
+
+        let mut errors = Vec::new();
+
+        if let Some(title) = typed_field(data, "title", "a string", as_string, &mut errors) {
+            entity.insert(Title(title));
+        }
+
+        if let Some(description) =
+            typed_field(data, "description", "a string", as_string, &mut errors)
+        {
+            entity.insert(Description(description));
+        }
+
+        if let Some(date) = typed_field(
+            data,
+            "date",
+            "a datetime or a date string",
+            as_date,
+            &mut errors,
+        ) {
+            entity.insert(Date::new(date, None));
+        }
+
+        if let Some(canonical) = typed_field(data, "canonical", "a string", as_string, &mut errors)
+        {
+            entity.insert(CanonicalUrl(canonical));
+        }
+
+        if let Some(template) = typed_field(data, "template", "a string", as_string, &mut errors) {
+            entity.insert(TemplateOverride(template));
+        }
+
+        // Replaces the file name derived from the page's path, extension and all.
+        if let Some(output) = typed_field(data, "output", "a file name", as_file_name, &mut errors)
+        {
+            entity.insert(FileName(output));
+        }
+
+        // Replaces the id derived from the page's path, so it survives renames.
+        if let Some(id) = typed_field(data, "id", "a non-empty string", as_id, &mut errors) {
+            entity.insert(PageId::new(id));
+        }
+
+        let authors = if data.contains_key("authors") {
+            typed_field(
+                data,
+                "authors",
+                "an array of strings",
+                as_strings,
+                &mut errors,
+            )
+        } else {
+            typed_field(data, "author", "a string", as_string, &mut errors)
+                .map(|author| vec![author])
+        };
+
+        if let Some(authors) = authors {
+            entity.insert(Authors(authors));
+        }
+
+        if let Some(tags) =
+            typed_field(data, "tags", "an array of strings", as_strings, &mut errors)
+        {
+            entity.insert(Tags(tags));
+        }
+
+        if let Some(extra) = typed_field(data, "extra", "a table", Value::as_table, &mut errors) {
+            entity.insert(Extra(extra.clone()));
+        }
+
+        if let Some(weight) =
+            typed_field(data, "weight", "an integer", Value::as_integer, &mut errors)
+        {
+            entity.insert(Weight(weight));
+        }
+
+        if let Some(menus) = typed_field(
+            data,
+            "menu",
+            "a menu name, an array of them or a table of menus",
+            as_menu_entries,
+            &mut errors,
+        ) {
+            entity.insert(MenuEntries(menus));
+        }
+
+        let toc = typed_field(data, "toc", "a boolean", Value::as_bool, &mut errors);
+        let toc_levels = typed_field(
+            data,
+            "toc_levels",
+            "an array of heading levels from 1 to 6",
+            as_heading_levels,
+            &mut errors,
+        );
+
+        if toc == Some(false) {
+            entity.insert(TocLevels(Vec::new()));
+        } else if let Some(levels) = toc_levels {
+            entity.insert(TocLevels(levels));
+        }
+
+        if typed_field(data, "raw", "a boolean", Value::as_bool, &mut errors).is_some_and(|raw| raw)
+        {
+            entity.insert(Raw);
+        }
+
+        if typed_field(data, "sanitize", "a boolean", Value::as_bool, &mut errors)
+            .is_some_and(|sanitize| !sanitize)
+        {
+            entity.insert(Trusted);
+        }
+
+        if typed_field(data, "draft", "a boolean", Value::as_bool, &mut errors)
+            .is_some_and(|draft| draft)
+        {
+            entity.insert(Draft);
+        }
+
+        if typed_field(data, "render", "a boolean", Value::as_bool, &mut errors)
+            .is_some_and(|render| !render)
+        {
+            entity.insert(Headless);
+        }
+
+        let in_listing = typed_field(data, "in_listing", "a boolean", Value::as_bool, &mut errors);
+        let unlisted = typed_field(data, "unlisted", "a boolean", Value::as_bool, &mut errors);
+
+        if in_listing == Some(false) || unlisted == Some(true) {
+            entity.insert(Unlisted);
+        }
+
+        if !errors.is_empty() {
+            entity.insert(FrontMatterErrors(errors));
+        }
+    }
+
+    fn extract_from_path(&self, entity: &mut EntityCommands, path: &Path) {
+        entity.insert(PageId::from_path(path));
+
+        if let Some(file_name) = path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .map(|file_name| {
+                if classify(path).is_listing() {
+                    String::from("index.html")
+                } else {
+                    let stem = file_name.trim_end_matches(".html").trim_end_matches(".md");
+                    let slug = slugify(stem);
+
+                    // Names made only of symbols have no slug, so keep them as is.
+                    format!("{}.html", if slug.is_empty() { stem } else { &slug })
+                }
+            })
+        {
+            entity.insert(FileName(file_name));
+        }
+    }
+}
+
+/// Spawns a batch of pages read from disk, reconciling them with those already loaded.
+fn spawn_content(
+    world: &mut World,
+    listed: &HashSet<PathBuf>,
+    pages: Vec<(FilePath, SourceFile, MarkdownPost)>,
+) {
+    world.resource_mut::<BuildReport>().pages_loaded += pages.len();
+
+    let digests: HashMap<&Path, u64> = pages
+        .iter()
+        .map(|(_, source, post)| (source.as_ref(), post.digest))
+        .collect();
+    let kept = reconcile_pages(world, listed, &digests);
+
+    let (html_pages, pages): (Vec<_>, Vec<_>) = pages
+        .into_iter()
+        .filter(|(_, source, _)| !kept.contains(source.as_ref()))
+        .partition(|(path, ..)| is_html(path.as_ref()));
+
+    world.spawn_batch(pages);
+    world.spawn_batch(
+        html_pages
+            .into_iter()
+            .map(|(path, source, post)| (path, source, post, HtmlSource)),
+    );
+}
+
+/// Reads `files`, each the path read from along with its path within its content root,
+/// decompressing those with an extension in `codecs`. Files that failed to decompress
+/// are returned separately, to be reported against their source.
+async fn read_content(
+    files: Vec<(PathBuf, PathBuf)>,
+    codecs: &BTreeMap<String, Codec>,
+) -> (Vec<(FilePath, SourceFile, MarkdownPost)>, Vec<ReadError>) {
+    let mut paths: HashMap<PathBuf, PathBuf> = files.into_iter().collect();
+    let mut failures = Vec::new();
+
+    let pages = read_decompressed_files(paths.keys().cloned().collect(), codecs)
+        .await
+        .into_iter()
+        .filter_map(|res| match res {
+            Ok((source, content)) => {
+                let page_path = paths.remove(&source)?;
+
+                trace!("Spawning {}", page_path.display());
+
+                Some((
+                    FilePath::new(page_path),
+                    SourceFile::new(source),
+                    MarkdownPost::new(content),
+                ))
+            }
+            Err(ReadError::Io(err)) => {
+                error!("Error reading file: {}", err);
+
+                None
+            }
+            Err(err) => {
+                failures.push(err);
+
+                None
+            }
+        })
+        .collect();
+
+    (pages, failures)
+}
+
+/// Reports content files that couldn't be decompressed against their source.
+fn report_unreadable_content(world: &mut World, failures: Vec<ReadError>) {
+    let mut diagnostics = world.resource_mut::<Diagnostics>();
+
+    for failure in failures {
+        if let ReadError::Decompress { path, .. } = &failure {
+            diagnostics.error(path.clone(), "undecompressable-file", failure.to_string());
+        }
+    }
+}
+
+/// Reconciles a batch of pages read by a rebuild with those loaded by earlier builds,
+/// returning the sources of pages left as they were. Pages whose source was removed
+/// or edited are despawned, edits and renames being read again as new pages, and the
+/// outputs they reserved are released to be removed. Sections listing them are marked
+/// as changed so their index is rendered again. Pages still `listed` but read in
+/// another batch are left for that batch.
+fn reconcile_pages(
+    world: &mut World,
+    listed: &HashSet<PathBuf>,
+    digests: &HashMap<&Path, u64>,
+) -> HashSet<PathBuf> {
+    let mut q_pages = world.query::<(Entity, &SourceFile, &FilePath, &MarkdownPost)>();
+    let mut kept = HashSet::new();
+    let mut removed = Vec::new();
+
+    for (page, source, path, post) in q_pages.iter(world) {
+        match digests.get(source.as_ref()) {
+            Some(digest) if *digest == post.digest => {
+                kept.insert(source.as_ref().to_path_buf());
+            }
+            // Virtual pages are only ever added, never read from disk.
+            _ if source.is_synthetic() => {}
+            None if listed.contains(source.as_ref()) => {}
+            _ => removed.push((page, path.as_ref().to_path_buf())),
+        }
+    }
+
+    if removed.is_empty() {
+        return kept;
+    }
+
+    // Found ahead of despawning the pages, which takes their section along with them.
+    let indexes: Vec<Entity> = removed
+        .iter()
+        .filter(|(_, path)| path.file_stem().is_some_and(|stem| stem != "_index"))
+        .filter_map(|(page, _)| world.get::<InSection>(*page))
+        .filter_map(|InSection(section)| world.get::<SectionIndex>(*section))
+        .map(|SectionIndex(index)| *index)
+        .collect();
+    let mut released = Vec::new();
+
+    for (page, path) in removed.iter() {
+        debug!("Removing {}, its source changed", path.display());
+
+        released.extend(world.resource_mut::<OutputRegistry>().release_page(*page));
+        world.despawn(*page);
+    }
+
+    world.resource_mut::<StaleOutputs>().0.extend(released);
+
+    for index in indexes {
+        if let Some(mut body) = world.get_mut::<HtmlBody>(index) {
+            body.set_changed();
+        }
+    }
+
+    kept
+}
+
+/// Maps `items` on the compute task pool a batch per thread, keeping their order.
+/// Results are gathered per batch rather than queued as a command each, so the hot
+/// systems can apply them all with a single command. An item panicking gives the panic
+/// message in its place, leaving the rest of its batch to complete.
+fn map_in_batches<T: Sync, R: Send + 'static>(
+    items: &[T],
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<Result<R, String>> {
+    let pool = ComputeTaskPool::get();
+    let size = items.len().div_ceil(pool.thread_num().max(1)).max(1);
+    let f = &f;
+
+    pool.scope(|scope| {
+        for batch in items.chunks(size) {
+            scope.spawn(async move {
+                batch
+                    .iter()
+                    .map(|item| catch_page_panic(|| f(item)))
+                    .collect::<Vec<_>>()
+            });
+        }
+    })
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Reports a page that panicked within a parallel query.
+fn report_panic(
+    par_commands: &ParallelCommands,
+    source: Option<&SourceFile>,
+    stage: &'static str,
+    message: String,
+) {
+    let source = source.map(|source| source.as_ref().to_path_buf());
+
+    par_commands.command_scope(move |mut commands| {
+        commands.add(PagePanicked {
+            source,
+            stage,
+            message,
+        });
+    });
+}
+
+/// Parses the page with the first parser whose delimiter it starts with.
+fn parse_front_matter(parsers: &[FrontMatterParser], page: &str) -> Result<ParsedData, ParseError> {
+    parsers
+        .iter()
+        .map(|parser| parser.parse(page))
+        .find(|parsed| !matches!(parsed, Err(ParseError::MissingFrontMatter)))
+        .unwrap_or(Err(ParseError::MissingFrontMatter))
+}
+
+fn markdown_to_html(markdown: &str, classes: &HashMap<String, String>) -> String {
+    let events = Parser::new_ext(markdown, Options::all()).collect();
+    let mut html = String::new();
+    html::push_html(&mut html, add_classes(events, classes).into_iter());
+    html
+}
+
+/// The kinds of element `[markdown.classes]` adds classes to.
+const CLASSED_ELEMENTS: &[&str] = &[
+    "blockquotes",
+    "footnote_definitions",
+    "footnote_references",
+    "headings",
+    "images",
+    "links",
+    "tables",
+];
+
+/// Adds the classes under `[markdown.classes]` to the elements they're for. Footnotes
+/// are rendered here, numbered the way pulldown-cmark numbers them, keeping its own
+/// classes. Images, links, tables and blockquotes are each rendered on their own,
+/// innermost first, with the class added to their opening tag.
+fn add_classes<'a>(
+    mut events: Vec<Event<'a>>,
+    classes: &'a HashMap<String, String>,
+) -> Vec<Event<'a>> {
+    let class = |kind: &str| {
+        classes
+            .get(kind)
+            .map(|class| class.trim())
+            .filter(|class| !class.is_empty())
+    };
+
+    if let Some(class) = class("headings") {
+        for event in events.iter_mut() {
+            if let Event::Start(Tag::Heading(_, _, classes)) = event {
+                classes.push(class);
+            }
+        }
+    }
+
+    let references = class("footnote_references");
+    let definitions = class("footnote_definitions");
+
+    if references.is_some() || definitions.is_some() {
+        events = render_footnotes(events, references, definitions);
+    }
+
+    let elements: [(&str, fn(&Tag) -> bool, &str); 4] = [
+        ("images", |tag| matches!(tag, Tag::Image(..)), "<img"),
+        ("links", |tag| matches!(tag, Tag::Link(..)), "<a"),
+        ("tables", |tag| matches!(tag, Tag::Table(..)), "<table"),
+        (
+            "blockquotes",
+            |tag| matches!(tag, Tag::BlockQuote),
+            "<blockquote",
+        ),
+    ];
+
+    for (kind, is_element, opening) in elements {
+        if let Some(class) = class(kind) {
+            events = render_with_class(events, is_element, opening, class);
+        }
+    }
+
+    events
+}
+
+/// Renders footnote references and the start of their definitions, adding a class to
+/// either.
+fn render_footnotes<'a>(
+    events: Vec<Event<'a>>,
+    references: Option<&str>,
+    definitions: Option<&str>,
+) -> Vec<Event<'a>> {
+    let mut numbers = HashMap::new();
+    let mut number_of = |name: CowStr<'a>| {
+        let next = numbers.len() + 1;
+        *numbers.entry(name).or_insert(next)
+    };
+    let classes = |own: &str, added: Option<&str>| match added {
+        Some(added) => escape_html_attr(&format!("{} {}", own, added)),
+        None => own.to_string(),
+    };
+
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::FootnoteReference(name) => Event::Html(
+                format!(
+                    "<sup class=\"{}\"><a href=\"#{}\">{}</a></sup>",
+                    classes("footnote-reference", references),
+                    escape_html_attr(&name),
+                    number_of(name),
+                )
+                .into(),
+            ),
+            Event::Start(Tag::FootnoteDefinition(name)) => Event::Html(
+                format!(
+                    "<div class=\"{}\" id=\"{}\"><sup class=\"footnote-definition-label\">{}</sup>",
+                    classes("footnote-definition", definitions),
+                    escape_html_attr(&name),
+                    number_of(name),
+                )
+                .into(),
+            ),
+            event => event,
+        })
+        .collect()
+}
+
+/// Renders every element `is_element` matches on its own, adding `class` to its
+/// `opening` tag.
+fn render_with_class<'a>(
+    events: Vec<Event<'a>>,
+    is_element: fn(&Tag) -> bool,
+    opening: &str,
+    class: &str,
+) -> Vec<Event<'a>> {
+    let mut rendered = Vec::with_capacity(events.len());
+    let mut element = Vec::new();
+    let mut depth = 0usize;
+
+    for event in events {
+        if depth == 0 {
+            match &event {
+                Event::Start(tag) if is_element(tag) => {
+                    depth = 1;
+                    element.push(event);
+                }
+                _ => rendered.push(event),
+            }
+
+            continue;
+        }
+
+        match &event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => {}
+        }
+
+        element.push(event);
+
+        if depth == 0 {
+            // Elements of the same kind nested within get their class too.
+            let end = element.pop();
+            let mut events = std::mem::take(&mut element).into_iter();
+            let start = events.next();
+            let inner = render_with_class(events.collect(), is_element, opening, class);
+
+            let mut html = String::new();
+            html::push_html(&mut html, start.into_iter().chain(inner).chain(end));
+
+            if html.starts_with(opening) {
+                html.insert_str(
+                    opening.len(),
+                    &format!(" class=\"{}\"", escape_html_attr(class)),
+                );
+            }
+
+            rendered.push(Event::Html(html.into()));
+        }
+    }
+
+    rendered
+}
+
+/// Renders markdown along with the headings it contains, giving every heading an
+/// anchor id. Ids set in the markdown with `{#id}` are kept, the rest are slugs of
+/// the heading text, suffixed to keep them unique. Elements are given the classes
+/// under `[markdown.classes]`.
+fn render_markdown(
+    markdown: &str,
+    classes: &HashMap<String, String>,
+    lines: Option<&BodyLines>,
+) -> (String, Vec<TocEntry>) {
+    let events: Vec<_> = match lines {
+        Some(lines) => with_source_lines(markdown, lines),
+        None => Parser::new_ext(markdown, Options::all()).collect(),
+    };
+    let mut taken: HashSet<String> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Start(Tag::Heading(_, Some(id), _)) => Some(id.to_string()),
+            _ => None,
+        })
+        .collect();
+
+    let mut toc = Vec::new();
+    let mut heading = None;
+
+    for event in events.iter() {
+        match event {
+            Event::Start(Tag::Heading(level, id, _)) => {
+                heading = Some((*level as u8, *id, String::new()));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((.., title)) = heading.as_mut() {
+                    title.push_str(text);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some((.., title)) = heading.as_mut() {
+                    title.push(' ');
+                }
+            }
+            Event::End(Tag::Heading(..)) => {
+                if let Some((level, id, title)) = heading.take() {
+                    let id = match id {
+                        Some(id) => id.to_string(),
+                        None => {
+                            let slug = slugify(&title);
+
+                            unique_slug(&mut taken, if slug.is_empty() { "section" } else { &slug })
+                        }
+                    };
+
+                    toc.push(TocEntry { level, id, title });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut ids = toc.iter().map(|entry| entry.id.as_str());
+    let events = events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Heading(level, _, classes)) => {
+                Event::Start(Tag::Heading(level, ids.next(), classes))
+            }
+            event => event,
+        })
+        .collect();
+
+    let mut html = String::new();
+    html::push_html(&mut html, add_classes(events, classes).into_iter());
+
+    (html, toc)
+}
+
+/// The events of `markdown` with a [`source_line_comment`] before each block, naming
+/// the line of the source file it starts on, and one after the last.
+fn with_source_lines<'a>(markdown: &'a str, lines: &BodyLines) -> Vec<Event<'a>> {
+    let mut events = Vec::new();
+
+    for (event, range) in Parser::new_ext(markdown, Options::all()).into_offset_iter() {
+        let block = match &event {
+            Event::Start(tag) => matches!(
+                tag,
+                Tag::Paragraph
+                    | Tag::Heading(..)
+                    | Tag::BlockQuote
+                    | Tag::CodeBlock(_)
+                    | Tag::List(_)
+                    | Tag::Item
+                    | Tag::FootnoteDefinition(_)
+                    | Tag::Table(_)
+            ),
+            Event::Rule => true,
+            _ => false,
+        };
+
+        if block {
+            let line = lines.line_at(markdown, range.start);
+            events.push(Event::Html(source_line_comment(Some(line)).into()));
+        }
+
+        events.push(event);
+    }
+
+    events.push(Event::Html(source_line_comment(None).into()));
+
+    events
+}
+
+/// Converts the value at `key`, recording a mismatch if it's there but not of the
+/// expected type.
+fn typed_field<'a, T>(
+    data: &'a toml::Table,
+    key: &'static str,
+    expected: &'static str,
+    convert: impl FnOnce(&'a Value) -> Option<T>,
+    errors: &mut Vec<FieldMismatch>,
+) -> Option<T> {
+    let value = data.get(key)?;
+    let converted = convert(value);
+
+    if converted.is_none() {
+        errors.push(FieldMismatch {
+            key,
+            expected,
+            found: match value {
+                Value::String(string) => format!("string {:?}", string),
+                value => value.type_str().to_string(),
+            },
+        });
+    }
+
+    converted
+}
+
+/// Merges `over` into `base`, key by key for tables found in both. Anything else in
+/// `over`, arrays included, replaces what's in `base`.
+fn merge_extra(base: &mut toml::Table, over: &toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(key), value) {
+            (Some(Value::Table(base)), Value::Table(over)) => merge_extra(base, over),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn as_string(value: &Value) -> Option<String> {
+    value.as_str().map(str::to_string)
+}
+
+/// A file name without any directories, such as `feed.xml`.
+fn as_file_name(value: &Value) -> Option<String> {
+    let name = value.as_str()?;
+    let normal = matches!(
+        Path::new(name).components().next(),
+        Some(path::Component::Normal(_))
+    );
+
+    (normal && !name.contains(['/', '\\'])).then(|| name.to_string())
+}
+
+fn as_id(value: &Value) -> Option<String> {
+    value
+        .as_str()
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+}
+
+fn as_strings(value: &Value) -> Option<Vec<String>> {
+    value.as_array()?.iter().map(as_string).collect()
+}
+
+/// A menu name, an array of them, or a table of menu names to tables with an optional
+/// `parent` and `weight`.
+fn as_menu_entries(value: &Value) -> Option<Vec<MenuEntry>> {
+    let entry = |menu: &str| MenuEntry {
+        menu: menu.to_string(),
+        parent: None,
+        weight: None,
+    };
+
+    match value {
+        Value::String(menu) => Some(vec![entry(menu)]),
+        Value::Array(menus) => menus.iter().map(|menu| menu.as_str().map(entry)).collect(),
+        Value::Table(menus) => menus
+            .iter()
+            .map(|(menu, options)| {
+                let options = options.as_table()?;
+                let parent = match options.get("parent") {
+                    Some(parent) => Some(as_string(parent)?),
+                    None => None,
+                };
+                let weight = match options.get("weight") {
+                    Some(weight) => Some(weight.as_integer()?),
+                    None => None,
+                };
+
+                Some(MenuEntry {
+                    parent,
+                    weight,
+                    ..entry(menu)
+                })
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+fn as_heading_levels(value: &Value) -> Option<Vec<u8>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|level| {
+            level
+                .as_integer()
+                .filter(|level| (1..=6).contains(level))
+                .map(|level| level as u8)
+        })
+        .collect()
+}
+
+/// Accepts TOML datetimes with a date, or strings [`parse_date`] understands.
+fn as_date(value: &Value) -> Option<String> {
+    match value {
+        Value::Datetime(datetime) if datetime.date.is_some() => Some(datetime.to_string()),
+        Value::String(date) if parse_date(date, None).is_some() => Some(date.clone()),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Component)]
+struct MarkdownBody(String);
+
+/// The content before the summary marker, in the page's own format.
+#[derive(Debug, Clone, Component)]
+struct MarkdownExcerpt(String);
+
+/// Marks pages already sanitized, so rebuilds don't sanitize them again.
+#[derive(Debug, Component)]
+struct Sanitized;
+
+/// Marks pages typography was already applied to, so rebuilds leave them be.
+#[derive(Debug, Component)]
+struct Typeset;
+
+/// A page as read from disk. Its content is released once the page is rendered,
+/// keeping only the digest rebuilds compare to tell whether the page changed.
+#[derive(Debug, Component)]
+pub struct MarkdownPost {
+    content: String,
+    digest: u64,
+}
+
+impl MarkdownPost {
+    /// The page as read, front matter included. Empty once the page is rendered,
+    /// unless it's marked with [`KeepMarkdown`].
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    fn new(content: String) -> Self {
+        let mut hasher = DefaultHasher::new();
+
+        content.hash(&mut hasher);
+
+        Self {
+            digest: hasher.finish(),
+            content,
+        }
+    }
+}
+
+/// Keeps the raw markdown of a page around once it's rendered, for features still
+/// reading it afterwards such as indexing the source for search.
+#[derive(Debug, Component)]
+pub struct KeepMarkdown;
+
+/// Content files found by [`Load`] but not read yet, read a batch at a time during
+/// [`LoadBatch`].
+#[derive(Debug, Resource)]
+struct PendingContent {
+    /// Every content file found, whichever batch reads it.
+    listed: Arc<HashSet<PathBuf>>,
+    batches: VecDeque<Vec<(PathBuf, PathBuf)>>,
+}
+
+#[derive(Debug, Component)]
+struct MarkdownParsed;
+
+/// The line of the source file each part of a page's [`MarkdownBody`] starts on, kept
+/// for `[markdown] source_maps`. With an excerpt, the body is the excerpt and the
+/// content joined by a blank line in place of the summary marker.
+#[derive(Debug, Component)]
+struct BodyLines {
+    /// The length of the excerpt and the line it starts on.
+    excerpt: Option<(usize, usize)>,
+    /// The line the content starts on.
+    content: usize,
+}
+
+impl BodyLines {
+    /// Finds where `excerpt` and `content`, as parsed from `page`, start in it. The
+    /// content always ends the page, bar trailing whitespace, and the excerpt comes
+    /// before it.
+    fn new(page: &str, excerpt: Option<&str>, content: &str) -> Self {
+        let line_of = |offset: usize| page[..offset].matches('\n').count() + 1;
+        let content_start = page.trim_end().len() - content.len();
+
+        Self {
+            excerpt: excerpt.map(|excerpt| {
+                let start = page[..content_start].rfind(excerpt).unwrap_or(0);
+
+                (excerpt.len(), line_of(start))
+            }),
+            content: line_of(content_start),
+        }
+    }
+
+    /// The line of the source file `offset` into `body` is on.
+    fn line_at(&self, body: &str, offset: usize) -> usize {
+        let (start, line) = match self.excerpt {
+            // The excerpt and the blank line after it.
+            Some((len, line)) if offset < len + 2 => (0, line),
+            Some((len, _)) => (len + 2, self.content),
+            None => (0, self.content),
+        };
+
+        line + body[start.min(offset)..offset].matches('\n').count()
+    }
+}
+
+/// The directory the front matter of each page is dumped into when debugging how it's
+/// parsed, kept apart from the output directory and never recorded in the
+/// [`Manifest`].
+#[derive(Debug, Resource)]
+pub struct MatterDebug(PathBuf);
+
+impl MatterDebug {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self(dir.into())
+    }
+
+    pub fn path(&self) -> &Path {
+        self.0.as_path()
+    }
+}
+
+/// Marks a content file that's already HTML, so is used as is rather than converted.
+#[derive(Debug, Component)]
+struct HtmlSource;
+
+fn is_html(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "html")
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::system::Command;
+
+    use crate::{file::EnumeratedSections, report::Severity};
+
+    use super::*;
+
+    #[test]
+    fn virtual_pages_are_processed_like_content_on_disk() {
+        let dir = std::env::temp_dir().join("webvy_virtual_pages_are_processed");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("blog")).unwrap();
+        std::fs::write(
+            dir.join("blog/on-disk.md"),
+            "+++\ntitle = \"Disk\"\n+++\nBody",
+        )
+        .unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::String(dir.display().to_string())).unwrap(),
+        ));
+        EnumeratedSections::new(dir.join("blog"))
+            .unwrap()
+            .apply(app.world_mut());
+        app.init_resource::<SiteConfig>()
+            .add_page(
+                "blog/virtual.md",
+                toml::from_str("title = \"Virtual\"\ndraft = true").unwrap(),
+                "# Hello",
+            )
+            .add_page("blog/on-disk.md", toml::Table::new(), "Shadowed")
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .run()
+            .unwrap();
+
+        let mut pages = app
+            .world_mut()
+            .query::<(&FilePath, &Title, &HtmlBody, &Permalink, Has<Draft>)>();
+        let mut pages: Vec<_> = pages
+            .iter(app.world())
+            .map(|(path, title, html, permalink, draft)| {
+                (
+                    path.as_ref().to_path_buf(),
+                    title.0.clone(),
+                    html.as_ref().to_string(),
+                    permalink.0.clone(),
+                    draft,
+                )
+            })
+            .collect();
+        pages.sort();
+
+        assert_eq!(
+            pages,
+            [(
+                PathBuf::from("blog/virtual.md"),
+                String::from("Virtual"),
+                String::from("<h1 id=\"hello\">Hello</h1>\n"),
+                String::from("/blog/virtual.html"),
+                true,
+            )]
+        );
+        assert!(matches!(
+            app.errors().iter().collect::<Vec<_>>().as_slice(),
+            [ProcessorError::VirtualCollision { path, .. }] if path == Path::new("blog/on-disk.md")
+        ));
+
+        let diagnostics = app.world().resource::<Diagnostics>();
+        let mut messages: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        messages.sort();
+
+        assert_eq!(
+            messages,
+            [
+                "[missing-date] <virtual:blog/on-disk.md>: Post has no date",
+                "[missing-date] <virtual:blog/virtual.md>: Post has no date",
+            ]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn compressed_content_is_read_as_if_it_were_not() {
+        let dir = std::env::temp_dir().join("webvy_compressed_content_is_read");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let page = Codec::Gzip
+            .compress(b"+++\ntitle = \"Archived\"\n+++\n*Old* news", None)
+            .unwrap();
+        std::fs::write(dir.join("archived.md.gz"), page).unwrap();
+        std::fs::write(dir.join("broken.md.gz"), "Not gzip at all").unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::String(dir.display().to_string())).unwrap(),
+        ));
+        app.init_resource::<SiteConfig>()
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .run()
+            .unwrap();
+
+        let mut pages = app
+            .world_mut()
+            .query::<(&FilePath, &FileName, &SourceFile, &Title, &HtmlBody)>();
+        let pages: Vec<_> = pages
+            .iter(app.world())
+            .map(|(path, file_name, source, title, html)| {
+                (
+                    path.as_ref().to_path_buf(),
+                    file_name.0.clone(),
+                    source.as_ref().to_path_buf(),
+                    title.0.clone(),
+                    html.as_ref().to_string(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            pages,
+            [(
+                PathBuf::from("archived.md"),
+                String::from("archived.html"),
+                dir.join("archived.md.gz"),
+                String::from("Archived"),
+                String::from("<p><em>Old</em> news</p>\n"),
+            )]
+        );
+
+        let diagnostics: Vec<_> = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.page.clone()))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            [("undecompressable-file", Some(dir.join("broken.md.gz")))]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn panicking_items_leave_the_rest_to_complete() {
+        // Sets up the compute task pool.
+        ProcessorApp::new();
+
+        let items: Vec<_> = (0..10).collect();
+        let mapped = map_in_batches(&items, |&n| match n {
+            4 => panic!("page {} is broken", n),
+            n => n * 2,
+        });
+
+        assert_eq!(mapped.len(), 10);
+        assert_eq!(mapped[4], Err(String::from("page 4 is broken")));
+        assert!(mapped
+            .iter()
+            .enumerate()
+            .all(|(n, mapped)| n == 4 || *mapped == Ok(n * 2)));
+    }
+
+    #[test]
+    fn html_content_is_used_without_conversion() {
+        let dir = std::env::temp_dir().join("webvy_html_content_is_used_without_conversion");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("resume.html"),
+            "+++\ntitle = \"Resume\"\nraw = true\n+++\n<main># Not a heading</main>",
+        )
+        .unwrap();
+        std::fs::write(dir.join("plain.html"), "<p>*As is*</p>").unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::String(dir.display().to_string())).unwrap(),
+        ));
+        app.init_resource::<SiteConfig>()
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .run()
+            .unwrap();
+
+        let mut pages = app.world_mut().query::<(&FileName, &HtmlBody, Has<Raw>)>();
+        let mut pages: Vec<_> = pages
+            .iter(app.world())
+            .map(|(file_name, html, raw)| (file_name.0.clone(), html.as_ref().to_string(), raw))
+            .collect();
+        pages.sort();
+
+        assert_eq!(
+            pages,
+            [
+                (
+                    String::from("plain.html"),
+                    String::from("<p>*As is*</p>"),
+                    false
+                ),
+                (
+                    String::from("resume.html"),
+                    String::from("<main># Not a heading</main>"),
+                    true
+                ),
+            ]
+        );
+        assert!(app.errors().is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn raw_markdown_is_released_unless_kept() {
+        let dir = std::env::temp_dir().join("webvy_raw_markdown_is_released_unless_kept");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("kept.md"), "+++\ntitle = \"Kept\"\n+++\n*Kept*").unwrap();
+        std::fs::write(
+            dir.join("released.md"),
+            "+++\ntitle = \"Gone\"\n+++\n*Gone*",
+        )
+        .unwrap();
+
+        fn keep_markdown(
+            mut commands: Commands,
+            q_pages: Query<(Entity, &FilePath), With<MarkdownPost>>,
+        ) {
+            for (page, path) in q_pages.iter() {
+                if path.as_ref() == Path::new("kept.md") {
+                    commands.entity(page).insert(KeepMarkdown);
+                }
+            }
+        }
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::String(dir.display().to_string())).unwrap(),
+        ));
+        app.init_resource::<SiteConfig>()
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .add_systems(Process, keep_markdown.before(MarkdownSet::Release))
+            .run()
+            .unwrap();
+
+        let mut pages = app
+            .world_mut()
+            .query::<(&FileName, &MarkdownPost, &HtmlBody, &Summary)>();
+        let mut pages: Vec<_> = pages
+            .iter(app.world())
+            .map(|(file_name, post, html, summary)| {
+                (
+                    file_name.0.as_str(),
+                    post.content(),
+                    html.as_ref(),
+                    summary.as_ref(),
+                )
+            })
+            .collect();
+        pages.sort();
+
+        assert_eq!(
+            pages,
+            [
+                (
+                    "kept.html",
+                    "+++\ntitle = \"Kept\"\n+++\n*Kept*",
+                    "<p><em>Kept</em></p>\n",
+                    "Kept"
+                ),
+                ("released.html", "", "<p><em>Gone</em></p>\n", "Gone"),
+            ]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn summaries_follow_the_configured_marker_and_length() {
+        let dir = std::env::temp_dir().join("webvy_summaries_follow_the_configured_marker");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("marked.md"),
+            "+++\ntitle = \"Marked\"\n+++\nIntro *text*\n<!--more-->\nThe rest",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("unmarked.md"),
+            "+++\ntitle = \"Unmarked\"\n+++\nOne *two* three four",
+        )
+        .unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::String(dir.display().to_string())).unwrap(),
+        ));
+        app.insert_resource(
+            toml::from_str::<SiteConfig>(
+                "[markdown]\nsummary_marker = \"<!--more-->\"\n\
+                 summary_length = 2\nsummary_strip_markup = false",
+            )
+            .unwrap(),
+        )
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .run()
+        .unwrap();
+
+        let mut pages = app.world_mut().query::<(&FileName, &HtmlBody, &Summary)>();
+        let mut pages: Vec<_> = pages
+            .iter(app.world())
+            .map(|(file_name, html, summary)| {
+                (
+                    file_name.0.clone(),
+                    html.as_ref().to_string(),
+                    summary.as_ref().to_string(),
+                )
+            })
+            .collect();
+        pages.sort();
+
+        assert_eq!(
+            pages,
+            [
+                (
+                    String::from("marked.html"),
+                    String::from("<p>Intro <em>text</em></p>\n<p>The rest</p>\n"),
+                    String::from("<p>Intro <em>text</em></p>"),
+                ),
+                (
+                    String::from("unmarked.html"),
+                    String::from("<p>One <em>two</em> three four</p>\n"),
+                    String::from("<p>One <em>two</em>…</p>"),
+                ),
+            ]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "sanitize")]
+    #[test]
+    fn untrusted_pages_are_sanitized() {
+        let dir = std::env::temp_dir().join("webvy_untrusted_pages_are_sanitized");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("guest.md"),
+            "+++\ntitle = \"Guest\"\n+++\n<b onclick=\"x()\">Hi</b><script>x()</script>\n\
+             <!-- more -->\n## Heading",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("trusted.md"),
+            "+++\ntitle = \"Trusted\"\nsanitize = false\n+++\n<script>ok()</script>",
+        )
+        .unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::String(dir.display().to_string())).unwrap(),
+        ));
+        app.insert_resource(
+            toml::from_str::<SiteConfig>(
+                "[markdown]\nsummary_strip_markup = false\n[markdown.sanitize]\nenabled = true",
+            )
+            .unwrap(),
+        )
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .run()
+        .unwrap();
+
+        let mut pages = app.world_mut().query::<(&FileName, &HtmlBody, &Summary)>();
+        let pages: HashMap<_, _> = pages
+            .iter(app.world())
+            .map(|(file_name, html, summary)| {
+                (
+                    file_name.0.as_str(),
+                    (html.as_ref().to_string(), summary.as_ref()),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            pages["guest.html"],
+            (
+                String::from("<p><b>Hi</b></p>\n<h2 id=\"heading\">Heading</h2>\n"),
+                "<p><b>Hi</b></p>"
+            )
+        );
+        assert!(pages["trusted.html"].0.contains("<script>ok()</script>"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "sanitize")]
+    #[test]
+    fn classes_are_added_ahead_of_highlighting_and_sanitizing() {
+        /// Stands in for a highlighter, which relies on the classes of code blocks.
+        fn highlight(mut q_pages: Query<&mut HtmlBody>) {
+            for mut html in q_pages.iter_mut() {
+                let highlighted = (*html).as_ref().replace(
+                    "<code class=\"language-rust\">fn",
+                    "<code class=\"language-rust\"><span class=\"kw\">fn</span>",
+                );
+                *html = HtmlBody::new(highlighted);
+            }
+        }
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+        app.insert_resource(
+            toml::from_str::<SiteConfig>(
+                "[markdown.sanitize]\nenabled = true\n\
+                 [markdown.classes]\n\
+                 headings = \"title\"\n\
+                 blockquotes = \"quote\"\n\
+                 links = \"link\"\n\
+                 tables = \"table is-striped\"\n\
+                 images = \"\\\"><script>\"\n\
+                 footnote_references = \"footnote-ref\"\n\
+                 footnote_definitions = \"footnote\"\n\
+                 lists = \"list\"",
+            )
+            .unwrap(),
+        )
+        .add_page(
+            "page.md",
+            toml::Table::new(),
+            "## Intro\n\n\
+             > A [link](/a) and a note[^1], quoting\n\
+             > > another [link](/b).\n\n\
+             | a | b |\n|:--|--:|\n| 1 | 2 |\n\n\
+             ![Alt](/image.png)\n\n\
+             ```rust\nfn\n```\n\n\
+             [^1]: The note.\n",
+        )
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .add_systems(Process, highlight.in_set(MarkdownSet::Render))
+        .run()
+        .unwrap();
+
+        let mut pages = app.world_mut().query::<&HtmlBody>();
+        let html = pages.single(app.world()).as_ref().to_string();
+
+        assert_eq!(
+            html,
+            "<h2 id=\"intro\" class=\"title\">Intro</h2>\n\
+             <blockquote class=\"quote\">\n\
+             <p>A <a class=\"link\" href=\"/a\">link</a> and a note\
+             <sup class=\"footnote-reference footnote-ref\"><a href=\"#1\">1</a></sup>, quoting</p>\n\
+             <blockquote class=\"quote\">\n\
+             <p>another <a class=\"link\" href=\"/b\">link</a>.</p>\n\
+             </blockquote>\n\
+             </blockquote>\n\
+             <table class=\"table is-striped\"><thead><tr><th>a</th><th>b</th></tr></thead><tbody>\n\
+             <tr><td>1</td><td>2</td></tr>\n\
+             </tbody></table>\n\
+             <p><img class=\"&quot;&gt;&lt;script&gt;\" src=\"/image.png\" alt=\"Alt\"></p>\n\
+             <pre><code class=\"language-rust\"><span class=\"kw\">fn</span>\n</code></pre>\n\
+             <div class=\"footnote-definition footnote\"><sup class=\"footnote-definition-label\">1</sup>\n\
+             <p>The note.</p>\n\
+             </div>\n"
+        );
+
+        let messages: Vec<_> = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+
+        assert_eq!(
+            messages,
+            [
+                "[unknown-class-element] [markdown.classes] has a class for lists, which isn't \
+              one of blockquotes, footnote_definitions, footnote_references, headings, \
+              images, links, tables"
+            ]
+        );
+    }
+
+    #[test]
+    fn diagnostics_point_at_the_source_file() {
+        let dir = std::env::temp_dir().join("webvy_diagnostics_point_at_the_source_file");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("blog")).unwrap();
+        std::fs::write(
+            dir.join("blog/undated.md"),
+            "+++\ntitle = \"Undated\"\nauthor = \"nobody\"\n+++\nBody",
+        )
+        .unwrap();
+        std::fs::write(dir.join("blog/broken.md"), "No front matter").unwrap();
+        std::fs::write(
+            dir.join("blog/unterminated.md"),
+            "+++\ntitle = \"Open\"\n\nThe body",
+        )
+        .unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::String(dir.display().to_string())).unwrap(),
+        ));
+        EnumeratedSections::new(dir.join("blog"))
+            .unwrap()
+            .apply(app.world_mut());
+        app.init_resource::<SiteConfig>()
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .run()
+            .unwrap();
+
+        let mut messages: Vec<_> = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        messages.sort();
+
+        let broken = dir.join("blog/broken.md");
+        let undated = dir.join("blog/undated.md");
+        let unterminated = dir.join("blog/unterminated.md");
+
+        assert_eq!(
+            messages,
+            [
+                format!(
+                    "[invalid-page] {}: Couldn't parse the page into front matter and body",
+                    broken.display()
+                ),
+                format!(
+                    "[invalid-page] {}: The front matter is never closed, add a +++ line after it",
+                    unterminated.display()
+                ),
+                format!("[missing-date] {}: Post has no date", undated.display()),
+                format!(
+                    "[unknown-author] {}: Author nobody isn't configured under [authors]",
+                    undated.display()
+                ),
+            ]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn blocks_are_marked_with_their_source_line() {
+        use crate::html::source_lines;
+
+        let dir = std::env::temp_dir().join("webvy_blocks_are_marked_with_their_source_line");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("blog")).unwrap();
+        std::fs::write(
+            dir.join("blog/mapped.md"),
+            "+++\ntitle = \"Mapped\"\n+++\n\n\
+             Intro paragraph\n\n<!-- more -->\n\n\
+             # Heading\n\n\
+             - one\n- two\n\n\
+             ```\ncode\n\nmore code\n```\n\n\
+             <pre>\nraw\n\npre\n</pre>\n\n\
+             Last paragraph\n",
+        )
+        .unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::String(dir.display().to_string())).unwrap(),
+        ));
+        EnumeratedSections::new(dir.join("blog"))
+            .unwrap()
+            .apply(app.world_mut());
+        app.insert_resource(
+            toml::from_str::<SiteConfig>("[markdown]\nsource_maps = true").unwrap(),
+        )
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .run()
+        .unwrap();
+
+        let mut q_html = app
+            .world_mut()
+            .query::<(&SourceFile, &HtmlBody, &Summary)>();
+        let (_, html, summary) = q_html
+            .iter(app.world())
+            .find(|(source, ..)| source.as_ref().ends_with("mapped.md"))
+            .unwrap();
+        let html = html.as_ref();
+
+        assert_eq!(
+            source_lines(html).map(|(_, line)| line).collect::<Vec<_>>(),
+            [
+                Some(5),
+                Some(9),
+                Some(11),
+                Some(11),
+                Some(12),
+                Some(14),
+                Some(26),
+                None
+            ]
+        );
+
+        for (start, _) in html.match_indices("<pre>") {
+            let end = start + html[start..].find("</pre>").unwrap();
+
+            assert!(
+                !html[start..end].contains("srcline"),
+                "no comment belongs in {}",
+                &html[start..end]
+            );
+        }
+
+        assert_eq!(summary.as_ref(), "Intro paragraph");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn front_matter_is_dumped_as_parsed_when_debugging() {
+        let dir = std::env::temp_dir().join("webvy_front_matter_is_dumped_when_debugging");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("content/blog")).unwrap();
+        std::fs::write(
+            dir.join("content/blog/post.md"),
+            "+++\ntitle = \"Post\"\ndate = 2024-05-01\n+++\nBody",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("content/blog/twice.md"),
+            "+++\ntitle = \"One\"\ntitle = \"Two\"\n+++\nBody",
+        )
+        .unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::String(dir.join("content").display().to_string()))
+                .unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        EnumeratedSections::new(dir.join("content/blog"))
+            .unwrap()
+            .apply(app.world_mut());
+        app.init_resource::<SiteConfig>()
+            .insert_resource(MatterDebug::new(dir.join(".webvy-debug")))
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .run()
+            .unwrap();
+
+        let twice = dir.join("content/blog/twice.md");
+        let diagnostic = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .find(|diagnostic| diagnostic.page.as_deref() == Some(twice.as_path()))
+            .map(|diagnostic| diagnostic.to_string());
+
+        assert_eq!(
+            diagnostic,
+            Some(format!(
+                "[invalid-front-matter] {}: Invalid front matter on line 3: duplicate key \
+                 `title` in document root",
+                twice.display()
+            ))
+        );
+
+        let read_dump = |name: &str| -> serde_json::Value {
+            let dump = std::fs::read_to_string(dir.join(".webvy-debug").join(name)).unwrap();
+            serde_json::from_str(&dump).unwrap()
+        };
+
+        let post = read_dump("blog/post.md.json");
+        assert_eq!(post["raw"], "\ntitle = \"Post\"\ndate = 2024-05-01\n");
+        assert_eq!(
+            post["parsed"],
+            serde_json::json!({ "title": "Post", "date": "2024-05-01" })
+        );
+        assert_eq!(post["error"], serde_json::Value::Null);
+
+        let twice = read_dump("blog/twice.md.json");
+        assert_eq!(twice["raw"], "\ntitle = \"One\"\ntitle = \"Two\"\n");
+        assert_eq!(twice["parsed"], serde_json::Value::Null);
+        assert!(twice["error"].as_str().unwrap().contains("line 3"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_and_empty_content_directories_are_reported() {
+        let dir = std::env::temp_dir().join("webvy_missing_and_empty_content_directories");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let build = |config: &str| {
+            let mut app = ProcessorApp::new();
+
+            app.world_mut().spawn((
+                FileConfig,
+                InputDir::from_value(&Value::String(dir.display().to_string())).unwrap(),
+            ));
+            app.insert_resource(toml::from_str::<SiteConfig>(config).unwrap())
+                .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+                .run()
+                .unwrap();
+
+            app
+        };
+
+        let mut app = build("");
+
+        assert!(matches!(
+            app.errors().iter().collect::<Vec<_>>().as_slice(),
+            [ProcessorError::MissingContentDir { path }] if *path == dir
+        ));
+        assert!(app.world().resource::<Diagnostics>().is_empty());
+        assert!(app.finish().is_err());
+
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut app = build("");
+        let codes: Vec<_> = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect();
+
+        assert_eq!(codes, ["no-content"]);
+        assert_eq!(app.finish().unwrap().pages_loaded, 0);
+
+        let mut app = build("[build]\nstrict = true");
+
+        assert!(app.finish().is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mistyped_front_matter_is_reported_instead_of_extracted() {
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+        app.init_resource::<SiteConfig>()
+            .add_page(
+                "mistyped.md",
+                toml::from_str(
+                    "title = 42\ndate = [2024]\ndraft = \"yes\"\n\
+                     tags = [\"a\", 1]\nweight = 1.5",
+                )
+                .unwrap(),
+                "Body",
+            )
+            .add_page(
+                "typed.md",
+                toml::from_str("title = \"Typed\"\ndate = 2024-03-10\ndraft = false").unwrap(),
+                "Body",
+            )
+            .add_page(
+                "unparseable.md",
+                toml::from_str("date = \"soon\"").unwrap(),
+                "Body",
+            )
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .run()
+            .unwrap();
+
+        let mut pages = app.world_mut().query::<(
+            &FilePath,
+            Option<&Title>,
+            Option<&Date>,
+            Option<&Tags>,
+            Option<&Weight>,
+            Has<Draft>,
+        )>();
+        let mut pages: Vec<_> = pages
+            .iter(app.world())
+            .map(|(path, title, date, tags, weight, draft)| {
+                (
+                    path.as_ref().display().to_string(),
+                    title.map(|title| title.0.clone()),
+                    date.map(|date| date.original.clone()),
+                    tags.is_some() || weight.is_some() || draft,
+                )
+            })
+            .collect();
+        pages.sort();
+
+        assert_eq!(
+            pages,
+            [
+                (String::from("mistyped.md"), None, None, false),
+                (
+                    String::from("typed.md"),
+                    Some(String::from("Typed")),
+                    Some(String::from("2024-03-10")),
+                    false
+                ),
+                (String::from("unparseable.md"), None, None, false),
+            ]
+        );
+
+        let mut messages: Vec<_> = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        messages.sort();
+
+        assert_eq!(
+            messages,
+            [
+                "[invalid-front-matter] <virtual:mistyped.md>: date should be a datetime or a date string, found array",
+                "[invalid-front-matter] <virtual:mistyped.md>: draft should be a boolean, found string \"yes\"",
+                "[invalid-front-matter] <virtual:mistyped.md>: tags should be an array of strings, found array",
+                "[invalid-front-matter] <virtual:mistyped.md>: title should be a string, found integer",
+                "[invalid-front-matter] <virtual:mistyped.md>: weight should be an integer, found float",
+                "[invalid-front-matter] <virtual:unparseable.md>: date should be a datetime or a date string, found string \"soon\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn page_ids_come_from_paths_unless_set_and_must_be_unique() {
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+        app.init_resource::<SiteConfig>()
+            .add_page("notes/first.md", toml::Table::new(), "First")
+            .add_page(
+                "renamed.md",
+                toml::from_str("id = \"original\"").unwrap(),
+                "Renamed",
+            )
+            .add_page(
+                "copied.md",
+                toml::from_str("id = \"original\"").unwrap(),
+                "Copied",
+            )
+            .add_page("blank.md", toml::from_str("id = \" \"").unwrap(), "Blank")
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .run()
+            .unwrap();
+
+        let mut pages = app.world_mut().query::<(&FilePath, &PageId)>();
+        let mut pages: Vec<_> = pages
+            .iter(app.world())
+            .map(|(path, id)| (path.as_ref().to_path_buf(), id.to_string()))
+            .collect();
+        pages.sort();
+
+        assert_eq!(
+            pages,
+            [
+                (PathBuf::from("blank.md"), String::from("blank.md")),
+                (PathBuf::from("copied.md"), String::from("original")),
+                (
+                    PathBuf::from("notes/first.md"),
+                    String::from("notes/first.md")
+                ),
+                (PathBuf::from("renamed.md"), String::from("original")),
+            ]
+        );
+
+        let mut messages: Vec<_> = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        messages.sort();
+
+        assert_eq!(
+            messages,
+            [
+                "[duplicate-page-id] <virtual:renamed.md>: Page id original is already used by \
+                 <virtual:copied.md>",
+                "[invalid-front-matter] <virtual:blank.md>: id should be a non-empty string, \
+                 found string \" \"",
+            ]
+        );
+    }
+
+    #[test]
+    fn extra_values_cascade_from_the_site_through_sections_to_pages() {
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+        EnumeratedSections::new(PathBuf::from("posts"))
+            .unwrap()
+            .apply(app.world_mut());
+        app.insert_resource(
+            toml::from_str::<SiteConfig>(
+                "[extra]\nbanner = \"site\"\nlinks = [\"a\", \"b\"]\n\
+                 [extra.theme]\ncolor = \"blue\"\nfont = \"serif\"\n\
+                 [extra.theme.logo]\nsrc = \"site.png\"\nalt = \"Site\"",
+            )
+            .unwrap(),
+        )
+        .add_page(
+            "posts/_index.md",
+            toml::from_str(
+                "[extra]\nbanner = \"section\"\n\
+                 [extra.theme]\ncolor = \"green\"\n\
+                 [extra.theme.logo]\nsrc = \"section.png\"",
+            )
+            .unwrap(),
+            "Posts",
+        )
+        .add_page(
+            "posts/first.md",
+            toml::from_str(
+                "cover = \"cover.png\"\n[extra]\nlinks = [\"c\"]\n\
+                 [extra.theme]\nfont = \"mono\"\n\
+                 [extra.theme.logo]\nalt = \"First\"",
+            )
+            .unwrap(),
+            "First",
+        )
+        .add_page("about.md", toml::Table::new(), "About")
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .run()
+        .unwrap();
+
+        let mut pages = app.world_mut().query::<(&FilePath, &PageExtra)>();
+        let mut extra = |path: &str| {
+            let (_, extra) = pages
+                .iter(app.world())
+                .find(|(page, _)| page.as_ref() == Path::new(path))
+                .unwrap();
+
+            toml::Value::Table(extra.0.clone())
+        };
+        let table = |source: &str| toml::from_str::<toml::Value>(source).unwrap();
+
+        assert_eq!(
+            extra("posts/first.md"),
+            table(
+                "banner = \"section\"\nlinks = [\"c\"]\n\
+                 [theme]\ncolor = \"green\"\nfont = \"mono\"\n\
+                 [theme.logo]\nsrc = \"section.png\"\nalt = \"First\""
+            )
+        );
+        assert_eq!(
+            extra("posts/_index.md"),
+            table(
+                "banner = \"section\"\nlinks = [\"a\", \"b\"]\n\
+                 [theme]\ncolor = \"green\"\nfont = \"serif\"\n\
+                 [theme.logo]\nsrc = \"section.png\"\nalt = \"Site\""
+            )
+        );
+        assert_eq!(
+            extra("about.md"),
+            table(
+                "banner = \"site\"\nlinks = [\"a\", \"b\"]\n\
+                 [theme]\ncolor = \"blue\"\nfont = \"serif\"\n\
+                 [theme.logo]\nsrc = \"site.png\"\nalt = \"Site\""
+            )
+        );
+    }
+
+    #[test]
+    fn front_matter_delimiters_follow_the_config() {
+        let dir = std::env::temp_dir().join("webvy_front_matter_delimiters_follow_the_config");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("toml.md"), "+++\ntitle = \"Pluses\"\n+++\nBody").unwrap();
+        std::fs::write(
+            dir.join("dashes.md"),
+            "---\ntitle = \"Dashes\"\n---\nBody\n\n---\n\nAfter a rule",
+        )
+        .unwrap();
+
+        let build = |delimiter: &str| {
+            let mut app = ProcessorApp::new();
+
+            app.world_mut().spawn((
+                FileConfig,
+                InputDir::from_value(&Value::String(dir.display().to_string())).unwrap(),
+            ));
+            app.insert_resource(
+                toml::from_str::<SiteConfig>(&format!(
+                    "[markdown]\nfront_matter_delimiter = \"{}\"",
+                    delimiter
+                ))
+                .unwrap(),
+            )
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .run()
+            .unwrap();
+
+            let mut pages = app.world_mut().query::<(&FileName, &Title, &HtmlBody)>();
+            let mut pages: Vec<_> = pages
+                .iter(app.world())
+                .map(|(file_name, title, html)| {
+                    (
+                        file_name.0.clone(),
+                        title.0.clone(),
+                        html.as_ref().to_string(),
+                    )
+                })
+                .collect();
+            pages.sort();
+
+            let codes: Vec<_> = app
+                .world()
+                .resource::<Diagnostics>()
+                .iter()
+                .map(|diagnostic| diagnostic.code)
+                .collect();
+
+            (pages, codes)
+        };
+
+        let dashes = (
+            String::from("dashes.html"),
+            String::from("Dashes"),
+            String::from("<p>Body</p>\n<hr />\n<p>After a rule</p>\n"),
+        );
+        let pluses = (
+            String::from("toml.html"),
+            String::from("Pluses"),
+            String::from("<p>Body</p>\n"),
+        );
+
+        assert_eq!(build("---"), (vec![dashes.clone()], vec!["invalid-page"]));
+        assert_eq!(build("auto"), (vec![dashes, pluses], vec![]));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn long_descriptions_are_reported_but_kept() {
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+        app.insert_resource(
+            toml::from_str::<SiteConfig>("[markdown]\ndescription_length = 10").unwrap(),
+        )
+        .add_page(
+            "short.md",
+            toml::from_str("description = \"Brief\"").unwrap(),
+            "Body",
+        )
+        .add_page(
+            "long.md",
+            toml::from_str("description = \"Rather too long\"").unwrap(),
+            "Body",
+        )
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .run()
+        .unwrap();
+
+        let mut pages = app.world_mut().query::<(&FilePath, &Description)>();
+        let mut pages: Vec<_> = pages
+            .iter(app.world())
+            .map(|(path, description)| (path.as_ref().display().to_string(), description.0.clone()))
+            .collect();
+        pages.sort();
+
+        assert_eq!(
+            pages,
+            [
+                (String::from("long.md"), String::from("Rather too long")),
+                (String::from("short.md"), String::from("Brief")),
+            ]
+        );
+
+        let messages: Vec<_> = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+
+        assert_eq!(
+            messages,
+            ["[long-description] <virtual:long.md>: Description is 15 characters long, search engines may cut it past 10"]
+        );
+    }
+
+    #[test]
+    fn permalinks_keep_the_base_url_subpath() {
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+        app.insert_resource(
+            toml::from_str::<SiteConfig>("base_url = \"https://example.com/blog/\"").unwrap(),
+        )
+        .add_page("café/note.md", toml::Table::new(), "Body")
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .run()
+        .unwrap();
+
+        let mut permalinks = app.world_mut().query::<&Permalink>();
+        let permalinks: Vec<_> = permalinks
+            .iter(app.world())
+            .map(|permalink| permalink.0.clone())
+            .collect();
+
+        assert_eq!(permalinks, ["https://example.com/blog/caf%C3%A9/note.html"]);
+    }
+
+    #[test]
+    fn permalinks_use_the_local_base_when_serving() {
+        let mut config =
+            toml::from_str::<SiteConfig>("base_url = \"https://example.com/blog/\"").unwrap();
+        config.serve_from(webvy_core::SiteUrl::parse("http://localhost:1111").unwrap());
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+        app.insert_resource(config)
+            .add_page("posts/index.md", toml::Table::new(), "Body")
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .run()
+            .unwrap();
+
+        let mut permalinks = app.world_mut().query::<&Permalink>();
+        let permalinks: Vec<_> = permalinks
+            .iter(app.world())
+            .map(|permalink| permalink.0.clone())
+            .collect();
+
+        assert_eq!(permalinks, ["http://localhost:1111/posts/"]);
+        assert_eq!(
+            app.world().resource::<SiteConfig>().base_url().host(),
+            Some("example.com")
+        );
+    }
+
+    #[test]
+    fn registered_front_matter_tables_become_components() {
+        #[derive(Debug, PartialEq, serde::Deserialize, Component)]
+        struct Gallery {
+            columns: u32,
+            images: Vec<String>,
+        }
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+        app.init_resource::<SiteConfig>()
+            .add_page(
+                "good.md",
+                toml::from_str("[gallery]\ncolumns = 3\nimages = [\"a.png\"]").unwrap(),
+                "Body",
+            )
+            .add_page(
+                "bad.md",
+                toml::from_str("[gallery]\ncolumns = \"three\"").unwrap(),
+                "Body",
+            )
+            .add_page("plain.md", toml::Table::new(), "Body")
+            .add_processor(
+                MarkdownProcessor::<MarkdownFrontMatter>::new()
+                    .register_matter_component::<Gallery>("gallery"),
+            )
+            .run()
+            .unwrap();
+
+        let mut pages = app.world_mut().query::<(&FilePath, Option<&Gallery>)>();
+        let mut pages: Vec<_> = pages
+            .iter(app.world())
+            .map(|(path, gallery)| (path.as_ref().to_path_buf(), gallery))
+            .collect();
+        pages.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        assert_eq!(
+            pages,
+            [
+                (PathBuf::from("bad.md"), None),
+                (
+                    PathBuf::from("good.md"),
+                    Some(&Gallery {
+                        columns: 3,
+                        images: vec![String::from("a.png")],
+                    })
+                ),
+                (PathBuf::from("plain.md"), None),
+            ]
+        );
+
+        let diagnostics = app.world().resource::<Diagnostics>();
+        let messages: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+
+        assert_eq!(messages.len(), 1, "{messages:?}");
+        assert!(messages[0]
+            .starts_with("[invalid-front-matter] <virtual:bad.md>: gallery couldn't be read: "));
+    }
+
+    #[test]
+    fn unknown_front_matter_keys_are_reported_with_suggestions() {
+        #[derive(Debug, serde::Deserialize, Component)]
+        struct Gallery {}
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+        // Registered by a plugin reading the key itself.
+        app.world_mut()
+            .get_resource_or_insert_with(FrontMatterKeys::default)
+            .register("series");
+        app.init_resource::<SiteConfig>()
+            .add_page(
+                "typos.md",
+                toml::from_str(
+                    "titel = \"Typo\"\ndaft = true\nlayout = \"wide\"\nseries = \"Notes\"\n\
+                     [gallery]\n[extra]\nanything = 1",
+                )
+                .unwrap(),
+                "Body",
+            )
+            .add_page(
+                "known.md",
+                toml::from_str("title = \"Known\"\ndraft = false\ntoc_levels = [2]").unwrap(),
+                "Body",
+            )
+            .add_processor(
+                MarkdownProcessor::<MarkdownFrontMatter>::new()
+                    .register_matter_component::<Gallery>("gallery"),
+            )
+            .run()
+            .unwrap();
+
+        let messages: Vec<_> = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+
+        assert_eq!(
+            messages,
+            [
+                "[unknown-front-matter] <virtual:typos.md>: Unknown front matter key daft, did you mean draft?",
+                "[unknown-front-matter] <virtual:typos.md>: Unknown front matter key layout",
+                "[unknown-front-matter] <virtual:typos.md>: Unknown front matter key titel, did you mean title?",
+            ]
+        );
+    }
+
+    #[test]
+    fn toc_levels_filter_the_table_but_not_the_anchors() {
+        let body = "# Guide\n\n## Install\n\n### From source\n\n## Install\n";
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+        app.insert_resource(
+            toml::from_str::<SiteConfig>("[markdown]\ntoc_levels = [2, 3]").unwrap(),
+        )
+        .add_page("site.md", toml::Table::new(), body)
+        .add_page("page.md", toml::from_str("toc_levels = [1]").unwrap(), body)
+        .add_page("none.md", toml::from_str("toc = false").unwrap(), body)
+        .add_page("bad.md", toml::from_str("toc_levels = [7]").unwrap(), body)
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .run()
+        .unwrap();
+
+        let mut pages = app
+            .world_mut()
+            .query::<(&FilePath, &HtmlBody, &TableOfContents)>();
+        let pages: HashMap<_, _> = pages
+            .iter(app.world())
+            .map(|(path, html, toc)| {
+                (
+                    path.as_ref().to_str().unwrap().to_string(),
+                    (
+                        html.as_ref().to_string(),
+                        toc.0
+                            .iter()
+                            .map(|entry| (entry.level, entry.id.as_str(), entry.title.as_str()))
+                            .collect::<Vec<_>>(),
+                    ),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            pages["site.md"].1,
+            [
+                (2, "install", "Install"),
+                (3, "from-source", "From source"),
+                (2, "install-1", "Install"),
+            ]
+        );
+        assert_eq!(pages["page.md"].1, [(1, "guide", "Guide")]);
+        assert!(pages["none.md"].1.is_empty());
+        assert_eq!(pages["bad.md"].1, pages["site.md"].1);
+
+        for (html, _) in pages.values() {
+            assert_eq!(
+                html,
+                "<h1 id=\"guide\">Guide</h1>\n<h2 id=\"install\">Install</h2>\n\
+                 <h3 id=\"from-source\">From source</h3>\n<h2 id=\"install-1\">Install</h2>\n"
+            );
+        }
+
+        let diagnostics = app.world().resource::<Diagnostics>();
+        let messages: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+
+        assert_eq!(
+            messages,
+            [
+                "[invalid-front-matter] <virtual:bad.md>: toc_levels should be an array of \
+              heading levels from 1 to 6, found array"
+            ]
+        );
+    }
+
+    #[cfg(feature = "tera")]
+    #[test]
+    fn removed_pages_take_their_outputs_with_them() {
+        use crate::{file::PageType, processor::TeraProcessor};
+
+        let dir = std::env::temp_dir().join("webvy_removed_pages_take_their_outputs");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("content")).unwrap();
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(dir.join("templates/page.html"), "{{ content | safe }}").unwrap();
+        std::fs::write(
+            dir.join("content/kept.md"),
+            "+++\ntitle = \"Kept\"\n+++\nKept",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("content/gone.md"),
+            "+++\ntitle = \"Gone\"\n+++\nGone",
+        )
+        .unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::String(dir.join("content").display().to_string()))
+                .unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        app.world_mut().spawn(PageType::Page);
+        app.init_resource::<SiteConfig>()
+            .init_resource::<Manifest>()
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+            .run()
+            .unwrap();
+
+        assert!(dir.join("public/gone.html").exists());
+
+        // An edited page is replaced, a removed one is gone for good.
+        std::fs::write(
+            dir.join("content/kept.md"),
+            "+++\ntitle = \"Kept\"\n+++\nEdited",
+        )
+        .unwrap();
+        std::fs::remove_file(dir.join("content/gone.md")).unwrap();
+
+        app.run().unwrap();
+
+        let mut pages = app.world_mut().query::<&FilePath>();
+        let pages: Vec<_> = pages
+            .iter(app.world())
+            .map(|path| path.as_ref().to_path_buf())
+            .collect();
+
+        assert_eq!(pages, [PathBuf::from("kept.md")]);
+        assert!(!dir.join("public/gone.html").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("public/kept.html")).unwrap(),
+            "<p>Edited</p>\n"
+        );
+
+        let manifest = app.world().resource::<Manifest>();
+        let outputs: Vec<_> = manifest.outputs().collect();
+
+        assert_eq!(outputs, [Path::new("kept.html")]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "tera")]
+    #[test]
+    fn batched_builds_match_building_all_at_once() {
+        use crate::{app::PendingBatches, file::PageType, processor::TeraProcessor};
+
+        let dir = std::env::temp_dir().join("webvy_batched_builds_match");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("content/blog")).unwrap();
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+
+        let templates = [
+            (
+                "index.html",
+                "{% for section in sections %}{{ section.name }}: {{ section.title }}{% endfor %}",
+            ),
+            ("page.html", "{{ content | safe }}"),
+            (
+                "section.html",
+                "{{ page.section.title }} {{ content | safe }}",
+            ),
+            (
+                "post.html",
+                "{{ page.section.title }} | {{ page.extra.series }} | \
+                 {{ page.summary | safe }} | {{ content | safe }}",
+            ),
+        ];
+
+        for (name, template) in templates {
+            std::fs::write(dir.join("templates").join(name), template).unwrap();
+        }
+
+        let mut content = vec![
+            (
+                String::from("_index.md"),
+                String::from("+++\ntitle = \"Home\"\n+++\nHome"),
+            ),
+            (
+                String::from("about.md"),
+                String::from("+++\ntitle = \"About\"\n+++\nAbout *us*"),
+            ),
+            (
+                String::from("blog/_index.md"),
+                String::from("+++\ntitle = \"The Blog\"\n[extra]\nseries = \"Notes\"\n+++\nPosts"),
+            ),
+        ];
+
+        for day in 1..=7 {
+            content.push((
+                format!("blog/post-{}.md", day),
+                format!(
+                    "+++\ntitle = \"Post {day}\"\ndate = 2024-05-0{day}\n+++\nIntro {day}\n\n<!-- more -->\n\nRest of post {day}"
+                ),
+            ));
+        }
+
+        for (path, page) in content.iter() {
+            std::fs::write(dir.join("content").join(path), page).unwrap();
+        }
+
+        let build = |batch_size: usize| {
+            let output = dir.join(format!("public-{}", batch_size));
+            let mut app = ProcessorApp::new();
+
+            app.world_mut().spawn((
+                FileConfig,
+                InputDir::from_value(&Value::String(dir.join("content").display().to_string()))
+                    .unwrap(),
+                OutputDir::new(&output),
+            ));
+            app.world_mut().spawn(PageType::Index);
+            app.world_mut().spawn(PageType::Page);
+            EnumeratedSections::new(dir.join("content/blog"))
+                .unwrap()
+                .apply(app.world_mut());
+            app.insert_resource(
+                toml::from_str::<SiteConfig>(&format!(
+                    "base_url = \"https://example.com\"\n[build]\nbatch_size = {}",
+                    batch_size
+                ))
+                .unwrap(),
+            )
+            .init_resource::<Manifest>()
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+            .run()
+            .unwrap();
+
+            assert_eq!(app.world().resource::<PendingBatches>().0, 0);
+            assert_eq!(app.report().pages_loaded, 10);
+            assert!(app.world().resource::<Diagnostics>().is_empty());
+
+            // Every page was rendered, so none of them hold on to their content.
+            let mut posts = app.world_mut().query::<&MarkdownPost>();
+            assert!(posts.iter(app.world()).all(|post| post.content.is_empty()));
+
+            let mut files =
+                smol::block_on(find_all_files_in_directory(&output, WalkOptions::default()))
+                    .unwrap();
+            files.sort();
+
+            files
+                .into_iter()
+                .map(|file| {
+                    let content = std::fs::read_to_string(&file).unwrap();
+
+                    (file.strip_prefix(&output).unwrap().to_path_buf(), content)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let all_at_once = build(0);
+
+        assert_eq!(all_at_once.len(), 10);
+        assert_eq!(
+            all_at_once
+                .iter()
+                .find(|(path, _)| path == Path::new("blog/post-3.html"))
+                .map(|(_, html)| html.as_str()),
+            Some("The Blog | Notes | Intro 3 | <p>Intro 3</p>\n<p>Rest of post 3</p>\n")
+        );
+
+        for batch_size in [1, 3, 50] {
+            assert_eq!(build(batch_size), all_at_once, "batches of {}", batch_size);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,307 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::PathBuf,
+};
+
+use bevy_ecs::{
+    change_detection::{DetectChanges, DetectChangesMut},
+    query::{Has, With},
+    system::{Query, Res, ResMut},
+};
+use url::Url;
+
+use crate::{
+    config::SiteConfig,
+    context::GlobalContext,
+    file::{FilePath, Permalink, SourceFile},
+    front_matter::{Draft, MenuEntries, Title, Weight},
+    menu::{MenuItem, Menus},
+    report::Diagnostics,
+};
+
+use super::markdown::MarkdownPost;
+
+/// An entry of a menu before it's nested under its parent.
+struct Entry {
+    /// The page adding the entry, for entries from the front matter.
+    source: Option<PathBuf>,
+    parent: Option<String>,
+    item: MenuItem,
+}
+
+/// Builds the [`Menus`] from the configured entries and those of pages, only replacing
+/// them when they differ, and sets them as the global `menus`. Configured entries come
+/// ahead of those of pages, which keeps them when a page takes the same name.
+pub(super) fn build_menus(
+    config: Res<SiteConfig>,
+    q_pages: Query<
+        (
+            &FilePath,
+            &SourceFile,
+            &MenuEntries,
+            Option<&Title>,
+            Option<&Permalink>,
+            Option<&Weight>,
+            Has<Draft>,
+        ),
+        With<MarkdownPost>,
+    >,
+    mut menus: ResMut<Menus>,
+    mut globals: ResMut<GlobalContext>,
+    mut diagnostics: ResMut<Diagnostics>,
+) {
+    let mut entries: BTreeMap<&str, Vec<Entry>> = BTreeMap::new();
+
+    for (menu, items) in config.menu.iter() {
+        let menu = entries.entry(menu.as_str()).or_default();
+
+        for item in items {
+            // Full URLs point elsewhere, anything else is within the site.
+            let url = match Url::parse(&item.url) {
+                Ok(_) => item.url.clone(),
+                Err(_) => config.url_for(&item.url),
+            };
+
+            menu.push(Entry {
+                source: None,
+                parent: item.parent.clone(),
+                item: MenuItem {
+                    name: item.name.clone(),
+                    url,
+                    weight: item.weight,
+                    children: Vec::new(),
+                },
+            });
+        }
+    }
+
+    let mut pages: Vec<_> = q_pages
+        .iter()
+        .filter(|(.., draft)| !draft || config.build.drafts)
+        .collect();
+    pages.sort_by(|(a, ..), (b, ..)| a.as_ref().cmp(b.as_ref()));
+
+    for (_, source, page_menus, title, permalink, weight, _) in pages {
+        let source = source.as_ref().to_path_buf();
+        let (Some(title), Some(permalink)) = (title, permalink) else {
+            diagnostics.warning(
+                source,
+                "unlinkable-menu-entry",
+                "Pages need a title and a permalink to be added to a menu",
+            );
+            continue;
+        };
+
+        for entry in page_menus.0.iter() {
+            entries.entry(entry.menu.as_str()).or_default().push(Entry {
+                source: Some(source.clone()),
+                parent: entry.parent.clone(),
+                item: MenuItem {
+                    name: title.0.clone(),
+                    url: permalink.0.clone(),
+                    weight: entry.weight.or(weight.map(|weight| weight.0)).unwrap_or(0),
+                    children: Vec::new(),
+                },
+            });
+        }
+    }
+
+    let mut built = BTreeMap::new();
+
+    for (menu, entries) in entries {
+        let mut names = HashSet::new();
+        let entries: Vec<_> = entries
+            .into_iter()
+            .filter(|entry| {
+                let unique = names.insert(entry.item.name.clone());
+
+                if !unique {
+                    diagnostics.warning(
+                        entry.source.clone(),
+                        "duplicate-menu-entry",
+                        format!(
+                            "The {} menu already has an entry named {}, so this one is left out",
+                            menu, entry.item.name
+                        ),
+                    );
+                }
+
+                unique
+            })
+            .collect();
+
+        let top_level: HashSet<_> = entries
+            .iter()
+            .filter(|entry| entry.parent.is_none())
+            .map(|entry| entry.item.name.clone())
+            .collect();
+        let (items, children): (Vec<_>, Vec<_>) = entries.into_iter().partition(|entry| {
+            entry
+                .parent
+                .as_ref()
+                .map_or(true, |parent| !top_level.contains(parent))
+        });
+
+        for entry in items.iter().filter(|entry| entry.parent.is_some()) {
+            diagnostics.warning(
+                entry.source.clone(),
+                "unknown-menu-parent",
+                format!(
+                    "{} isn't a top level entry of the {} menu, so {} is kept at the top level",
+                    entry.parent.as_deref().unwrap_or_default(),
+                    menu,
+                    entry.item.name
+                ),
+            );
+        }
+
+        let mut items: Vec<_> = items.into_iter().map(|entry| entry.item).collect();
+
+        for child in children {
+            if let Some(parent) = items
+                .iter_mut()
+                .find(|item| Some(&item.name) == child.parent.as_ref())
+            {
+                parent.children.push(child.item);
+            }
+        }
+
+        sort_items(&mut items);
+        built.insert(menu.to_string(), items);
+    }
+
+    menus.set_if_neq(Menus::new(built));
+
+    if menus.is_changed() {
+        globals
+            .insert("menus", &menus.context(None))
+            .expect("menus should always be serializable");
+    }
+}
+
+/// Sorts lightest first, with ties in order of name, and the same within each item.
+fn sort_items(items: &mut [MenuItem]) {
+    items.sort_by(|a, b| (a.weight, &a.name).cmp(&(b.weight, &b.name)));
+
+    for item in items.iter_mut() {
+        sort_items(&mut item.children);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Value;
+
+    use crate::{
+        app::ProcessorApp,
+        processor::{FileConfig, InputDir, MarkdownFrontMatter, MarkdownProcessor},
+    };
+
+    use super::*;
+
+    #[test]
+    fn configured_and_page_entries_are_merged_into_menus() {
+        let mut app = ProcessorApp::new();
+        let page = |matter: &str| toml::from_str::<toml::Table>(matter).unwrap();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+        app.insert_resource(
+            toml::from_str::<SiteConfig>(
+                r#"
+                base_url = "https://example.com"
+
+                [[menu.main]]
+                name = "Docs"
+                url = "/docs/"
+                weight = 2
+
+                [[menu.main]]
+                name = "Blog"
+                url = "/blog/"
+                weight = 2
+
+                [[menu.main]]
+                name = "Source"
+                url = "https://github.com/example/site"
+                weight = 9
+                "#,
+            )
+            .unwrap(),
+        )
+        .add_page("about.md", page("title = \"About\"\nmenu = \"main\""), "")
+        .add_page(
+            "install.md",
+            page("title = \"Install\"\nweight = 5\nmenu = { main = { parent = \"Docs\" } }"),
+            "",
+        )
+        .add_page(
+            "usage.md",
+            page("title = \"Usage\"\nmenu.main = { parent = \"Docs\", weight = 1 }"),
+            "",
+        )
+        .add_page(
+            "lost.md",
+            page("title = \"Lost\"\nmenu.main = { parent = \"Usage\" }"),
+            "",
+        )
+        .add_page("blog.md", page("title = \"Blog\"\nmenu = [\"main\"]"), "")
+        .add_page(
+            "draft.md",
+            page("title = \"Draft\"\nmenu = \"main\"\ndraft = true"),
+            "",
+        )
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .run()
+        .unwrap();
+
+        let menus = app.world().resource::<Menus>();
+        let names = |items: &[MenuItem]| -> Vec<String> {
+            items.iter().map(|item| item.name.clone()).collect()
+        };
+        let main = menus.items("main");
+
+        assert_eq!(names(main), ["About", "Lost", "Blog", "Docs", "Source"]);
+        assert_eq!(names(&main[3].children), ["Usage", "Install"]);
+        assert_eq!(main[3].url, "https://example.com/docs/");
+        assert_eq!(main[4].url, "https://github.com/example/site");
+        assert_eq!(main[3].children[1].weight, 5);
+
+        let context = menus.context(Some("https://example.com/usage.html"));
+
+        assert_eq!(context["main"]["active"], "Usage");
+        assert_eq!(
+            context["main"]["items"][0]["url"],
+            "https://example.com/about.html"
+        );
+        assert_eq!(
+            menus.context(Some("https://example.com/docs"))["main"]["active"],
+            "Docs"
+        );
+        assert_eq!(
+            menus.context(None)["main"]["active"],
+            serde_json::Value::Null
+        );
+
+        let globals = app.world().resource::<GlobalContext>();
+
+        assert_eq!(globals.get("menus"), Some(&menus.context(None)));
+
+        let messages: Vec<_> = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+
+        assert_eq!(
+            messages,
+            [
+                "[duplicate-menu-entry] <virtual:blog.md>: The main menu already has an entry named Blog, so this one is left out",
+                "[unknown-menu-parent] <virtual:lost.md>: Usage isn't a top level entry of the main menu, so Lost is kept at the top level",
+            ]
+        );
+    }
+}
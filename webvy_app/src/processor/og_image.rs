@@ -0,0 +1,505 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use bevy_ecs::{
+    component::Component,
+    entity::Entity,
+    query::{Has, With, Without},
+    schedule::IntoSystemConfigs,
+    system::{CommandQueue, Commands, IntoSystem, Query, Res, ResMut, Resource},
+    world::World,
+};
+use log::{error, info, trace};
+use smol::fs::read_to_string;
+
+use crate::{
+    app::{Finish, Load, PostProcess, ProcessorApp, Write},
+    config::{DiffMode, FileConfig, OgImageConfig, OutputDir, SiteConfig},
+    deferred::DeferredTask,
+    file::{Classified, FileName, FilePath, InSection, OgImage, PageType, SourceFile},
+    front_matter::{Date, Draft, FrontMatterKeys, Headless, Raw, Title},
+    io::{create_directory, write_bytes_to_disk, write_file_to_disk},
+    logging::{LOAD, RENDER},
+    manifest::{is_known_output, Manifest},
+    output::{OutputClaim, OutputPath, OutputRegistry},
+    report::{causes_of, Diagnostics},
+    traits::ProcessorPlugin,
+};
+
+use super::{markdown::MarkdownFrontMatter, tera::TeraSet};
+
+/// Folder within the output directory generated images are written to.
+const OG_IMAGE_DIR: &str = "og";
+
+/// File within the output directory recording the hash of every generated image's SVG,
+/// so unchanged images aren't rendered again.
+const CACHE_FILE: &str = ".webvy-og-cache";
+
+/// Generates a PNG social card for every post from the SVG template set with
+/// `[og_image] template`, exposed to templates as `page.og_image`. Posts setting
+/// `image` in their front matter use that instead.
+#[derive(Debug, Default)]
+pub struct OgImageProcessor;
+
+impl OgImageProcessor {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn read_template_task(
+        config: Res<SiteConfig>,
+        q_config: Query<&OutputDir, With<FileConfig>>,
+        deferred: Res<DeferredTask>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        let Some(template) = config.og_image.template.clone() else {
+            return;
+        };
+
+        if !Rasterizer::is_available() {
+            diagnostics.warning(
+                None,
+                "og-image-unavailable",
+                "webvy was built without OpenGraph image support, skipping [og_image]",
+            );
+            return;
+        }
+
+        let output_dir = q_config.single().path().to_path_buf();
+
+        deferred
+            .scoped_task(|scope| async move {
+                info!(target: LOAD, "Reading the OpenGraph image template");
+                let mut queue = CommandQueue::default();
+
+                let cache = match read_to_string(output_dir.join(CACHE_FILE)).await {
+                    Ok(cache) => parse_cache(&cache),
+                    Err(e) => {
+                        trace!("No OpenGraph image cache: {}", e);
+                        HashMap::new()
+                    }
+                };
+
+                match read_to_string(template.as_path()).await {
+                    Ok(source) => queue.push(move |world: &mut World| {
+                        world.insert_resource(OgImageTemplate(source));
+                        world.insert_resource(OgImageCache {
+                            previous: cache,
+                            current: BTreeMap::new(),
+                        });
+                    }),
+                    Err(e) => queue.push(move |world: &mut World| {
+                        world.resource_mut::<Diagnostics>().error(
+                            template,
+                            "og-image-template",
+                            format!("Unable to read the OpenGraph image template: {}", e),
+                        );
+                    }),
+                }
+
+                scope.send(queue);
+            })
+            .detach();
+    }
+
+    fn prepare_images(
+        mut commands: Commands,
+        config: Res<SiteConfig>,
+        template: Option<Res<OgImageTemplate>>,
+        q_pages: Query<
+            (
+                Entity,
+                &FilePath,
+                &FileName,
+                &SourceFile,
+                Option<&Title>,
+                Option<&Date>,
+                Option<&MarkdownFrontMatter>,
+                Option<&Classified>,
+                Has<InSection>,
+                Has<Draft>,
+            ),
+            (Without<OgImage>, Without<Raw>, Without<Headless>),
+        >,
+        mut registry: ResMut<OutputRegistry>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        for (page, path, file_name, source, title, date, front_matter, class, in_section, draft) in
+            q_pages.iter()
+        {
+            let image = front_matter
+                .and_then(MarkdownFrontMatter::access)
+                .and_then(|table| table.get("image"))
+                .and_then(toml::Value::as_str);
+
+            if let Some(image) = image {
+                commands.entity(page).insert(OgImage(image.to_string()));
+                continue;
+            }
+
+            let (Some(template), Some(title)) = (template.as_ref(), title) else {
+                continue;
+            };
+
+            // Only posts get an image, not section listings or root pages.
+            let is_post = in_section && class.is_some_and(|class| class.kind == PageType::Post);
+
+            if !is_post || (draft && !config.build.drafts) {
+                continue;
+            }
+
+            let (lines, font_size) = wrap_title(&title.0, &config.og_image);
+
+            let mut context = tera::Context::new();
+            context.insert("title", &title.0);
+            context.insert("title_lines", &lines);
+            context.insert("font_size", &font_size);
+            context.insert("line_height", &(font_size * 1.2));
+            context.insert("date", &date.map(|date| date.original.as_str()));
+            context.insert("site_title", &config.title);
+
+            let svg = match tera::Tera::one_off(&template.0, &context, true) {
+                Ok(svg) => svg,
+                Err(e) => {
+                    diagnostics.error_with_causes(
+                        source.as_ref().to_path_buf(),
+                        "og-image-failed",
+                        format!("Unable to render the OpenGraph image template: {}", e),
+                        causes_of(&e),
+                    );
+                    continue;
+                }
+            };
+
+            let output = registry.reserve(
+                Path::new(OG_IMAGE_DIR)
+                    .join(path.as_ref().with_file_name(&file_name.0))
+                    .with_extension("png"),
+                OutputClaim::new("OpenGraph image", source.as_ref().to_path_buf()).for_page(page),
+            );
+
+            match output {
+                Ok(output) => {
+                    commands.entity(page).insert((
+                        OgImage(output.url(&config)),
+                        PendingOgImage {
+                            hash: hash_svg(&svg),
+                            output,
+                            svg,
+                        },
+                    ));
+                }
+                Err(collision) => collision.report(&mut diagnostics),
+            }
+        }
+    }
+
+    fn render_images(
+        q_pages: Query<(&PendingOgImage, &SourceFile)>,
+        cache: Option<ResMut<OgImageCache>>,
+        mut manifest: ResMut<Manifest>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) -> Vec<(OutputPath, Vec<u8>)> {
+        let Some(mut cache) = cache else {
+            return Vec::new();
+        };
+
+        let previous = manifest.previous();
+        let mut rasterizer = None;
+        let mut images = Vec::new();
+
+        info!(target: RENDER, "Rendering OpenGraph images");
+
+        for (image, source) in q_pages.iter() {
+            let output = image.output.as_path();
+            let unchanged = cache.previous.get(output) == Some(&image.hash)
+                && is_known_output(previous.as_deref(), output);
+
+            if unchanged {
+                trace!("{} is unchanged", output.display());
+                manifest.record([output.to_path_buf()]);
+                cache.current.insert(output.to_path_buf(), image.hash);
+                continue;
+            }
+
+            // Loading fonts is slow, so is left until an image actually needs rendering.
+            match rasterizer
+                .get_or_insert_with(Rasterizer::new)
+                .render(&image.svg)
+            {
+                Ok(png) => {
+                    cache.current.insert(output.to_path_buf(), image.hash);
+                    images.push((image.output.clone(), png));
+                }
+                Err(e) => diagnostics.error(
+                    source.as_ref().to_path_buf(),
+                    "og-image-failed",
+                    format!("Unable to render the OpenGraph image: {}", e),
+                ),
+            }
+        }
+
+        images
+    }
+
+    fn write_cache(
+        q_config: Query<&OutputDir, With<FileConfig>>,
+        config: Res<SiteConfig>,
+        cache: Option<Res<OgImageCache>>,
+        deferred: Res<DeferredTask>,
+    ) {
+        let Some(cache) = cache.filter(|_| config.build.diff == DiffMode::Off) else {
+            return;
+        };
+
+        let path = q_config.single().path().to_path_buf();
+        let cache = cache
+            .current
+            .iter()
+            .map(|(output, hash)| format!("{:016x} {}\n", hash, output.display()))
+            .collect::<String>();
+
+        deferred
+            .scoped_task(|_| async move {
+                if let Err(e) = create_directory(path.as_path()).await {
+                    error!("Unable to create {}: {}", path.display(), e);
+                }
+
+                if let Err(e) =
+                    write_file_to_disk(path.join(CACHE_FILE).as_path(), cache.as_bytes()).await
+                {
+                    error!("Unable to write the OpenGraph image cache: {}", e);
+                }
+            })
+            .detach();
+    }
+}
+
+impl ProcessorPlugin for OgImageProcessor {
+    fn register(self, app: &mut ProcessorApp) {
+        app.world_mut()
+            .get_resource_or_insert_with(FrontMatterKeys::default)
+            .register("image");
+
+        app.add_systems(Load, Self::read_template_task)
+            .add_systems(PostProcess, Self::prepare_images.before(TeraSet::Context))
+            .add_systems(Write, Self::render_images.pipe(write_bytes_to_disk))
+            .add_systems(Finish, Self::write_cache);
+    }
+}
+
+#[derive(Debug, Resource)]
+struct OgImageTemplate(String);
+
+/// Hashes of the SVG behind each image, from the previous build and this one.
+#[derive(Debug, Resource)]
+struct OgImageCache {
+    previous: HashMap<PathBuf, u64>,
+    current: BTreeMap<PathBuf, u64>,
+}
+
+/// An image waiting to be rendered, relative to the output directory.
+#[derive(Debug, Component)]
+struct PendingOgImage {
+    output: OutputPath,
+    svg: String,
+    hash: u64,
+}
+
+fn hash_svg(svg: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    svg.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+fn parse_cache(cache: &str) -> HashMap<PathBuf, u64> {
+    cache
+        .lines()
+        .filter_map(|line| {
+            let (hash, output) = line.split_once(' ')?;
+
+            Some((PathBuf::from(output), u64::from_str_radix(hash, 16).ok()?))
+        })
+        .collect()
+}
+
+/// Wraps the title onto at most `max_lines` lines, shrinking the font for long titles
+/// and cutting off what still doesn't fit with an ellipsis. Returns the lines along
+/// with the font size they fit at.
+fn wrap_title(title: &str, config: &OgImageConfig) -> (Vec<String>, f32) {
+    let max_lines = config.max_lines.max(1);
+    let mut font_size = config.font_size;
+
+    loop {
+        let width = ((config.line_chars as f32 * config.font_size / font_size) as usize).max(1);
+        let mut lines = wrap_words(title, width);
+
+        if lines.len() <= max_lines {
+            return (lines, font_size);
+        }
+
+        if font_size <= config.min_font_size {
+            lines.truncate(max_lines);
+
+            if let Some(last) = lines.last_mut() {
+                let kept: String = last.chars().take(width - 1).collect();
+
+                *last = format!("{}…", kept.trim_end());
+            }
+
+            return (lines, font_size);
+        }
+
+        font_size = (font_size * 0.9).max(config.min_font_size);
+    }
+}
+
+/// Breaks text into lines of at most `width` characters between words, splitting
+/// words longer than a line.
+fn wrap_words(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_len = 0;
+
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+
+        if line_len > 0 && line_len + 1 + word.len() <= width {
+            line.push(' ');
+            line.extend(&word);
+            line_len += 1 + word.len();
+            continue;
+        }
+
+        if line_len > 0 {
+            lines.push(std::mem::take(&mut line));
+        }
+
+        while word.len() > width {
+            lines.push(word.drain(..width).collect());
+        }
+
+        line_len = word.len();
+        line.extend(word);
+    }
+
+    if line_len > 0 {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Turns rendered SVG into PNG images.
+struct Rasterizer {
+    #[cfg(feature = "og-image")]
+    options: resvg::usvg::Options<'static>,
+}
+
+impl Rasterizer {
+    /// Whether support for rendering images was compiled in.
+    fn is_available() -> bool {
+        cfg!(feature = "og-image")
+    }
+
+    #[cfg(feature = "og-image")]
+    fn new() -> Self {
+        let mut options = resvg::usvg::Options::default();
+
+        options.fontdb_mut().load_system_fonts();
+
+        Self { options }
+    }
+
+    #[cfg(not(feature = "og-image"))]
+    fn new() -> Self {
+        Self {}
+    }
+
+    #[cfg(feature = "og-image")]
+    fn render(&self, svg: &str) -> Result<Vec<u8>, String> {
+        use resvg::{tiny_skia, usvg};
+
+        let tree = usvg::Tree::from_str(svg, &self.options).map_err(|e| e.to_string())?;
+        let size = tree.size().to_int_size();
+        let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+            .ok_or_else(|| String::from("the image has no size"))?;
+
+        resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+
+        pixmap.encode_png().map_err(|e| e.to_string())
+    }
+
+    #[cfg(not(feature = "og-image"))]
+    fn render(&self, _svg: &str) -> Result<Vec<u8>, String> {
+        Err(String::from(
+            "webvy was built without OpenGraph image support",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_titles_shrink_then_wrap_rather_than_clip() {
+        let config = OgImageConfig {
+            line_chars: 10,
+            max_lines: 2,
+            font_size: 60.0,
+            min_font_size: 50.0,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            wrap_title("Short title", &config),
+            (vec![String::from("Short"), String::from("title")], 60.0)
+        );
+
+        assert_eq!(
+            wrap_title("A slightly longer one", &config),
+            (
+                vec![String::from("A slightly"), String::from("longer one")],
+                60.0
+            )
+        );
+
+        // Shrinking the font lets eleven characters fit on a line.
+        let (lines, font_size) = wrap_title("Shrinking fits titles", &config);
+
+        assert_eq!(lines, ["Shrinking", "fits titles"]);
+        assert!((font_size - 54.0).abs() < 0.01, "{font_size}");
+
+        let (lines, font_size) = wrap_title(
+            "Titles far too long to ever fit get cut short instead",
+            &config,
+        );
+
+        assert_eq!(font_size, 50.0);
+        assert_eq!(lines, ["Titles far", "too long to…"]);
+    }
+
+    #[test]
+    fn words_longer_than_a_line_are_split() {
+        assert_eq!(
+            wrap_words("Supercalifragilistic day", 8),
+            ["Supercal", "ifragili", "stic day"]
+        );
+    }
+
+    #[test]
+    fn caches_round_trip() {
+        let cache = parse_cache("00000000000000ff og/blog/post.png\nbroken\n");
+
+        assert_eq!(
+            cache,
+            HashMap::from([(PathBuf::from("og/blog/post.png"), 255)])
+        );
+    }
+}
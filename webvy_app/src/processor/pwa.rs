@@ -0,0 +1,395 @@
+use std::{
+    collections::HashSet,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use bevy_ecs::{
+    query::With,
+    schedule::IntoSystemConfigs,
+    system::{CommandQueue, Commands, Query, Res, ResMut, Resource},
+    world::World,
+};
+use log::info;
+use serde::Serialize;
+use smol::fs::read_to_string;
+
+use crate::{
+    app::{Finish, Load, PostProcess, ProcessorApp},
+    cancel::CancellationToken,
+    config::{FileConfig, OutputDir, SiteConfig},
+    deferred::DeferredTask,
+    file::{FileName, FilePath},
+    front_matter::Draft,
+    io::{write_pages, WriteOptions, WriteSummary},
+    logging::{LOAD, RENDER},
+    manifest::{record_removed_outputs, Manifest, OutputDigest},
+    output::{locate, safe_join, OutputClaim, OutputPath, OutputRegistry, PathError},
+    report::{causes_of, BuildErrors, BuildReport, Diagnostics},
+    traits::ProcessorPlugin,
+};
+
+/// The service worker, at the root of the site so it controls every page.
+const WORKER_FILE: &str = "sw.js";
+
+/// The list of precached assets, fetched by workers wanting to check for changes.
+const PRECACHE_FILE: &str = "precache.json";
+
+/// The service worker used unless `[pwa] template` is set. Every asset is cached on
+/// install, requests go to the network first and fall back to the cache when offline.
+const DEFAULT_TEMPLATE: &str = r#"const CACHE = "webvy-{{ version }}";
+const PRECACHE = {{ assets | json_encode() | safe }};
+
+self.addEventListener("install", (event) => {
+  event.waitUntil(
+    caches
+      .open(CACHE)
+      .then((cache) => cache.addAll(PRECACHE.map((asset) => asset.url)))
+      .then(() => self.skipWaiting())
+  );
+});
+
+self.addEventListener("activate", (event) => {
+  event.waitUntil(
+    caches
+      .keys()
+      .then((keys) =>
+        Promise.all(keys.filter((key) => key !== CACHE).map((key) => caches.delete(key)))
+      )
+      .then(() => self.clients.claim())
+  );
+});
+
+self.addEventListener("fetch", (event) => {
+  if (event.request.method !== "GET") {
+    return;
+  }
+
+  event.respondWith(
+    fetch(event.request).catch(() =>
+      caches
+        .match(event.request, { ignoreSearch: true })
+        .then((response) => response || Response.error())
+    )
+  );
+});
+"#;
+
+/// Generates a service worker precaching the site's pages and assets for offline use,
+/// when `[pwa] enabled` is set. The worker embeds the content hash of everything it
+/// caches, so it changes, and clients refetch, whenever any of them do. Drafts are
+/// never cached.
+#[derive(Debug, Default)]
+pub struct PwaProcessor;
+
+impl PwaProcessor {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn read_template_task(
+        config: Res<SiteConfig>,
+        deferred: Res<DeferredTask>,
+        mut commands: Commands,
+    ) {
+        if !config.pwa.enabled {
+            return;
+        }
+
+        let Some(template) = config.pwa.template.clone() else {
+            commands.insert_resource(WorkerTemplate(DEFAULT_TEMPLATE.to_string()));
+            return;
+        };
+
+        deferred
+            .scoped_task(|scope| async move {
+                info!(target: LOAD, "Reading the service worker template");
+                let mut queue = CommandQueue::default();
+
+                match read_to_string(template.as_path()).await {
+                    Ok(source) => queue.push(move |world: &mut World| {
+                        world.insert_resource(WorkerTemplate(source));
+                    }),
+                    Err(e) => queue.push(move |world: &mut World| {
+                        world.resource_mut::<Diagnostics>().error(
+                            template,
+                            "pwa-template",
+                            format!("Unable to read the service worker template: {}", e),
+                        );
+                    }),
+                }
+
+                scope.send(queue);
+            })
+            .detach();
+    }
+
+    fn reserve_outputs(
+        mut commands: Commands,
+        config: Res<SiteConfig>,
+        q_drafts: Query<(&FilePath, &FileName), With<Draft>>,
+        mut registry: ResMut<OutputRegistry>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        if !config.pwa.enabled {
+            return;
+        }
+
+        let mut reserve = |file: &str, producer| {
+            registry
+                .reserve(file, OutputClaim::new(producer, None))
+                .map_err(|collision| collision.report(&mut diagnostics))
+                .ok()
+        };
+
+        let (Some(worker), Some(precache)) = (
+            reserve(WORKER_FILE, "service worker"),
+            reserve(PRECACHE_FILE, "precache manifest"),
+        ) else {
+            return;
+        };
+
+        commands.insert_resource(WorkerOutputs {
+            worker,
+            precache,
+            drafts: q_drafts
+                .iter()
+                .map(|(path, file_name)| path.as_ref().with_file_name(&file_name.0))
+                .collect(),
+        });
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn write_service_worker(
+        config: Res<SiteConfig>,
+        q_config: Query<&OutputDir, With<FileConfig>>,
+        outputs: Option<Res<WorkerOutputs>>,
+        template: Option<Res<WorkerTemplate>>,
+        mut manifest: ResMut<Manifest>,
+        cancel: Res<CancellationToken>,
+        deferred: Res<DeferredTask>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        let (Some(outputs), Some(template)) = (outputs, template) else {
+            return;
+        };
+
+        // An interrupted build didn't write everything, so can't precache it.
+        if cancel.is_cancelled() {
+            return;
+        }
+
+        let assets = precache_entries(
+            manifest
+                .outputs()
+                .filter_map(|output| Some((output, manifest.digest(output)?))),
+            &config,
+            &outputs.drafts,
+        );
+        let version = precache_version(&assets);
+
+        info!(
+            target: RENDER,
+            "Rendering the service worker, precaching {} assets",
+            assets.len()
+        );
+
+        let mut context = tera::Context::new();
+        context.insert("version", &version);
+        context.insert("assets", &assets);
+        context.insert("precache_url", &outputs.precache.url(&config));
+
+        let worker = match tera::Tera::one_off(&template.0, &context, false) {
+            Ok(worker) => worker,
+            Err(e) => {
+                diagnostics.error_with_causes(
+                    config.pwa.template.clone(),
+                    "pwa-template",
+                    format!("Unable to render the service worker template: {}", e),
+                    causes_of(&e),
+                );
+                return;
+            }
+        };
+
+        let precache = serde_json::to_string_pretty(&serde_json::json!({
+            "version": version,
+            "assets": assets,
+        }))
+        .expect("the precache should always be serializable");
+
+        let dir = q_config.single().path();
+        let files = [(&outputs.worker, worker), (&outputs.precache, precache)]
+            .into_iter()
+            .map(|(output, content)| Ok((safe_join(dir, output)?, content)))
+            .collect::<Result<Vec<_>, PathError>>();
+
+        let files = match files {
+            Ok(files) => files,
+            Err(e) => {
+                diagnostics.error(None, "unsafe-output-path", e.to_string());
+                return;
+            }
+        };
+
+        // The manifest is written during this schedule too, so record these ahead of
+        // writing them.
+        manifest.record([
+            outputs.worker.as_path().to_path_buf(),
+            outputs.precache.as_path().to_path_buf(),
+        ]);
+
+        let mut options = WriteOptions::from_config(&config, dir, &manifest, &cancel);
+        // Compressed siblings would be missing from the manifest, so aren't written.
+        options.compress.codecs.clear();
+
+        deferred
+            .scoped_task(move |scope| async move {
+                let WriteSummary {
+                    report,
+                    changes,
+                    errors,
+                    ..
+                } = write_pages(files, options).await;
+
+                let mut queue = CommandQueue::default();
+
+                queue.push(move |world: &mut World| {
+                    let mut build = world.resource_mut::<BuildReport>();
+
+                    build.written += report.written;
+                    build.unchanged += report.unchanged;
+                    build.changes.extend(changes);
+
+                    world.resource_mut::<BuildErrors>().0.extend(errors);
+                });
+
+                scope.send(queue);
+            })
+            .detach();
+    }
+}
+
+impl ProcessorPlugin for PwaProcessor {
+    fn register(self, app: &mut ProcessorApp) {
+        app.init_resource::<Manifest>()
+            .add_systems(Load, Self::read_template_task)
+            .add_systems(PostProcess, Self::reserve_outputs)
+            .add_systems(
+                Finish,
+                Self::write_service_worker.before(record_removed_outputs),
+            );
+    }
+}
+
+#[derive(Debug, Resource)]
+struct WorkerTemplate(String);
+
+/// Where the worker and its precache list are written, along with the outputs of
+/// drafts, which are never precached.
+#[derive(Debug, Resource)]
+struct WorkerOutputs {
+    worker: OutputPath,
+    precache: OutputPath,
+    drafts: HashSet<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+struct PrecacheEntry {
+    url: String,
+    /// The content hash of the asset, changing whenever its content does.
+    revision: String,
+    size: u64,
+}
+
+/// Lists the outputs with a precached extension and within the size cap, sorted by
+/// path. Pages are listed by their URL rather than their `index.html`.
+fn precache_entries<'a>(
+    outputs: impl Iterator<Item = (&'a Path, OutputDigest)>,
+    config: &SiteConfig,
+    drafts: &HashSet<PathBuf>,
+) -> Vec<PrecacheEntry> {
+    let pwa = &config.pwa;
+    let mut outputs: Vec<_> = outputs
+        .filter(|(output, digest)| {
+            let extension = output.extension().and_then(|extension| extension.to_str());
+
+            extension
+                .is_some_and(|extension| pwa.extensions.iter().any(|allowed| allowed == extension))
+                && digest.size <= pwa.max_asset_size
+                && !drafts.contains(*output)
+        })
+        .collect();
+    outputs.sort_by_key(|(output, _)| *output);
+
+    outputs
+        .into_iter()
+        .map(|(output, digest)| PrecacheEntry {
+            url: locate(config, output).url,
+            revision: format!("{:016x}", digest.hash),
+            size: digest.size,
+        })
+        .collect()
+}
+
+/// Hashes every entry together, so the version changes along with any asset.
+fn precache_version(assets: &[PrecacheEntry]) -> String {
+    let mut hasher = DefaultHasher::new();
+
+    assets.hash(&mut hasher);
+
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drafts_and_large_or_unlisted_outputs_are_not_precached() {
+        let config = toml::from_str::<SiteConfig>(
+            "base_url = \"https://example.com/blog\"\n[pwa]\nenabled = true\nmax_asset_size = 100",
+        )
+        .unwrap();
+        let digest = |size, hash| OutputDigest { size, hash };
+        let outputs = [
+            (Path::new("style.css"), digest(10, 1)),
+            (Path::new("index.html"), digest(20, 2)),
+            (Path::new("posts/draft.html"), digest(20, 3)),
+            (Path::new("posts/index.html"), digest(20, 4)),
+            (Path::new("posts/huge.html"), digest(200, 5)),
+            (Path::new("og/posts/a.png"), digest(20, 6)),
+            (Path::new("index.html.gz"), digest(10, 7)),
+        ];
+        let drafts = HashSet::from([PathBuf::from("posts/draft.html")]);
+
+        let assets = precache_entries(outputs.into_iter(), &config, &drafts);
+        let urls: Vec<_> = assets.iter().map(|asset| asset.url.as_str()).collect();
+
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/blog/",
+                "https://example.com/blog/posts/",
+                "https://example.com/blog/style.css",
+            ]
+        );
+        assert_eq!(assets[0].revision, "0000000000000002");
+
+        let version = precache_version(&assets);
+        let mut changed = assets.clone();
+        changed[2].revision = String::from("0000000000000008");
+
+        assert_ne!(precache_version(&changed), version);
+
+        let mut context = tera::Context::new();
+        context.insert("version", &version);
+        context.insert("assets", &assets);
+
+        let worker = tera::Tera::one_off(DEFAULT_TEMPLATE, &context, false).unwrap();
+
+        assert!(worker.contains(&format!("const CACHE = \"webvy-{}\";", version)));
+        assert!(worker.contains("\"url\":\"https://example.com/blog/posts/\""));
+        assert!(worker.contains("\"revision\":\"0000000000000002\""));
+    }
+}
@@ -0,0 +1,438 @@
+use std::{cmp::Ordering, collections::HashMap, path::Path};
+
+use bevy_ecs::{
+    entity::{Entity, EntityHashMap},
+    query::{Has, With, Without},
+    system::{Commands, Query, Res, ResMut, Resource},
+};
+use chrono::{DateTime, Utc};
+use log::info;
+
+use crate::{
+    build_info::BuildClock,
+    config::{SectionConfig, SiteConfig, SortBy},
+    file::{
+        Classified, FilePath, InSection, PageType, Permalink, SectionIndex, SectionInfo,
+        SectionName, SourceFile,
+    },
+    front_matter::{Date, Description, Draft, Title, Unlisted, Weight},
+    report::Diagnostics,
+};
+
+use super::markdown::MarkdownPost;
+
+/// The posts of every section in listing order, following each section's `sort_by`.
+/// Drafts are only included when they're being rendered, while future dated posts and
+/// those with `in_listing = false` never are. Built once per build during [`PostProcess`](crate::app::PostProcess), so
+/// listings, feeds and navigation all agree on the same order.
+#[derive(Debug, Default, Resource)]
+pub struct SectionPosts {
+    sections: HashMap<String, Vec<Entity>>,
+    positions: EntityHashMap<(String, usize)>,
+}
+
+impl SectionPosts {
+    /// The posts of `section`, in listing order.
+    pub fn iter_posts(&self, section: &str) -> impl Iterator<Item = Entity> + '_ {
+        self.sections
+            .get(section)
+            .into_iter()
+            .flat_map(|posts| posts.iter().copied())
+    }
+
+    /// The section a post is listed in and its position within it.
+    pub fn position_of(&self, entity: Entity) -> Option<(&str, usize)> {
+        self.positions
+            .get(&entity)
+            .map(|(section, position)| (section.as_str(), *position))
+    }
+
+    fn insert(&mut self, section: &str, posts: Vec<Entity>) {
+        for (position, post) in posts.iter().enumerate() {
+            self.positions
+                .insert(*post, (section.to_string(), position));
+        }
+
+        self.sections.insert(section.to_string(), posts);
+    }
+}
+
+/// Links every page within a section to it with [`InSection`], and each section to its
+/// `_index.md` with [`SectionIndex`], describing the section with the index's title,
+/// description and permalink. Pages belong to the section named by the first directory
+/// of their path, so pages in a directory matching no section are reported instead.
+pub(super) fn link_sections(
+    mut commands: Commands,
+    q_sections: Query<(
+        Entity,
+        &PageType,
+        &SectionName,
+        Option<&SectionIndex>,
+        Option<&SectionInfo>,
+    )>,
+    q_pages: Query<
+        (
+            Entity,
+            &Classified,
+            &SourceFile,
+            Option<&InSection>,
+            Option<&Title>,
+            Option<&Description>,
+            Option<&Permalink>,
+        ),
+        With<MarkdownPost>,
+    >,
+    mut diagnostics: ResMut<Diagnostics>,
+) {
+    let mut sections: HashMap<&str, Vec<Entity>> = HashMap::new();
+
+    for (section, _, name, ..) in q_sections
+        .iter()
+        .filter(|(_, page_type, ..)| **page_type == PageType::Section)
+    {
+        sections.entry(name.as_ref()).or_default().push(section);
+    }
+
+    let mut indexes = EntityHashMap::default();
+
+    for (page, class, source, linked, ..) in q_pages.iter() {
+        let Some(name) = class.section() else {
+            continue;
+        };
+
+        let section = match sections.get(name).map(Vec::as_slice) {
+            Some(&[section]) => section,
+            Some(matches) if !matches.is_empty() => {
+                diagnostics.error(
+                    source.as_ref().to_path_buf(),
+                    "ambiguous-section",
+                    format!("{} sections are named {}", matches.len(), name),
+                );
+                continue;
+            }
+            _ => {
+                diagnostics.warning(
+                    source.as_ref().to_path_buf(),
+                    "orphan-page",
+                    format!("{} isn't a content section, so the page is left out", name),
+                );
+                continue;
+            }
+        };
+
+        if linked != Some(&InSection(section)) {
+            commands.entity(page).insert(InSection(section));
+        }
+
+        if class.is_section_index() {
+            indexes.insert(section, page);
+        }
+    }
+
+    for (section, _, name, linked_index, current) in q_sections
+        .iter()
+        .filter(|(_, page_type, ..)| **page_type == PageType::Section)
+    {
+        let index = indexes.get(&section).copied();
+        let mut info = SectionInfo {
+            name: name.as_ref().to_string(),
+            ..Default::default()
+        };
+
+        if let Some((.., title, description, permalink)) =
+            index.and_then(|index| q_pages.get(index).ok())
+        {
+            info.title = title.map(|title| title.0.clone());
+            info.description = description.map(|description| description.0.clone());
+            info.permalink = permalink.map(|permalink| permalink.0.clone());
+        }
+
+        let mut section = commands.entity(section);
+
+        match index {
+            Some(index) if linked_index != Some(&SectionIndex(index)) => {
+                section.insert(SectionIndex(index));
+            }
+            None if linked_index.is_some() => {
+                section.remove::<SectionIndex>();
+            }
+            _ => {}
+        }
+
+        // Left alone when unchanged, so pages showing it aren't rendered again.
+        if current != Some(&info) {
+            section.insert(info);
+        }
+    }
+}
+
+pub(super) fn build_section_posts(
+    config: Res<SiteConfig>,
+    q_sections: Query<(Entity, &PageType, &SectionName, Option<&SectionConfig>)>,
+    q_posts: Query<
+        (
+            Entity,
+            &FilePath,
+            &Classified,
+            &InSection,
+            Option<&Date>,
+            Option<&Title>,
+            Option<&Weight>,
+            Has<Draft>,
+        ),
+        (With<MarkdownPost>, Without<Unlisted>),
+    >,
+    mut section_posts: ResMut<SectionPosts>,
+    clock: Res<BuildClock>,
+) {
+    info!("Sorting section posts");
+    let now = clock.now();
+
+    *section_posts = SectionPosts::default();
+
+    for (section_entity, _, section, section_config) in q_sections
+        .iter()
+        .filter(|(_, page_type, ..)| **page_type == PageType::Section)
+    {
+        let mut posts: Vec<_> = q_posts
+            .iter()
+            .filter(|(_, _, class, in_section, ..)| {
+                in_section.0 == section_entity && class.kind == PageType::Post
+            })
+            .map(|(entity, path, _, _, date, title, weight, draft)| {
+                let post = SortablePost {
+                    entity,
+                    path: path.as_ref(),
+                    date: date.and_then(Date::to_datetime),
+                    title: title.map(|title| title.0.as_str()),
+                    weight: weight.map(|weight| weight.0),
+                };
+
+                (post, draft)
+            })
+            .filter(|(post, draft)| is_listed(&config, *draft, post.date, now))
+            .map(|(post, _)| post)
+            .collect();
+
+        sort_posts(
+            &mut posts,
+            section_config.map_or(SortBy::default(), SectionConfig::sort_by),
+        );
+
+        section_posts.insert(
+            section.as_ref(),
+            posts.into_iter().map(|post| post.entity).collect(),
+        );
+    }
+}
+
+/// Whether a post is listed: drafts only when they're being rendered, and never
+/// before its date.
+pub(super) fn is_listed(
+    config: &SiteConfig,
+    draft: bool,
+    date: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> bool {
+    (!draft || config.build.drafts) && date.map_or(true, |date| date <= now)
+}
+
+pub(super) struct SortablePost<'a> {
+    pub entity: Entity,
+    pub path: &'a Path,
+    pub date: Option<DateTime<Utc>>,
+    pub title: Option<&'a str>,
+    pub weight: Option<i64>,
+}
+
+/// Sorts newest first by date, lightest first by weight or alphabetically by title,
+/// with posts missing the sort key last and ties broken by path.
+pub(super) fn sort_posts(posts: &mut [SortablePost], sort_by: SortBy) {
+    fn missing_last<T>(a: &Option<T>, b: &Option<T>) -> Ordering {
+        a.is_none().cmp(&b.is_none())
+    }
+
+    posts.sort_by(|a, b| {
+        match sort_by {
+            SortBy::Date => missing_last(&a.date, &b.date).then(b.date.cmp(&a.date)),
+            SortBy::Weight => missing_last(&a.weight, &b.weight).then(a.weight.cmp(&b.weight)),
+            SortBy::Title => missing_last(&a.title, &b.title).then(a.title.cmp(&b.title)),
+        }
+        .then_with(|| a.path.cmp(b.path))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::{system::Command, world::World};
+    use toml::Value;
+
+    use crate::{
+        app::ProcessorApp,
+        file::{EnumeratedSections, RootPageType},
+        processor::{FileConfig, InputDir, MarkdownFrontMatter, MarkdownProcessor},
+    };
+
+    use super::*;
+
+    #[test]
+    fn pages_are_linked_to_the_section_they_are_in() {
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+
+        for section in ["notes", "notes-archive"] {
+            EnumeratedSections::new(section.into())
+                .unwrap()
+                .apply(app.world_mut());
+        }
+
+        // Enumerating a section again keeps the one already there.
+        EnumeratedSections::new("notes".into())
+            .unwrap()
+            .apply(app.world_mut());
+
+        let page = |title: &str| toml::from_str(&format!("title = \"{}\"", title)).unwrap();
+        let post = |title: &str, date: &str| {
+            toml::from_str(&format!("title = \"{}\"\ndate = {}", title, date)).unwrap()
+        };
+
+        app.init_resource::<SiteConfig>()
+            .add_page("_index.md", page("Home"), "")
+            .add_page("about.md", page("About"), "")
+            .add_page("notes/_index.md", page("Notes"), "")
+            .add_page("notes/b.md", post("B", "2024-01-01"), "")
+            .add_page("notes/nested/a.md", post("A", "2024-02-01"), "")
+            .add_page("notes-archive/c.md", post("C", "2024-03-01"), "")
+            .add_page("drafts/d.md", page("D"), "")
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .run()
+            .unwrap();
+
+        let mut sections = app
+            .world_mut()
+            .query::<(Entity, &PageType, &SectionName, Option<&SectionIndex>)>();
+        let sections: HashMap<String, (Entity, Option<Entity>)> = sections
+            .iter(app.world())
+            .filter(|(_, page_type, ..)| **page_type == PageType::Section)
+            .map(|(section, _, name, index)| {
+                (
+                    name.as_ref().to_string(),
+                    (section, index.map(|index| index.0)),
+                )
+            })
+            .collect();
+
+        assert_eq!(sections.len(), 2);
+
+        let (notes, notes_index) = sections["notes"];
+        let (archive, archive_index) = sections["notes-archive"];
+
+        let mut pages = app
+            .world_mut()
+            .query::<(Entity, &FilePath, Option<&InSection>)>();
+        let pages: HashMap<String, (Entity, Option<Entity>)> = pages
+            .iter(app.world())
+            .map(|(page, path, in_section)| {
+                (
+                    path.as_ref().display().to_string(),
+                    (page, in_section.map(|in_section| in_section.0)),
+                )
+            })
+            .collect();
+        let linked = |path: &str| pages[path].1;
+
+        assert_eq!(linked("_index.md"), None);
+        assert_eq!(linked("about.md"), None);
+        assert_eq!(linked("notes/_index.md"), Some(notes));
+        assert_eq!(linked("notes/b.md"), Some(notes));
+        assert_eq!(linked("notes/nested/a.md"), Some(notes));
+        assert_eq!(linked("notes-archive/c.md"), Some(archive));
+        assert_eq!(linked("drafts/d.md"), None);
+
+        assert_eq!(notes_index, Some(pages["notes/_index.md"].0));
+        assert_eq!(archive_index, None);
+
+        let section_posts = app.world().resource::<SectionPosts>();
+        let titles = |section: &str| {
+            section_posts
+                .iter_posts(section)
+                .map(|post| app.world().get::<Title>(post).unwrap().0.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(titles("notes"), ["A", "B"]);
+        assert_eq!(titles("notes-archive"), ["C"]);
+
+        let info = app.world().get::<SectionInfo>(notes).unwrap();
+
+        assert_eq!(info.title.as_deref(), Some("Notes"));
+
+        let messages: Vec<_> = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+
+        assert_eq!(
+            messages,
+            ["[orphan-page] <virtual:drafts/d.md>: drafts isn't a content section, so the page is left out"]
+        );
+    }
+
+    #[test]
+    fn page_types_are_only_registered_once() {
+        let mut world = World::new();
+
+        for _ in 0..2 {
+            RootPageType(PageType::Index).apply(&mut world);
+            RootPageType(PageType::Page).apply(&mut world);
+            EnumeratedSections::new("notes".into())
+                .unwrap()
+                .apply(&mut world);
+        }
+
+        let mut page_types = world.query::<&PageType>();
+        let mut page_types: Vec<_> = page_types.iter(&world).map(ToString::to_string).collect();
+        page_types.sort();
+
+        assert_eq!(page_types, ["index", "page", "post", "section"]);
+    }
+
+    #[test]
+    fn posts_are_sorted_by_key_with_a_stable_tiebreak() {
+        let post = |path, date: Option<&str>, title, weight| SortablePost {
+            entity: Entity::PLACEHOLDER,
+            path: Path::new(path),
+            date: date.and_then(|date| Date::new(date, None).to_datetime()),
+            title,
+            weight,
+        };
+        let order = |posts: &[SortablePost]| {
+            posts
+                .iter()
+                .map(|post| post.path.display().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let mut posts = [
+            post("b.md", Some("2024-01-01"), Some("Beta"), Some(2)),
+            post("a.md", Some("2024-01-01"), None, None),
+            post("c.md", None, Some("Alpha"), Some(1)),
+            post("d.md", Some("2024-05-01"), Some("Beta"), Some(2)),
+        ];
+
+        sort_posts(&mut posts, SortBy::Date);
+        assert_eq!(order(&posts), ["d.md", "a.md", "b.md", "c.md"]);
+
+        sort_posts(&mut posts, SortBy::Weight);
+        assert_eq!(order(&posts), ["c.md", "b.md", "d.md", "a.md"]);
+
+        sort_posts(&mut posts, SortBy::Title);
+        assert_eq!(order(&posts), ["c.md", "b.md", "d.md", "a.md"]);
+    }
+}
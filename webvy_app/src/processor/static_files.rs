@@ -0,0 +1,376 @@
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use bevy_ecs::{
+    query::With,
+    system::{CommandQueue, Commands, IntoSystem, Query, Res, ResMut, Resource},
+    world::World,
+};
+use log::{info, trace};
+use smol::fs::read;
+
+use crate::{
+    app::{Finish, Load, PostProcess, ProcessorApp, Write},
+    assets::{AssetHandler, AssetHandlers},
+    config::{FileConfig, SiteConfig, StaticDir},
+    deferred::DeferredTask,
+    files::{find_all_files_in_directory, WalkOptions},
+    io::write_bytes_to_disk,
+    logging::LOAD,
+    output::{OutputClaim, OutputPath, OutputRegistry},
+    report::Diagnostics,
+    svg::{minify_svg, CachedSvg, SvgCache},
+    traits::ProcessorPlugin,
+};
+
+/// Copies every file in the `[files] static` directory into the output directory, at
+/// the same path within it. Files are copied byte for byte, unless `[assets.handlers]`
+/// gives their extension another [`AssetHandler`]. Files over `[assets]
+/// max_asset_size` are warned about. SVG files are also kept in the [`SvgCache`], for
+/// templates to inline.
+#[derive(Debug, Default)]
+pub struct StaticProcessor;
+
+impl StaticProcessor {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn read_static_files_task(
+        q_config: Query<&StaticDir, With<FileConfig>>,
+        config: Res<SiteConfig>,
+        deferred: Res<DeferredTask>,
+    ) {
+        let Ok(dir) = q_config.get_single() else {
+            return;
+        };
+
+        let dir = dir.path().to_path_buf();
+        let walk = WalkOptions::from_config(&config);
+
+        deferred
+            .scoped_task(move |scope| async move {
+                info!(target: LOAD, "Reading static files from disk");
+                let mut files = Vec::new();
+                let mut errors = Vec::new();
+
+                match find_all_files_in_directory(&dir, walk).await {
+                    Ok(found) => {
+                        for source in found {
+                            match read(&source).await {
+                                Ok(content) => {
+                                    let path = source.strip_prefix(&dir).unwrap_or(&source);
+
+                                    files.push(StaticFile {
+                                        path: path.to_path_buf(),
+                                        source,
+                                        content,
+                                    });
+                                }
+                                Err(e) => errors.push((source, e.to_string())),
+                            }
+                        }
+                    }
+                    Err(e) => errors.push((dir, e.to_string())),
+                }
+
+                let svgs: HashMap<_, _> = files
+                    .iter()
+                    .filter_map(|file| Some((svg_name(&file.path)?, file)))
+                    .filter_map(|(name, file)| {
+                        let svg = std::str::from_utf8(&file.content).ok()?;
+
+                        Some((
+                            name,
+                            CachedSvg {
+                                svg: minify_svg(svg).into(),
+                                size: file.content.len(),
+                            },
+                        ))
+                    })
+                    .collect();
+
+                let mut queue = CommandQueue::default();
+
+                queue.push(move |world: &mut World| {
+                    // Replacing the same SVGs would have every page rendered again.
+                    if !world.resource::<SvgCache>().holds(&svgs) {
+                        world.resource_mut::<SvgCache>().replace(svgs);
+                    }
+
+                    let mut diagnostics = world.resource_mut::<Diagnostics>();
+
+                    for (source, e) in errors {
+                        diagnostics.error(
+                            source,
+                            "unreadable-static-file",
+                            format!("Unable to read the static file: {}", e),
+                        );
+                    }
+
+                    world.insert_resource(StaticFiles(files));
+                });
+
+                scope.send(queue);
+            })
+            .detach();
+    }
+
+    fn reserve_outputs(
+        mut commands: Commands,
+        config: Res<SiteConfig>,
+        files: Option<ResMut<StaticFiles>>,
+        handlers: Res<AssetHandlers>,
+        mut registry: ResMut<OutputRegistry>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        let Some(mut files) = files else {
+            return;
+        };
+
+        let max_size = config.assets.max_asset_size;
+        let mut outputs = Vec::new();
+
+        for file in std::mem::take(&mut files.0) {
+            if file.content.len() as u64 > max_size {
+                diagnostics.warning(
+                    file.source.clone(),
+                    "large-asset",
+                    format!(
+                        "Static file is {} bytes, over the {} byte `max_asset_size` under \
+                         [assets]",
+                        file.content.len(),
+                        max_size
+                    ),
+                );
+            }
+
+            let handler = match handlers.handler_for(&config.assets, &file.path) {
+                Ok(handler) => handler,
+                Err(e) => {
+                    diagnostics.error(file.source, "unknown-asset-handler", e.to_string());
+                    continue;
+                }
+            };
+
+            let claim = OutputClaim::new("static file", file.source.clone());
+            match registry.reserve(&file.path, claim) {
+                Ok(output) => outputs.push((output, file, handler)),
+                Err(collision) => collision.report(&mut diagnostics),
+            }
+        }
+
+        commands.insert_resource(StaticOutputs(outputs));
+    }
+
+    fn handle_static_files(
+        outputs: Option<ResMut<StaticOutputs>>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) -> Vec<(OutputPath, Vec<u8>)> {
+        let Some(mut outputs) = outputs else {
+            return Vec::new();
+        };
+
+        info!(target: LOAD, "Copying {} static files", outputs.0.len());
+
+        std::mem::take(&mut outputs.0)
+            .into_iter()
+            .filter_map(|(output, file, handler)| {
+                trace!("Handling static file {}", file.source.display());
+
+                match handler.handle(&file.path, file.content) {
+                    Ok(content) => Some((output, content)),
+                    Err(e) => {
+                        diagnostics.error(file.source, "asset-handler", e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Warns about the oversized SVGs templates inlined during this build.
+    fn report_oversized_svgs(
+        config: Res<SiteConfig>,
+        svgs: Res<SvgCache>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        let max_size = config.assets.max_inline_svg_size;
+
+        for (path, size) in svgs.take_used() {
+            if size as u64 > max_size {
+                diagnostics.warning(
+                    None,
+                    "large-inline-svg",
+                    format!(
+                        "`{}` is {} bytes, over the {} byte `max_inline_svg_size` under \
+                         [assets], and is repeated in every page inlining it",
+                        path, size, max_size
+                    ),
+                );
+            }
+        }
+    }
+}
+
+impl ProcessorPlugin for StaticProcessor {
+    fn register(self, app: &mut ProcessorApp) {
+        app.world_mut()
+            .get_resource_or_insert_with(AssetHandlers::default);
+
+        app.init_resource::<SvgCache>()
+            .add_systems(Load, Self::read_static_files_task)
+            .add_systems(PostProcess, Self::reserve_outputs)
+            .add_systems(Write, Self::handle_static_files.pipe(write_bytes_to_disk))
+            .add_systems(Finish, Self::report_oversized_svgs);
+    }
+}
+
+/// The name templates inline an SVG by, its path within the static directory with `/`
+/// separators.
+fn svg_name(path: &std::path::Path) -> Option<String> {
+    if !path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("svg"))
+    {
+        return None;
+    }
+
+    let parts: Option<Vec<_>> = path
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect();
+
+    parts.map(|parts| parts.join("/"))
+}
+
+#[derive(Debug)]
+struct StaticFile {
+    /// Where the file is within the static directory, and so the output directory.
+    path: PathBuf,
+    /// Where the file was read from.
+    source: PathBuf,
+    content: Vec<u8>,
+}
+
+/// The static files read during this build.
+#[derive(Debug, Resource)]
+struct StaticFiles(Vec<StaticFile>);
+
+/// The static files along with where they're written and the handler of each.
+#[derive(Resource)]
+struct StaticOutputs(Vec<(OutputPath, StaticFile, Arc<dyn AssetHandler>)>);
+
+#[cfg(test)]
+mod tests {
+    use toml::Value;
+
+    use crate::{
+        config::{InputDir, OutputDir},
+        manifest::Manifest,
+    };
+
+    use super::*;
+
+    /// Shouts text assets, showing handlers other than copying can be added.
+    struct Shout;
+
+    impl AssetHandler for Shout {
+        fn handle(&self, _path: &std::path::Path, content: Vec<u8>) -> Result<Vec<u8>, String> {
+            String::from_utf8(content)
+                .map(|text| text.to_uppercase().into_bytes())
+                .map_err(|e| e.to_string())
+        }
+    }
+
+    #[test]
+    fn static_files_are_copied_byte_for_byte() {
+        let dir = std::env::temp_dir().join("webvy_static_files_are_copied_byte_for_byte");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("static/images")).unwrap();
+
+        // A PNG signature followed by bytes that aren't valid UTF-8.
+        let png = [
+            0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0xff, 0xfe, 0x00, 0xc3, 0x28,
+        ];
+        let bom = b"\xef\xbb\xbfHello\r\n";
+        std::fs::write(dir.join("static/images/logo.PNG"), png).unwrap();
+        std::fs::write(dir.join("static/bom.txt"), bom).unwrap();
+        std::fs::write(dir.join("static/notes.md"), "hello").unwrap();
+        std::fs::write(
+            dir.join("static/icon.svg"),
+            "<!-- Icon -->\n<svg >\n</svg>\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("static/video.mp4"), vec![0; 64]).unwrap();
+
+        let build = |config: &str| {
+            let mut app = ProcessorApp::new();
+
+            app.world_mut().spawn((
+                FileConfig,
+                InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+                OutputDir::new(dir.join("public")),
+                StaticDir::new(dir.join("static")),
+            ));
+            app.insert_resource(toml::from_str::<SiteConfig>(config).unwrap())
+                .init_resource::<Manifest>()
+                .add_processor(StaticProcessor::new());
+            app.world_mut()
+                .resource_mut::<AssetHandlers>()
+                .register("shout", Shout);
+            app.run().unwrap();
+            app
+        };
+
+        let mut app = build(
+            "[assets]\nmax_asset_size = 32\n[assets.handlers]\npng = \"copy\"\nmd = \"shout\"\n\
+             svg = \"minify\"",
+        );
+
+        assert_eq!(
+            std::fs::read(dir.join("public/images/logo.PNG")).unwrap(),
+            png
+        );
+        assert_eq!(std::fs::read(dir.join("public/bom.txt")).unwrap(), bom);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("public/notes.md")).unwrap(),
+            "HELLO"
+        );
+        assert_eq!(
+            std::fs::read(dir.join("public/video.mp4")).unwrap().len(),
+            64
+        );
+
+        let diagnostics: Vec<_> = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.page.clone()))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            [("large-asset", Some(dir.join("static/video.mp4")))]
+        );
+        assert!(app.finish().is_ok());
+
+        // Strict builds fail on oversized assets, as do unknown handlers.
+        let mut app = build("[build]\nstrict = true\n[assets]\nmax_asset_size = 32");
+        assert!(app.finish().is_err());
+
+        let mut app = build("[assets.handlers]\ntxt = \"whisper\"");
+        let diagnostics: Vec<_> = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.message.clone()))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            [(
+                "unknown-asset-handler",
+                String::from("Unknown asset handler `whisper`")
+            )]
+        );
+        assert!(app.finish().is_err());
+    }
+}
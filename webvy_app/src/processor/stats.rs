@@ -0,0 +1,301 @@
+//! A page of statistics about the site's posts, written with only the public APIs a
+//! processor of another crate has: it reads the [`DateIndex`], [`TaxonomyIndex`] and
+//! [`WordCount`] of posts, and renders through [`ExtraPages`].
+
+use std::path::Path;
+
+use bevy_ecs::system::Resource;
+use log::info;
+use serde::Serialize;
+
+use crate::{prelude::*, taxonomy::TAGS};
+
+/// Renders `[stats] template` to `/stats/` when `[stats] enabled` is set, with a `stats`
+/// context of the site's listed posts: how many there are and their words, how many
+/// were posted each month, the most used tags and the longest and shortest posts.
+#[derive(Debug, Default)]
+pub struct StatsProcessor;
+
+impl StatsProcessor {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn reserve_output(
+        mut commands: Commands,
+        config: Res<SiteConfig>,
+        output: Option<Res<StatsOutput>>,
+        mut registry: ResMut<OutputRegistry>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        // An output reserved by an earlier build would otherwise collide with itself.
+        if !config.stats.enabled || output.is_some() {
+            return;
+        }
+
+        let path = config
+            .stats
+            .output
+            .clone()
+            .unwrap_or_else(|| Path::new("stats").join(&config.build.index_file));
+
+        match registry.reserve(path, OutputClaim::new("stats page", None)) {
+            Ok(output) => commands.insert_resource(StatsOutput(output)),
+            Err(collision) => collision.report(&mut diagnostics),
+        }
+    }
+
+    fn add_stats_page(
+        config: Res<SiteConfig>,
+        output: Option<Res<StatsOutput>>,
+        posts: Res<SectionPosts>,
+        dates: Res<DateIndex>,
+        taxonomies: Res<TaxonomyIndex>,
+        q_posts: Query<(
+            Entity,
+            &Indexable,
+            Option<&Permalink>,
+            Option<&WordCount>,
+            Option<&ReadingTime>,
+        )>,
+        mut pages: ResMut<ExtraPages>,
+    ) {
+        let Some(StatsOutput(output)) = output.as_deref() else {
+            return;
+        };
+
+        if !config.stats.enabled {
+            pages.remove(output);
+            return;
+        }
+
+        info!("Gathering statistics about posts");
+        let mut listed: Vec<_> = q_posts
+            .iter()
+            .filter_map(|(post, indexable, permalink, words, time)| {
+                let position = posts.position_of(post)?;
+                let stats = PostStats {
+                    title: indexable.title.clone(),
+                    permalink: permalink.map(|permalink| permalink.as_ref().to_string()),
+                    words: words.map_or(0, |words| words.0),
+                };
+
+                Some((position, stats, time.map_or(0, |time| time.0)))
+            })
+            .collect();
+        // Ties go to the post listed first, so the page doesn't change between builds.
+        listed.sort_by_key(|(position, ..)| *position);
+
+        let words = listed.iter().map(|(_, post, _)| post.words).sum();
+        let reading_time: usize = listed.iter().map(|(.., time)| time).sum();
+
+        let mut tags: Vec<_> = taxonomies
+            .terms(TAGS)
+            .map(|(slug, pages)| TagStats {
+                slug: slug.to_string(),
+                posts: pages.len(),
+            })
+            .collect();
+        tags.sort_by(|a, b| b.posts.cmp(&a.posts).then_with(|| a.slug.cmp(&b.slug)));
+        tags.truncate(config.stats.top_tags);
+
+        let stats = SiteStats {
+            posts: listed.len(),
+            words,
+            average_reading_time: match listed.len() {
+                0 => 0.0,
+                posts => reading_time as f64 / posts as f64,
+            },
+            years: dates
+                .years()
+                .map(|year| {
+                    let months: Vec<_> = dates
+                        .months(year)
+                        .map(|(month, pages)| MonthStats {
+                            month,
+                            posts: pages.len(),
+                        })
+                        .collect();
+
+                    YearStats {
+                        year,
+                        posts: months.iter().map(|month| month.posts).sum(),
+                        months,
+                    }
+                })
+                .collect(),
+            top_tags: tags,
+            longest: listed
+                .iter()
+                .rev()
+                .max_by_key(|(_, post, _)| post.words)
+                .map(|(_, post, _)| post.clone()),
+            shortest: listed
+                .iter()
+                .min_by_key(|(_, post, _)| post.words)
+                .map(|(_, post, _)| post.clone()),
+        };
+
+        let mut context = tera::Context::new();
+        context.insert("stats", &stats);
+
+        pages.insert(
+            output.clone(),
+            ExtraPage {
+                template: config.stats.template.clone(),
+                context,
+            },
+        );
+    }
+}
+
+impl ProcessorPlugin for StatsProcessor {
+    fn register(self, app: &mut ProcessorApp) {
+        app.add_systems(
+            PostProcess,
+            (Self::reserve_output, Self::add_stats_page)
+                .chain()
+                .after(MarkdownSet::Sections)
+                .after(MarkdownSet::Indexes),
+        );
+    }
+}
+
+/// Where the stats page is written.
+#[derive(Debug, Resource)]
+struct StatsOutput(OutputPath);
+
+/// The `stats` templates see.
+#[derive(Debug, Serialize)]
+struct SiteStats {
+    posts: usize,
+    words: usize,
+    /// In minutes, per post.
+    average_reading_time: f64,
+    /// The years with dated posts, oldest first, each with its months from 1 for
+    /// January.
+    years: Vec<YearStats>,
+    /// The most used tags, most posts first.
+    top_tags: Vec<TagStats>,
+    longest: Option<PostStats>,
+    shortest: Option<PostStats>,
+}
+
+#[derive(Debug, Serialize)]
+struct YearStats {
+    year: i32,
+    posts: usize,
+    months: Vec<MonthStats>,
+}
+
+#[derive(Debug, Serialize)]
+struct MonthStats {
+    month: u32,
+    posts: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct TagStats {
+    slug: String,
+    posts: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PostStats {
+    title: Option<String>,
+    permalink: Option<String>,
+    words: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bevy_ecs::system::Command;
+    use toml::Value;
+
+    use crate::{
+        config::SectionConfig,
+        file::{EnumeratedSections, PageType},
+        manifest::Manifest,
+        processor::{FileConfig, InputDir, OutputDir},
+    };
+
+    use super::*;
+
+    #[test]
+    fn stats_are_gathered_from_listed_posts() {
+        let dir = std::env::temp_dir().join("webvy_stats_are_gathered_from_listed_posts");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(
+            dir.join("templates/stats.html"),
+            "{{ stats.posts }}|{{ stats.words }}|{{ stats.average_reading_time }}|\
+             {% for year in stats.years %}{{ year.year }}:{{ year.posts }}\
+             {% for month in year.months %} {{ month.month }}={{ month.posts }}{% endfor %}\
+             {% endfor %}|\
+             {% for tag in stats.top_tags %}{{ tag.slug }}={{ tag.posts }} {% endfor %}|\
+             {{ stats.longest.title }}|{{ stats.shortest.title }}",
+        )
+        .unwrap();
+        std::fs::write(dir.join("templates/post.html"), "").unwrap();
+        std::fs::write(dir.join("templates/section.html"), "").unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        EnumeratedSections::new(PathBuf::from("blog"))
+            .unwrap()
+            .apply(app.world_mut());
+
+        let mut sections = app.world_mut().query::<(Entity, &PageType)>();
+        let section = sections
+            .iter(app.world())
+            .find(|(_, page_type)| **page_type == PageType::Section)
+            .map(|(section, _)| section)
+            .unwrap();
+        app.world_mut()
+            .entity_mut(section)
+            .insert(toml::from_str::<SectionConfig>("feed = true").unwrap());
+
+        app.insert_resource(
+            toml::from_str::<SiteConfig>(
+                "base_url = \"https://example.com\"\n[markdown]\nwords_per_minute = 2\n\
+                 [stats]\nenabled = true\ntop_tags = 1",
+            )
+            .unwrap(),
+        )
+        .init_resource::<Manifest>()
+        .add_page(
+            "blog/first.md",
+            toml::from_str("title = \"First\"\ndate = 2024-01-05\ntags = [\"rust\"]").unwrap(),
+            "Two words",
+        )
+        .add_page(
+            "blog/second.md",
+            toml::from_str("title = \"Second\"\ndate = 2024-03-10\ntags = [\"rust\", \"web\"]")
+                .unwrap(),
+            "Quite a few more words",
+        )
+        .add_page(
+            "blog/third.md",
+            toml::from_str("title = \"Third\"\ndate = 2024-03-20").unwrap(),
+            "Just three words",
+        )
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+        .add_processor(StatsProcessor::new())
+        .run()
+        .unwrap();
+
+        let page = std::fs::read_to_string(dir.join("public/stats/index.html")).unwrap();
+
+        assert_eq!(page, "3|10|2|2024:3 1=1 3=2|rust=2 |Second|First");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
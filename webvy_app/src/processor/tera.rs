@@ -1,229 +1,2964 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
 
 use bevy_ecs::{
+    change_detection::DetectChanges,
     component::Component,
-    entity::{Entity, EntityHashMap},
-    query::{With, Without},
-    system::{Commands, In, IntoSystem, Query, Res, ResMut, Resource},
-};
-use bevy_tasks::Task;
-use log::{error, info, trace};
-use smol::{
-    fs::{DirBuilder, File},
-    io::{AsyncWriteExt, BufWriter},
-    stream::{iter, StreamExt},
+    entity::{Entity, EntityHashMap, EntityHashSet},
+    query::{Changed, Has, Or, With, Without},
+    removal_detection::RemovedComponents,
+    schedule::{IntoSystemConfigs, IntoSystemSetConfigs, SystemSet},
+    system::{CommandQueue, Commands, IntoSystem, Query, Res, ResMut, Resource},
+    world::{Mut, Ref, World},
 };
-use tera::Tera;
+use bevy_tasks::ComputeTaskPool;
+use chrono::SecondsFormat;
+use log::{debug, error, info, trace};
+use tera::{Template, Tera};
 
 use crate::{
-    app::{PostProcess, Process, Write},
+    app::{Load, PostProcess, Process, Write},
+    build_info::BuildInfo,
+    cancel::CancellationToken,
+    config::{BuildMode, FileConfig, SiteConfig, TemplatesConfig, TemplatesDir},
+    context::GlobalContext,
     deferred::DeferredTask,
-    file::{FileName, FilePath, HtmlBody, PageType, SectionName},
+    file::{
+        classify_pages, CanonicalUrl, Classified, FeedUrl, FileName, FilePath, HtmlBody, InSection,
+        Indexable, OgImage, PageExtra, PageType, Permalink, ReadingTime, SectionInfo, SectionName,
+        SourceFile, Summary, TableOfContents, WordCount,
+    },
+    files::{read_matching_from_directory, WalkOptions},
+    front_matter::{Authors, Description, Draft, Headless, Raw, Tags, TemplateOverride, Unlisted},
+    include::{IncludedPage, PageSnapshot},
+    io::write_to_disk,
+    logging::{LOAD, RENDER},
+    menu::Menus,
+    output::{OutputClaim, OutputPath, OutputRegistry},
+    report::{causes_of, BuildReport, Diagnostics, Severity},
+    svg::SvgCache,
+    taxonomy::{TermMetadata, TAGS},
     traits::ProcessorPlugin,
+    validate,
+    value::{table_to_json, toml_to_json},
 };
 
-use super::configuration::{FileConfig, OutputDir};
+use super::{bundle::AssetUrls, data::SiteData};
 
 #[derive(Debug, Resource)]
 pub struct TeraProcessor {
+    /// Set with [`TeraProcessor::with_directory`], over the configured directory.
+    dir: Option<PathBuf>,
+    /// The templates, escaping variables whatever the template's extension.
     templates: Tera,
+    /// The same templates without escaping, for outputs that aren't markup.
+    unescaped: Tera,
+    /// Templates that couldn't be loaded, with the reason why.
+    broken: HashMap<String, String>,
+    /// A hash of the templates last loaded, so reloading the same ones is a no-op.
+    fingerprint: Option<u64>,
+    /// A hash of each template last loaded, so only those that changed are parsed
+    /// again.
+    hashes: HashMap<String, u64>,
+    /// The `[templates]` settings the templates were last loaded with.
+    settings: TemplatesConfig,
+    /// The pages templates can include, shared with their `include_page` and
+    /// `summary_of` functions.
+    snapshot: PageSnapshot,
+    /// The URLs of bundles, shared with the `asset_url` function.
+    assets: AssetUrls,
+    /// The SVGs of the static directory, shared with the `inline_svg` function.
+    svgs: SvgCache,
 }
 
 impl TeraProcessor {
     pub fn new() -> Self {
-        let templates = Tera::new("templates/**/*").unwrap();
+        // An empty suffix matches every template name, so whether output is escaped
+        // depends on which of the two it's rendered with.
+        let mut templates = Tera::default();
+        templates.autoescape_on(vec![""]);
+
+        let mut unescaped = Tera::default();
+        unescaped.autoescape_on(Vec::new());
+
+        let snapshot = PageSnapshot::default();
+        let assets = AssetUrls::default();
+        let svgs = SvgCache::default();
+
+        for tera in [&mut templates, &mut unescaped] {
+            tera.register_function(
+                "include_page",
+                IncludeFunction {
+                    snapshot: snapshot.clone(),
+                    summary: false,
+                },
+            );
+            tera.register_function(
+                "summary_of",
+                IncludeFunction {
+                    snapshot: snapshot.clone(),
+                    summary: true,
+                },
+            );
+            tera.register_function(
+                "asset_url",
+                AssetUrlFunction {
+                    assets: assets.clone(),
+                },
+            );
+            tera.register_function("inline_svg", InlineSvgFunction { svgs: svgs.clone() });
+        }
+
+        Self {
+            dir: None,
+            templates,
+            unescaped,
+            broken: HashMap::new(),
+            fingerprint: None,
+            hashes: HashMap::new(),
+            settings: TemplatesConfig::default(),
+            snapshot,
+            assets,
+            svgs,
+        }
+    }
+
+    /// Loads templates from `dir` instead of the configured templates directory.
+    pub fn with_directory(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    fn load_templates(
+        config: Res<SiteConfig>,
+        tera: Res<Self>,
+        q_config: Query<&TemplatesDir, With<FileConfig>>,
+        deferred: Res<DeferredTask>,
+    ) {
+        let dir = tera
+            .dir
+            .clone()
+            .or_else(|| {
+                q_config
+                    .get_single()
+                    .ok()
+                    .map(|dir| dir.path().to_path_buf())
+            })
+            .unwrap_or_else(|| PathBuf::from(TemplatesDir::DEFAULT));
+        let settings = config.templates.clone();
+        let extensions = settings.extensions.clone();
+        let walk = WalkOptions::from_config(&config);
+
+        deferred
+            .scoped_task(move |scope| async move {
+                info!(target: LOAD, "Reading templates from disk");
+                let mut files = Vec::new();
+
+                let filter = |path: &Path| is_template(path, &extensions);
+
+                for res in read_matching_from_directory(dir.as_path(), walk, filter).await {
+                    match res {
+                        Ok((path, content)) => {
+                            let name = path
+                                .strip_prefix(&dir)
+                                .unwrap_or(&path)
+                                .components()
+                                .filter_map(|component| component.as_os_str().to_str())
+                                .collect::<Vec<_>>()
+                                .join("/");
+
+                            files.push((name, path, content));
+                        }
+                        Err(err) => error!("Error reading template: {}", err),
+                    }
+                }
+
+                files.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+
+                let mut hasher = DefaultHasher::new();
+                files
+                    .iter()
+                    .for_each(|(name, _, content)| (name, content).hash(&mut hasher));
+                (
+                    settings.trim_blocks,
+                    &settings.autoescape,
+                    &settings.no_autoescape,
+                )
+                    .hash(&mut hasher);
+                let fingerprint = hasher.finish();
+
+                let mut queue = CommandQueue::default();
+
+                queue.push(move |world: &mut World| {
+                    // Changing the templates re-renders every page, so only do so when
+                    // they did change.
+                    if world.resource::<Self>().fingerprint == Some(fingerprint) {
+                        return;
+                    }
+
+                    world.resource_scope(|world, mut tera: Mut<Self>| {
+                        let start = Instant::now();
+                        let total = files.len();
+
+                        tera.fingerprint = Some(fingerprint);
+                        tera.settings = settings;
+                        let parsed =
+                            tera.add_templates(files, &mut world.resource_mut::<Diagnostics>());
+
+                        info!(
+                            target: LOAD,
+                            "Reloaded templates in {:.2?}, parsing {} of {}",
+                            start.elapsed(),
+                            parsed,
+                            total
+                        );
+                    });
+
+                    Self::invalidate_associations(world);
+                });
+
+                scope.send(queue);
+            })
+            .detach();
+    }
+
+    /// Forgets the template of every page type and the page type of every page, so
+    /// both are worked out again against the templates just loaded. Otherwise a page
+    /// type keeps using a renamed template, or the shared one after its section gets
+    /// its own.
+    fn invalidate_associations(world: &mut World) {
+        let page_types: Vec<_> = world
+            .query_filtered::<Entity, With<TemplateName>>()
+            .iter(world)
+            .collect();
+
+        for page_type in page_types {
+            world
+                .entity_mut(page_type)
+                .remove::<(TemplateName, MissingTemplate)>();
+        }
+
+        let pages: Vec<_> = world
+            .query_filtered::<Entity, With<AssociatedPageType>>()
+            .iter(world)
+            .collect();
+
+        for page in pages {
+            world.entity_mut(page).remove::<AssociatedPageType>();
+        }
+    }
+
+    /// Parses each template on its own, so one that fails only takes the templates
+    /// extending it or importing its macros down with it. Replaces any templates loaded
+    /// before, dropping those no longer on disk.
+    /// Replaces the templates with `files`, returning how many had to be parsed. Those
+    /// unchanged since the last load are kept as they were parsed, unless a template
+    /// that changed is extended or has its macros imported by another, in which case
+    /// every template is parsed again.
+    fn add_templates(
+        &mut self,
+        files: Vec<(String, PathBuf, String)>,
+        diagnostics: &mut Diagnostics,
+    ) -> usize {
+        let mut paths = HashMap::new();
+        let hashes: HashMap<_, _> = files
+            .iter()
+            .map(|(name, _, content)| {
+                let mut hasher = DefaultHasher::new();
+                (content, self.settings.trim_blocks).hash(&mut hasher);
+
+                (name.clone(), hasher.finish())
+            })
+            .collect();
+        let changed: HashSet<_> = hashes
+            .keys()
+            .chain(self.hashes.keys())
+            .filter(|name| hashes.get(*name) != self.hashes.get(*name))
+            .cloned()
+            .collect();
+        let depended_on = self.templates.templates.values().any(|template| {
+            !changed.contains(&template.name)
+                && template
+                    .parent
+                    .iter()
+                    .chain(template.imported_macro_files.iter().map(|(file, _)| file))
+                    .any(|dependency| changed.contains(dependency))
+        });
+
+        let mut previous = std::mem::take(&mut self.templates.templates);
+        let mut parsed = 0;
+
+        if depended_on {
+            debug!(target: LOAD, "Changed templates are used by others, parsing them all");
+            previous.clear();
+        }
+
+        self.broken.clear();
+        self.hashes = hashes;
+
+        for (name, path, mut content) in files {
+            if let Some(template) = previous.remove(&name).filter(|_| !changed.contains(&name)) {
+                self.templates.templates.insert(name.clone(), template);
+                paths.insert(name, path);
+                continue;
+            }
+
+            trace!("Parsing template {}", name);
+            parsed += 1;
+
+            if self.settings.trim_blocks {
+                content = trim_blocks(&content);
+            }
+
+            match Template::new(&name, Some(path.display().to_string()), &content) {
+                Ok(template) => {
+                    self.templates.templates.insert(name.clone(), template);
+                }
+                Err(e) => {
+                    let reason = format!("it failed to parse, {}", e);
+
+                    diagnostics.error(
+                        path.clone(),
+                        "invalid-template",
+                        format!("Template {} can't be used: {}", name, reason),
+                    );
+                    self.broken.insert(name.clone(), reason);
+                }
+            }
+
+            paths.insert(name, path);
+        }
 
-        Self { templates }
+        // Dependents are removed until none are left relying on a missing template.
+        loop {
+            let unresolved: Vec<_> = self
+                .templates
+                .templates
+                .values()
+                .filter_map(|template| {
+                    template
+                        .parent
+                        .iter()
+                        .chain(template.imported_macro_files.iter().map(|(file, _)| file))
+                        .find(|dependency| !self.templates.templates.contains_key(*dependency))
+                        .map(|dependency| (template.name.clone(), dependency.clone()))
+                })
+                .collect();
+
+            if unresolved.is_empty() {
+                break;
+            }
+
+            for (name, dependency) in unresolved {
+                let reason = match self.broken.get(&dependency) {
+                    Some(_) => format!("it depends on {}, which can't be used", dependency),
+                    None => format!("it depends on {}, which doesn't exist", dependency),
+                };
+
+                diagnostics.error(
+                    paths.get(&name).cloned(),
+                    "invalid-template",
+                    format!("Template {} can't be used: {}", name, reason),
+                );
+                self.templates.templates.remove(&name);
+                self.broken.insert(name, reason);
+            }
+        }
+
+        if let Err(e) = self.templates.build_inheritance_chains() {
+            diagnostics.error(None, "invalid-template", e.to_string());
+        }
+
+        self.unescaped.templates = self.templates.templates.clone();
+
+        if let Err(e) = self.unescaped.build_inheritance_chains() {
+            error!("Unable to build the unescaped templates: {}", e);
+        }
+
+        parsed
+    }
+
+    /// Renders `template` for `output`. Variables are escaped for the outputs set under
+    /// `[templates]`, by default HTML and XML, and left as they are for anything else.
+    fn render(
+        &self,
+        template: &str,
+        context: &tera::Context,
+        output: &OutputPath,
+    ) -> tera::Result<String> {
+        if self.settings.escapes(template, output.as_path()) {
+            self.templates.render(template, context)
+        } else {
+            self.unescaped.render(template, context)
+        }
     }
 
     fn index_templates(
         mut commands: Commands,
+        tera: Res<Self>,
         q_page_types: Query<(Entity, &PageType, Option<&SectionName>), Without<TemplateName>>,
+        mut report: ResMut<BuildReport>,
     ) {
         info!("Indexing templates");
+        let registered: HashSet<&str> = tera.templates.get_template_names().collect();
+
         for (page, page_type, section_name) in q_page_types.iter() {
-            match page_type {
-                PageType::Index | PageType::Page => {
-                    let path: PathBuf = [format!("{}.html", page_type)].into_iter().collect();
-                    trace!("Indexed template as {}", path.display());
-                    commands.entity(page).insert(TemplateName(path));
-                }
+            let own_template = format!("{}.html", page_type);
+
+            // Sections share the root template unless they have their own.
+            let candidates: Vec<PathBuf> = match page_type {
+                PageType::Index | PageType::Page => vec![own_template.into()],
                 PageType::Section | PageType::Post => {
                     let parent = section_name.unwrap();
-                    let path: PathBuf =
-                        [parent.as_ref().to_string(), format!("{}.html", page_type)]
-                            .into_iter()
-                            .collect();
-                    trace!("Indexed template as {}", path.display());
-                    commands.entity(page).insert(TemplateName(path));
+
+                    vec![
+                        Path::new(parent.as_ref()).join(&own_template),
+                        own_template.into(),
+                    ]
                 }
+            };
+
+            // A broken template is still picked, so it's reported rather than skipped.
+            let path = candidates
+                .iter()
+                .find(|path| {
+                    path.to_str().is_some_and(|path| {
+                        registered.contains(path) || tera.broken.contains_key(path)
+                    })
+                })
+                .unwrap_or(&candidates[0])
+                .clone();
+
+            let key = match section_name {
+                Some(section) => format!("{}/{}", section.as_ref(), page_type),
+                None => page_type.to_string(),
+            };
+
+            debug!("{} pages are rendered with {}", key, path.display());
+            report
+                .templates
+                .insert(key, path.to_string_lossy().into_owned());
+            commands.entity(page).insert(TemplateName(path));
+        }
+    }
+
+    fn check_templates(
+        mut commands: Commands,
+        tera: Res<Self>,
+        q_page_types: Query<
+            (Entity, &PageType, Option<&SectionName>, &TemplateName),
+            Without<MissingTemplate>,
+        >,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        let registered: HashSet<&str> = tera.templates.get_template_names().collect();
+
+        for name in tera.settings.no_autoescape.iter() {
+            if !registered.contains(name.as_str()) && !tera.broken.contains_key(name) {
+                diagnostics.warning(
+                    None,
+                    "template-not-found",
+                    format!(
+                        "Template {} is excluded from autoescaping under [templates], but doesn't exist",
+                        name
+                    ),
+                );
+            }
+        }
+
+        for (page_type_entity, page_type, section, template) in q_page_types.iter() {
+            let name = template.0.to_str().unwrap_or_default();
+
+            // Broken templates are reported for each page relying on them instead.
+            if registered.contains(name) || tera.broken.contains_key(name) {
+                continue;
             }
+
+            let pages = match section {
+                Some(section) => format!("{} pages in the {} section", page_type, section.as_ref()),
+                None => format!("{} pages", page_type),
+            };
+
+            diagnostics.warning(
+                None,
+                "template-not-found",
+                format!(
+                    "Template {} doesn't exist, so {} can't be rendered. Add it, or a shared {}.html at the root of the templates directory",
+                    name, pages, page_type
+                ),
+            );
+
+            commands.entity(page_type_entity).insert(MissingTemplate);
         }
     }
 
     fn associate_pages_to_templates(
         mut commands: Commands,
-        q_pages: Query<(Entity, &FilePath), Without<AssociatedPageType>>,
+        q_pages: Query<
+            (
+                Entity,
+                &FilePath,
+                &Classified,
+                &SourceFile,
+                Option<&InSection>,
+            ),
+            (Without<AssociatedPageType>, Without<Raw>, Without<Headless>),
+        >,
         q_page_types: Query<(Entity, &PageType, Option<&SectionName>)>,
+        q_sections: Query<&SectionName>,
+        mut diagnostics: ResMut<Diagnostics>,
     ) {
         info!("Associating pages to templates");
-        q_pages.iter().for_each(|(page, path)| {
-            let dir = path.as_ref().parent().unwrap();
-            let dir = dir.to_str().unwrap();
-            let is_root = dir.is_empty();
-            let is_listing = path.as_ref().ends_with("_index.md");
-
-            let page_type = match (is_root, is_listing) {
-                (true, true) => PageType::Index,
-                (true, false) => PageType::Page,
-                (false, true) => PageType::Section,
-                (false, false) => PageType::Post,
-            };
+        let mut page_types = HashMap::new();
 
-            q_page_types
-                .iter()
-                .find_map(|(page, kind, section)| match page_type {
-                    PageType::Index | PageType::Page if page_type.eq(kind) => {
-                        Some(AssociatedPageType(page))
-                    }
-                    PageType::Section | PageType::Post
-                        if page_type.eq(kind)
-                            && section.is_some_and(|section| dir.contains(section.as_ref())) =>
-                    {
-                        Some(AssociatedPageType(page))
-                    }
-                    _ => None,
-                })
-                .map_or_else(
+        for (page_type, kind, section) in q_page_types.iter() {
+            page_types
+                .entry((*kind, section.map(AsRef::as_ref)))
+                .or_insert(page_type);
+        }
+
+        q_pages
+            .iter()
+            .for_each(|(page, path, class, source, in_section)| {
+                let path = path.as_ref();
+
+                if let Some(segment) = non_utf8_segment(path) {
+                    diagnostics.warning(
+                        source.as_ref().to_path_buf(),
+                        "non-utf8-path",
+                        format!(
+                            "`{}` in the page's path isn't valid UTF-8, so its URL won't match it",
+                            segment
+                        ),
+                    );
+                }
+
+                // Pages in a directory matching no section were already reported.
+                if class.depth > 0 && in_section.is_none() {
+                    return;
+                }
+
+                let page_type = class.kind;
+
+                let associated_type = match (page_type, in_section) {
+                    (PageType::Section, Some(InSection(section))) => Some(*section),
+                    (PageType::Post, Some(InSection(section))) => q_sections
+                        .get(*section)
+                        .ok()
+                        .and_then(|name| page_types.get(&(PageType::Post, Some(name.as_ref()))))
+                        .copied(),
+                    _ => page_types.get(&(page_type, None)).copied(),
+                };
+
+                associated_type.map_or_else(
                     || {
-                        error!("{} doesn't exist. Maybe it hasn't been indexed?", page_type);
+                        diagnostics.warning(
+                            source.as_ref().to_path_buf(),
+                            "missing-template",
+                            format!("{} doesn't exist. Maybe it hasn't been indexed?", page_type),
+                        );
                     },
                     |associated_type| {
-                        trace!("{} indexed as {}", path.as_ref().display(), page_type);
-                        commands.entity(page).insert(associated_type);
+                        trace!("{} indexed as {}", path.display(), page_type);
+                        commands
+                            .entity(page)
+                            .insert(AssociatedPageType(associated_type));
                     },
                 );
-        });
+            });
+    }
+
+    fn check_page_templates(
+        tera: Res<Self>,
+        q_pages: Query<
+            (
+                &SourceFile,
+                Option<&AssociatedPageType>,
+                Option<&TemplateOverride>,
+            ),
+            Without<Raw>,
+        >,
+        q_page_types: Query<&TemplateName>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        for (source, page_type, template_override) in q_pages.iter() {
+            let template = match template_override {
+                Some(template_override) => template_override.0.as_str(),
+                None => match page_type.and_then(|page_type| q_page_types.get(page_type.0).ok()) {
+                    Some(template) => template.0.to_str().unwrap_or_default(),
+                    None => continue,
+                },
+            };
+
+            if let Some(reason) = tera.broken.get(template) {
+                diagnostics.error(
+                    source.as_ref().to_path_buf(),
+                    "broken-template",
+                    format!("Unable to render {}: {}", template, reason),
+                );
+            }
+        }
+    }
+
+    fn reserve_page_outputs(
+        mut commands: Commands,
+        q_pages: Query<
+            (Entity, &FileName, &FilePath, &SourceFile, Has<Draft>),
+            (
+                Or<(With<AssociatedPageType>, With<Raw>)>,
+                Without<PageOutput>,
+                Without<Headless>,
+            ),
+        >,
+        config: Res<SiteConfig>,
+        mut registry: ResMut<OutputRegistry>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        for (page, file_name, path, source, _) in q_pages
+            .iter()
+            .filter(|(.., draft)| !draft || config.build.drafts)
+        {
+            let output = path.as_ref().with_file_name(&file_name.0);
+            let mut claim = OutputClaim::new("page", source.as_ref().to_path_buf()).for_page(page);
+
+            // Outputs are named by the slug of the file name, which is what collides
+            // rather than the name itself.
+            if let Some(slug) = output
+                .file_stem()
+                .filter(|slug| *slug != "index" && Some(*slug) != path.as_ref().file_stem())
+            {
+                claim = claim.via(format!("slug `{}`", slug.to_string_lossy()));
+            }
+
+            match registry.reserve(output, claim) {
+                Ok(output) => {
+                    commands.entity(page).insert(PageOutput(output));
+                }
+                Err(collision) => collision.report(&mut diagnostics),
+            }
+        }
+    }
+
+    fn reserve_extra_outputs(
+        mut commands: Commands,
+        config: Res<SiteConfig>,
+        tera: Res<Self>,
+        outputs: Option<Res<ExtraOutputs>>,
+        mut registry: ResMut<OutputRegistry>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        // Reserved by an earlier build, which would otherwise collide with itself.
+        if outputs.is_some() {
+            return;
+        }
+
+        let mut outputs = Vec::new();
+
+        for extra in config.extra_templates.iter() {
+            if !tera
+                .templates
+                .get_template_names()
+                .any(|name| name == extra.template)
+            {
+                diagnostics.error(
+                    None,
+                    "template-not-found",
+                    format!(
+                        "{} is listed in extra_templates but doesn't exist",
+                        extra.template
+                    ),
+                );
+                continue;
+            }
+
+            match registry.reserve(&extra.output, OutputClaim::new("extra template", None)) {
+                Ok(output) => {
+                    let mut context = tera::Context::new();
+
+                    for (key, value) in extra.context.iter() {
+                        context.insert(key, &toml_to_json(value));
+                    }
+
+                    outputs.push((extra.template.clone(), output, context));
+                }
+                Err(collision) => collision.report(&mut diagnostics),
+            }
+        }
+
+        commands.insert_resource(ExtraOutputs(outputs));
+    }
+
+    /// Takes a snapshot of every page for pages and templates to include, when any of
+    /// them changed.
+    fn snapshot_pages(
+        q_pages: Query<(&FilePath, &HtmlBody, Option<&Summary>)>,
+        q_changed: Query<(), Or<(Changed<HtmlBody>, Changed<Summary>)>>,
+        mut removed: RemovedComponents<HtmlBody>,
+        mut snapshot: ResMut<PageSnapshot>,
+    ) {
+        if q_changed.is_empty() && removed.read().next().is_none() {
+            return;
+        }
+
+        snapshot.replace(
+            q_pages
+                .iter()
+                .map(|(path, content, summary)| {
+                    (
+                        path.as_ref().to_path_buf(),
+                        IncludedPage {
+                            content: content.shared(),
+                            summary: summary.map(|summary| summary.0.clone()),
+                        },
+                    )
+                })
+                .collect(),
+        );
     }
 
+    /// Assembles the context of every stale page from its components. Whatever is
+    /// derived from a page, such as its summary or reading time, is kept in a component
+    /// rather than added to contexts elsewhere, so feeds and the JSON output read the
+    /// same values as templates do.
+    #[allow(clippy::too_many_arguments)]
     fn populate_context(
-        mut q_pages: Query<(Entity, &HtmlBody)>,
+        q_pages: Query<(
+            Entity,
+            &FilePath,
+            &SourceFile,
+            &HtmlBody,
+            Option<&FeedUrl>,
+            Option<&Permalink>,
+            Option<&CanonicalUrl>,
+            Option<&Authors>,
+            Option<&Summary>,
+            (
+                Option<&Description>,
+                Has<Unlisted>,
+                Option<&Indexable>,
+                Option<&WordCount>,
+                Option<&ReadingTime>,
+            ),
+            Option<&OgImage>,
+            Option<&TableOfContents>,
+            Option<&PageExtra>,
+            Option<&InSection>,
+            Option<&Tags>,
+        )>,
+        q_sections: Query<Ref<SectionInfo>>,
+        q_changed: Query<
+            Entity,
+            Or<(
+                Changed<HtmlBody>,
+                Changed<PageOutput>,
+                Changed<FeedUrl>,
+                Changed<Permalink>,
+                Changed<CanonicalUrl>,
+                Changed<Authors>,
+                Changed<Summary>,
+                Or<(
+                    Changed<Description>,
+                    Changed<Unlisted>,
+                    Changed<Indexable>,
+                    Changed<WordCount>,
+                )>,
+                Changed<OgImage>,
+                Changed<TableOfContents>,
+                Changed<PageExtra>,
+                Changed<InSection>,
+                Changed<Tags>,
+            )>,
+        >,
+        config: Res<SiteConfig>,
+        mode: Res<BuildMode>,
+        build: Res<BuildInfo>,
+        data: Option<Res<SiteData>>,
+        globals: Res<GlobalContext>,
+        menus: Option<Res<Menus>>,
+        snapshot: Res<PageSnapshot>,
+        assets: Res<AssetUrls>,
+        svgs: Res<SvgCache>,
         mut contexts: ResMut<PageContexts>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        // Global values are often set again to what they were, so they're compared
+        // rather than trusting change detection.
+        let site = site_context(&config, &mode, &build, data.as_deref(), &globals);
+        let site_changed = *contexts.site != site;
+
+        if site_changed {
+            contexts.site = Arc::new(site);
+        }
+
+        // Anything shared by every page changing means every page is stale, as does
+        // any page changing once templates include pages.
+        let everything = config.is_changed()
+            || site_changed
+            || assets.is_changed()
+            || svgs.is_changed()
+            || menus.as_ref().is_some_and(|menus| menus.is_changed())
+            || q_sections.iter().any(|section| section.is_changed())
+            || (snapshot.is_changed() && snapshot.is_used());
+        let mut stale: EntityHashSet = if everything {
+            q_pages.iter().map(|(page, ..)| page).collect()
+        } else {
+            q_changed.iter().collect()
+        };
+
+        if snapshot.is_changed() {
+            stale.extend(contexts.includes.iter().copied());
+        }
+
+        info!(target: RENDER, "Populating the contexts of {} pages", stale.len());
+        let (terms, _) = TermMetadata::load(&config, data.as_ref().map(|data| &data.0), TAGS);
+        let no_extra = toml::Table::new();
+        let mut sections: Vec<_> = q_sections.iter().map(Ref::into_inner).collect();
+        sections.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for (
+            page,
+            path,
+            source,
+            content,
+            feed_url,
+            permalink,
+            canonical,
+            authors,
+            summary,
+            (description, unlisted, indexable, word_count, reading_time),
+            og_image,
+            toc,
+            extra,
+            section,
+            tags,
+        ) in q_pages.iter_many(&stale)
+        {
+            let content = match snapshot.expand(path.as_ref(), content.as_ref()) {
+                Ok(expanded) => {
+                    match expanded.is_some() {
+                        true => contexts.includes.insert(page),
+                        false => contexts.includes.remove(&page),
+                    };
+
+                    expanded.unwrap_or_else(|| content.as_ref().to_string())
+                }
+                Err(e) => {
+                    diagnostics.error(
+                        source.as_ref().to_path_buf(),
+                        "include-failed",
+                        e.to_string(),
+                    );
+                    contexts.includes.insert(page);
+
+                    content.as_ref().to_string()
+                }
+            };
+
+            let context = contexts.contexts.entry(page).or_default();
+
+            context.insert("content", &content);
+
+            let permalink = permalink.map(AsRef::as_ref);
+            let section = section.and_then(|section| q_sections.get(section.0).ok());
+
+            context.insert(
+                "page",
+                &serde_json::json!({
+                    "title": indexable.and_then(|indexable| indexable.title.as_deref()),
+                    "date": indexable
+                        .and_then(|indexable| indexable.date)
+                        .map(|date| date.to_rfc3339_opts(SecondsFormat::Secs, true)),
+                    "permalink": permalink,
+                    "canonical": canonical.map(AsRef::as_ref).or(permalink),
+                    "authors": config.resolve_authors(authors),
+                    "tags": terms.resolve(tags.map_or(&[], |tags| tags.0.as_slice())),
+                    "summary": summary.map(AsRef::as_ref),
+                    "description": description.map(|description| description.0.as_str()),
+                    "og_image": og_image.map(AsRef::as_ref),
+                    "word_count": word_count.map(|count| count.0),
+                    "reading_time": reading_time.map(|time| time.0),
+                    "toc": toc.map_or(&[][..], |toc| toc.0.as_slice()),
+                    "extra": table_to_json(extra.map_or(&no_extra, |extra| &extra.0)),
+                    "section": section.as_deref(),
+                    "noindex": unlisted,
+                }),
+            );
+
+            if let Some(feed_url) = feed_url {
+                context.insert("feed_url", feed_url.as_ref());
+            }
+
+            // Pages see the global menus with their own item marked as active.
+            if let Some(menus) = &menus {
+                context.insert("menus", &menus.context(permalink));
+            }
+
+            // The site's index lists every section.
+            if path.as_ref() == Path::new("_index.md") {
+                context.insert("sections", &sections);
+            }
+        }
+
+        contexts.stale.extend(stale);
+    }
+
+    /// Warns about term metadata that's malformed, or describes a term no page uses.
+    fn check_term_metadata(
+        q_tags: Query<&Tags>,
+        config: Res<SiteConfig>,
+        data: Option<Res<SiteData>>,
+        mut diagnostics: ResMut<Diagnostics>,
     ) {
-        info!("Populating page contexts");
-        for (page, content) in q_pages.iter_mut() {
-            let context = contexts.0.entry(page).or_default();
+        let (terms, invalid) = TermMetadata::load(&config, data.as_ref().map(|data| &data.0), TAGS);
+
+        for slug in invalid {
+            diagnostics.warning(
+                None,
+                "invalid-term",
+                format!(
+                    "{} in data/{} should be a table of name, description and image",
+                    slug, TAGS
+                ),
+            );
+        }
 
-            context.insert("content", content.as_ref());
+        for slug in terms.unused(q_tags.iter().flat_map(|tags| tags.0.iter())) {
+            diagnostics.warning(
+                None,
+                "unknown-term",
+                format!(
+                    "Term {} of {} has metadata, but no page uses it",
+                    slug, TAGS
+                ),
+            );
         }
     }
 
     fn process_pages(
-        q_config: Query<&OutputDir, With<FileConfig>>,
-        q_pages: Query<(Entity, &AssociatedPageType, &FileName, &FilePath)>,
-        q_page_types: Query<&TemplateName>,
+        q_pages: Query<(
+            Entity,
+            &AssociatedPageType,
+            &PageOutput,
+            &SourceFile,
+            Option<&TemplateOverride>,
+        )>,
+        q_page_types: Query<(&TemplateName, Has<MissingTemplate>)>,
         tera: Res<Self>,
-        contexts: Res<PageContexts>,
-    ) -> Vec<(PathBuf, String)> {
-        let dir = q_config.single().path();
+        mut contexts: ResMut<PageContexts>,
+        cancel: Res<CancellationToken>,
+        mut rendered: ResMut<RenderedPages>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        info!(target: RENDER, "Rendering content to templates");
 
-        info!("Rendering content to templates");
+        // Every page is rendered again when the templates change, otherwise only the
+        // ones whose context changed since they were last rendered.
+        let everything = tera.is_changed();
+        let stale = std::mem::take(&mut contexts.stale);
+        let site = &contexts.site;
+        let contexts = &contexts.contexts;
 
-        q_pages
+        let pages = q_pages
             .iter()
-            .map(|(page, template_name, file_name, path)| {
-                let output_path = dir.join(path.as_ref().with_file_name(&file_name.0));
+            .take_while(|_| !cancel.is_cancelled())
+            .filter(|(page, ..)| everything || stale.contains(page))
+            .filter_map(
+                |(page, page_type, PageOutput(output_path), source, template_override)| {
+                    let template_name = match template_override {
+                        Some(template_override) => template_override.0.as_str(),
+                        None => match q_page_types.get(page_type.0).unwrap() {
+                            // Already reported once for the whole page type
+                            (_, true) => return None,
+                            (template_name, false) => template_name.0.to_str().unwrap(),
+                        },
+                    };
 
-                let template_name = q_page_types.get(template_name.0).unwrap();
+                    let context = layered(site, contexts.get(&page).unwrap());
 
-                let context = contexts.0.get(&page).unwrap();
+                    match tera.render(template_name, &context, output_path) {
+                        Ok(content) => Some((output_path.clone(), content)),
+                        Err(e) => {
+                            diagnostics.error_with_causes(
+                                source.as_ref().to_path_buf(),
+                                "render-failed",
+                                format!("Unable to render {}: {}", template_name, e),
+                                causes_of(&e),
+                            );
 
-                let content = tera
-                    .templates
-                    .render(template_name.0.to_str().unwrap(), context)
-                    .unwrap();
+                            None
+                        }
+                    }
+                },
+            );
 
-                (output_path, content)
-            })
-            .collect()
+        rendered.0.extend(pages);
     }
 
-    async fn write_file_to_disk(file: &Path, content: &[u8]) -> std::io::Result<()> {
-        let mut file = BufWriter::new(File::create(file).await?);
+    fn render_extra_templates(
+        outputs: Option<Res<ExtraOutputs>>,
+        extra_pages: Res<ExtraPages>,
+        contexts: Res<PageContexts>,
+        tera: Res<Self>,
+        mut rendered: ResMut<RenderedPages>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        let configured = outputs
+            .iter()
+            .flat_map(|outputs| outputs.0.iter())
+            .map(|(template, output, context)| (template.as_str(), output, context));
+        let added = extra_pages
+            .0
+            .iter()
+            .map(|(output, page)| (page.template.as_str(), output, &page.context));
+
+        for (template, output, context) in configured.chain(added) {
+            match tera.render(template, &layered(&contexts.site, context), output) {
+                Ok(content) => rendered.0.push((output.clone(), content)),
+                Err(e) => diagnostics.error_with_causes(
+                    None,
+                    "render-failed",
+                    format!("Unable to render {}: {}", template, e),
+                    causes_of(&e),
+                ),
+            }
+        }
+    }
 
-        file.write_all(content).await?;
+    fn render_raw_pages(
+        q_pages: Query<
+            (&HtmlBody, &PageOutput, &FilePath),
+            (With<Raw>, Or<(Changed<HtmlBody>, Changed<PageOutput>)>),
+        >,
+        mut rendered: ResMut<RenderedPages>,
+    ) {
+        let pages = q_pages.iter().map(|(content, PageOutput(output), path)| {
+            trace!("Passing through raw page {}", path.as_ref().display());
 
-        file.flush().await?;
+            (output.clone(), content.as_ref().to_string())
+        });
 
-        Ok(())
+        rendered.0.extend(pages);
     }
 
-    fn write_to_disk(In(pages): In<Vec<(PathBuf, String)>>, deferred: Res<DeferredTask>) {
-        deferred
-            .scoped_task(|scope| async move {
-                info!("Writing rendered content to disk");
-                let stream: Vec<Task<_>> = iter(pages.into_iter())
-                    .then(|(output_path, content)| async move {
-                        if let Some(directory) = output_path.parent().filter(|path| !path.exists())
-                        {
-                            trace!("Creating directory: {}", directory.display());
-
-                            if let Err(e) =
-                                DirBuilder::new().recursive(true).create(directory).await
-                            {
-                                error!("Error creating directory {}: {}", directory.display(), e);
-                            }
-                        }
+    fn validate_pages(
+        config: Res<SiteConfig>,
+        rendered: Res<RenderedPages>,
+        registry: Res<OutputRegistry>,
+        mut diagnostics: ResMut<Diagnostics>,
+    ) {
+        let a11y = &config.check.a11y;
+        let checks = validate::Checks {
+            html: config.build.validate_html,
+            a11y: a11y.enabled.then_some(a11y),
+        };
 
-                        (output_path, content)
-                    })
-                    .map(|(output_path, content)| {
-                        trace!("Spawning write task for {}", output_path.display());
+        if !checks.html && checks.a11y.is_none() {
+            return;
+        }
 
-                        scope.spawn(async move {
-                            trace!("Writing {}", output_path.display());
+        if !validate::is_available() {
+            diagnostics.warning(
+                None,
+                "validate-unavailable",
+                "webvy was built without HTML validation support, so pages can't be validated",
+            );
+            return;
+        }
 
-                            Self::write_file_to_disk(output_path.as_path(), content.as_bytes())
-                                .await
-                        })
-                    })
-                    .collect()
-                    .await;
+        let results = ComputeTaskPool::get().scope(|scope| {
+            rendered
+                .0
+                .iter()
+                .filter(|(output, _)| {
+                    output
+                        .as_path()
+                        .extension()
+                        .is_some_and(|extension| extension == "html")
+                })
+                .for_each(|(output, html)| {
+                    scope.spawn(async move { (output.clone(), validate::check_html(html, checks)) })
+                });
+        });
 
-                for handle in stream.into_iter() {
-                    if let Err(e) = handle.await {
-                        error!("Error writing to disk: {}", e);
-                    };
+        for (output, findings) in results {
+            let source = registry
+                .claim(output)
+                .and_then(|claim| claim.source.clone());
+
+            for finding in findings {
+                let message = finding.to_string();
+
+                match finding.severity {
+                    Severity::Warning => diagnostics.warning(source.clone(), finding.code, message),
+                    Severity::Error => diagnostics.error(source.clone(), finding.code, message),
                 }
-            })
-            .detach();
+            }
+        }
+    }
+
+    fn take_rendered_pages(mut rendered: ResMut<RenderedPages>) -> Vec<(OutputPath, String)> {
+        std::mem::take(&mut rendered.0)
     }
 }
 
 impl ProcessorPlugin for TeraProcessor {
     fn register(self, app: &mut crate::app::ProcessorApp) {
-        app.insert_resource(self)
+        app.insert_resource(self.snapshot.clone())
+            .insert_resource(self.assets.clone())
+            .insert_resource(self.svgs.clone())
+            .insert_resource(self)
+            .init_resource::<BuildMode>()
+            .init_resource::<ExtraPages>()
             .init_resource::<PageContexts>()
-            .add_systems(Process, Self::index_templates)
+            .init_resource::<RenderedPages>()
+            .configure_sets(
+                Write,
+                (TeraSet::Render, TeraSet::Validate, TeraSet::Write).chain(),
+            )
+            .configure_sets(Process, (TeraSet::Index, TeraSet::Check).chain())
+            .configure_sets(PostProcess, (TeraSet::Associate, TeraSet::Context).chain())
+            .add_systems(Load, Self::load_templates)
+            .add_systems(
+                Process,
+                (
+                    Self::index_templates.in_set(TeraSet::Index),
+                    Self::check_templates.in_set(TeraSet::Check),
+                ),
+            )
             .add_systems(
                 PostProcess,
-                (Self::associate_pages_to_templates, Self::populate_context),
+                (
+                    (
+                        Self::associate_pages_to_templates.after(classify_pages),
+                        (Self::check_page_templates, Self::reserve_page_outputs),
+                    )
+                        .chain()
+                        .in_set(TeraSet::Associate),
+                    Self::reserve_extra_outputs.in_set(TeraSet::Associate),
+                    Self::snapshot_pages.in_set(TeraSet::Associate),
+                    (Self::populate_context, Self::check_term_metadata).in_set(TeraSet::Context),
+                ),
             )
-            .add_systems(Write, Self::process_pages.pipe(Self::write_to_disk));
+            .add_systems(
+                Write,
+                (
+                    (
+                        Self::process_pages,
+                        Self::render_raw_pages,
+                        Self::render_extra_templates,
+                    )
+                        .in_set(TeraSet::Render),
+                    Self::validate_pages.in_set(TeraSet::Validate),
+                    Self::take_rendered_pages
+                        .pipe(write_to_disk)
+                        .in_set(TeraSet::Write),
+                ),
+            );
     }
 }
 
-impl Default for TeraProcessor {
-    fn default() -> Self {
-        Self::new()
+/// What every template sees regardless of the page: the site's `config`, the `build`
+/// and any site `data`, over the values of the [`GlobalContext`].
+fn site_context(
+    config: &SiteConfig,
+    mode: &BuildMode,
+    build: &BuildInfo,
+    data: Option<&SiteData>,
+    globals: &GlobalContext,
+) -> tera::Context {
+    let mut context = tera::Context::new();
+
+    for (key, value) in globals.iter() {
+        context.insert(key, value);
     }
-}
 
-#[derive(Debug, Component)]
-struct TemplateName(PathBuf);
+    context.insert(
+        "config",
+        &serde_json::json!({
+            "mode": mode.name(),
+            "base_url": config.url_for(""),
+            "extra": table_to_json(&config.extra),
+        }),
+    );
 
-#[derive(Debug, Component)]
-struct AssociatedPageType(Entity);
+    context.insert(
+        "build",
+        &serde_json::json!({
+            "version": build.version,
+            "commit": build.commit,
+            "timestamp": build.timestamp,
+            "date": build.date,
+            "generator": config.build.generator.then(|| build.generator()),
+        }),
+    );
 
-#[derive(Debug, Default, Resource)]
-struct PageContexts(EntityHashMap<tera::Context>);
+    if let Some(data) = data {
+        context.insert("data", &data.0);
+    }
+
+    context
+}
+
+/// The context a page is rendered with, its own values over those of the site. Only
+/// the one being rendered is put together, rather than every page keeping a copy of
+/// the site's values.
+fn layered(site: &tera::Context, page: &tera::Context) -> tera::Context {
+    let mut context = site.clone();
+    context.extend(page.clone());
+    context
+}
+
+/// Whether a file, relative to the templates directory, should be loaded as a template.
+/// Hidden files and editor backups are skipped.
+fn is_template(path: &Path, extensions: &[String]) -> bool {
+    let hidden = path
+        .components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'));
+    let backup = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with('~') || name.ends_with(".swp"));
+    let extension = path.extension().and_then(|extension| extension.to_str());
+
+    !hidden
+        && !backup
+        && extension.is_some_and(|extension| extensions.iter().any(|allowed| allowed == extension))
+}
+
+/// The first segment of `path` that isn't valid UTF-8, as best it can be shown.
+/// Rewrites `source` so the first newline after each block tag is left out of the
+/// output. The newline is moved inside the tag, where Tera ignores it, rather than
+/// removed, so line numbers in errors still match the file. Tags already trimming with
+/// `-%}` and the contents of `{% raw %}` blocks are left alone.
+fn trim_blocks(source: &str) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut rest = source;
+
+    while let Some(start) = find_tag_start(rest) {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("{#") {
+            let end = rest.find("#}").map_or(rest.len(), |end| end + 2);
+
+            output.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        let end = block_tag_end(rest);
+        let tag = &rest[..end];
+        let newline = ["\r\n", "\n"]
+            .into_iter()
+            .find(|newline| rest[end..].starts_with(newline))
+            .map_or(0, str::len);
+
+        rest = &rest[end..];
+
+        if newline > 0 && tag.ends_with("%}") && !tag.ends_with("-%}") {
+            output.push_str(&tag[..tag.len() - 2]);
+            output.push_str(&rest[..newline]);
+            output.push_str("%}");
+            rest = &rest[newline..];
+        } else {
+            output.push_str(tag);
+        }
+
+        if block_tag_name(tag) == "raw" {
+            let end = rest
+                .match_indices("{%")
+                .map(|(index, _)| index)
+                .find(|index| block_tag_name(&rest[*index..]) == "endraw")
+                .unwrap_or(rest.len());
+
+            output.push_str(&rest[..end]);
+            rest = &rest[end..];
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Where the next block tag or comment in `source` starts.
+fn find_tag_start(source: &str) -> Option<usize> {
+    source
+        .match_indices('{')
+        .map(|(index, _)| index)
+        .find(|index| matches!(source.as_bytes().get(index + 1), Some(b'%' | b'#')))
+}
+
+/// Finds the end of the block tag at the start of `source`, ignoring any `%}` within
+/// quoted strings.
+fn block_tag_end(source: &str) -> usize {
+    let bytes = source.as_bytes();
+    let mut quote = None;
+
+    for (index, &c) in bytes.iter().enumerate().skip(2) {
+        match (quote, c) {
+            (None, c @ (b'"' | b'\'' | b'`')) => quote = Some(c),
+            (Some(open), c) if open == c => quote = None,
+            (None, b'%') if bytes.get(index + 1) == Some(&b'}') => return index + 2,
+            _ => {}
+        }
+    }
+
+    source.len()
+}
+
+/// The name of the block tag at the start of `source`, such as `if` for `{%- if x %}`.
+fn block_tag_name(source: &str) -> &str {
+    let tag = source
+        .trim_start_matches("{%")
+        .trim_start_matches('-')
+        .trim_start();
+    let end = tag
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(tag.len());
+
+    &tag[..end]
+}
+
+fn non_utf8_segment(path: &Path) -> Option<String> {
+    path.components()
+        .map(|component| component.as_os_str())
+        .find(|segment| segment.to_str().is_none())
+        .map(|segment| segment.to_string_lossy().into_owned())
+}
+
+/// Stages of the tera processor, for ordering custom systems against it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemSet)]
+pub enum TeraSet {
+    /// Assigns templates to page types, during [`Process`].
+    Index,
+    /// Reports templates that page types need but don't exist, during [`Process`].
+    Check,
+    /// Associates pages with their page type, reporting pages relying on templates
+    /// that failed to load, and reserves their outputs, during [`PostProcess`].
+    Associate,
+    /// Populates the template context of every page, during [`PostProcess`].
+    Context,
+    /// Renders pages into [`RenderedPages`], during [`Write`].
+    Render,
+    /// Reports mistakes in the HTML of [`RenderedPages`] as warnings, when
+    /// `build.validate_html` is set, during [`Write`].
+    Validate,
+    /// Writes [`RenderedPages`] to the output directory, during [`Write`].
+    Write,
+}
+
+/// Rendered pages waiting to be written, keyed by their output path.
+#[derive(Debug, Default, Resource)]
+pub struct RenderedPages(pub Vec<(OutputPath, String)>);
+
+impl Default for TeraProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Component)]
+struct TemplateName(PathBuf);
+
+#[derive(Debug, Component)]
+struct AssociatedPageType(Entity);
+
+/// Where a page is written, reserved ahead of rendering it.
+#[derive(Debug, Component)]
+struct PageOutput(OutputPath);
+
+/// Marks a page type whose template doesn't exist, so its pages aren't rendered.
+#[derive(Debug, Component)]
+struct MissingTemplate;
+
+/// Pages rendered from a template with a context of their own rather than from content,
+/// such as the [`StatsProcessor`](super::StatsProcessor)'s. Processors reserve the
+/// output in the [`OutputRegistry`], then insert the page ahead of [`TeraSet::Render`].
+/// Each is rendered over the site context, like those under `[[extra_templates]]`, and
+/// every build until it's removed.
+#[derive(Debug, Default, Resource)]
+pub struct ExtraPages(BTreeMap<OutputPath, ExtraPage>);
+
+impl ExtraPages {
+    /// Renders `page` to `output`, replacing any page inserted there before.
+    pub fn insert(&mut self, output: OutputPath, page: ExtraPage) {
+        self.0.insert(output, page);
+    }
+
+    pub fn remove(&mut self, output: &OutputPath) -> Option<ExtraPage> {
+        self.0.remove(output)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtraPage {
+    pub template: String,
+    pub context: tera::Context,
+}
+
+/// The templates listed under `[[extra_templates]]` along with their reserved outputs
+/// and their own context.
+#[derive(Debug, Resource)]
+struct ExtraOutputs(Vec<(String, OutputPath, tera::Context)>);
+
+/// The template context of every page, along with the pages whose context changed
+/// since they were last rendered.
+#[derive(Debug, Default, Resource)]
+struct PageContexts {
+    /// What every template sees, shared by the pages rather than copied into each.
+    site: Arc<tera::Context>,
+    /// The values of each page, layered over `site` when rendering it.
+    contexts: EntityHashMap<tera::Context>,
+    stale: EntityHashSet,
+    /// Pages whose content includes other pages, stale whenever any page changes.
+    includes: EntityHashSet,
+}
+
+/// The `include_page` and `summary_of` template functions, reading from the
+/// [`PageSnapshot`]. What they return is HTML, so it isn't escaped.
+struct IncludeFunction {
+    snapshot: PageSnapshot,
+    summary: bool,
+}
+
+impl tera::Function for IncludeFunction {
+    fn call(&self, args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+        let Some(path) = args.get("path").and_then(tera::Value::as_str) else {
+            return Err(tera::Error::msg("Expected a `path` string argument"));
+        };
+
+        self.snapshot.mark_used();
+
+        let included = match self.summary {
+            true => self
+                .snapshot
+                .summary_of(path)
+                .map(|summary| summary.to_string()),
+            false => self.snapshot.include_page(path),
+        };
+
+        included
+            .map(tera::Value::String)
+            .map_err(|e| tera::Error::msg(e.to_string()))
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+}
+
+/// The `asset_url` template function, returning the URL of a bundle from the
+/// [`AssetUrls`]. URLs come from the configuration rather than content, so aren't
+/// escaped.
+struct AssetUrlFunction {
+    assets: AssetUrls,
+}
+
+impl tera::Function for AssetUrlFunction {
+    fn call(&self, args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+        let Some(name) = args.get("name").and_then(tera::Value::as_str) else {
+            return Err(tera::Error::msg("Expected a `name` string argument"));
+        };
+
+        self.assets
+            .get(name)
+            .map(tera::Value::String)
+            .ok_or_else(|| tera::Error::msg(format!("No bundle named `{}`", name)))
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+}
+
+/// The `inline_svg` template function, returning an SVG from the [`SvgCache`] with
+/// an optional `class` added to it. What it returns is markup, so it isn't escaped.
+struct InlineSvgFunction {
+    svgs: SvgCache,
+}
+
+impl tera::Function for InlineSvgFunction {
+    fn call(&self, args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+        let Some(path) = args.get("path").and_then(tera::Value::as_str) else {
+            return Err(tera::Error::msg("Expected a `path` string argument"));
+        };
+        let class = match args.get("class") {
+            Some(class) => Some(
+                class
+                    .as_str()
+                    .ok_or_else(|| tera::Error::msg("Expected `class` to be a string"))?,
+            ),
+            None => None,
+        };
+
+        self.svgs
+            .inline(path, class)
+            .map(tera::Value::String)
+            .map_err(|e| tera::Error::msg(e.to_string()))
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(all(test, feature = "markdown"))]
+mod tests {
+    use bevy_ecs::system::Command;
+    use chrono::{TimeZone, Utc};
+    use toml::Value;
+
+    use crate::{
+        app::ProcessorApp,
+        build_info::BuildClock,
+        file::EnumeratedSections,
+        front_matter::{Date, Title},
+        manifest::Manifest,
+        processor::{FileConfig, InputDir, MarkdownFrontMatter, MarkdownProcessor, OutputDir},
+    };
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_segments_are_found() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let path = Path::new(OsStr::from_bytes(b"blog/caf\xe9/post.md"));
+
+        assert_eq!(non_utf8_segment(path).as_deref(), Some("caf\u{fffd}"));
+        assert_eq!(non_utf8_segment(Path::new("blog/café/post.md")), None);
+    }
+
+    #[test]
+    fn broken_templates_only_fail_the_pages_using_them() {
+        let dir = std::env::temp_dir().join("webvy_broken_templates_only_fail_their_pages");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join(".hidden")).unwrap();
+
+        for (name, template) in [
+            ("base.html", "<main>{% block content %}{% endblock content %}</main>"),
+            (
+                "page.html",
+                "{% extends \"base.html\" %}{% block content %}{{ content | safe }}{% endblock content %}",
+            ),
+            ("broken.html", "{% if %}"),
+            ("post.html", "{% extends \"broken.html\" %}"),
+            ("notes.md", "{{ not a template"),
+            (".page.html.swp", "{%"),
+            ("page.html~", "{%"),
+            (".hidden/page.html", "{%"),
+        ] {
+            std::fs::write(dir.join(name), template).unwrap();
+        }
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+        ));
+        app.world_mut().spawn(PageType::Page);
+        app.init_resource::<SiteConfig>()
+            .add_page("about.md", toml::Table::new(), "About")
+            .add_page(
+                "other.md",
+                toml::from_str("template = \"post.html\"").unwrap(),
+                "Other",
+            )
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .add_processor(TeraProcessor::new().with_directory(&dir))
+            .run()
+            .unwrap();
+
+        let tera = app.world().resource::<TeraProcessor>();
+        let mut registered: Vec<_> = tera.templates.get_template_names().collect();
+        registered.sort();
+
+        assert_eq!(registered, ["base.html", "page.html"]);
+
+        let diagnostics = app.world().resource::<Diagnostics>();
+        let mut messages: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        messages.sort();
+
+        assert_eq!(messages.len(), 3, "{messages:?}");
+        assert_eq!(
+            messages[0],
+            "[broken-template] <virtual:other.md>: Unable to render post.html: it depends on broken.html, which can't be used"
+        );
+        assert!(messages[1].starts_with(&format!(
+            "[invalid-template] {}: Template broken.html can't be used: it failed to parse",
+            dir.join("broken.html").display()
+        )));
+        assert_eq!(
+            messages[2],
+            format!(
+                "[invalid-template] {}: Template post.html can't be used: it depends on broken.html, which can't be used",
+                dir.join("post.html").display()
+            )
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failures_in_nested_macros_keep_their_causes() {
+        let dir = std::env::temp_dir().join("webvy_failures_in_nested_macros");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(
+            dir.join("templates/macros.html"),
+            "{% macro title(page) %}{{ page.extra.missing }}{% endmacro title %}\
+             {% macro header(page) %}<h1>{{ self::title(page=page) }}</h1>{% endmacro header %}",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("templates/page.html"),
+            "{% import \"macros.html\" as macros %}{{ macros::header(page=page) }}",
+        )
+        .unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        app.world_mut().spawn(PageType::Page);
+        app.init_resource::<SiteConfig>()
+            .init_resource::<Manifest>()
+            .add_page("about.md", toml::Table::new(), "About")
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+            .run()
+            .unwrap();
+
+        let diagnostics: Vec<_> = app.world().resource::<Diagnostics>().iter().collect();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "[render-failed] <virtual:about.md>: Unable to render page.html: Failed to render \
+             'page.html': error while rendering macro `self::title`\n  Variable \
+             `page.extra.missing` not found in context while rendering 'macros.html'"
+        );
+        assert_eq!(
+            serde_json::to_value(diagnostics[0]).unwrap()["causes"],
+            serde_json::json!([
+                "Variable `page.extra.missing` not found in context while rendering 'macros.html'"
+            ])
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn build_info_follows_the_build_clock() {
+        let dir = std::env::temp_dir().join("webvy_build_info_follows_the_build_clock");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(
+            dir.join("templates/page.html"),
+            "{{ build.date }}|{{ build.timestamp }}|{{ build.generator }}",
+        )
+        .unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        app.world_mut().spawn(PageType::Page);
+        app.insert_resource(BuildClock::fixed(
+            Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap(),
+        ))
+        .insert_resource(toml::from_str::<SiteConfig>("[build]\ngenerator = true").unwrap())
+        .init_resource::<Manifest>()
+        .add_page("about.md", toml::Table::new(), "About")
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+        .run()
+        .unwrap();
+
+        let page = std::fs::read_to_string(dir.join("public/about.html")).unwrap();
+
+        assert!(
+            page.starts_with(&format!(
+                "2024-05-01|2024-05-01T12:30:00Z|webvy {}",
+                env!("CARGO_PKG_VERSION")
+            )),
+            "{page}"
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn extra_values_keep_their_types_in_templates() {
+        let dir = std::env::temp_dir().join("webvy_extra_values_keep_their_types");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(
+            dir.join("templates/page.html"),
+            "{% if page.extra.featured %}featured{% endif %}|\
+             {% if page.extra.hidden %}hidden{% endif %}|\
+             {% if page.extra.rating > 3 %}good{% endif %}|\
+             {{ page.extra.updated }}|{{ page.extra.tags | length }}|\
+             {{ config.extra.since + 1 }}",
+        )
+        .unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        app.world_mut().spawn(PageType::Page);
+        app.insert_resource(toml::from_str::<SiteConfig>("[extra]\nsince = 2019").unwrap())
+            .init_resource::<Manifest>()
+            .add_page(
+                "about.md",
+                toml::from_str(
+                    "[extra]\nfeatured = true\nhidden = false\nrating = 4.5\n\
+                     updated = 2024-05-01\ntags = [\"a\", \"b\"]",
+                )
+                .unwrap(),
+                "About",
+            )
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+            .run()
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dir.join("public/about.html")).unwrap(),
+            "featured||good|2024-05-01|2|2020"
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pages_written_to_the_same_path_fail_the_build() {
+        let dir = std::env::temp_dir().join("webvy_pages_written_to_the_same_path");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(dir.join("templates/page.html"), "{{ content | safe }}").unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        app.world_mut().spawn(PageType::Page);
+        app.init_resource::<SiteConfig>()
+            .init_resource::<Manifest>()
+            .add_page("about.md", toml::Table::new(), "About")
+            .add_page("about.html", toml::Table::new(), "<p>About</p>")
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+            .run()
+            .unwrap();
+
+        let diagnostics = app.world().resource::<Diagnostics>();
+        let messages: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+
+        assert_eq!(messages.len(), 1, "{messages:?}");
+        assert!(messages[0].starts_with("[output-collision] "));
+        assert!(messages[0].contains("about.html is written by both page <virtual:"));
+        assert!(messages[0].contains("<virtual:about.md>"));
+        assert!(!dir.join("public/about.html").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "validate")]
+    #[test]
+    fn invalid_html_is_reported_against_its_page() {
+        let dir = std::env::temp_dir().join("webvy_invalid_html_is_reported");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(dir.join("templates/page.html"), "{{ content | safe }}").unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        app.world_mut().spawn(PageType::Page);
+        app.insert_resource(toml::from_str::<SiteConfig>("[build]\nvalidate_html = true").unwrap())
+            .init_resource::<Manifest>()
+            .add_page("about.md", toml::Table::new(), "About")
+            .add_page(
+                "broken.html",
+                toml::Table::new(),
+                "<div>\n<img src=\"a.png\">",
+            )
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+            .run()
+            .unwrap();
+
+        let diagnostics = app.world().resource::<Diagnostics>();
+        let messages: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+
+        assert_eq!(
+            messages,
+            [
+                "[invalid-html] <virtual:broken.html>: Line 1: <div> is never closed",
+                "[invalid-html] <virtual:broken.html>: Line 2: <img> has no alt attribute",
+            ]
+        );
+        assert!(dir.join("public/broken.html").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn outputs_are_only_escaped_when_they_are_markup() {
+        let dir = std::env::temp_dir().join("webvy_outputs_are_escaped_when_markup");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(dir.join("templates/page.html"), "<p>{{ data.team }}</p>").unwrap();
+        std::fs::write(
+            dir.join("templates/team.xml"),
+            "<team>{{ data.team }}</team>",
+        )
+        .unwrap();
+        std::fs::write(dir.join("templates/humans.txt"), "Team: {{ data.team }}").unwrap();
+        std::fs::write(
+            dir.join("templates/landing.html"),
+            "<h1>{{ headline }}</h1><p>{{ seats + 1 }} by {{ ends }}, {{ data }}</p>",
+        )
+        .unwrap();
+
+        let mut app = ProcessorApp::new();
+        let mut data = serde_json::Map::new();
+        data.insert("team".into(), "Tom & Jerry".into());
+        let front_matter: toml::Table =
+            toml::from_str("template = \"team.xml\"\noutput = \"team.xml\"").unwrap();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        app.world_mut().spawn(PageType::Page);
+        app.insert_resource(
+            toml::from_str::<SiteConfig>(
+                "[[extra_templates]]\ntemplate = \"humans.txt\"\noutput = \"humans.txt\"\n\
+                 [[extra_templates]]\ntemplate = \"landing.html\"\noutput = \"lp/offer/index.html\"\n\
+                 [extra_templates.context]\nheadline = \"Half off\"\nseats = 3\n\
+                 ends = 2024-06-01\ndata = \"Shadowed\"",
+            )
+            .unwrap(),
+        )
+        .insert_resource(SiteData(data))
+        .init_resource::<Manifest>()
+        .add_page("about.md", toml::Table::new(), "About")
+        .add_page("team.md", front_matter, "Team")
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+        .run()
+        .unwrap();
+
+        let read = |path: &str| std::fs::read_to_string(dir.join("public").join(path)).unwrap();
+
+        assert_eq!(read("about.html"), "<p>Tom &amp; Jerry</p>");
+        assert_eq!(read("team.xml"), "<team>Tom &amp; Jerry</team>");
+        assert_eq!(read("humans.txt"), "Team: Tom & Jerry");
+        assert_eq!(
+            read("lp/offer/index.html"),
+            "<h1>Half off</h1><p>4 by 2024-06-01, Shadowed</p>"
+        );
+        assert!(!dir.join("public/team.html").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn escaping_follows_the_templates_config() {
+        let dir = std::env::temp_dir().join("webvy_escaping_follows_the_templates_config");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(dir.join("templates/team.tera"), "{{ data.team }}").unwrap();
+        std::fs::write(dir.join("templates/raw.tera"), "{{ data.team }}").unwrap();
+
+        let render = |templates: &str| {
+            let mut app = ProcessorApp::new();
+            let mut data = serde_json::Map::new();
+            data.insert("team".into(), "Tom & <Jerry>".into());
+
+            app.world_mut().spawn((
+                FileConfig,
+                InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+                OutputDir::new(dir.join("public")),
+            ));
+            app.insert_resource(
+                toml::from_str::<SiteConfig>(&format!(
+                    "[templates]\n{}\n\
+                     [[extra_templates]]\ntemplate = \"team.tera\"\noutput = \"team.html\"\n\
+                     [[extra_templates]]\ntemplate = \"team.tera\"\noutput = \"team.xml\"\n\
+                     [[extra_templates]]\ntemplate = \"team.tera\"\noutput = \"team.txt\"\n\
+                     [[extra_templates]]\ntemplate = \"raw.tera\"\noutput = \"raw.html\"",
+                    templates
+                ))
+                .unwrap(),
+            )
+            .insert_resource(SiteData(data))
+            .init_resource::<Manifest>()
+            .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+            .run()
+            .unwrap();
+
+            let read = |path: &str| std::fs::read_to_string(dir.join("public").join(path)).unwrap();
+            let messages: Vec<_> = app
+                .world()
+                .resource::<Diagnostics>()
+                .iter()
+                .map(|diagnostic| diagnostic.to_string())
+                .collect();
+
+            (
+                [
+                    read("team.html"),
+                    read("team.xml"),
+                    read("team.txt"),
+                    read("raw.html"),
+                ],
+                messages,
+            )
+        };
+
+        let escaped = "Tom &amp; &lt;Jerry&gt;";
+        let unescaped = "Tom & <Jerry>";
+
+        assert_eq!(
+            render("").0,
+            [escaped, escaped, unescaped, escaped].map(String::from)
+        );
+        assert_eq!(
+            render("autoescape = [\".html\", \"txt\"]\nno_autoescape = [\"raw.tera\", \"gone.html\"]"),
+            (
+                [escaped, unescaped, escaped, unescaped].map(String::from),
+                vec![
+                    "[template-not-found] Template gone.html is excluded from autoescaping under [templates], but doesn't exist".to_string()
+                ]
+            )
+        );
+        assert!(toml::from_str::<SiteConfig>("[templates]\nautoescape_on = []").is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn trimmed_blocks_keep_their_line_numbers() {
+        assert_eq!(
+            trim_blocks("{% for x in xs %}\n{{ x }}\n{% endfor -%}\n{# no %} #}\n"),
+            "{% for x in xs \n%}{{ x }}\n{% endfor -%}\n{# no %} #}\n"
+        );
+        assert_eq!(
+            trim_blocks("{% set a = \"%}\" %}\r\n{% raw %}\n{% if %}\n{% endraw %}\nend"),
+            "{% set a = \"%}\" \r\n%}{% raw \n%}{% if %}\n{% endraw \n%}end"
+        );
+
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "list",
+            &trim_blocks("<ul>\n{% for x in xs %}\n<li>{{ x }}</li>\n{% endfor %}\n</ul>"),
+        )
+        .unwrap();
+        let mut context = tera::Context::new();
+        context.insert("xs", &[1, 2]);
+
+        assert_eq!(
+            tera.render("list", &context).unwrap(),
+            "<ul>\n<li>1</li>\n<li>2</li>\n</ul>"
+        );
+    }
+
+    #[test]
+    fn tags_are_shown_by_name_and_linked_by_slug() {
+        let dir = std::env::temp_dir().join("webvy_tags_are_shown_by_name");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(
+            dir.join("templates/page.html"),
+            "{% for tag in page.tags %}<a href=\"/tags/{{ tag.slug }}/\" \
+             title=\"{{ tag.description | default(value='') }}\">{{ tag.name }}</a>{% endfor %}",
+        )
+        .unwrap();
+
+        let mut app = ProcessorApp::new();
+        let data = serde_json::json!({
+            "rust": { "name": "Rust 🦀", "description": "Posts about Rust" },
+            "bevy": { "name": "Bevy" },
+        });
+        let mut site_data = serde_json::Map::new();
+        site_data.insert(TAGS.into(), data);
+        let front_matter: toml::Table =
+            toml::from_str("tags = [\"Rust\", \"Web Dev\", \"gamedev\"]").unwrap();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        app.world_mut().spawn(PageType::Page);
+        app.insert_resource(
+            toml::from_str::<SiteConfig>(
+                "[taxonomies.tags.terms.web-dev]\nname = \"Web Development\"",
+            )
+            .unwrap(),
+        )
+        .insert_resource(SiteData(site_data))
+        .init_resource::<Manifest>()
+        .add_page("about.md", front_matter, "About")
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+        .run()
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dir.join("public/about.html")).unwrap(),
+            "<a href=\"/tags/rust/\" title=\"Posts about Rust\">Rust 🦀</a>\
+             <a href=\"/tags/web-dev/\" title=\"\">Web Development</a>\
+             <a href=\"/tags/gamedev/\" title=\"\">gamedev</a>"
+        );
+
+        let messages: Vec<_> = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+
+        assert_eq!(
+            messages,
+            ["[unknown-term] Term bevy of tags has metadata, but no page uses it"]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn global_values_are_computed_once_for_every_template() {
+        let dir = std::env::temp_dir().join("webvy_global_values_are_computed_once");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(
+            dir.join("templates/page.html"),
+            "{{ content | safe }}<footer>\
+             {% for post in recent_posts %}\
+             <a href=\"{{ post.permalink | safe }}\">{{ post.title }}</a>\
+             {% endfor %}\
+             {% for tag in tag_cloud %} {{ tag.name }} ({{ tag.count }}){% endfor %}\
+             </footer>",
+        )
+        .unwrap();
+
+        /// The footer's latest two posts.
+        fn recent_posts(
+            q_pages: Query<(&Title, &Date, &Permalink)>,
+            mut globals: ResMut<GlobalContext>,
+        ) {
+            let mut posts: Vec<_> = q_pages.iter().collect();
+            posts.sort_by_key(|(_, date, _)| std::cmp::Reverse(date.utc));
+
+            let posts: Vec<_> = posts
+                .into_iter()
+                .take(2)
+                .map(|(title, _, permalink)| {
+                    serde_json::json!({ "title": title.0, "permalink": permalink.as_ref() })
+                })
+                .collect();
+
+            globals.insert("recent_posts", &posts).unwrap();
+        }
+
+        /// Every tag along with how many pages use it.
+        fn tag_cloud(q_tags: Query<&Tags>, mut globals: ResMut<GlobalContext>) {
+            let mut counts = std::collections::BTreeMap::<_, usize>::new();
+
+            for tag in q_tags.iter().flat_map(|tags| tags.0.iter()) {
+                *counts.entry(tag.as_str()).or_default() += 1;
+            }
+
+            let tags: Vec<_> = counts
+                .into_iter()
+                .map(|(name, count)| serde_json::json!({ "name": name, "count": count }))
+                .collect();
+
+            globals.insert("tag_cloud", &tags).unwrap();
+        }
+
+        let mut app = ProcessorApp::new();
+        let post = |title: &str, date: &str, tags: &str| {
+            toml::from_str::<toml::Table>(&format!(
+                "title = \"{}\"\ndate = {}\ntags = [{}]",
+                title, date, tags
+            ))
+            .unwrap()
+        };
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        app.world_mut().spawn(PageType::Page);
+        app.init_resource::<SiteConfig>()
+            .init_resource::<Manifest>()
+            .add_page("first.md", post("First", "2024-01-01", "\"rust\""), "")
+            .add_page(
+                "second.md",
+                post("Second", "2024-02-01", "\"rust\", \"bevy\""),
+                "",
+            )
+            .add_page("third.md", post("Third", "2024-03-01", ""), "")
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+            .add_systems(
+                PostProcess,
+                (recent_posts, tag_cloud).before(TeraSet::Context),
+            )
+            .run()
+            .unwrap();
+
+        let footer =
+            "<footer><a href=\"/third.html\">Third</a><a href=\"/second.html\">Second</a> \
+                      bevy (1) rust (2)</footer>";
+
+        for page in ["first.html", "second.html", "third.html"] {
+            assert_eq!(
+                std::fs::read_to_string(dir.join("public").join(page)).unwrap(),
+                footer,
+                "{}",
+                page
+            );
+        }
+
+        // Computed once, rather than copied into the context of each page.
+        let contexts = app.world().resource::<PageContexts>();
+        assert!(contexts.site.contains_key("tag_cloud"));
+        assert!(contexts
+            .contexts
+            .values()
+            .all(|context| !context.contains_key("tag_cloud")));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn sections_are_described_by_their_index_page() {
+        let dir = std::env::temp_dir().join("webvy_sections_are_described_by_their_index");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(
+            dir.join("templates/index.html"),
+            "{% for section in sections %}{{ section.name }}: {{ section.title }}{% endfor %}",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("templates/section.html"),
+            "{{ page.section.title }}",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("templates/post.html"),
+            "{{ page.section.title }} | {{ page.section.description }} | \
+             {{ page.section.permalink | safe }}",
+        )
+        .unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        app.world_mut().spawn(PageType::Index);
+        EnumeratedSections::new(PathBuf::from("posts"))
+            .unwrap()
+            .apply(app.world_mut());
+        app.insert_resource(
+            toml::from_str::<SiteConfig>("base_url = \"https://example.com\"").unwrap(),
+        )
+        .init_resource::<Manifest>()
+        .add_page("_index.md", toml::Table::new(), "Home")
+        .add_page(
+            "posts/_index.md",
+            toml::from_str("title = \"The Blog\"\ndescription = \"Notes and such\"").unwrap(),
+            "Posts",
+        )
+        .add_page("posts/first.md", toml::Table::new(), "First")
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+        .run()
+        .unwrap();
+
+        let read = |path: &str| std::fs::read_to_string(dir.join("public").join(path)).unwrap();
+
+        assert_eq!(read("index.html"), "posts: The Blog");
+        assert_eq!(read("posts/index.html"), "The Blog");
+        assert_eq!(
+            read("posts/first.html"),
+            "The Blog | Notes and such | https://example.com/posts/"
+        );
+
+        let mut sections = app.world_mut().query::<(Entity, &SectionInfo)>();
+        let (section, _) = sections.single(app.world());
+        let mut pages = app.world_mut().query::<(&FilePath, Option<&InSection>)>();
+        let mut linked: Vec<_> = pages
+            .iter(app.world())
+            .map(|(path, in_section)| {
+                (
+                    path.as_ref().display().to_string(),
+                    in_section.map(|in_section| in_section.0),
+                )
+            })
+            .collect();
+        linked.sort();
+
+        assert_eq!(
+            linked,
+            [
+                (String::from("_index.md"), None),
+                (String::from("posts/_index.md"), Some(section)),
+                (String::from("posts/first.md"), Some(section)),
+            ]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rebuilds_only_render_pages_that_changed() {
+        let dir = std::env::temp_dir().join("webvy_rebuilds_only_render_changed_pages");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(dir.join("templates/page.html"), "{{ content | safe }}").unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        app.world_mut().spawn(PageType::Page);
+        app.init_resource::<SiteConfig>()
+            .init_resource::<Manifest>()
+            .add_page("about.md", toml::Table::new(), "About")
+            .add_page("contact.md", toml::Table::new(), "Contact")
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+            .run()
+            .unwrap();
+
+        assert_eq!(app.report().written, 2);
+
+        let modified = |page: &str| {
+            std::fs::metadata(dir.join("public").join(page))
+                .unwrap()
+                .modified()
+                .unwrap()
+        };
+        let about = modified("about.html");
+        let contact = modified("contact.html");
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+
+        let mut q_pages = app.world_mut().query::<(&FilePath, &mut HtmlBody)>();
+        for (path, mut html) in q_pages.iter_mut(app.world_mut()) {
+            if path.as_ref() == Path::new("about.md") {
+                *html = HtmlBody::new(String::from("<p>About us</p>"));
+            }
+        }
+
+        app.run().unwrap();
+
+        assert_eq!(app.report().written, 3);
+        assert_eq!(app.report().unchanged, 0);
+        assert_ne!(modified("about.html"), about);
+        assert_eq!(modified("contact.html"), contact);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("public/about.html")).unwrap(),
+            "<p>About us</p>"
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn templates_added_between_rebuilds_are_picked_up() {
+        let dir = std::env::temp_dir().join("webvy_templates_added_between_rebuilds");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(dir.join("templates/section.html"), "Section").unwrap();
+        std::fs::write(
+            dir.join("templates/post.html"),
+            "Shared {{ content | safe }}",
+        )
+        .unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        EnumeratedSections::new(PathBuf::from("posts"))
+            .unwrap()
+            .apply(app.world_mut());
+        app.init_resource::<SiteConfig>()
+            .init_resource::<Manifest>()
+            .add_page("posts/_index.md", toml::Table::new(), "")
+            .add_page("posts/first.md", toml::Table::new(), "First")
+            .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+            .run()
+            .unwrap();
+
+        let read = |path: &str| std::fs::read_to_string(dir.join("public").join(path)).unwrap();
+
+        assert_eq!(read("posts/first.html"), "Shared <p>First</p>\n");
+
+        std::fs::create_dir_all(dir.join("templates/posts")).unwrap();
+        std::fs::write(
+            dir.join("templates/posts/post.html"),
+            "Own {{ content | safe }}",
+        )
+        .unwrap();
+
+        app.run().unwrap();
+
+        assert_eq!(read("posts/first.html"), "Own <p>First</p>\n");
+        assert_eq!(
+            app.report().templates.get("posts/post").map(String::as_str),
+            Some("posts/post.html")
+        );
+
+        std::fs::remove_file(dir.join("templates/posts/post.html")).unwrap();
+        app.run().unwrap();
+
+        assert_eq!(read("posts/first.html"), "Shared <p>First</p>\n");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_changed_templates_are_parsed_again() {
+        let mut tera = TeraProcessor::new();
+        let mut diagnostics = Diagnostics::default();
+        let files = |base: &str, note: &str| {
+            [
+                ("base.html", base),
+                (
+                    "page.html",
+                    "{% extends \"base.html\" %}{% block body %}Page{% endblock %}",
+                ),
+                ("macros.html", "{% macro hi() %}Hi{% endmacro %}"),
+                ("note.txt", note),
+            ]
+            .map(|(name, content)| (name.to_string(), PathBuf::from(name), content.to_string()))
+            .to_vec()
+        };
+        let render = |tera: &TeraProcessor, name: &str| {
+            tera.templates.render(name, &tera::Context::new()).unwrap()
+        };
+
+        assert_eq!(
+            tera.add_templates(
+                files("<{% block body %}{% endblock %}>", "A"),
+                &mut diagnostics
+            ),
+            4
+        );
+        assert_eq!(
+            tera.add_templates(
+                files("<{% block body %}{% endblock %}>", "A"),
+                &mut diagnostics
+            ),
+            0
+        );
+        assert_eq!(
+            tera.add_templates(
+                files("<{% block body %}{% endblock %}>", "B"),
+                &mut diagnostics
+            ),
+            1
+        );
+        assert_eq!(render(&tera, "note.txt"), "B");
+        assert_eq!(render(&tera, "page.html"), "<Page>");
+
+        // Changing a parent parses its children again along with everything else.
+        assert_eq!(
+            tera.add_templates(
+                files("[{% block body %}{% endblock %}]", "B"),
+                &mut diagnostics
+            ),
+            4
+        );
+        assert_eq!(render(&tera, "page.html"), "[Page]");
+        assert!(diagnostics.iter().next().is_none());
+    }
+
+    #[test]
+    fn contexts_and_feeds_read_the_same_page_data() {
+        use crate::{config::SectionConfig, processor::FeedProcessor};
+
+        let dir = std::env::temp_dir().join("webvy_contexts_and_feeds_read_the_same_data");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(
+            dir.join("templates/post.html"),
+            "{{ page.title }}|{{ page.date }}|{{ page.word_count }}|{{ page.reading_time }}|\
+             {{ page.summary | safe }}",
+        )
+        .unwrap();
+        std::fs::write(dir.join("templates/section.html"), "").unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        EnumeratedSections::new(PathBuf::from("blog"))
+            .unwrap()
+            .apply(app.world_mut());
+
+        let mut sections = app.world_mut().query::<(Entity, &PageType)>();
+        let section = sections
+            .iter(app.world())
+            .find(|(_, page_type)| **page_type == PageType::Section)
+            .map(|(section, _)| section)
+            .unwrap();
+        app.world_mut()
+            .entity_mut(section)
+            .insert(toml::from_str::<SectionConfig>("feed = true").unwrap());
+
+        app.insert_resource(
+            toml::from_str::<SiteConfig>(
+                "base_url = \"https://example.com\"\n[markdown]\nwords_per_minute = 4\n\
+                 [feeds]\natom = false\njson = true",
+            )
+            .unwrap(),
+        )
+        .init_resource::<Manifest>()
+        .add_page(
+            "blog/post.md",
+            toml::from_str("title = \"Post\"\ndate = 2024-05-01").unwrap(),
+            "The *opening* words\n\nAnd the rest",
+        )
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+        .add_processor(FeedProcessor::new())
+        .run()
+        .unwrap();
+
+        let page = std::fs::read_to_string(dir.join("public/blog/post.html")).unwrap();
+        let feed: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(dir.join("public/blog/feed.json")).unwrap(),
+        )
+        .unwrap();
+        let item = &feed["items"][0];
+
+        assert_eq!(
+            page,
+            "Post|2024-05-01T00:00:00Z|6|2|The opening words And the rest"
+        );
+        assert_eq!(
+            page.rsplit('|').next(),
+            item["summary"].as_str(),
+            "the page and its feed entry should have the same summary"
+        );
+        assert_eq!(item["title"], "Post");
+        assert_eq!(item["date_published"], "2024-05-01T00:00:00Z");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn headless_pages_are_loaded_but_never_written() {
+        use crate::{
+            config::SectionConfig,
+            front_matter::Unlisted,
+            processor::{FeedProcessor, JsonProcessor, SectionPosts},
+        };
+
+        let dir = std::env::temp_dir().join("webvy_headless_pages_are_never_written");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(dir.join("templates/post.html"), "{{ content | safe }}").unwrap();
+        std::fs::write(dir.join("templates/page.html"), "{{ content | safe }}").unwrap();
+        std::fs::write(dir.join("templates/section.html"), "{{ content | safe }}").unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        app.world_mut().spawn(PageType::Page);
+        EnumeratedSections::new(PathBuf::from("blog"))
+            .unwrap()
+            .apply(app.world_mut());
+
+        let mut sections = app.world_mut().query::<(Entity, &PageType)>();
+        let section = sections
+            .iter(app.world())
+            .find(|(_, page_type)| **page_type == PageType::Section)
+            .map(|(section, _)| section)
+            .unwrap();
+        app.world_mut()
+            .entity_mut(section)
+            .insert(toml::from_str::<SectionConfig>("feed = true").unwrap());
+
+        app.insert_resource(
+            toml::from_str::<SiteConfig>(
+                "base_url = \"https://example.com\"\n[build]\njson_output = true\n[feeds]\natom = true",
+            )
+            .unwrap(),
+        )
+        .init_resource::<Manifest>()
+        .add_page(
+            "snippet.md",
+            toml::from_str("render = false").unwrap(),
+            "Shared",
+        )
+        .add_page(
+            "blog/post.md",
+            toml::from_str("title = \"Post\"\ndate = 2024-05-01").unwrap(),
+            "Post",
+        )
+        .add_page(
+            "blog/fragment.md",
+            toml::from_str("title = \"Fragment\"\ndate = 2024-05-02\nrender = false").unwrap(),
+            "Fragment",
+        )
+        .add_page(
+            "blog/unlisted.md",
+            toml::from_str("title = \"Unlisted\"\ndate = 2024-05-03\nin_listing = false")
+                .unwrap(),
+            "Unlisted",
+        )
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+        .add_processor(FeedProcessor::new())
+        .add_processor(JsonProcessor::new())
+        .run()
+        .unwrap();
+
+        let mut outputs: Vec<_> = smol::block_on(crate::files::find_all_files_in_directory(
+            &dir.join("public"),
+            WalkOptions::default(),
+        ))
+        .unwrap()
+        .into_iter()
+        .map(|output| {
+            output
+                .strip_prefix(dir.join("public"))
+                .unwrap()
+                .to_path_buf()
+        })
+        .collect();
+        outputs.sort();
+
+        assert_eq!(
+            outputs,
+            [
+                PathBuf::from("blog/atom.xml"),
+                PathBuf::from("blog/post.html"),
+                PathBuf::from("blog/post.json"),
+                PathBuf::from("blog/unlisted.html"),
+                PathBuf::from("blog/unlisted.json"),
+                PathBuf::from("pages.json"),
+            ]
+        );
+
+        let feed = std::fs::read_to_string(dir.join("public/blog/atom.xml")).unwrap();
+        assert!(feed.contains("<title>Post</title>"));
+        assert!(!feed.contains("Fragment"));
+        assert!(!feed.contains("Unlisted"));
+
+        // Headless pages are still loaded and listed, just never given a permalink.
+        let mut pages = app.world_mut().query::<(
+            Entity,
+            &FilePath,
+            &HtmlBody,
+            Option<&Permalink>,
+            Has<Unlisted>,
+        )>();
+        let section_posts = app.world().resource::<SectionPosts>();
+        let mut pages: Vec<_> = pages
+            .iter(app.world())
+            .map(|(page, path, html, permalink, unlisted)| {
+                (
+                    path.as_ref().display().to_string(),
+                    html.as_ref().to_string(),
+                    permalink.is_some(),
+                    unlisted,
+                    section_posts
+                        .position_of(page)
+                        .map(|(_, position)| position),
+                )
+            })
+            .collect();
+        pages.sort();
+
+        assert_eq!(
+            pages,
+            [
+                (
+                    String::from("blog/fragment.md"),
+                    String::from("<p>Fragment</p>\n"),
+                    false,
+                    false,
+                    Some(0)
+                ),
+                (
+                    String::from("blog/post.md"),
+                    String::from("<p>Post</p>\n"),
+                    true,
+                    false,
+                    Some(1)
+                ),
+                (
+                    String::from("blog/unlisted.md"),
+                    String::from("<p>Unlisted</p>\n"),
+                    true,
+                    true,
+                    None
+                ),
+                (
+                    String::from("snippet.md"),
+                    String::from("<p>Shared</p>\n"),
+                    false,
+                    false,
+                    None
+                ),
+            ]
+        );
+        assert!(app.world().resource::<Diagnostics>().is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn drafts_unlisted_and_headless_pages_stay_off_each_surface() {
+        use crate::{
+            config::SectionConfig,
+            processor::{DateIndex, FeedProcessor, JsonProcessor, SectionPosts, TaxonomyIndex},
+        };
+
+        let dir = std::env::temp_dir().join("webvy_unlisted_pages_stay_off_each_surface");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(
+            dir.join("templates/post.html"),
+            "{% if page.noindex %}<meta name=\"robots\" content=\"noindex\">{% endif %}\
+             {{ content | safe }}",
+        )
+        .unwrap();
+        std::fs::write(dir.join("templates/page.html"), "{{ content | safe }}").unwrap();
+        std::fs::write(dir.join("templates/section.html"), "{{ content | safe }}").unwrap();
+
+        let mut app = ProcessorApp::new();
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+        ));
+        app.world_mut().spawn(PageType::Page);
+        EnumeratedSections::new(PathBuf::from("blog"))
+            .unwrap()
+            .apply(app.world_mut());
+
+        let mut sections = app.world_mut().query::<(Entity, &PageType)>();
+        let section = sections
+            .iter(app.world())
+            .find(|(_, page_type)| **page_type == PageType::Section)
+            .map(|(section, _)| section)
+            .unwrap();
+        app.world_mut()
+            .entity_mut(section)
+            .insert(toml::from_str::<SectionConfig>("feed = true").unwrap());
+
+        let names = ["listed", "draft", "unlisted", "headless"];
+        let matter = ["", "draft = true", "unlisted = true", "render = false"];
+
+        app.insert_resource(BuildClock::fixed(
+            Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap(),
+        ))
+        .insert_resource(
+            toml::from_str::<SiteConfig>(
+                "base_url = \"https://example.com\"\n[build]\njson_output = true\n\
+                 [feeds]\natom = true",
+            )
+            .unwrap(),
+        )
+        .init_resource::<Manifest>();
+
+        for (name, matter) in names.iter().zip(matter) {
+            app.add_page(
+                format!("blog/{name}.md"),
+                toml::from_str(&format!(
+                    "title = \"{name}\"\ndate = 2024-05-01\ntags = [\"rust\"]\n{matter}"
+                ))
+                .unwrap(),
+                *name,
+            );
+        }
+
+        app.add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+            .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+            .add_processor(FeedProcessor::new())
+            .add_processor(JsonProcessor::new())
+            .run()
+            .unwrap();
+
+        let public = dir.join("public");
+        let feed = std::fs::read_to_string(public.join("blog/atom.xml")).unwrap();
+        let index = std::fs::read_to_string(public.join("pages.json")).unwrap();
+        let mut q_pages = app.world_mut().query::<(Entity, &Title)>();
+        let world = app.world();
+        let pages: Vec<_> = q_pages.iter(world).collect();
+        let page = |name: &str| {
+            pages
+                .iter()
+                .find(|(_, title)| title.0 == name)
+                .map(|(page, _)| *page)
+                .unwrap()
+        };
+
+        // Whether each page is written, listed in its section, in the feed, in the JSON
+        // index, indexed by date and by tag, and marked noindex.
+        let surfaces: Vec<_> = names
+            .iter()
+            .map(|name| {
+                let html = std::fs::read_to_string(public.join(format!("blog/{name}.html")));
+
+                (
+                    *name,
+                    html.is_ok(),
+                    world
+                        .resource::<SectionPosts>()
+                        .position_of(page(name))
+                        .is_some(),
+                    feed.contains(&format!("<title>{name}</title>")),
+                    index.contains(&format!("\"title\": \"{name}\"")),
+                    world
+                        .resource::<DateIndex>()
+                        .pages(2024, 5)
+                        .contains(&page(name)),
+                    world
+                        .resource::<TaxonomyIndex>()
+                        .pages(TAGS, "rust")
+                        .contains(&page(name)),
+                    html.is_ok_and(|html| html.contains("noindex")),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            surfaces,
+            [
+                ("listed", true, true, true, true, true, true, false),
+                ("draft", false, false, false, false, false, false, false),
+                ("unlisted", true, false, false, false, false, false, true),
+                ("headless", false, true, false, false, false, false, false),
+            ]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pages_include_other_pages_and_their_summaries() {
+        let dir = std::env::temp_dir().join("webvy_pages_include_other_pages");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::write(
+            dir.join("templates/post.html"),
+            "{{ content | safe }}<footer>{{ summary_of(path=\"snippets/warning.md\") }}</footer>",
+        )
+        .unwrap();
+        std::fs::write(dir.join("templates/page.html"), "{{ content | safe }}").unwrap();
+        std::fs::write(dir.join("templates/section.html"), "{{ content | safe }}").unwrap();
+
+        let build = |pages: &[(&str, &str, &str)]| {
+            let mut app = ProcessorApp::new();
+
+            app.world_mut().spawn((
+                FileConfig,
+                InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+                OutputDir::new(dir.join("public")),
+            ));
+            app.world_mut().spawn(PageType::Page);
+
+            for section in ["blog", "snippets"] {
+                EnumeratedSections::new(PathBuf::from(section))
+                    .unwrap()
+                    .apply(app.world_mut());
+            }
+
+            app.init_resource::<SiteConfig>()
+                .init_resource::<Manifest>()
+                .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+                .add_processor(TeraProcessor::new().with_directory(dir.join("templates")));
+
+            for (path, front_matter, content) in pages {
+                app.add_page(*path, toml::from_str(front_matter).unwrap(), *content);
+            }
+
+            app.run().unwrap();
+
+            let mut messages: Vec<_> = app
+                .world()
+                .resource::<Diagnostics>()
+                .iter()
+                .map(|diagnostic| diagnostic.to_string())
+                .collect();
+            messages.sort();
+
+            messages
+        };
+
+        let snippet = (
+            "snippets/warning.md",
+            "render = false\ndate = 2024-05-01",
+            "**Careful** now",
+        );
+
+        let messages = build(&[
+            snippet,
+            (
+                "blog/post.md",
+                "title = \"Post\"\ndate = 2024-05-01",
+                "{{ include_page(path=\"snippets/warning.md\") }}\n\nAfter",
+            ),
+        ]);
+
+        assert!(messages.is_empty(), "{messages:?}");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("public/blog/post.html")).unwrap(),
+            "<p><strong>Careful</strong> now</p>\n\n<p>After</p>\n<footer>Careful now</footer>"
+        );
+
+        let messages = build(&[
+            snippet,
+            (
+                "missing.md",
+                "",
+                "{{ include_page(path=\"snippets/missing.md\") }}",
+            ),
+            ("cycle.md", "", "{{ include_page(path=\"cycle.md\") }}"),
+        ]);
+
+        assert_eq!(
+            messages,
+            [
+                "[include-failed] <virtual:cycle.md>: cycle.md includes itself through cycle.md → cycle.md",
+                &format!(
+                    "[include-failed] <virtual:missing.md>: missing.md includes {}, which doesn't exist",
+                    Path::new("snippets/missing.md").display()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn svgs_are_inlined_from_the_static_directory() {
+        let dir = std::env::temp_dir().join("webvy_svgs_are_inlined_from_the_static_directory");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("templates")).unwrap();
+        std::fs::create_dir_all(dir.join("static/icons")).unwrap();
+        std::fs::write(
+            dir.join("static/icons/rss.svg"),
+            "<?xml version=\"1.0\"?>\n<!-- An icon -->\n<svg viewBox=\"0 0 8 8\">\n  <circle r=\"4\"/>\n</svg>\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("templates/page.html"),
+            "<p>{{ inline_svg(path=\"icons/rss.svg\", class=\"icon\") }}</p>",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("templates/missing.html"),
+            "{{ inline_svg(path=\"icons/missing.svg\") }}",
+        )
+        .unwrap();
+
+        let mut app = ProcessorApp::new();
+        let mut missing = toml::Table::new();
+        missing.insert("template".into(), "missing.html".into());
+
+        app.world_mut().spawn((
+            FileConfig,
+            InputDir::from_value(&Value::Array(Vec::new())).unwrap(),
+            OutputDir::new(dir.join("public")),
+            crate::config::StaticDir::new(dir.join("static")),
+        ));
+        app.world_mut().spawn(PageType::Page);
+        app.insert_resource(
+            toml::from_str::<SiteConfig>("[assets]\nmax_inline_svg_size = 16").unwrap(),
+        )
+        .init_resource::<Manifest>()
+        .add_page("about.md", toml::Table::new(), "About")
+        .add_page("missing.md", missing, "Missing")
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .add_processor(crate::processor::StaticProcessor::new())
+        .add_processor(TeraProcessor::new().with_directory(dir.join("templates")))
+        .run()
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dir.join("public/about.html")).unwrap(),
+            "<p><svg class=\"icon\" viewBox=\"0 0 8 8\"><circle r=\"4\"/></svg></p>"
+        );
+
+        let diagnostics: Vec<_> = app
+            .world()
+            .resource::<Diagnostics>()
+            .iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.to_string()))
+            .collect();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].0, "render-failed");
+        assert!(diagnostics[0].1.contains("'missing.html'"));
+        assert!(diagnostics[0].1.contains("No SVG at `icons/missing.svg`"));
+        assert_eq!(diagnostics[1].0, "large-inline-svg");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,383 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use bevy_ecs::system::Resource;
+use serde::Serialize;
+
+use crate::{diff::OutputChange, errors::ProcessorError};
+
+/// Summary of what a build produced, updated as the processors run.
+#[derive(Debug, Default, Clone, Serialize, Resource)]
+pub struct BuildReport {
+    /// Content pages found, from disk or provided in memory. A build without any is
+    /// most likely pointed at the wrong content directory.
+    pub pages_loaded: usize,
+    /// Output files written to disk.
+    pub written: usize,
+    /// Output files skipped as their content on disk was already identical.
+    pub unchanged: usize,
+    /// Bytes saved by the pre-compressed outputs, compared to their originals.
+    pub bytes_saved: u64,
+    /// Bundles written from `[[bundles]]`, along with the files they were made from and
+    /// their size once concatenated.
+    pub bundles: usize,
+    pub bundled_files: usize,
+    pub bundled_bytes: u64,
+    /// Pages that panicked while being processed, each also reported as an error.
+    pub panicked: usize,
+    /// Warnings reported during the build, excluding allowed ones.
+    pub warnings: usize,
+    /// The template rendering each page type, keyed by `<section>/<type>` for sections.
+    pub templates: BTreeMap<String, String>,
+    /// Outputs that would change, when the build compares rather than writes them.
+    pub changes: Vec<OutputChange>,
+    /// Where the site was read from and written to.
+    pub paths: SitePaths,
+}
+
+/// The directories of a site, resolved against the directory of its config file unless
+/// they're absolute, and made absolute themselves.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct SitePaths {
+    pub config: Option<PathBuf>,
+    pub content: Vec<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub templates: Option<PathBuf>,
+    #[serde(rename = "static")]
+    pub static_files: Option<PathBuf>,
+    pub data: Option<PathBuf>,
+}
+
+/// Errors encountered during a build that didn't stop the remaining work.
+#[derive(Debug, Default, Resource)]
+pub struct BuildErrors(pub Vec<ProcessorError>);
+
+impl BuildErrors {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ProcessorError> {
+        self.0.iter()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found with the site, identified by a stable code such as `missing-date`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The source file the problem was found in, such as `content/blog/post.md`.
+    pub page: Option<PathBuf>,
+    pub code: &'static str,
+    pub message: String,
+    /// What led to the problem, outermost first, such as each template a render
+    /// failed in on the way to the one that actually failed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub causes: Vec<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.page {
+            Some(page) => write!(f, "[{}] {}: {}", self.code, page.display(), self.message)?,
+            None => write!(f, "[{}] {}", self.code, self.message)?,
+        }
+
+        write_causes(f, &self.causes, 2)
+    }
+}
+
+/// Writes each cause on its own line, indented one level further than the last.
+fn write_causes(f: &mut impl fmt::Write, causes: &[String], indent: usize) -> fmt::Result {
+    for (level, cause) in causes.iter().enumerate() {
+        write!(f, "\n{:indent$}{}", "", cause, indent = indent + level * 2)?;
+    }
+
+    Ok(())
+}
+
+/// The messages of every error that caused `error`, outermost first. Errors such as
+/// Tera's only describe themselves, leaving what failed within a nested template or
+/// macro to their sources.
+pub fn causes_of(error: &dyn std::error::Error) -> Vec<String> {
+    let mut causes = Vec::new();
+    let mut source = error.source();
+
+    while let Some(cause) = source {
+        causes.push(cause.to_string());
+        source = cause.source();
+    }
+
+    causes
+}
+
+/// Callback receiving each diagnostic as soon as it's recorded.
+#[derive(Clone)]
+pub struct DiagnosticSink(Arc<dyn Fn(&Diagnostic) + Send + Sync>);
+
+impl DiagnosticSink {
+    pub fn new(sink: impl Fn(&Diagnostic) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(sink))
+    }
+}
+
+impl fmt::Debug for DiagnosticSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DiagnosticSink")
+    }
+}
+
+/// Warnings and errors reported by the processors. Errors fail the build, as do
+/// warnings when `[build] strict` is set. Each problem is only recorded once, though
+/// checks run again for every batch of content.
+#[derive(Debug, Default, Resource)]
+pub struct Diagnostics {
+    entries: Vec<Diagnostic>,
+    recorded: HashSet<Diagnostic>,
+    sink: Option<DiagnosticSink>,
+}
+
+impl Diagnostics {
+    pub fn set_sink(&mut self, sink: DiagnosticSink) {
+        self.sink = Some(sink);
+    }
+
+    pub fn warning(
+        &mut self,
+        page: impl Into<Option<PathBuf>>,
+        code: &'static str,
+        message: impl Into<String>,
+    ) {
+        self.push(
+            Severity::Warning,
+            page.into(),
+            code,
+            message.into(),
+            Vec::new(),
+        );
+    }
+
+    pub fn error(
+        &mut self,
+        page: impl Into<Option<PathBuf>>,
+        code: &'static str,
+        message: impl Into<String>,
+    ) {
+        self.push(
+            Severity::Error,
+            page.into(),
+            code,
+            message.into(),
+            Vec::new(),
+        );
+    }
+
+    /// Records an error along with what caused it, such as from [`causes_of`].
+    pub fn error_with_causes(
+        &mut self,
+        page: impl Into<Option<PathBuf>>,
+        code: &'static str,
+        message: impl Into<String>,
+        causes: Vec<String>,
+    ) {
+        self.push(Severity::Error, page.into(), code, message.into(), causes);
+    }
+
+    fn push(
+        &mut self,
+        severity: Severity,
+        page: Option<PathBuf>,
+        code: &'static str,
+        message: String,
+        causes: Vec<String>,
+    ) {
+        let diagnostic = Diagnostic {
+            severity,
+            page,
+            code,
+            message,
+            causes,
+        };
+
+        if !self.recorded.insert(diagnostic.clone()) {
+            return;
+        }
+
+        if let Some(sink) = &self.sink {
+            (sink.0)(&diagnostic);
+        }
+
+        self.entries.push(diagnostic);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.entries.iter()
+    }
+
+    /// Removes every recorded diagnostic.
+    pub fn take(&mut self) -> Vec<Diagnostic> {
+        self.recorded.clear();
+        std::mem::take(&mut self.entries)
+    }
+}
+
+/// Renders diagnostics grouped by code, then by page.
+pub fn summarize(diagnostics: &[Diagnostic]) -> String {
+    let mut codes: BTreeMap<&str, BTreeMap<Option<&PathBuf>, Vec<&Diagnostic>>> = BTreeMap::new();
+
+    for diagnostic in diagnostics {
+        codes
+            .entry(diagnostic.code)
+            .or_default()
+            .entry(diagnostic.page.as_ref())
+            .or_default()
+            .push(diagnostic);
+    }
+
+    let warnings = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Warning)
+        .count();
+
+    let mut summary = format!(
+        "{} warning(s), {} error(s)\n",
+        warnings,
+        diagnostics.len() - warnings
+    );
+
+    for (code, pages) in codes {
+        let count: usize = pages.values().map(Vec::len).sum();
+        summary.push_str(&format!("{} ({}):\n", code, count));
+
+        for (page, diagnostics) in pages {
+            for diagnostic in diagnostics {
+                let severity = match diagnostic.severity {
+                    Severity::Warning => "warning",
+                    Severity::Error => "error",
+                };
+
+                match page {
+                    Some(page) => summary.push_str(&format!(
+                        "  {} {}: {}",
+                        severity,
+                        page.display(),
+                        diagnostic.message
+                    )),
+                    None => summary.push_str(&format!("  {} {}", severity, diagnostic.message)),
+                }
+
+                write_causes(&mut summary, &diagnostic.causes, 4)
+                    .expect("writing to a string can't fail");
+                summary.push('\n');
+            }
+        }
+    }
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_groups_by_code_then_page() {
+        let mut diagnostics = Diagnostics::default();
+
+        diagnostics.warning(
+            PathBuf::from("blog/b.md"),
+            "missing-date",
+            "Post has no date",
+        );
+        diagnostics.error(None, "invalid-page", "Unreadable");
+        diagnostics.warning(
+            PathBuf::from("blog/a.md"),
+            "missing-date",
+            "Post has no date",
+        );
+
+        assert_eq!(
+            summarize(&diagnostics.take()),
+            "2 warning(s), 1 error(s)\n\
+             invalid-page (1):\n  error Unreadable\n\
+             missing-date (2):\n  \
+             warning blog/a.md: Post has no date\n  \
+             warning blog/b.md: Post has no date\n"
+        );
+    }
+
+    #[test]
+    fn causes_are_indented_under_their_diagnostic() {
+        let mut diagnostics = Diagnostics::default();
+
+        diagnostics.error_with_causes(
+            PathBuf::from("blog/a.md"),
+            "render-failed",
+            "Unable to render post.html",
+            vec![
+                String::from("error while rendering macro `header`"),
+                String::from("Variable `title` not found"),
+            ],
+        );
+
+        let recorded = diagnostics.take();
+
+        assert_eq!(
+            recorded[0].to_string(),
+            "[render-failed] blog/a.md: Unable to render post.html\n  \
+             error while rendering macro `header`\n    Variable `title` not found"
+        );
+        assert_eq!(
+            summarize(&recorded),
+            format!(
+                "0 warning(s), 1 error(s)\nrender-failed (1):\n  error {}: Unable to render \
+                 post.html\n    error while rendering macro `header`\n      Variable `title` \
+                 not found\n",
+                std::path::Path::new("blog/a.md").display()
+            )
+        );
+    }
+
+    #[test]
+    fn sink_receives_diagnostics_as_they_are_recorded() {
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut diagnostics = Diagnostics::default();
+
+        diagnostics.set_sink(DiagnosticSink::new({
+            let received = received.clone();
+            move |diagnostic| {
+                received
+                    .lock()
+                    .unwrap()
+                    .push(serde_json::to_string(diagnostic).unwrap())
+            }
+        }));
+        diagnostics.warning(
+            PathBuf::from("blog/a.md"),
+            "missing-date",
+            "Post has no date",
+        );
+
+        assert_eq!(
+            received.lock().unwrap().as_slice(),
+            [
+                r#"{"severity":"warning","page":"blog/a.md","code":"missing-date","message":"Post has no date"}"#
+            ]
+        );
+    }
+}
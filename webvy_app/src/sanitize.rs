@@ -0,0 +1,141 @@
+use serde::Deserialize;
+
+/// Elements whose `class` is kept, so syntax highlighting and the classes under
+/// `[markdown.classes]` survive sanitization.
+#[cfg(feature = "sanitize")]
+const CLASSED_ELEMENTS: &[&str] = &[
+    "a",
+    "blockquote",
+    "code",
+    "div",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "img",
+    "pre",
+    "span",
+    "sup",
+    "table",
+];
+
+/// Elements whose `id` is kept, so heading anchors survive sanitization.
+#[cfg(feature = "sanitize")]
+const ANCHORED_ELEMENTS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// Sanitization of rendered pages, found under `[markdown.sanitize]`. Pages can opt
+/// out with `sanitize = false` in their front matter.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SanitizeConfig {
+    /// Strip scripts, event handlers and unsafe URLs from every page.
+    pub enabled: bool,
+    /// Elements allowed on top of the default safe set.
+    pub tags: Vec<String>,
+    /// Attributes allowed on any element, on top of the default safe set.
+    pub attributes: Vec<String>,
+}
+
+/// Removes anything that could run code from rendered HTML, keeping an allowlist of
+/// safe elements and attributes extended by a [`SanitizeConfig`].
+pub struct Sanitizer<'a> {
+    #[cfg(feature = "sanitize")]
+    builder: ammonia::Builder<'a>,
+    #[cfg(not(feature = "sanitize"))]
+    _config: std::marker::PhantomData<&'a SanitizeConfig>,
+}
+
+impl<'a> Sanitizer<'a> {
+    /// Whether support for sanitization was compiled in.
+    pub fn is_available() -> bool {
+        cfg!(feature = "sanitize")
+    }
+
+    #[cfg(feature = "sanitize")]
+    pub fn new(config: &'a SanitizeConfig) -> Self {
+        let tags = config.tags.iter().map(String::as_str);
+        let mut builder = ammonia::Builder::default();
+
+        builder
+            .link_rel(None)
+            .add_tags(tags.clone())
+            .rm_clean_content_tags(tags)
+            .add_generic_attributes(config.attributes.iter().map(String::as_str));
+
+        for tag in CLASSED_ELEMENTS {
+            builder.add_tag_attributes(tag, ["class"]);
+        }
+
+        for tag in ANCHORED_ELEMENTS {
+            builder.add_tag_attributes(tag, ["id"]);
+        }
+
+        Self { builder }
+    }
+
+    #[cfg(not(feature = "sanitize"))]
+    pub fn new(_config: &'a SanitizeConfig) -> Self {
+        Self {
+            _config: std::marker::PhantomData,
+        }
+    }
+
+    /// Keeps comments, which are otherwise removed.
+    #[cfg(feature = "sanitize")]
+    pub fn keeping_comments(mut self) -> Self {
+        self.builder.strip_comments(false);
+        self
+    }
+
+    #[cfg(not(feature = "sanitize"))]
+    pub fn keeping_comments(self) -> Self {
+        self
+    }
+
+    #[cfg(feature = "sanitize")]
+    pub fn clean(&self, html: &str) -> String {
+        self.builder.clean(html).to_string()
+    }
+
+    /// Without sanitization compiled in, everything is escaped rather than let through.
+    #[cfg(not(feature = "sanitize"))]
+    pub fn clean(&self, html: &str) -> String {
+        crate::escape::escape_html_text(html)
+    }
+}
+
+#[cfg(all(test, feature = "sanitize"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsafe_markup_is_removed() {
+        let config = SanitizeConfig::default();
+        let sanitizer = Sanitizer::new(&config);
+
+        assert_eq!(
+            sanitizer.clean(
+                "<p onclick=\"steal()\">Hi<script>steal()</script></p>\
+                 <a href=\"javascript:steal()\">link</a><a href=\"/ok\">ok</a>"
+            ),
+            "<p>Hi</p><a>link</a><a href=\"/ok\">ok</a>"
+        );
+    }
+
+    #[test]
+    fn anchors_highlighting_and_allowed_extras_are_kept() {
+        let config = SanitizeConfig {
+            enabled: true,
+            tags: vec![String::from("iframe")],
+            attributes: vec![String::from("data-note")],
+        };
+        let sanitizer = Sanitizer::new(&config);
+        let html = "<h2 id=\"intro\">Intro</h2>\
+                    <pre><code class=\"language-rust\"><span class=\"kw\">fn</span></code></pre>\
+                    <p data-note=\"x\">Note</p><iframe></iframe>";
+
+        assert_eq!(sanitizer.clean(html), html);
+    }
+}
@@ -0,0 +1,205 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    app::ProcessorApp,
+    cancel::CancellationToken,
+    config::DiffMode,
+    errors::ProcessorResult,
+    processor::{
+        BuildMode, BundleProcessor, ConfigurationProcessor, DataProcessor, FeedProcessor,
+        JsonProcessor, MarkdownFrontMatter, MarkdownProcessor, MatterDebug, OgImageProcessor,
+        PwaProcessor, StaticProcessor, StatsProcessor, TeraProcessor,
+    },
+    report::{BuildReport, DiagnosticSink},
+};
+
+/// Where front matter is dumped with [`SiteOptions::debug_matter`], relative to the
+/// config.
+const MATTER_DEBUG_DIR: &str = ".webvy-debug";
+
+/// Options for a one-shot build of a site with [`build`].
+#[derive(Debug, Clone)]
+pub struct SiteOptions {
+    /// Path to the site's configuration file. Paths within it are relative to its
+    /// directory.
+    pub config: PathBuf,
+    /// Writes the output here instead of the configured output directory. Relative to
+    /// the working directory, rather than the config.
+    pub output: Option<PathBuf>,
+    /// Overrides whether draft pages are rendered.
+    pub drafts: Option<bool>,
+    /// Overrides whether warnings fail the build.
+    pub strict: Option<bool>,
+    /// Compares outputs against the output directory instead of writing them.
+    pub diff: Option<DiffMode>,
+    /// Builds for deployment, or for serving locally.
+    pub mode: BuildMode,
+    /// Receives each diagnostic as soon as it's recorded.
+    pub on_diagnostic: Option<DiagnosticSink>,
+    /// Stops the build early once cancelled.
+    pub cancel: Option<CancellationToken>,
+    /// Dumps the front matter of each page into `.webvy-debug/`, beside the config.
+    pub debug_matter: bool,
+}
+
+impl SiteOptions {
+    pub fn new(config: impl Into<PathBuf>) -> Self {
+        Self {
+            config: config.into(),
+            output: None,
+            drafts: None,
+            strict: None,
+            diff: None,
+            mode: BuildMode::Production,
+            on_diagnostic: None,
+            cancel: None,
+            debug_matter: false,
+        }
+    }
+
+    pub fn with_output(mut self, output: impl Into<PathBuf>) -> Self {
+        self.output = Some(output.into());
+        self
+    }
+
+    pub fn with_drafts(mut self, drafts: bool) -> Self {
+        self.drafts = Some(drafts);
+        self
+    }
+
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = Some(strict);
+        self
+    }
+
+    pub fn with_diff(mut self, diff: DiffMode) -> Self {
+        self.diff = Some(diff);
+        self
+    }
+
+    pub fn with_mode(mut self, mode: BuildMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_diagnostic_sink(mut self, sink: DiagnosticSink) -> Self {
+        self.on_diagnostic = Some(sink);
+        self
+    }
+
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    pub fn with_debug_matter(mut self, debug_matter: bool) -> Self {
+        self.debug_matter = debug_matter;
+        self
+    }
+}
+
+impl Default for SiteOptions {
+    fn default() -> Self {
+        Self::new("blog.toml")
+    }
+}
+
+/// Builds a site with the standard set of processors, returning a report of what was
+/// produced or the errors that were encountered.
+pub fn build(options: SiteOptions) -> ProcessorResult<BuildReport> {
+    let debug_matter = options.debug_matter.then(|| {
+        let root = options.config.parent().unwrap_or(Path::new(""));
+
+        MatterDebug::new(root.join(MATTER_DEBUG_DIR))
+    });
+    let mut configuration = ConfigurationProcessor::new(options.config).with_mode(options.mode);
+
+    if let Some(output) = options.output {
+        configuration = configuration.with_output(output);
+    }
+
+    if let Some(drafts) = options.drafts {
+        configuration = configuration.with_drafts(drafts);
+    }
+
+    if let Some(strict) = options.strict {
+        configuration = configuration.with_strict(strict);
+    }
+
+    if let Some(diff) = options.diff {
+        configuration = configuration.with_diff(diff);
+    }
+
+    let mut app = ProcessorApp::new();
+
+    if let Some(sink) = options.on_diagnostic {
+        app.on_diagnostic(sink);
+    }
+
+    if let Some(cancel) = options.cancel {
+        app.insert_resource(cancel);
+    }
+
+    if let Some(debug_matter) = debug_matter {
+        app.insert_resource(debug_matter);
+    }
+
+    app.add_processor(configuration)
+        .add_processor(MarkdownProcessor::<MarkdownFrontMatter>::new())
+        .add_processor(DataProcessor::new())
+        .add_processor(StaticProcessor::new())
+        .add_processor(BundleProcessor::new())
+        .add_processor(TeraProcessor::new())
+        .add_processor(FeedProcessor::new())
+        .add_processor(JsonProcessor::new())
+        .add_processor(OgImageProcessor::new())
+        .add_processor(PwaProcessor::new())
+        .add_processor(StatsProcessor::new())
+        .run()?;
+
+    app.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_relative_to_the_config_rather_than_the_working_directory() {
+        let dir = std::env::temp_dir().join("webvy_paths_are_relative_to_the_config");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("site/content")).unwrap();
+        std::fs::create_dir_all(dir.join("site/layouts")).unwrap();
+        std::fs::write(
+            dir.join("site/blog.toml"),
+            "base_url = \"https://example.com\"\n\
+             [files]\ncontent = \"./content\"\noutput = \"public\"\ntemplates = \"layouts\"\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("site/content/_index.md"), "+++\n+++\nHome").unwrap();
+        std::fs::write(dir.join("site/layouts/index.html"), "{{ content | safe }}").unwrap();
+
+        // Tests run from the crate's directory, well away from the site.
+        assert_ne!(std::env::current_dir().unwrap(), dir.join("site"));
+
+        let report = build(SiteOptions::new(dir.join("site/blog.toml"))).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(dir.join("site/public/index.html")).unwrap(),
+            "<p>Home</p>\n"
+        );
+        assert_eq!(report.paths.content, [dir.join("site/content")]);
+        assert_eq!(report.paths.output, Some(dir.join("site/public")));
+        assert_eq!(report.paths.templates, Some(dir.join("site/layouts")));
+        assert_eq!(report.paths.data, None);
+
+        let report =
+            build(SiteOptions::new(dir.join("site/blog.toml")).with_output(dir.join("ci")))
+                .unwrap();
+
+        assert!(dir.join("ci/index.html").exists());
+        assert_eq!(report.paths.output, Some(dir.join("ci")));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+use std::{borrow::Cow, collections::HashSet};
+
+use serde::Deserialize;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// How [`slugify_with`] builds slugs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SlugOptions {
+    /// Joins the words of the slug.
+    pub separator: char,
+    /// Longest slug in characters, shortened to the last whole word that fits.
+    pub max_length: Option<usize>,
+    /// Replace non-Latin scripts with an ASCII approximation. Needs the `transliterate`
+    /// feature, otherwise they're kept as is.
+    pub transliterate: bool,
+}
+
+impl Default for SlugOptions {
+    fn default() -> Self {
+        Self {
+            separator: '-',
+            max_length: None,
+            transliterate: false,
+        }
+    }
+}
+
+/// Turns `text` into a lowercase slug with the default [`SlugOptions`].
+pub fn slugify(text: &str) -> String {
+    slugify_with(text, &SlugOptions::default())
+}
+
+/// Turns `text` into a lowercase slug of its letters and digits, joined by the
+/// separator. Diacritics are stripped from Latin letters, while other scripts are
+/// kept whole unless transliterated.
+pub fn slugify_with(text: &str, options: &SlugOptions) -> String {
+    let text = transliterate(text, options.transliterate);
+
+    // Marks are only dropped from Latin letters, as elsewhere they can change the
+    // letter entirely. The kept ones are recomposed below.
+    let mut latin_base = false;
+    let stripped: String = text
+        .nfkd()
+        .filter(|c| {
+            if is_combining_mark(*c) {
+                !latin_base
+            } else {
+                latin_base = c.is_ascii_alphabetic();
+                true
+            }
+        })
+        .collect();
+
+    let mut slug = String::with_capacity(stripped.len());
+    let mut separate = false;
+
+    for c in stripped.nfc().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            if separate && !slug.is_empty() {
+                slug.push(options.separator);
+            }
+
+            separate = false;
+            slug.push(c);
+        } else {
+            separate = true;
+        }
+    }
+
+    match options.max_length {
+        Some(max_length) => truncate_at_word(slug, max_length, options.separator),
+        None => slug,
+    }
+}
+
+/// Returns `base`, or `base` with the first free numbered suffix if it's already taken,
+/// marking the returned slug as taken.
+pub fn unique_slug(taken: &mut HashSet<String>, base: &str) -> String {
+    let mut slug = base.to_string();
+    let mut suffix = 1;
+
+    while !taken.insert(slug.clone()) {
+        slug = format!("{}-{}", base, suffix);
+        suffix += 1;
+    }
+
+    slug
+}
+
+fn truncate_at_word(slug: String, max_length: usize, separator: char) -> String {
+    if slug.chars().count() <= max_length {
+        return slug;
+    }
+
+    let cut: String = slug.chars().take(max_length).collect();
+    let mid_word = slug.chars().nth(max_length) != Some(separator);
+
+    let cut = match cut.rfind(separator) {
+        Some(index) if mid_word => &cut[..index],
+        _ => cut.as_str(),
+    };
+
+    cut.trim_end_matches(separator).to_string()
+}
+
+#[cfg(feature = "transliterate")]
+fn transliterate(text: &str, enabled: bool) -> Cow<'_, str> {
+    if enabled {
+        Cow::Owned(deunicode::deunicode(text))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+#[cfg(not(feature = "transliterate"))]
+fn transliterate(text: &str, _enabled: bool) -> Cow<'_, str> {
+    Cow::Borrowed(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs_handle_any_script() {
+        let cases = [
+            ("Hello, World!", "hello-world"),
+            ("  --Already--Slugged--  ", "already-slugged"),
+            ("Crème Brûlée", "creme-brulee"),
+            ("e\u{301}te\u{301}", "ete"),
+            ("ﬁne print", "fine-print"),
+            ("Straße", "straße"),
+            ("Привет, мир", "привет-мир"),
+            ("Ёлка и йод", "ёлка-и-йод"),
+            ("日本語 テキスト", "日本語-テキスト"),
+            ("ガイド", "ガイド"),
+            ("I ❤️ Rust 🦀", "i-rust"),
+            ("🦀", ""),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(slugify(text), expected, "slug of {:?}", text);
+        }
+    }
+
+    #[test]
+    fn long_slugs_are_cut_at_a_word_boundary() {
+        let options = |max_length| SlugOptions {
+            separator: '_',
+            max_length: Some(max_length),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            slugify_with("The quick brown fox", &options(12)),
+            "the_quick"
+        );
+        assert_eq!(
+            slugify_with("The quick brown fox", &options(9)),
+            "the_quick"
+        );
+        assert_eq!(
+            slugify_with("The quick brown fox", &options(10)),
+            "the_quick"
+        );
+        assert_eq!(slugify_with("Supercalifragilistic", &options(5)), "super");
+    }
+
+    #[cfg(feature = "transliterate")]
+    #[test]
+    fn transliteration_replaces_non_latin_scripts() {
+        let options = SlugOptions {
+            transliterate: true,
+            ..Default::default()
+        };
+
+        assert_eq!(slugify_with("Привет, мир", &options), "privet-mir");
+    }
+
+    #[test]
+    fn unique_slugs_are_suffixed() {
+        let mut taken = HashSet::new();
+
+        assert_eq!(unique_slug(&mut taken, "intro"), "intro");
+        assert_eq!(unique_slug(&mut taken, "intro"), "intro-1");
+        assert_eq!(unique_slug(&mut taken, "intro"), "intro-2");
+        assert_eq!(unique_slug(&mut taken, "intro-1-1"), "intro-1-1");
+    }
+}
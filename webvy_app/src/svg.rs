@@ -0,0 +1,262 @@
+//! Cleaning up SVG files, both to inline them into pages through the
+//! `{{ inline_svg(path="...") }}` template function and to minify copied ones with
+//! `svg = "minify"` under `[assets.handlers]`.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use bevy_ecs::system::Resource;
+
+use crate::{
+    escape::escape_html_attr,
+    html::{find_closing_tag, tag_end, tag_name},
+};
+
+/// Strips the XML declaration, doctype, comments and `<metadata>` from `svg`, along
+/// with the whitespace between elements and within tags. Text and CDATA sections are
+/// kept as written.
+pub fn minify_svg(svg: &str) -> String {
+    let mut minified = String::with_capacity(svg.len());
+    let mut rest = svg;
+
+    while let Some(start) = rest.find('<') {
+        let text = &rest[..start];
+        if !text.trim().is_empty() {
+            minified.push_str(text);
+        }
+        rest = &rest[start..];
+
+        let skip = |rest: &str, end: &str| rest.find(end).map_or(rest.len(), |at| at + end.len());
+
+        if rest.starts_with("<?") {
+            rest = &rest[skip(rest, "?>")..];
+        } else if rest.starts_with("<!--") {
+            rest = &rest[skip(rest, "-->")..];
+        } else if rest.starts_with("<![CDATA[") {
+            let end = skip(rest, "]]>");
+            minified.push_str(&rest[..end]);
+            rest = &rest[end..];
+        } else if rest.starts_with("<!") {
+            rest = &rest[tag_end(rest)..];
+        } else {
+            let end = tag_end(rest);
+            let tag = &rest[..end];
+            let (name, closing) = tag_name(tag);
+            rest = &rest[end..];
+
+            if !closing && name.eq_ignore_ascii_case("metadata") {
+                if !tag.ends_with("/>") {
+                    let close = find_closing_tag(rest, name);
+                    rest = &rest[close..];
+                    rest = &rest[tag_end(rest)..];
+                }
+                continue;
+            }
+
+            minified.push_str(&collapse_tag(tag));
+        }
+    }
+
+    if !rest.trim().is_empty() {
+        minified.push_str(rest);
+    }
+
+    minified
+}
+
+/// Collapses the whitespace within a tag outside of attribute values, dropping it
+/// entirely ahead of the closing `>` or `/>`.
+fn collapse_tag(tag: &str) -> String {
+    let mut collapsed = String::with_capacity(tag.len());
+    let mut quote = None;
+    let mut space = false;
+
+    for c in tag.chars() {
+        if quote.is_none() && c.is_whitespace() {
+            space = true;
+            continue;
+        }
+
+        if space && !matches!(c, '>' | '/') {
+            collapsed.push(' ');
+        }
+        space = false;
+
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), c) if open == c => quote = None,
+            _ => {}
+        }
+
+        collapsed.push(c);
+    }
+
+    collapsed
+}
+
+/// Adds `class` to the root `<svg>` element of `svg`, alongside any class it already
+/// has. `None` when there's no `<svg>` element.
+pub fn with_class(svg: &str, class: &str) -> Option<String> {
+    let mut offset = 0;
+
+    let (start, end) = loop {
+        let start = offset + svg[offset..].find('<')?;
+        let end = start + tag_end(&svg[start..]);
+        let (name, closing) = tag_name(&svg[start..end]);
+
+        if !closing && name == "svg" {
+            break (start, end);
+        }
+
+        offset = end;
+    };
+
+    let class = escape_html_attr(class);
+    let tag = &svg[start..end];
+    let mut quote = None;
+
+    for (index, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), c) if open == c => quote = None,
+            (None, c) if c.is_whitespace() && tag[index + 1..].starts_with("class=") => {
+                let value = index + 1 + "class=".len();
+                let Some(open @ ('"' | '\'')) = tag[value..].chars().next() else {
+                    continue;
+                };
+                let close = value + 1 + tag[value + 1..].find(open)?;
+                let insert = start + close;
+
+                return Some(format!("{} {}{}", &svg[..insert], class, &svg[insert..]));
+            }
+            _ => {}
+        }
+    }
+
+    let insert = start + "<svg".len();
+
+    Some(format!(
+        "{} class=\"{}\"{}",
+        &svg[..insert],
+        class,
+        &svg[insert..]
+    ))
+}
+
+/// An SVG from the static directory, minified ahead of being inlined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedSvg {
+    pub svg: Arc<str>,
+    /// The size of the file as read, in bytes.
+    pub size: usize,
+}
+
+/// Every SVG in the static directory, by its path within it, read during
+/// [`Load`](crate::app::Load) so templates inlining them don't wait on the disk.
+/// Shared with the `inline_svg` template function, which can't borrow the world.
+/// Clones share the same SVGs.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct SvgCache(Arc<Shared>);
+
+#[derive(Debug, Default)]
+struct Shared {
+    svgs: RwLock<HashMap<String, CachedSvg>>,
+    /// The SVGs inlined since last taken, so oversized ones can be reported.
+    used: Mutex<BTreeSet<String>>,
+}
+
+impl SvgCache {
+    /// Replaces the SVGs with `svgs`, for every clone.
+    pub fn replace(&mut self, svgs: HashMap<String, CachedSvg>) {
+        *self.0.svgs.write().unwrap() = svgs;
+    }
+
+    /// Whether the cache holds exactly `svgs`.
+    pub fn holds(&self, svgs: &HashMap<String, CachedSvg>) -> bool {
+        *self.0.svgs.read().unwrap() == *svgs
+    }
+
+    /// The SVG at `path` within the static directory, with `class` added to its root
+    /// element.
+    pub fn inline(&self, path: &str, class: Option<&str>) -> Result<String, SvgError> {
+        let path = path.trim_start_matches('/');
+        let svg = self
+            .0
+            .svgs
+            .read()
+            .unwrap()
+            .get(path)
+            .map(|cached| cached.svg.clone())
+            .ok_or_else(|| SvgError::Missing(path.to_string()))?;
+
+        self.0.used.lock().unwrap().insert(path.to_string());
+
+        match class {
+            Some(class) => {
+                with_class(&svg, class).ok_or_else(|| SvgError::NotSvg(path.to_string()))
+            }
+            None => Ok(svg.to_string()),
+        }
+    }
+
+    /// The SVGs inlined since this was last called, with their size as read.
+    pub fn take_used(&self) -> Vec<(String, usize)> {
+        let used = std::mem::take(&mut *self.0.used.lock().unwrap());
+        let svgs = self.0.svgs.read().unwrap();
+
+        used.into_iter()
+            .filter_map(|path| {
+                let size = svgs.get(&path)?.size;
+                Some((path, size))
+            })
+            .collect()
+    }
+}
+
+/// Why an SVG couldn't be inlined.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SvgError {
+    #[error("No SVG at `{0}` in the static directory")]
+    Missing(String),
+    #[error("`{0}` has no <svg> element to add a class to")]
+    NotSvg(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn svgs_are_minified_and_given_a_class() {
+        let svg = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" "http://www.w3.org/Graphics/SVG/1.1/DTD/svg11.dtd">
+<!-- Generator: Some Editor -->
+<svg xmlns="http://www.w3.org/2000/svg"
+     viewBox="0 0 24 24" >
+  <metadata><rdf:RDF><dc:title>Feed</dc:title></rdf:RDF></metadata>
+  <title>RSS  feed</title>
+  <path d="M4 11a9 9 0 0 1 9 9" />
+</svg>
+"#;
+
+        let minified = minify_svg(svg);
+
+        assert_eq!(
+            minified,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 24 24\">\
+             <title>RSS  feed</title><path d=\"M4 11a9 9 0 0 1 9 9\"/></svg>"
+        );
+        assert_eq!(
+            with_class(&minified, "icon \"rss\"").unwrap(),
+            "<svg class=\"icon &quot;rss&quot;\" xmlns=\"http://www.w3.org/2000/svg\" \
+             viewBox=\"0 0 24 24\"><title>RSS  feed</title><path d=\"M4 11a9 9 0 0 1 9 9\"/></svg>"
+        );
+        assert_eq!(
+            with_class("<svg data-x='a class=b' class='old'><g/></svg>", "icon").unwrap(),
+            "<svg data-x='a class=b' class='old icon'><g/></svg>"
+        );
+        assert_eq!(with_class("<p>Not an icon</p>", "icon"), None);
+    }
+}
@@ -0,0 +1,162 @@
+//! Metadata for taxonomy terms, such as the display name and description of a tag.
+//! Terms are keyed by their slug, so `Rust` and `rust` in the front matter share one
+//! entry, while the name shown can be anything, such as `Rust 🦀`.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    config::{SiteConfig, TermConfig},
+    slug::slugify,
+};
+
+/// The taxonomy made of the `tags` of each page.
+pub const TAGS: &str = "tags";
+
+/// A term of a page, as shown to templates.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Term {
+    /// The slug of the term, for use in URLs.
+    pub slug: String,
+    /// The display name of the term, or the term as written when it has none.
+    pub name: String,
+    pub description: Option<String>,
+    pub image: Option<String>,
+}
+
+/// The metadata of every described term of a taxonomy, keyed by slug.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TermMetadata(BTreeMap<String, TermConfig>);
+
+impl TermMetadata {
+    /// Merges the metadata in `data/<taxonomy>` with that under
+    /// `[taxonomies.<taxonomy>.terms]`, the configuration taking precedence for each
+    /// value it sets. Data file entries that aren't tables of metadata are skipped,
+    /// returning their keys.
+    pub fn load(
+        config: &SiteConfig,
+        data: Option<&Map<String, Value>>,
+        taxonomy: &str,
+    ) -> (Self, Vec<String>) {
+        let mut terms = BTreeMap::new();
+        let mut invalid = Vec::new();
+
+        if let Some(Value::Object(entries)) = data.and_then(|data| data.get(taxonomy)) {
+            for (slug, entry) in entries {
+                match serde_json::from_value::<TermConfig>(entry.clone()) {
+                    Ok(term) if entry.is_object() => {
+                        terms.insert(slug.clone(), term);
+                    }
+                    _ => invalid.push(slug.clone()),
+                }
+            }
+        }
+
+        if let Some(taxonomy) = config.taxonomies.get(taxonomy) {
+            for (slug, term) in taxonomy.terms.iter() {
+                let merged: &mut TermConfig = terms.entry(slug.clone()).or_default();
+
+                merged.name = term.name.clone().or(merged.name.take());
+                merged.description = term.description.clone().or(merged.description.take());
+                merged.image = term.image.clone().or(merged.image.take());
+            }
+        }
+
+        (Self(terms), invalid)
+    }
+
+    /// Resolves the terms of a page as written in its front matter.
+    pub fn resolve(&self, terms: &[String]) -> Vec<Term> {
+        terms
+            .iter()
+            .map(|term| {
+                let slug = slugify(term);
+                let metadata = self.0.get(&slug);
+
+                Term {
+                    name: metadata
+                        .and_then(|metadata| metadata.name.clone())
+                        .unwrap_or_else(|| term.clone()),
+                    description: metadata.and_then(|metadata| metadata.description.clone()),
+                    image: metadata.and_then(|metadata| metadata.image.clone()),
+                    slug,
+                }
+            })
+            .collect()
+    }
+
+    /// The slugs of described terms that none of the `used` terms, as written in the
+    /// front matter, share.
+    pub fn unused<'a>(&self, used: impl IntoIterator<Item = &'a String>) -> Vec<&str> {
+        let used: HashSet<_> = used.into_iter().map(|term| slugify(term)).collect();
+
+        self.0
+            .keys()
+            .filter(|slug| !used.contains(*slug))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn configured_terms_override_the_data_file() {
+        let config: SiteConfig = toml::from_str(
+            "[taxonomies.tags.terms.rust]\nname = \"Rust 🦀\"\n\
+             [taxonomies.tags.terms.web-dev]\ndescription = \"Building for browsers\"",
+        )
+        .unwrap();
+        let data = json!({
+            "tags": {
+                "rust": { "name": "Rust", "description": "Systems programming" },
+                "bevy": { "name": "Bevy", "image": "/images/bevy.png" },
+                "broken": "Not a table",
+            }
+        });
+        let Value::Object(data) = data else {
+            unreachable!()
+        };
+
+        let (terms, invalid) = TermMetadata::load(&config, Some(&data), TAGS);
+
+        assert_eq!(invalid, ["broken"]);
+        assert_eq!(
+            terms.resolve(&[
+                String::from("Rust"),
+                String::from("Web Dev"),
+                String::from("gamedev"),
+            ]),
+            [
+                Term {
+                    slug: String::from("rust"),
+                    name: String::from("Rust 🦀"),
+                    description: Some(String::from("Systems programming")),
+                    image: None,
+                },
+                Term {
+                    slug: String::from("web-dev"),
+                    name: String::from("Web Dev"),
+                    description: Some(String::from("Building for browsers")),
+                    image: None,
+                },
+                Term {
+                    slug: String::from("gamedev"),
+                    name: String::from("gamedev"),
+                    description: None,
+                    image: None,
+                },
+            ]
+        );
+        assert_eq!(
+            terms.unused(&[String::from("RUST"), String::from("web dev")]),
+            ["bevy"]
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! The site's timezone, set with `timezone` in the configuration, which dates and
+//! datetimes without an offset are taken to be in. Timezones are looked up in the IANA
+//! database, which is only built in with the `timezones` feature.
+
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+#[cfg(feature = "timezones")]
+use chrono::{LocalResult, Offset, TimeDelta, TimeZone};
+use serde::Deserialize;
+
+/// A timezone from the IANA database, such as `Europe/Helsinki`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Timezone(#[cfg(feature = "timezones")] chrono_tz::Tz);
+
+impl Timezone {
+    /// Whether webvy was built with the timezone database.
+    pub fn is_available() -> bool {
+        cfg!(feature = "timezones")
+    }
+
+    /// The timezone named `name`, as in the IANA database.
+    pub fn from_name(name: &str) -> Result<Self, TimezoneError> {
+        #[cfg(feature = "timezones")]
+        return name
+            .parse()
+            .map(Self)
+            .map_err(|_| TimezoneError::Unknown(name.to_string()));
+
+        #[cfg(not(feature = "timezones"))]
+        Err(TimezoneError::Unavailable(name.to_string()))
+    }
+
+    /// The instant `local` stands for in this timezone. Times skipped by a change to
+    /// daylight saving time are moved forward by the gap, and of times repeated when it
+    /// ends the earlier is taken.
+    pub fn to_utc(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        #[cfg(feature = "timezones")]
+        return match self.0.from_local_datetime(&local) {
+            LocalResult::Single(datetime) | LocalResult::Ambiguous(datetime, _) => {
+                datetime.to_utc()
+            }
+            LocalResult::None => {
+                // Offsets change at most once a day, so a day before is the offset in
+                // effect up to the gap.
+                let before = self
+                    .0
+                    .offset_from_utc_datetime(&(local - TimeDelta::days(1)))
+                    .fix();
+
+                (local - TimeDelta::seconds(before.local_minus_utc().into())).and_utc()
+            }
+        };
+
+        #[cfg(not(feature = "timezones"))]
+        local.and_utc()
+    }
+
+    /// The local time in this timezone at `utc`.
+    pub fn from_utc(&self, utc: DateTime<Utc>) -> NaiveDateTime {
+        #[cfg(feature = "timezones")]
+        return utc.with_timezone(&self.0).naive_local();
+
+        #[cfg(not(feature = "timezones"))]
+        utc.naive_utc()
+    }
+}
+
+impl TryFrom<String> for Timezone {
+    type Error = TimezoneError;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Self::from_name(&name)
+    }
+}
+
+impl fmt::Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[cfg(feature = "timezones")]
+        return f.write_str(self.0.name());
+
+        #[cfg(not(feature = "timezones"))]
+        f.write_str("UTC")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TimezoneError {
+    #[error("Unknown timezone `{0}`, expected an IANA name such as `Europe/Helsinki`")]
+    Unknown(String),
+    #[error("Unable to use timezone `{0}`, as webvy was built without timezone support")]
+    Unavailable(String),
+}
+
+/// Interprets `date` as an instant. Dates and datetimes with an offset keep it, while
+/// those without one are in `timezone`, or UTC without one. Dates are taken to be at
+/// midnight.
+pub fn parse_date(date: &str, timezone: Option<&Timezone>) -> Option<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return Some(datetime.to_utc());
+    }
+
+    let local = NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default())
+        })
+        .ok()?;
+
+    Some(match timezone {
+        Some(timezone) => timezone.to_utc(local),
+        None => local.and_utc(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_without_an_offset_are_in_the_timezone() {
+        let utc = |date: &str| DateTime::parse_from_rfc3339(date).unwrap().to_utc();
+
+        assert_eq!(
+            parse_date("2024-03-10", None),
+            Some(utc("2024-03-10T00:00:00Z"))
+        );
+        assert_eq!(
+            parse_date("2024-03-10T09:00:00+05:00", None),
+            Some(utc("2024-03-10T04:00:00Z"))
+        );
+        assert_eq!(parse_date("March 10th", None), None);
+
+        if !Timezone::is_available() {
+            assert_eq!(
+                Timezone::from_name("Europe/Helsinki"),
+                Err(TimezoneError::Unavailable(String::from("Europe/Helsinki")))
+            );
+            return;
+        }
+
+        let helsinki = Timezone::from_name("Europe/Helsinki").unwrap();
+        let date = |date: &str| parse_date(date, Some(&helsinki));
+
+        assert_eq!(helsinki.to_string(), "Europe/Helsinki");
+        assert_eq!(date("2024-03-10"), Some(utc("2024-03-09T22:00:00Z")));
+        assert_eq!(
+            date("2024-07-01 12:00:00"),
+            Some(utc("2024-07-01T09:00:00Z"))
+        );
+        // Explicit offsets win over the timezone.
+        assert_eq!(
+            date("2024-07-01T12:00:00Z"),
+            Some(utc("2024-07-01T12:00:00Z"))
+        );
+        // Clocks skip from 03:00 to 04:00 as summer time starts...
+        assert_eq!(
+            date("2024-03-31T03:30:00"),
+            Some(utc("2024-03-31T01:30:00Z"))
+        );
+        // ...and go from 04:00 back to 03:00 as it ends, taking the first 03:30.
+        assert_eq!(
+            date("2024-10-27T03:30:00"),
+            Some(utc("2024-10-27T00:30:00Z"))
+        );
+
+        // Chile starts summer time at midnight, so the day starts at 01:00.
+        let santiago = Timezone::from_name("America/Santiago").unwrap();
+        assert_eq!(
+            parse_date("2024-09-08", Some(&santiago)),
+            Some(utc("2024-09-08T04:00:00Z"))
+        );
+
+        assert_eq!(
+            Timezone::from_name("Mars/Olympus_Mons"),
+            Err(TimezoneError::Unknown(String::from("Mars/Olympus_Mons")))
+        );
+    }
+}
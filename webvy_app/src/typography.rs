@@ -0,0 +1,217 @@
+use std::collections::HashSet;
+
+use serde::Deserialize;
+
+use crate::html::{find_closing_tag, tag_end, tag_name};
+
+const NBSP: &str = "&nbsp;";
+
+/// Elements whose last line shouldn't be left with a single word.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "li",
+    "dt",
+    "dd",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "figcaption",
+];
+
+/// Typography rules for a language, found under `[markdown.rules.<language>]`. Unset
+/// values fall back to rules suited to English.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TypographyRules {
+    /// Words kept on the same line as the word following them.
+    pub short_words: Vec<String>,
+    /// Units kept on the same line as the number before them.
+    pub units: Vec<String>,
+    /// Keep em-dashes on the same line as the word before them.
+    pub dashes: bool,
+    /// Keep the last two words of a paragraph, list item or heading together.
+    pub widows: bool,
+}
+
+impl Default for TypographyRules {
+    fn default() -> Self {
+        Self {
+            short_words: [
+                "a", "an", "the", "i", "of", "to", "in", "on", "at", "by", "or",
+            ]
+            .map(String::from)
+            .to_vec(),
+            units: [
+                "%", "mm", "cm", "m", "km", "g", "kg", "ms", "s", "min", "h", "KB", "MB", "GB",
+            ]
+            .map(String::from)
+            .to_vec(),
+            dashes: true,
+            widows: true,
+        }
+    }
+}
+
+/// Inserts non-breaking spaces into the text of rendered HTML, following a set of
+/// [`TypographyRules`]. Markup, comments and the contents of `<code>`, `<pre>`,
+/// `<script>` and `<style>` are left untouched.
+#[derive(Debug, Clone)]
+pub struct Typographer {
+    short_words: HashSet<String>,
+    units: HashSet<String>,
+    dashes: bool,
+    widows: bool,
+}
+
+impl Typographer {
+    pub fn new(rules: &TypographyRules) -> Self {
+        Self {
+            short_words: rules
+                .short_words
+                .iter()
+                .map(|word| word.to_lowercase())
+                .collect(),
+            units: rules.units.iter().cloned().collect(),
+            dashes: rules.dashes,
+            widows: rules.widows,
+        }
+    }
+
+    pub fn apply(&self, html: &str) -> String {
+        let mut output = String::with_capacity(html.len() + html.len() / 16);
+        let mut rest = html;
+        // Nesting depth of elements whose text is left as is
+        let mut verbatim = 0usize;
+        // Where the last breakable space of the current block was written
+        let mut widow = None;
+
+        while !rest.is_empty() {
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                let end = comment.find("-->").map_or(rest.len(), |end| end + 7);
+
+                output.push_str(&rest[..end]);
+                rest = &rest[end..];
+            } else if rest.starts_with('<') {
+                let end = tag_end(rest);
+                let (name, closing) = tag_name(&rest[..end]);
+                let name = name.to_ascii_lowercase();
+
+                output.push_str(&rest[..end]);
+                rest = &rest[end..];
+
+                match name.as_str() {
+                    "script" | "style" if !closing => {
+                        let end = find_closing_tag(rest, &name);
+
+                        output.push_str(&rest[..end]);
+                        rest = &rest[end..];
+                    }
+                    "code" | "pre" if closing => verbatim = verbatim.saturating_sub(1),
+                    "code" | "pre" => verbatim += 1,
+                    name if BLOCK_ELEMENTS.contains(&name) => match widow.take() {
+                        Some(index) if closing && self.widows => {
+                            output.replace_range(index..index + 1, NBSP);
+                        }
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+
+                if verbatim > 0 {
+                    output.push_str(&rest[..end]);
+                } else {
+                    self.apply_text(&rest[..end], &mut output, &mut widow);
+                }
+
+                rest = &rest[end..];
+            }
+        }
+
+        output
+    }
+
+    fn apply_text(&self, text: &str, output: &mut String, widow: &mut Option<usize>) {
+        let mut word_start = 0;
+
+        for (index, c) in text.char_indices() {
+            if c != ' ' && c != '\n' {
+                continue;
+            }
+
+            let before = &text[word_start..index];
+            let after = text[index + 1..].split([' ', '\n']).next().unwrap_or("");
+
+            output.push_str(before);
+
+            if self.keeps_together(before, after) {
+                output.push_str(NBSP);
+            } else {
+                if !after.is_empty() {
+                    *widow = Some(output.len());
+                }
+
+                output.push(c);
+            }
+
+            word_start = index + 1;
+        }
+
+        output.push_str(&text[word_start..]);
+    }
+
+    fn keeps_together(&self, before: &str, after: &str) -> bool {
+        let word = before.trim_start_matches(|c: char| !c.is_alphanumeric());
+
+        (!word.is_empty() && self.short_words.contains(&word.to_lowercase()))
+            || (before.ends_with(|c: char| c.is_ascii_digit())
+                && self
+                    .units
+                    .contains(after.trim_end_matches(['.', ',', ';', ':', '!', '?', ')'])))
+            || (self.dashes && after.starts_with('—'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(html: &str) -> String {
+        Typographer::new(&TypographyRules::default()).apply(html)
+    }
+
+    #[test]
+    fn short_words_units_and_dashes_are_kept_together() {
+        assert_eq!(
+            apply("<p>Walked 5 km to a shop — twice, not once</p>"),
+            "<p>Walked 5&nbsp;km to&nbsp;a&nbsp;shop&nbsp;— twice, not&nbsp;once</p>"
+        );
+    }
+
+    #[test]
+    fn markup_and_code_are_left_untouched() {
+        let html = "<p title=\"a b > c\"><code>a b</code></p>\n\
+                    <pre><code class=\"rust\">let a = 1;\n</code></pre>\n\
+                    <!-- a comment -->\n<script>if (a < b) { a b }</script>";
+
+        assert_eq!(apply(html), html);
+    }
+
+    #[test]
+    fn widows_only_join_the_last_words_of_a_block() {
+        let rules = TypographyRules {
+            short_words: Vec::new(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Typographer::new(&rules).apply("<h1>One two three</h1>\n<p>Four <em>five</em> six</p>"),
+            "<h1>One two&nbsp;three</h1>\n<p>Four <em>five</em>&nbsp;six</p>"
+        );
+    }
+}
@@ -0,0 +1,677 @@
+//! A forgiving check of rendered HTML for mistakes browsers silently paper over, and
+//! for accessibility problems. Both are found in a single scan of each page.
+
+use std::fmt;
+
+use crate::{config::A11yConfig, html::source_lines, report::Severity};
+
+/// A problem found in a page, along with the line it's on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub line: usize,
+    /// The line of the page's source file the block it's in starts on, when the page
+    /// marks them with [`source_line_comment`](crate::html::source_line_comment).
+    pub source_line: Option<usize>,
+    pub message: String,
+    /// The diagnostic code it's reported under, such as `invalid-html`.
+    pub code: &'static str,
+    pub severity: Severity,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.source_line {
+            Some(line) => write!(f, "Source line {}: {}", line, self.message),
+            None => write!(f, "Line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+/// Whether support for validating HTML was compiled in.
+pub fn is_available() -> bool {
+    cfg!(feature = "validate")
+}
+
+/// Which checks a scan runs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Checks<'a> {
+    /// Unclosed tags, duplicate ids, images without alt text and block elements inside
+    /// paragraphs.
+    pub html: bool,
+    /// The accessibility lints, each at its configured level.
+    pub a11y: Option<&'a A11yConfig>,
+}
+
+/// Scans `html` for unclosed tags, duplicate ids, images without alt text and block
+/// elements inside paragraphs, in a single pass without building a tree.
+pub fn validate_html(html: &str) -> Vec<Finding> {
+    check_html(
+        html,
+        Checks {
+            html: true,
+            a11y: None,
+        },
+    )
+}
+
+/// Runs `checks` over `html` in a single pass, sorted by line.
+#[cfg(feature = "validate")]
+pub fn check_html(html: &str, checks: Checks) -> Vec<Finding> {
+    let mut findings = scanner::scan(html, checks);
+
+    add_source_lines(html, &mut findings);
+
+    findings
+}
+
+/// Points each finding at the line of the last
+/// [`source_line_comment`](crate::html::source_line_comment) on or before its own.
+#[cfg_attr(not(feature = "validate"), allow(dead_code))]
+fn add_source_lines(html: &str, findings: &mut [Finding]) {
+    let mut line = 1;
+    let mut counted = 0;
+    let marks: Vec<_> = source_lines(html)
+        .map(|(offset, source_line)| {
+            line += html[counted..offset].matches('\n').count();
+            counted = offset;
+
+            (line, source_line)
+        })
+        .collect();
+
+    if marks.is_empty() {
+        return;
+    }
+
+    for finding in findings {
+        finding.source_line = marks
+            .iter()
+            .rev()
+            .find(|(line, _)| *line <= finding.line)
+            .and_then(|(_, source_line)| *source_line);
+    }
+}
+
+#[cfg(not(feature = "validate"))]
+pub fn check_html(_html: &str, _checks: Checks) -> Vec<Finding> {
+    Vec::new()
+}
+
+#[cfg(feature = "validate")]
+mod scanner {
+    use std::collections::{HashMap, HashSet};
+
+    use crate::{
+        config::{A11yConfig, RuleLevel},
+        html::{find_closing_tag, tag_end, tag_name, truncate_words, VOID_ELEMENTS},
+        report::Severity,
+    };
+
+    use super::{Checks, Finding};
+
+    /// Link text saying nothing about where the link goes.
+    const VAGUE_LINK_TEXT: &[&str] = &["here", "click here", "click", "this", "link"];
+
+    /// Input types that are buttons or never shown, so don't need a label.
+    const UNLABELLED_INPUT_TYPES: &[&str] = &["hidden", "submit", "button", "reset", "image"];
+
+    /// Elements whose closing tag can be left out.
+    const OPTIONAL_END: &[&str] = &[
+        "body", "caption", "colgroup", "dd", "dt", "head", "html", "li", "optgroup", "option", "p",
+        "rp", "rt", "tbody", "td", "tfoot", "th", "thead", "tr",
+    ];
+
+    /// Elements that end an open paragraph, so can't be nested within one.
+    const BLOCK_ELEMENTS: &[&str] = &[
+        "address",
+        "article",
+        "aside",
+        "blockquote",
+        "details",
+        "div",
+        "dl",
+        "fieldset",
+        "figcaption",
+        "figure",
+        "footer",
+        "form",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "header",
+        "hr",
+        "main",
+        "nav",
+        "ol",
+        "p",
+        "pre",
+        "section",
+        "table",
+        "ul",
+    ];
+
+    /// Elements whose content is text, even when it looks like markup.
+    const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style", "textarea", "title"];
+
+    pub fn scan(html: &str, checks: Checks) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        // Open elements and where their tags start.
+        let mut open: Vec<(String, usize)> = Vec::new();
+        let mut ids: HashMap<&str, usize> = HashMap::new();
+        let mut a11y = A11yScan::default();
+        let mut index = 0;
+
+        while let Some(start) = html[index..].find('<') {
+            let at = index + start;
+            let rest = &html[at..];
+
+            if rest.starts_with("<!--") {
+                index = at + rest.find("-->").map_or(rest.len(), |end| end + 3);
+                continue;
+            }
+
+            let end = tag_end(rest);
+            let tag = &rest[..end];
+            let (name, closing) = tag_name(tag);
+
+            // Doctypes and processing instructions, or a stray `<` in text.
+            if name.is_empty() {
+                index = at
+                    + if rest.starts_with("<!") || rest.starts_with("<?") {
+                        end
+                    } else {
+                        1
+                    };
+                continue;
+            }
+
+            let name = name.to_ascii_lowercase();
+            index = at + end;
+
+            if closing {
+                match open.iter().rposition(|(open, _)| *open == name) {
+                    Some(position) => {
+                        for (unclosed, offset) in open.drain(position..).skip(1) {
+                            if checks.html {
+                                report_unclosed(html, &unclosed, offset, &mut findings);
+                            }
+                        }
+                    }
+                    None if checks.html => findings.push(finding(
+                        html,
+                        at,
+                        format!("</{}> closes an element that isn't open", name),
+                    )),
+                    None => {}
+                }
+
+                continue;
+            }
+
+            let attributes = attributes(tag);
+
+            if let Some(rules) = checks.a11y {
+                let element = Element {
+                    name: &name,
+                    at,
+                    content: index,
+                    attributes: &attributes,
+                };
+
+                a11y.element(
+                    html,
+                    &element,
+                    &open,
+                    rules,
+                    |offset, level, code, message| {
+                        lint(&mut findings, html, offset, level, code, message)
+                    },
+                );
+            }
+
+            if let Some(id) = attributes
+                .iter()
+                .filter(|_| checks.html)
+                .find(|(attribute, _)| attribute.eq_ignore_ascii_case("id"))
+                .and_then(|(_, value)| *value)
+            {
+                match ids.get(id) {
+                    Some(&first) => findings.push(finding(
+                        html,
+                        at,
+                        format!(
+                            "The id \"{}\" is already used on line {}",
+                            id,
+                            line_at(html, first)
+                        ),
+                    )),
+                    None => {
+                        ids.insert(id, at);
+                    }
+                }
+            }
+
+            if checks.html
+                && name == "img"
+                && !attributes
+                    .iter()
+                    .any(|(attribute, _)| attribute.eq_ignore_ascii_case("alt"))
+            {
+                findings.push(finding(
+                    html,
+                    at,
+                    String::from("<img> has no alt attribute"),
+                ));
+            }
+
+            if checks.html
+                && BLOCK_ELEMENTS.contains(&name.as_str())
+                && open.iter().any(|(open, _)| open == "p")
+            {
+                findings.push(finding(
+                    html,
+                    at,
+                    format!("<{}> isn't allowed inside <p>", name),
+                ));
+            }
+
+            if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                index += find_closing_tag(&html[index..], &name);
+            }
+
+            if !VOID_ELEMENTS.contains(&name.as_str()) && !tag.ends_with("/>") {
+                open.push((name, at));
+            }
+        }
+
+        if checks.html {
+            for (unclosed, offset) in open {
+                report_unclosed(html, &unclosed, offset, &mut findings);
+            }
+        }
+
+        if let Some(rules) = checks.a11y {
+            for (offset, name) in a11y.unlabelled() {
+                lint(
+                    &mut findings,
+                    html,
+                    offset,
+                    rules.unlabelled_inputs,
+                    "a11y-unlabelled-input",
+                    format!("<{}> has no label", name),
+                );
+            }
+        }
+
+        findings.sort_by_key(|finding| finding.line);
+        findings
+    }
+
+    /// What the accessibility lints need to know about the elements seen so far.
+    #[derive(Debug, Default)]
+    struct A11yScan<'a> {
+        /// The level of the last heading.
+        heading: Option<u8>,
+        /// Where the first `<h1>` starts.
+        h1: Option<usize>,
+        /// The ids `<label for="...">` points to.
+        labels: HashSet<&'a str>,
+        /// Form fields without a label of their own, by id, waiting to find out whether
+        /// a later `<label>` points to them.
+        fields: Vec<(Option<&'a str>, usize, String)>,
+    }
+
+    /// An element's start tag, as the accessibility lints see it.
+    struct Element<'e, 'a> {
+        name: &'e str,
+        /// Where its tag starts.
+        at: usize,
+        /// Where its content starts, after its tag.
+        content: usize,
+        attributes: &'e [(&'a str, Option<&'a str>)],
+    }
+
+    impl<'a> A11yScan<'a> {
+        /// Lints `element`, within the `open` elements.
+        fn element(
+            &mut self,
+            html: &str,
+            element: &Element<'_, 'a>,
+            open: &[(String, usize)],
+            rules: &A11yConfig,
+            mut lint: impl FnMut(usize, RuleLevel, &'static str, String),
+        ) {
+            let Element {
+                name,
+                at,
+                content,
+                attributes,
+            } = *element;
+            let attribute = |wanted: &str| {
+                attributes
+                    .iter()
+                    .find(|(attribute, _)| attribute.eq_ignore_ascii_case(wanted))
+            };
+
+            match name {
+                "img" if attribute("alt").is_none() => lint(
+                    at,
+                    rules.missing_alt,
+                    "a11y-missing-alt",
+                    String::from("<img> has no alt text"),
+                ),
+                "a" if attribute("aria-label").is_none() => {
+                    let inner = &html[content..content + find_closing_tag(&html[content..], "a")];
+                    let text = truncate_words(inner, usize::MAX, true).to_lowercase();
+                    let text = text.trim().trim_end_matches(['.', '!']);
+
+                    if VAGUE_LINK_TEXT.contains(&text) {
+                        lint(
+                            at,
+                            rules.vague_links,
+                            "a11y-vague-link",
+                            format!("The link text \"{}\" doesn't say where it goes", text),
+                        );
+                    }
+                }
+                "label" => {
+                    if let Some((_, Some(id))) = attribute("for") {
+                        self.labels.insert(id);
+                    }
+                }
+                "input" | "select" | "textarea" => {
+                    let kind = attribute("type").and_then(|(_, kind)| *kind);
+                    let exempt = name == "input"
+                        && kind.is_some_and(|kind| {
+                            UNLABELLED_INPUT_TYPES
+                                .iter()
+                                .any(|exempt| kind.eq_ignore_ascii_case(exempt))
+                        });
+                    let labelled = ["aria-label", "aria-labelledby", "title"]
+                        .iter()
+                        .any(|wanted| attribute(wanted).is_some())
+                        || open.iter().any(|(open, _)| open == "label");
+
+                    if !exempt && !labelled {
+                        let id = attribute("id").and_then(|(_, id)| *id);
+                        self.fields.push((id, at, name.to_string()));
+                    }
+                }
+                _ => {}
+            }
+
+            let level = match name.as_bytes() {
+                [b'h', level @ b'1'..=b'6'] => level - b'0',
+                _ => return,
+            };
+
+            if let Some(previous) = self.heading.filter(|previous| level > previous + 1) {
+                lint(
+                    at,
+                    rules.heading_jumps,
+                    "a11y-heading-jump",
+                    format!("<h{}> follows <h{}>, skipping a level", level, previous),
+                );
+            }
+            self.heading = Some(level);
+
+            if level == 1 {
+                match self.h1 {
+                    Some(first) => lint(
+                        at,
+                        rules.multiple_h1,
+                        "a11y-multiple-h1",
+                        format!(
+                            "A second <h1>, after the one on line {}",
+                            line_at(html, first)
+                        ),
+                    ),
+                    None => self.h1 = Some(at),
+                }
+            }
+        }
+
+        /// The fields no `<label>` pointed to, with where they start.
+        fn unlabelled(self) -> impl Iterator<Item = (usize, String)> + 'a {
+            let labels = self.labels;
+
+            self.fields
+                .into_iter()
+                .filter(move |(id, ..)| !id.is_some_and(|id| labels.contains(id)))
+                .map(|(_, at, name)| (at, name))
+        }
+    }
+
+    fn lint(
+        findings: &mut Vec<Finding>,
+        html: &str,
+        offset: usize,
+        level: RuleLevel,
+        code: &'static str,
+        message: String,
+    ) {
+        if let Some(severity) = level.severity() {
+            findings.push(Finding {
+                line: line_at(html, offset),
+                source_line: None,
+                message,
+                code,
+                severity,
+            });
+        }
+    }
+
+    fn report_unclosed(html: &str, name: &str, offset: usize, findings: &mut Vec<Finding>) {
+        if !OPTIONAL_END.contains(&name) {
+            findings.push(finding(html, offset, format!("<{}> is never closed", name)));
+        }
+    }
+
+    fn finding(html: &str, offset: usize, message: String) -> Finding {
+        Finding {
+            line: line_at(html, offset),
+            source_line: None,
+            message,
+            code: "invalid-html",
+            severity: Severity::Warning,
+        }
+    }
+
+    /// The line `offset` is on, counting from one.
+    fn line_at(html: &str, offset: usize) -> usize {
+        html[..offset].matches('\n').count() + 1
+    }
+
+    /// The attributes of a tag along with their values, if they have one.
+    fn attributes(tag: &str) -> Vec<(&str, Option<&str>)> {
+        let tag = tag.trim_start_matches('<').trim_end_matches('>');
+        let mut rest = tag
+            .find(|c: char| c.is_whitespace())
+            .map_or("", |end| &tag[end..]);
+        let mut attributes = Vec::new();
+
+        loop {
+            rest = rest.trim_start();
+
+            let Some(first) = rest.chars().next() else {
+                break;
+            };
+
+            if matches!(first, '=' | '/') {
+                rest = &rest[1..];
+                continue;
+            }
+
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+                .unwrap_or(rest.len());
+            let name = &rest[..end];
+            rest = rest[end..].trim_start();
+
+            let value = match rest.strip_prefix('=').map(str::trim_start) {
+                Some(value) => match value.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let close = value[1..].find(quote).map_or(value.len(), |end| end + 1);
+
+                        rest = value.get(close + 1..).unwrap_or("");
+                        Some(&value[1..close])
+                    }
+                    _ => {
+                        let end = value.find(char::is_whitespace).unwrap_or(value.len());
+
+                        rest = &value[end..];
+                        Some(&value[..end])
+                    }
+                },
+                None => None,
+            };
+
+            attributes.push((name, value));
+        }
+
+        attributes
+    }
+}
+
+#[cfg(all(test, feature = "validate"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn findings_within_marked_blocks_name_their_source_line() {
+        let html = "<h1>Title</h1>\n\
+                    <!-- srcline: 5 -->\n\
+                    <p>Text\n<div>Block</div></p>\n\
+                    <!-- srcline: end -->\n\
+                    <footer><span></footer>";
+
+        let findings: Vec<_> = validate_html(html)
+            .into_iter()
+            .map(|finding| finding.to_string())
+            .collect();
+
+        assert_eq!(
+            findings,
+            [
+                "Source line 5: <div> isn't allowed inside <p>",
+                "Line 6: <span> is never closed"
+            ]
+        );
+    }
+
+    #[test]
+    fn mistakes_are_found_with_their_line() {
+        let html = "<h2 id=\"intro\">Intro</h2>\n\
+                    <p>Text <img src=\"a.png\">\n\
+                    <div>Block</div></p>\n\
+                    <h2 id=\"intro\">Again</h2>\n\
+                    <ul><li>One<li>Two</ul>\n\
+                    <section><em>Open</section>\n\
+                    <img src=\"b.png\" alt=\"\"><br/>\n\
+                    <script>if (a < b) { document.write(\"<p>\") }</script>\n\
+                    </span><!-- <div> -->\n\
+                    <article>";
+
+        let findings: Vec<_> = validate_html(html)
+            .into_iter()
+            .map(|finding| finding.to_string())
+            .collect();
+
+        assert_eq!(
+            findings,
+            [
+                "Line 2: <img> has no alt attribute",
+                "Line 3: <div> isn't allowed inside <p>",
+                "Line 4: The id \"intro\" is already used on line 1",
+                "Line 6: <em> is never closed",
+                "Line 9: </span> closes an element that isn't open",
+                "Line 10: <article> is never closed",
+            ]
+        );
+    }
+
+    #[test]
+    fn well_formed_pages_have_no_findings() {
+        let html = "<!DOCTYPE html>\n<html><head><title>A <b> title</title></head>\n\
+                    <body><p>Hello <a href=\"/x?a=1&b=2\" title='>'>there</a></p>\n\
+                    <img alt src=x></body></html>";
+
+        assert_eq!(validate_html(html), Vec::new());
+    }
+
+    #[test]
+    fn accessibility_rules_report_at_their_level() {
+        let html = "<h1>Title</h1>\n\
+                    <h2>Intro</h2>\n\
+                    <p>Read more <a href=\"/a\">Click <b>here</b>!</a>, \
+                    <a href=\"/b\" aria-label=\"Guide\">here</a> or <a href=\"/c\">the guide</a></p>\n\
+                    <h4>Details</h4>\n\
+                    <img src=\"a.png\"><img src=\"b.png\" alt=\"\">\n\
+                    <h1>Another</h1>\n\
+                    <form><input id=\"name\"><label for=\"name\">Name</label>\n\
+                    <label>Email <input type=\"email\"></label><input type=\"submit\">\n\
+                    <textarea></textarea><input aria-label=\"Search\"></form>";
+        let rules: A11yConfig =
+            toml::from_str("enabled = true\nmissing_alt = \"error\"\nmultiple_h1 = \"off\"")
+                .unwrap();
+
+        let findings: Vec<_> = check_html(
+            html,
+            Checks {
+                html: false,
+                a11y: Some(&rules),
+            },
+        )
+        .into_iter()
+        .map(|finding| (finding.code, finding.severity, finding.to_string()))
+        .collect();
+
+        assert_eq!(
+            findings,
+            [
+                (
+                    "a11y-vague-link",
+                    Severity::Warning,
+                    String::from("Line 3: The link text \"click here\" doesn't say where it goes")
+                ),
+                (
+                    "a11y-heading-jump",
+                    Severity::Warning,
+                    String::from("Line 4: <h4> follows <h2>, skipping a level")
+                ),
+                (
+                    "a11y-missing-alt",
+                    Severity::Error,
+                    String::from("Line 5: <img> has no alt text")
+                ),
+                (
+                    "a11y-unlabelled-input",
+                    Severity::Warning,
+                    String::from("Line 9: <textarea> has no label")
+                ),
+            ]
+        );
+
+        let rules = A11yConfig {
+            enabled: true,
+            ..A11yConfig::default()
+        };
+        let both = check_html(
+            html,
+            Checks {
+                html: true,
+                a11y: Some(&rules),
+            },
+        );
+
+        assert!(
+            both.iter()
+                .any(|finding| finding.to_string()
+                    == "Line 6: A second <h1>, after the one on line 1")
+        );
+        assert!(both.iter().any(|finding| finding.code == "invalid-html"));
+    }
+}
@@ -0,0 +1,93 @@
+//! Converts TOML values, as found in front matter, configuration and data files, into
+//! the JSON values templates and JSON outputs see.
+
+use serde_json::{Map, Number, Value};
+
+/// Converts `value` into the JSON value of the same type. Integers stay integers and
+/// tables become objects, while datetimes become their RFC 3339 string, such as
+/// `1979-05-27T07:32:00Z` or just `1979-05-27` for a date. Floats that JSON can't
+/// represent, infinities and NaN, become `null`.
+pub fn toml_to_json(value: &toml::Value) -> Value {
+    match value {
+        toml::Value::String(string) => Value::String(string.clone()),
+        toml::Value::Integer(integer) => Value::Number((*integer).into()),
+        toml::Value::Float(float) => Number::from_f64(*float).map_or(Value::Null, Value::Number),
+        toml::Value::Boolean(boolean) => Value::Bool(*boolean),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(array) => Value::Array(array.iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => table_to_json(table),
+    }
+}
+
+/// Converts every value of `table`, keeping its keys, into a JSON object.
+pub fn table_to_json(table: &toml::Table) -> Value {
+    Value::Object(
+        table
+            .iter()
+            .map(|(key, value)| (key.clone(), toml_to_json(value)))
+            .collect::<Map<_, _>>(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn every_toml_type_keeps_its_json_type() {
+        let cases = [
+            ("\"text\"", json!("text")),
+            ("42", json!(42)),
+            ("-7", json!(-7)),
+            ("9223372036854775807", json!(i64::MAX)),
+            ("-9223372036854775808", json!(i64::MIN)),
+            ("1.5", json!(1.5)),
+            ("-0.0", json!(-0.0)),
+            ("inf", Value::Null),
+            ("nan", Value::Null),
+            ("true", json!(true)),
+            ("false", json!(false)),
+            ("1979-05-27T07:32:00Z", json!("1979-05-27T07:32:00Z")),
+            (
+                "1979-05-27T00:32:00-07:00",
+                json!("1979-05-27T00:32:00-07:00"),
+            ),
+            ("1979-05-27 07:32:00.5", json!("1979-05-27T07:32:00.5")),
+            ("1979-05-27", json!("1979-05-27")),
+            ("07:32:00", json!("07:32:00")),
+            ("[1, \"two\", 3.0, false]", json!([1, "two", 3.0, false])),
+            ("[[1, 2], []]", json!([[1, 2], []])),
+            (
+                "{ featured = true, rating = { stars = 4 } }",
+                json!({ "featured": true, "rating": { "stars": 4 } }),
+            ),
+            ("{}", json!({})),
+        ];
+
+        for (toml, expected) in cases {
+            let table: toml::Table = toml::from_str(&format!("value = {}", toml)).unwrap();
+            let value = toml_to_json(&table["value"]);
+
+            assert_eq!(value, expected, "{}", toml);
+
+            // Integers must stay integers, rather than becoming floats.
+            if let toml::Value::Integer(integer) = table["value"] {
+                assert_eq!(value.as_i64(), Some(integer), "{}", toml);
+            }
+        }
+    }
+
+    #[test]
+    fn tables_become_objects_with_the_same_keys() {
+        let table: toml::Table =
+            toml::from_str("title = \"Post\"\n[extra]\nfeatured = true\ndate = 2024-05-01")
+                .unwrap();
+
+        assert_eq!(
+            table_to_json(&table),
+            json!({ "title": "Post", "extra": { "featured": true, "date": "2024-05-01" } })
+        );
+    }
+}
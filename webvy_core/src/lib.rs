@@ -1,5 +1,9 @@
 use bevy_ecs::system::EntityCommands;
 
+mod site_url;
+
+pub use site_url::{SiteUrl, SiteUrlError};
+
 pub trait Extractor {
     fn extract(&self, commands: &mut EntityCommands);
 }
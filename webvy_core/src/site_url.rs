@@ -0,0 +1,213 @@
+use std::fmt;
+
+use serde::{Deserialize, Deserializer};
+use thiserror::Error;
+use url::Url;
+
+/// The public address of the site, which every URL written into pages and feeds is
+/// built from. Sites without one get root relative URLs, starting with `/`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SiteUrl {
+    /// Scheme, host and port, e.g. `https://example.com`. Empty for root relative URLs.
+    origin: String,
+    /// The path the site lives under, e.g. `/blog`, without a trailing slash.
+    prefix: String,
+    host: Option<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum SiteUrlError {
+    #[error("The base URL \"{url}\" needs a scheme, e.g. https://{url}")]
+    MissingScheme { url: String },
+    #[error("The base URL \"{url}\" has no host")]
+    MissingHost { url: String },
+    #[error("The base URL \"{url}\" is invalid: {source}")]
+    Invalid {
+        url: String,
+        source: url::ParseError,
+    },
+}
+
+impl SiteUrl {
+    /// Parses an absolute base URL, which must have a scheme and host and may have a
+    /// path the site is served under.
+    pub fn parse(url: &str) -> Result<Self, SiteUrlError> {
+        let parsed = Url::parse(url).map_err(|source| match source {
+            url::ParseError::RelativeUrlWithoutBase => SiteUrlError::MissingScheme {
+                url: url.to_string(),
+            },
+            source => SiteUrlError::Invalid {
+                url: url.to_string(),
+                source,
+            },
+        })?;
+
+        if parsed.cannot_be_a_base() || parsed.host_str().is_none() {
+            return Err(SiteUrlError::MissingHost {
+                url: url.to_string(),
+            });
+        }
+
+        Ok(Self {
+            origin: parsed.origin().ascii_serialization(),
+            prefix: parsed.path().trim_end_matches('/').to_string(),
+            host: parsed.host_str().map(str::to_string),
+        })
+    }
+
+    /// The host of the site, if it has an absolute base URL.
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    /// Joins a site relative path onto the base URL, keeping the path the site is
+    /// served under. Repeated slashes are collapsed, a trailing slash is kept, and
+    /// anything not allowed in a path is percent-encoded.
+    pub fn join_path(&self, rel: &str) -> String {
+        let mut joined = self.origin.clone();
+
+        for segment in self
+            .prefix
+            .split('/')
+            .chain(rel.split('/'))
+            .filter(|segment| !segment.is_empty())
+        {
+            joined.push('/');
+            encode_segment(segment, &mut joined);
+        }
+
+        if rel.is_empty() || rel.ends_with('/') || joined == self.origin {
+            joined.push('/');
+        }
+
+        joined
+    }
+}
+
+impl fmt::Display for SiteUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.join_path(""))
+    }
+}
+
+impl<'de> Deserialize<'de> for SiteUrl {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let url = String::deserialize(deserializer)?;
+
+        Self::parse(&url).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Percent-encodes everything but the characters RFC 3986 allows in a path segment,
+/// leaving existing escapes as they are.
+fn encode_segment(segment: &str, out: &mut String) {
+    let bytes = segment.as_bytes();
+
+    for (i, &byte) in bytes.iter().enumerate() {
+        let is_escape = byte == b'%'
+            && bytes.len() > i + 2
+            && bytes[i + 1].is_ascii_hexdigit()
+            && bytes[i + 2].is_ascii_hexdigit();
+
+        if is_escape || byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(&byte) {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_join_onto_the_base_and_its_subpath() {
+        let site = SiteUrl::parse("https://example.com").unwrap();
+        let blog = SiteUrl::parse("https://example.com/blog/").unwrap();
+
+        assert_eq!(
+            site.join_path("posts/a.html"),
+            "https://example.com/posts/a.html"
+        );
+        assert_eq!(
+            blog.join_path("posts/a.html"),
+            "https://example.com/blog/posts/a.html"
+        );
+        assert_eq!(blog.join_path(""), "https://example.com/blog/");
+        assert_eq!(blog.to_string(), "https://example.com/blog/");
+        assert_eq!(blog.host(), Some("example.com"));
+    }
+
+    #[test]
+    fn slashes_are_normalized() {
+        for base in [
+            "https://example.com/blog",
+            "https://example.com/blog/",
+            "https://example.com//blog//",
+        ] {
+            let url = SiteUrl::parse(base).unwrap();
+
+            for (rel, expected) in [
+                ("posts/a.html", "https://example.com/blog/posts/a.html"),
+                ("/posts/a.html", "https://example.com/blog/posts/a.html"),
+                ("posts//a.html", "https://example.com/blog/posts/a.html"),
+                ("posts/", "https://example.com/blog/posts/"),
+                ("/posts//", "https://example.com/blog/posts/"),
+                ("/", "https://example.com/blog/"),
+            ] {
+                assert_eq!(url.join_path(rel), expected, "{base} + {rel}");
+            }
+        }
+    }
+
+    #[test]
+    fn segments_are_percent_encoded() {
+        let url = SiteUrl::parse("https://example.com/blåg").unwrap();
+
+        assert_eq!(
+            url.join_path("posts/café au lait.html"),
+            "https://example.com/bl%C3%A5g/posts/caf%C3%A9%20au%20lait.html"
+        );
+        assert_eq!(
+            url.join_path("日本/100%25?#.html"),
+            "https://example.com/bl%C3%A5g/%E6%97%A5%E6%9C%AC/100%25%3F%23.html"
+        );
+    }
+
+    #[test]
+    fn sites_without_a_base_get_root_relative_urls() {
+        let url = SiteUrl::default();
+
+        assert_eq!(url.join_path("posts/a.html"), "/posts/a.html");
+        assert_eq!(url.join_path("posts/"), "/posts/");
+        assert_eq!(url.to_string(), "/");
+        assert_eq!(url.host(), None);
+    }
+
+    #[test]
+    fn bases_without_a_scheme_or_host_are_rejected() {
+        assert!(matches!(
+            SiteUrl::parse("example.com/blog"),
+            Err(SiteUrlError::MissingScheme { .. })
+        ));
+        assert!(matches!(
+            SiteUrl::parse("mailto:me@example.com"),
+            Err(SiteUrlError::MissingHost { .. })
+        ));
+        assert!(matches!(
+            SiteUrl::parse("https://exa mple.com"),
+            Err(SiteUrlError::Invalid { .. })
+        ));
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[allow(dead_code)]
+            base_url: SiteUrl,
+        }
+
+        let error = toml::from_str::<Config>("base_url = \"/blog\"").unwrap_err();
+
+        assert!(error.message().contains("needs a scheme"), "{error}");
+    }
+}
@@ -1,3 +1,4 @@
+use thiserror::Error;
 use toml::{Table, Value};
 
 #[derive(Debug)]
@@ -19,43 +20,119 @@ impl Parser {
         self
     }
 
-    pub fn parse(&self, page: &str) -> Option<ParsedData> {
-        page.strip_prefix(self.delimiter.as_str())
-            .map(|matter| matter.split_terminator(self.delimiter.as_str()))
-            .map(|mut split_text| {
-                let matter = split_text
-                    .next()
-                    .and_then(|matter| toml::from_str(matter).ok());
-
-                let content = split_text.next();
-
-                let (excerpt, content) = content
-                    .zip(self.excerpt.as_ref())
-                    .and_then(|(text, delimiter)| text.split_once(delimiter))
-                    .map_or_else(
-                        || {
-                            (
-                                None,
-                                content.map_or_else(
-                                    || page.trim().to_string(),
-                                    |content| content.trim().to_string(),
-                                ),
-                            )
-                        },
-                        |(excerpt, content)| {
-                            (Some(excerpt.trim().to_string()), content.trim().to_string())
-                        },
-                    );
-
-                ParsedData {
-                    matter,
-                    excerpt,
-                    content,
-                }
+    /// Splits `page` into its front matter, excerpt and content. Front matter with
+    /// nothing between the delimiters is valid, and parses into an empty table.
+    ///
+    /// The excerpt is everything before the first excerpt marker outside of a fenced
+    /// code block, so a marker at the start of the body gives an empty excerpt, and
+    /// any later markers are left in the content. The excerpt and content are both
+    /// trimmed of surrounding whitespace, but nothing within them is touched.
+    ///
+    /// Invalid front matter, such as one with a key given twice, is reported along with
+    /// the line of the page it's on.
+    pub fn parse(&self, page: &str) -> Result<ParsedData, ParseError> {
+        let (matter, content) = self.split(page)?;
+
+        let matter = toml::from_str(matter).map_err(|source: toml::de::Error| {
+            // The front matter starts right after the opening delimiter.
+            let at = self.delimiter.len() + source.span().map_or(0, |span| span.start);
+
+            ParseError::InvalidMatter {
+                line: page[..at].matches('\n').count() + 1,
+                source,
+            }
+        })?;
+
+        let (excerpt, content) = match self
+            .excerpt
+            .as_ref()
+            .and_then(|marker| Some((find_marker(content, marker)?, marker.len())))
+        {
+            Some((at, len)) => (
+                Some(content[..at].trim().to_string()),
+                content[at + len..].trim(),
+            ),
+            None => (None, content.trim()),
+        };
+
+        Ok(ParsedData {
+            matter: Some(matter),
+            excerpt,
+            content: content.to_string(),
+        })
+    }
+
+    /// Splits `page` into its front matter, exactly as written between the delimiters,
+    /// and everything after it, without parsing either.
+    pub fn split<'a>(&self, page: &'a str) -> Result<(&'a str, &'a str), ParseError> {
+        let rest = page
+            .strip_prefix(self.delimiter.as_str())
+            .ok_or(ParseError::MissingFrontMatter)?;
+
+        rest.split_once(self.delimiter.as_str())
+            .ok_or_else(|| ParseError::Unterminated {
+                delimiter: self.delimiter.clone(),
             })
     }
 }
 
+/// Finds the first `marker` in `content` that isn't within a fenced code block.
+fn find_marker(content: &str, marker: &str) -> Option<usize> {
+    // The fence character and length of the open code block, if any.
+    let mut fence: Option<(char, usize)> = None;
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+
+        let trimmed = line.trim();
+
+        match fence {
+            Some((fence_char, length)) => {
+                if code_fence(trimmed)
+                    .is_some_and(|(c, n)| c == fence_char && n >= length && trimmed.len() == n)
+                {
+                    fence = None;
+                }
+            }
+            None => match code_fence(trimmed) {
+                Some(opened) => fence = Some(opened),
+                None => {
+                    if let Some(at) = line.find(marker) {
+                        return Some(start + at);
+                    }
+                }
+            },
+        }
+    }
+
+    None
+}
+
+/// The character and length of the code fence `line` starts with, if it starts one.
+fn code_fence(line: &str) -> Option<(char, usize)> {
+    let fence_char = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let length = line.chars().take_while(|c| *c == fence_char).count();
+
+    (length >= 3).then_some((fence_char, length))
+}
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("The page doesn't start with front matter")]
+    MissingFrontMatter,
+    #[error("The front matter is never closed, add a {delimiter} line after it")]
+    Unterminated { delimiter: String },
+    #[error("Invalid front matter on line {line}: {}", source.message())]
+    InvalidMatter {
+        /// The line of the page the problem is on, counting from 1.
+        line: usize,
+        #[source]
+        source: toml::de::Error,
+    },
+}
+
 #[derive(Debug)]
 pub struct ParsedData {
     matter: Option<Table>,
@@ -130,13 +207,110 @@ mod tests {
     }
 
     #[test]
-    fn returns_none_if_unable_to_find_frontmatter() {
+    fn excerpts_follow_the_marker_contract() {
+        let parser = Parser::default().with_excerpt("<!-- more -->");
+
+        let cases = [
+            // (body, excerpt, content)
+            ("Intro\n<!-- more -->\nRest", Some("Intro"), "Rest"),
+            ("No marker at all\n", None, "No marker at all"),
+            ("<!-- more -->\nAll content", Some(""), "All content"),
+            ("\n\n  <!-- more -->Rest", Some(""), "Rest"),
+            (
+                "One<!-- more -->Two<!-- more -->Three",
+                Some("One"),
+                "Two<!-- more -->Three",
+            ),
+            (
+                "  Intro  \n\n<!-- more -->\n\n  Indented rest\n\n",
+                Some("Intro"),
+                "Indented rest",
+            ),
+            (
+                "```html\n<!-- more -->\n```\nAfter",
+                None,
+                "```html\n<!-- more -->\n```\nAfter",
+            ),
+            (
+                "````\n```\n<!-- more -->\n````\nShown<!-- more -->Hidden",
+                Some("````\n```\n<!-- more -->\n````\nShown"),
+                "Hidden",
+            ),
+            (
+                "~~~\n<!-- more -->\n~~~\nIntro\n<!-- more -->\nRest",
+                Some("~~~\n<!-- more -->\n~~~\nIntro"),
+                "Rest",
+            ),
+            (
+                "```\nnever closed\n<!-- more -->",
+                None,
+                "```\nnever closed\n<!-- more -->",
+            ),
+            ("Inline `<!-- more -->` code", Some("Inline `"), "` code"),
+        ];
+
+        for (body, excerpt, content) in cases {
+            let result = parser.parse(&format!("+++\n+++\n{}", body)).unwrap();
+
+            assert_eq!(result.excerpt(), excerpt, "excerpt of {:?}", body);
+            assert_eq!(result.content(), content, "content of {:?}", body);
+        }
+    }
+
+    #[test]
+    fn errors_if_unable_to_find_frontmatter() {
         let parser = Parser::default();
 
         let test_page = "Everything else I don't want to include.\n\nA Paragraph\n";
 
         let result = parser.parse(test_page);
 
-        assert!(result.is_none());
+        assert!(matches!(result, Err(ParseError::MissingFrontMatter)));
+    }
+
+    #[test]
+    fn errors_if_frontmatter_is_never_closed() {
+        let parser = Parser::default();
+
+        let test_page = "+++\ntitle = \"Forgot\"\n\nThe whole body";
+
+        let result = parser.parse(test_page);
+
+        assert!(matches!(
+            result,
+            Err(ParseError::Unterminated { delimiter }) if delimiter == "+++"
+        ));
+    }
+
+    #[test]
+    fn invalid_frontmatter_points_at_the_line_of_the_page() {
+        let parser = Parser::default();
+
+        let test_page = "+++\ntitle = \"One\"\ndraft = true\ntitle = \"Two\"\n+++\nBody";
+
+        let error = parser.parse(test_page).unwrap_err();
+
+        assert!(matches!(error, ParseError::InvalidMatter { line: 4, .. }));
+        assert_eq!(
+            error.to_string(),
+            "Invalid front matter on line 4: duplicate key `title` in document root"
+        );
+        assert_eq!(
+            parser.split(test_page).unwrap(),
+            (
+                "\ntitle = \"One\"\ndraft = true\ntitle = \"Two\"\n",
+                "\nBody"
+            )
+        );
+    }
+
+    #[test]
+    fn empty_frontmatter_keeps_the_whole_body() {
+        let parser = Parser::default();
+
+        let mut result = parser.parse("+++\n+++\nBody with +++ inside\n").unwrap();
+
+        assert_eq!(result.take_matter(), Some(Table::new()));
+        assert_eq!(result.content(), "Body with +++ inside");
     }
 }